@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
+#[cfg(feature = "validation")]
+use crate::interface::Interface;
 use crate::interface::{Export, Import};
+#[cfg(feature = "validation")]
+use crate::validate::{validate_wasm_and_report_errors, WasmValidationError};
 
 /// A struct containing data for more efficient matching.
 ///
@@ -27,3 +31,117 @@ impl InterfaceMatcher {
         bincode::deserialize(bytes).ok()
     }
 }
+
+/// How well a module matches each of several candidate interfaces, see
+/// [`InterfaceMatcher::best_match`].
+#[cfg(feature = "validation")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceMatch {
+    /// The index of the candidate the module is closest to
+    pub best: usize,
+    /// The ways the module violates each candidate, in the order of the candidates.
+    /// Empty for the candidates the module satisfies.
+    pub mismatches: Vec<Vec<String>>,
+}
+
+#[cfg(feature = "validation")]
+impl InterfaceMatch {
+    /// Whether the module satisfies the best candidate
+    pub fn is_match(&self) -> bool {
+        self.mismatches[self.best].is_empty()
+    }
+}
+
+#[cfg(feature = "validation")]
+impl InterfaceMatcher {
+    /// Finds the interface the module matches best: the first one it satisfies or,
+    /// when it satisfies none, the one it violates the least.
+    ///
+    /// Fails if the module is not valid wasm or if there are no candidates.
+    pub fn best_match(
+        wasm: &[u8],
+        interfaces: &[Interface],
+    ) -> Result<InterfaceMatch, WasmValidationError> {
+        if interfaces.is_empty() {
+            return Err(WasmValidationError::NoInterface);
+        }
+        let mismatches = interfaces
+            .iter()
+            .map(
+                |interface| match validate_wasm_and_report_errors(wasm, interface) {
+                    Ok(()) => Ok(vec![]),
+                    Err(WasmValidationError::InterfaceViolated { errors }) => Ok(errors),
+                    Err(e) => Err(e),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        let best = (0..mismatches.len())
+            .min_by_key(|i| mismatches[*i].len())
+            .expect("there is at least one interface");
+        Ok(InterfaceMatch { best, mismatches })
+    }
+}
+
+#[cfg(all(test, feature = "validation"))]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    const WAT: &str = r#"(module
+(import "env" "log" (func $log (param i32)))
+(func (export "run") (param i32) (result i32) local.get 0)
+)"#;
+
+    fn interface(src: &str) -> Interface {
+        parser::parse_interface(src).unwrap()
+    }
+
+    #[test]
+    fn best_match_picks_the_satisfied_interface() {
+        let wasm = wat::parse_str(WAT).unwrap();
+        let candidates = [
+            // wrong import type
+            interface(r#"(interface (func (import "env" "log") (param i64)))"#),
+            interface(
+                r#"(interface
+                (func (import "env" "log") (param i32))
+                (func (export "run") (param i32) (result i32)))"#,
+            ),
+            // missing import
+            interface(r#"(interface (func (export "run") (param i32) (result i32)))"#),
+        ];
+        let result = InterfaceMatcher::best_match(&wasm, &candidates).unwrap();
+        assert_eq!(result.best, 1);
+        assert!(result.is_match());
+        assert_eq!(result.mismatches[0].len(), 1);
+        assert!(result.mismatches[0][0].contains("expected i64 found i32"));
+        assert_eq!(result.mismatches[2], vec![r#"Missing import "env" "log""#]);
+    }
+
+    #[test]
+    fn best_match_picks_the_closest_interface_when_none_match() {
+        let wasm = wat::parse_str(WAT).unwrap();
+        let candidates = [
+            interface(
+                r#"(interface
+                (func (import "other" "log") (param i32))
+                (func (export "run") (param i64) (result i64)))"#,
+            ),
+            interface(
+                r#"(interface
+                (func (import "env" "log") (param i32))
+                (func (export "run") (param i64) (result i32)))"#,
+            ),
+        ];
+        let result = InterfaceMatcher::best_match(&wasm, &candidates).unwrap();
+        assert!(!result.is_match());
+        assert_eq!(result.best, 1);
+        assert_eq!(result.mismatches[1].len(), 1);
+    }
+
+    #[test]
+    fn best_match_needs_candidates() {
+        let wasm = wat::parse_str(WAT).unwrap();
+        assert!(InterfaceMatcher::best_match(&wasm, &[]).is_err());
+    }
+}
@@ -122,6 +122,66 @@ pub fn validate_wasm_and_report_errors(
     }
 }
 
+/// A post-MVP wasm proposal that a module may rely on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum WasmFeature {
+    Threads,
+    ReferenceTypes,
+    Simd,
+    BulkMemory,
+    MultiValue,
+}
+
+impl WasmFeature {
+    pub const ALL: [WasmFeature; 5] = [
+        WasmFeature::Threads,
+        WasmFeature::ReferenceTypes,
+        WasmFeature::Simd,
+        WasmFeature::BulkMemory,
+        WasmFeature::MultiValue,
+    ];
+}
+
+/// Detects which post-MVP features a wasm module uses.
+///
+/// The module is validated once with every feature enabled and then once per
+/// feature with only that feature disabled; a feature is in use if disabling it
+/// makes the module invalid.
+pub fn detect_wasm_features(wasm: &[u8]) -> Result<Vec<WasmFeature>, WasmValidationError> {
+    use wasmparser::WasmDecoder;
+
+    let validate_with = |disabled: Option<WasmFeature>| -> Result<(), String> {
+        let enabled = |feature| disabled != Some(feature);
+        let mut parser = wasmparser::ValidatingParser::new(
+            wasm,
+            Some(wasmparser::ValidatingParserConfig {
+                operator_config: wasmparser::OperatorValidatorConfig {
+                    enable_threads: enabled(WasmFeature::Threads),
+                    enable_reference_types: enabled(WasmFeature::ReferenceTypes),
+                    enable_simd: enabled(WasmFeature::Simd),
+                    enable_bulk_memory: enabled(WasmFeature::BulkMemory),
+                    enable_multi_value: enabled(WasmFeature::MultiValue),
+                },
+            }),
+        );
+        loop {
+            match parser.read() {
+                wasmparser::ParserState::EndWasm => return Ok(()),
+                wasmparser::ParserState::Error(e) => return Err(format!("{}", e)),
+                _ => {}
+            }
+        }
+    };
+
+    validate_with(None).map_err(|error| WasmValidationError::InvalidWasm { error })?;
+
+    Ok(WasmFeature::ALL
+        .iter()
+        .cloned()
+        .filter(|feature| validate_with(Some(*feature)).is_err())
+        .collect())
+}
+
 /// Validates the import functions, checking the name and type against the given
 /// `Interface`
 fn validate_imports(
@@ -466,11 +526,44 @@ mod validation_tests {
 
         assert!(result.is_err(), "missing a required export is an error");
     }
+
+    #[test]
+    fn detect_features() {
+        const MVP_WAT: &str = r#"(module
+(func (export "add") (param i32 i32) (result i32)
+  (i32.add (local.get 0) (local.get 1)))
+)"#;
+        let wasm = wat::parse_str(MVP_WAT).unwrap();
+        assert_eq!(detect_wasm_features(&wasm).unwrap(), vec![]);
+
+        const MULTI_VALUE_WAT: &str = r#"(module
+(func (export "pair") (result i32 i32)
+  (i32.const 1) (i32.const 2))
+)"#;
+        let wasm = wat::parse_str(MULTI_VALUE_WAT).unwrap();
+        assert_eq!(
+            detect_wasm_features(&wasm).unwrap(),
+            vec![WasmFeature::MultiValue]
+        );
+
+        assert!(
+            detect_wasm_features(b"not wasm").is_err(),
+            "invalid wasm is reported as an error"
+        );
+    }
 }
 
 #[derive(Debug)]
 pub enum WasmValidationError {
-    InvalidWasm { error: String },
-    InterfaceViolated { errors: Vec<String> },
-    UnsupportedType { error: String },
+    InvalidWasm {
+        error: String,
+    },
+    InterfaceViolated {
+        errors: Vec<String>,
+    },
+    UnsupportedType {
+        error: String,
+    },
+    /// There was no interface to match the module against
+    NoInterface,
 }
@@ -7,11 +7,14 @@ use wapm_cli::update_notifier;
 use wapm_cli::{commands, logging};
 
 #[derive(StructOpt, Debug)]
-#[structopt(global_settings = &[AppSettings::VersionlessSubcommands, AppSettings::ColorAuto, AppSettings::ColoredHelp])]
+#[structopt(
+    global_settings = &[AppSettings::VersionlessSubcommands, AppSettings::ColorAuto, AppSettings::ColoredHelp],
+    after_help = "Pass `--log-format json` before the subcommand to print the logs as JSON lines."
+)]
 enum Command {
     #[structopt(name = "whoami")]
     /// Prints the current user (if authed) in the stdout
-    WhoAmI,
+    WhoAmI(commands::WhoAmIOpt),
 
     #[structopt(name = "login")]
     /// Logins into wapm, saving the token locally for future commands
@@ -39,7 +42,7 @@ enum Command {
         settings = &[AppSettings::TrailingVarArg, AppSettings::AllowLeadingHyphen],
     )]
     /// Run a command from the package or one of the dependencies
-    Run(commands::RunOpt),
+    Run(Box<commands::RunOpt>),
 
     #[cfg(feature = "full")]
     #[structopt(name = "search")]
@@ -68,6 +71,11 @@ enum Command {
     /// List the currently installed packages and their commands
     List(commands::ListOpt),
 
+    #[cfg(feature = "full")]
+    #[structopt(name = "outdated")]
+    /// List the dependencies of the manifest that have newer versions in the registry
+    Outdated(commands::OutdatedOpt),
+
     #[cfg(feature = "full")]
     #[cfg(feature = "packagesigning")]
     #[structopt(name = "keys")]
@@ -83,6 +91,11 @@ enum Command {
     /// Get the .bin dir path
     Bin(commands::BinOpt),
 
+    #[cfg(feature = "full")]
+    #[structopt(name = "cache")]
+    /// Manage the cache of compiled modules used by `wapm run`
+    Cache(commands::CacheOpt),
+
     #[cfg(feature = "update-notifications")]
     #[structopt(name = "run-background-update-check")]
     /// Run the background updater explicitly
@@ -96,23 +109,48 @@ enum Command {
     /// Remove packages from the manifest
     Remove(commands::RemoveOpt),
 
+    #[structopt(name = "upgrade")]
+    /// Raise the versions of the dependencies in the manifest and install them
+    Upgrade(commands::UpgradeOpt),
+
+    #[structopt(name = "fmt")]
+    /// Rewrite the manifest in the canonical format
+    Fmt(commands::FmtOpt),
+
+    #[structopt(name = "verify-manifest")]
+    /// Check a manifest file without the files of its package
+    VerifyManifest(commands::VerifyManifestOpt),
+
     #[cfg(feature = "full")]
     /// Execute a command, installing it temporarily if necessary
     Execute(commands::ExecuteOpt),
+
+    #[cfg(feature = "full")]
+    #[structopt(name = "doctor")]
+    /// Look for common problems and repair them with --fix
+    Doctor(commands::DoctorOpt),
 }
 
 fn main() {
+    let (log_format, cli_args) = match logging::extract_log_format(env::args().collect()) {
+        Ok(log_format_and_args) => log_format_and_args,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     #[cfg(not(target_os = "wasi"))]
     {
         let is_atty = atty::is(atty::Stream::Stdout);
-        if let Err(e) = logging::set_up_logging(is_atty) {
+        if let Err(e) = logging::set_up_logging(is_atty, log_format) {
             eprintln!("Error: {}", e);
         }
     }
 
     #[cfg(target_os = "wasi")]
     {
-        if let Err(e) = logging::set_up_logging(true) {
+        if let Err(e) = logging::set_up_logging(true, log_format) {
             eprintln!("Error: {}", e);
         }
     }
@@ -129,11 +167,11 @@ fn main() {
     };
 
     let prog_name = path::PathBuf::from(
-        env::args()
-            .next()
+        cli_args
+            .first()
             .expect("Fatal error could not find any arguments!"),
     );
-    let maybe_subcommand_name = env::args().nth(1);
+    let maybe_subcommand_name = cli_args.get(1).cloned();
     let prog_name = prog_name
         .file_name()
         .expect("Could not parse argv[0] as a path")
@@ -142,18 +180,18 @@ fn main() {
     #[cfg(feature = "full")]
     let args = if prog_name == "wax" {
         Command::Execute(commands::ExecuteOpt::ExecArgs(
-            env::args().skip(1).collect(),
+            cli_args.into_iter().skip(1).collect(),
         ))
     } else if maybe_subcommand_name == Some("execute".to_string()) {
         Command::Execute(commands::ExecuteOpt::ExecArgs(
-            env::args().skip(2).collect(),
+            cli_args.into_iter().skip(2).collect(),
         ))
     } else {
-        Command::from_args()
+        Command::from_iter(cli_args)
     };
 
     #[cfg(not(feature = "full"))]
-    let args = Command::from_args();
+    let args = Command::from_iter(cli_args);
 
     #[cfg(feature = "update-notifications")]
     // Only show the async check on certain commands
@@ -173,16 +211,21 @@ fn main() {
     };
 
     let result = match args {
-        Command::WhoAmI => commands::whoami(),
+        Command::WhoAmI(whoami_options) => commands::whoami(whoami_options),
         Command::Login(login_options) => commands::login(login_options),
         Command::Logout => commands::logout(),
         Command::Config(config_options) => commands::config(config_options),
         Command::Install(install_options) => commands::install(install_options),
         Command::Add(add_options) => commands::add(add_options),
         Command::Remove(remove_options) => commands::remove(remove_options),
+        Command::Upgrade(upgrade_options) => commands::upgrade(upgrade_options),
+        Command::Fmt(fmt_options) => commands::fmt(fmt_options),
+        Command::VerifyManifest(verify_manifest_options) => {
+            commands::verify_manifest(verify_manifest_options)
+        }
         #[cfg(feature = "full")]
         Command::Publish(publish_options) => commands::publish(publish_options),
-        Command::Run(run_options) => commands::run(run_options),
+        Command::Run(run_options) => commands::run(*run_options),
         #[cfg(feature = "full")]
         Command::Execute(execute_options) => commands::execute(execute_options),
         #[cfg(feature = "full")]
@@ -194,6 +237,8 @@ fn main() {
         #[cfg(feature = "full")]
         Command::List(list_options) => commands::list(list_options),
         #[cfg(feature = "full")]
+        Command::Outdated(outdated_options) => commands::outdated(outdated_options),
+        #[cfg(feature = "full")]
         #[cfg(feature = "packagesigning")]
         Command::Keys(key_options) => commands::keys(key_options),
         Command::Completions(completion_options) => {
@@ -207,6 +252,10 @@ fn main() {
         Command::Uninstall(uninstall_options) => commands::uninstall(uninstall_options),
         #[cfg(feature = "full")]
         Command::Bin(bin_options) => commands::bin(bin_options),
+        #[cfg(feature = "full")]
+        Command::Cache(cache_options) => commands::cache(cache_options),
+        #[cfg(feature = "full")]
+        Command::Doctor(doctor_options) => commands::doctor(doctor_options),
         #[cfg(feature = "update-notifications")]
         Command::BackgroundUpdateCheck => {
             update_notifier::run_subprocess_check();
@@ -233,11 +282,11 @@ fn main() {
         }
     }
 
-    if result.is_err() {
+    if let Err(e) = &result {
         #[cfg(feature = "telemetry")]
         {
             drop(_guard);
         };
-        std::process::exit(-1);
+        std::process::exit(commands::error_exit_code(e));
     }
 }
@@ -5,7 +5,9 @@ use crate::graphql::execute_query;
 use graphql_client::*;
 use thiserror::Error;
 
-use crate::data::manifest::Manifest;
+use crate::data::manifest::{add_dependency, Manifest};
+use crate::data::manifest_extensions::save_manifest;
+use crate::dataflow::manifest_packages::ManifestResult;
 use structopt::StructOpt;
 
 /// Options for the `add` subcommand
@@ -37,7 +39,10 @@ pub fn add(options: AddOpt) -> anyhow::Result<()> {
     let mut error = false;
     let mut manifest: Manifest = {
         let cur_dir = crate::config::Config::get_current_dir()?;
-        Manifest::find_in_directory(cur_dir).map_err(|_| AddError::NoManifest)?
+        match ManifestResult::find_in_directory(cur_dir) {
+            ManifestResult::Manifest(manifest) => manifest,
+            _ => return Err(AddError::NoManifest.into()),
+        }
     };
 
     if options.packages.is_empty() {
@@ -62,7 +67,7 @@ pub fn add(options: AddOpt) -> anyhow::Result<()> {
 
         if let Some(pv) = response.package_version {
             info!("Adding {}@{}", &package_name, &pv.version);
-            manifest.add_dependency(package_name, pv.version);
+            add_dependency(&mut manifest, package_name, pv.version)?;
         } else {
             error = true;
             if let Some(ver) = maybe_version {
@@ -73,7 +78,7 @@ pub fn add(options: AddOpt) -> anyhow::Result<()> {
         }
     }
 
-    manifest.save()?;
+    save_manifest(&manifest)?;
 
     if error {
         Err(AddError::GenericError.into())
@@ -0,0 +1,21 @@
+//! Subcommand to manage the cache of modules compiled by `wapm run`
+
+use crate::module_cache::ModuleCache;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum CacheOpt {
+    #[structopt(name = "clean")]
+    /// Delete all the compiled modules
+    Clean,
+}
+
+pub fn cache(cache_opt: CacheOpt) -> anyhow::Result<()> {
+    match cache_opt {
+        CacheOpt::Clean => {
+            ModuleCache::in_config_folder()?.clean()?;
+            info!("Cleaned the module cache");
+            Ok(())
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use crate::config::{get, set, Config};
+use crate::config::{get_for_display, set, Config, ConfigError};
+use anyhow::Context;
+use std::fs;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -10,6 +13,18 @@ pub enum ConfigOpt {
     #[structopt(name = "get")]
     /// Gets a key
     Get(ConfigKey),
+
+    #[structopt(name = "export")]
+    /// Writes the config to a file, to import it on another machine
+    Export(ConfigExportOpt),
+
+    #[structopt(name = "import")]
+    /// Merges an exported config into the current one
+    Import(ConfigImportOpt),
+
+    #[structopt(name = "remove-registry")]
+    /// Removes a registry and its login token
+    RemoveRegistry(ConfigRemoveRegistryOpt),
 }
 
 #[derive(StructOpt, Debug)]
@@ -25,17 +40,83 @@ pub struct ConfigKeyValue {
 pub struct ConfigKey {
     #[structopt(parse(from_str))]
     key: String,
+
+    /// Print secrets like `registry.token` in full instead of masked
+    #[structopt(long = "show")]
+    show: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ConfigExportOpt {
+    /// The file to write the config to. Printed when missing
+    #[structopt(long = "out", parse(from_os_str))]
+    out: Option<PathBuf>,
+
+    /// Export the registry login tokens and the headers carrying credentials too
+    #[structopt(long = "include-tokens")]
+    include_tokens: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ConfigImportOpt {
+    /// The exported config
+    #[structopt(parse(from_os_str))]
+    file: PathBuf,
+
+    /// Print the config the import results in instead of saving it
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ConfigRemoveRegistryOpt {
+    /// The URL of the registry
+    #[structopt(parse(from_str))]
+    url: String,
 }
 
 pub fn config(config_opt: ConfigOpt) -> anyhow::Result<()> {
     let mut config = Config::from_file()?;
     match config_opt {
         ConfigOpt::Set(ConfigKeyValue { key, value }) => set(&mut config, key, value),
-        ConfigOpt::Get(ConfigKey { key }) => {
-            let value = get(&mut config, key)?;
+        ConfigOpt::Get(ConfigKey { key, show }) => {
+            let value = get_for_display(&mut config, key, show)?;
             println!("{}", value);
             Ok(())
         }
+        ConfigOpt::Export(ConfigExportOpt {
+            out,
+            include_tokens,
+        }) => {
+            let exported = config.export(include_tokens)?;
+            match out {
+                Some(out) => fs::write(out, exported)?,
+                None => print!("{}", exported),
+            }
+            Ok(())
+        }
+        ConfigOpt::Import(ConfigImportOpt { file, dry_run }) => {
+            let exported = fs::read_to_string(&file)
+                .with_context(|| format!("Could not read \"{}\"", file.display()))?;
+            let imported = config.import(&exported)?;
+            if dry_run {
+                print!("{}", imported.export(false)?);
+                return Ok(());
+            }
+            imported.save()
+        }
+        ConfigOpt::RemoveRegistry(ConfigRemoveRegistryOpt { url }) => {
+            if !config.registry.remove_registry(&url) {
+                return Err(ConfigError::UnknownRegistry { registry: url }.into());
+            }
+            config.save()?;
+            println!(
+                "Removed registry {:?}, the current registry is {:?}",
+                url,
+                config.registry.get_current_registry()
+            );
+            Ok(())
+        }
     }
 }
 
@@ -47,6 +128,6 @@ impl ConfigOpt {
     }
 
     pub fn get(key: String) -> Self {
-        ConfigOpt::Get(ConfigKey { key })
+        ConfigOpt::Get(ConfigKey { key, show: true })
     }
 }
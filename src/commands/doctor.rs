@@ -0,0 +1,269 @@
+//! Code pertaining to the `doctor` subcommand: it looks for the common problems of a
+//! wapm setup and, with `--fix`, repairs them.
+
+use crate::config::{Config, GLOBAL_CONFIG_DATABASE_FILE_NAME, GLOBAL_WAX_INDEX_FILE_NAME};
+use crate::data::lock::LOCKFILE_NAME;
+use crate::data::manifest::MANIFEST_FILE_NAME;
+use crate::data::wax_index::WaxIndex;
+use crate::database;
+use crate::dataflow;
+use crate::util;
+use rusqlite::{params, Connection, OpenFlags};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use thiserror::Error;
+
+/// Options for the `doctor` subcommand
+#[derive(StructOpt, Debug)]
+pub struct DoctorOpt {
+    /// Repair the problems found, after asking for each one
+    #[structopt(long = "fix")]
+    fix: bool,
+    /// Don't ask before the safe repairs. The ones that lose data are always asked
+    #[structopt(long = "yes", short = "y", requires = "fix")]
+    yes: bool,
+}
+
+#[derive(Debug, Error)]
+enum DoctorError {
+    #[error("{0} problem(s) found, run `wapm doctor --fix` to repair them")]
+    ProblemsFound(usize),
+    #[error("{0} problem(s) were not repaired")]
+    NotRepaired(usize),
+}
+
+/// A problem of the wapm setup
+#[derive(Debug, PartialEq)]
+enum Problem {
+    /// The config folder doesn't exist
+    MissingConfigFolder(PathBuf),
+    /// The wax index can't be read
+    CorruptWaxIndex(PathBuf, String),
+    /// The database of the trusted keys can't be read
+    CorruptKeyDatabase(PathBuf, String),
+    /// The directory has a manifest but no lockfile
+    MissingLockfile(PathBuf),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::MissingConfigFolder(path) => {
+                write!(f, "The config folder \"{}\" does not exist", path.display())
+            }
+            Problem::CorruptWaxIndex(path, e) => {
+                write!(f, "The wax index \"{}\" is corrupt: {}", path.display(), e)
+            }
+            Problem::CorruptKeyDatabase(path, e) => {
+                write!(
+                    f,
+                    "The key database \"{}\" is corrupt: {}",
+                    path.display(),
+                    e
+                )
+            }
+            Problem::MissingLockfile(directory) => write!(
+                f,
+                "The package in \"{}\" has no {}",
+                directory.display(),
+                LOCKFILE_NAME
+            ),
+        }
+    }
+}
+
+impl Problem {
+    /// What the repair does, to confirm it
+    fn repair(&self) -> String {
+        match self {
+            Problem::MissingConfigFolder(path) => format!("Create \"{}\"", path.display()),
+            Problem::CorruptWaxIndex(..) => {
+                "Reset the wax index, the commands run with wax are downloaded again".to_string()
+            }
+            Problem::CorruptKeyDatabase(path, _) => format!(
+                "Create an empty key database, the trusted keys are lost. The corrupt one is \
+                 kept as \"{}\"",
+                corrupt_copy_path(path).display()
+            ),
+            Problem::MissingLockfile(_) => {
+                format!("Generate {} from {}", LOCKFILE_NAME, MANIFEST_FILE_NAME)
+            }
+        }
+    }
+
+    /// Whether the repair loses data, in which case `--yes` doesn't confirm it
+    fn is_destructive(&self) -> bool {
+        matches!(self, Problem::CorruptKeyDatabase(..))
+    }
+
+    fn fix(&self) -> anyhow::Result<()> {
+        match self {
+            Problem::MissingConfigFolder(path) => fs::create_dir_all(path)?,
+            // an empty index is a valid one
+            Problem::CorruptWaxIndex(path, _) => fs::write(path, "")?,
+            Problem::CorruptKeyDatabase(path, _) => {
+                fs::rename(path, corrupt_copy_path(path))?;
+                let mut conn = Connection::open(path)?;
+                database::apply_migrations(&mut conn)?;
+            }
+            Problem::MissingLockfile(directory) => {
+                dataflow::update(vec![], vec![], directory)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where a corrupt file is kept when it is replaced
+fn corrupt_copy_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".corrupt");
+    path.with_file_name(file_name)
+}
+
+/// Run the doctor command
+pub fn doctor(options: DoctorOpt) -> anyhow::Result<()> {
+    let problems = find_problems(&Config::get_folder_path()?, &Config::get_current_dir()?);
+    if problems.is_empty() {
+        println!("No problems found");
+        return Ok(());
+    }
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    if !options.fix {
+        return Err(DoctorError::ProblemsFound(problems.len()).into());
+    }
+
+    let mut not_repaired = 0;
+    for problem in problems.iter() {
+        let confirmed = if options.yes && !problem.is_destructive() {
+            true
+        } else {
+            util::prompt_user_for_yes(&format!("{}. {}?", problem, problem.repair()))?
+        };
+        if !confirmed {
+            not_repaired += 1;
+            continue;
+        }
+        match problem.fix() {
+            Ok(()) => info!("{}: done", problem.repair()),
+            Err(e) => {
+                error!("{}: {}", problem.repair(), e);
+                not_repaired += 1;
+            }
+        }
+    }
+    if not_repaired > 0 {
+        return Err(DoctorError::NotRepaired(not_repaired).into());
+    }
+    Ok(())
+}
+
+/// The problems of the config folder and of the package in `current_dir`
+fn find_problems(config_folder: &Path, current_dir: &Path) -> Vec<Problem> {
+    let mut problems = vec![];
+    if !config_folder.is_dir() {
+        problems.push(Problem::MissingConfigFolder(config_folder.to_path_buf()));
+    } else {
+        let wax_index_path = config_folder.join(GLOBAL_WAX_INDEX_FILE_NAME);
+        if let Err(e) = check_wax_index(&wax_index_path) {
+            problems.push(Problem::CorruptWaxIndex(wax_index_path, e));
+        }
+        let database_path = config_folder.join(GLOBAL_CONFIG_DATABASE_FILE_NAME);
+        if let Err(e) = check_database(&database_path) {
+            problems.push(Problem::CorruptKeyDatabase(database_path, e));
+        }
+    }
+    if current_dir.join(MANIFEST_FILE_NAME).is_file() && !current_dir.join(LOCKFILE_NAME).exists() {
+        problems.push(Problem::MissingLockfile(current_dir.to_path_buf()));
+    }
+    problems
+}
+
+/// Checks that the wax index parses, a missing or empty one is valid
+fn check_wax_index(path: &Path) -> Result<(), String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.to_string()),
+    };
+    if contents.is_empty() {
+        return Ok(());
+    }
+    serde_json::from_str::<WaxIndex>(&contents)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Checks the integrity of the database, a missing one is created when needed
+fn check_database(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let result: String = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .and_then(|conn| conn.query_row("PRAGMA integrity_check", params![], |row| row.get(0)))
+        .map_err(|e| e.to_string())?;
+    if result != "ok" {
+        return Err(result);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::create_temp_dir;
+
+    #[test]
+    fn missing_config_folder_is_created() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let config_folder = tmp_dir.path().join(".wasmer");
+        let problems = find_problems(&config_folder, tmp_dir.path());
+        assert_eq!(
+            problems,
+            vec![Problem::MissingConfigFolder(config_folder.clone())]
+        );
+        assert!(!problems[0].is_destructive());
+
+        problems[0].fix().unwrap();
+        assert!(config_folder.is_dir());
+        assert!(find_problems(&config_folder, tmp_dir.path()).is_empty());
+    }
+
+    #[test]
+    fn corrupt_wax_index_is_reset() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let wax_index_path = tmp_dir.path().join(GLOBAL_WAX_INDEX_FILE_NAME);
+        fs::write(&wax_index_path, "{\"base_dir\": \"/tmp/wax\", \"ind").unwrap();
+        let problems = find_problems(tmp_dir.path(), tmp_dir.path());
+        assert_eq!(problems.len(), 1);
+        assert!(
+            matches!(problems[0], Problem::CorruptWaxIndex(ref path, _) if *path == wax_index_path)
+        );
+        assert!(!problems[0].is_destructive());
+
+        problems[0].fix().unwrap();
+        assert!(find_problems(tmp_dir.path(), tmp_dir.path()).is_empty());
+    }
+
+    #[test]
+    fn corrupt_key_database_is_destructive() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let database_path = tmp_dir.path().join(GLOBAL_CONFIG_DATABASE_FILE_NAME);
+        fs::write(
+            &database_path,
+            "not a database, but long enough to have a header",
+        )
+        .unwrap();
+        let problems = find_problems(tmp_dir.path(), tmp_dir.path());
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].is_destructive());
+
+        problems[0].fix().unwrap();
+        assert!(corrupt_copy_path(&database_path).exists());
+        assert!(find_problems(tmp_dir.path(), tmp_dir.path()).is_empty());
+    }
+}
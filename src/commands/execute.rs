@@ -2,6 +2,7 @@
 
 //use crate::constants::RFC3339_FORMAT_STRING_WITH_TIMEZONE;
 use crate::config;
+use crate::data::manifest_extensions::manifest_from_str;
 use crate::data::wax_index;
 use crate::dataflow::find_command_result::FindCommandResult;
 use crate::dataflow::installed_packages::{InstalledPackages, RegistryInstaller};
@@ -73,6 +74,14 @@ struct ExecuteOptInner {
     #[structopt(long = "no-default-preopen")]
     no_default_preopen: bool,
 
+    /// Print how long each phase of the run took to stderr.
+    #[structopt(long = "print-timings")]
+    print_timings: bool,
+
+    /// Keep the temporary files of the run and print where they are.
+    #[structopt(long = "keep-temp")]
+    keep_temp: bool,
+
     /// The command to run.
     #[structopt(long = "which", value_name = "COMMAND")]
     which: Option<String>,
@@ -155,6 +164,7 @@ struct WaxGetCommandQuery;
 struct WaxGetPackageCommandQuery;
 
 use crate::commands::add::get_package_version_query;
+use crate::commands::run::{RunSettings, RunTimings};
 
 use super::add::GetPackageVersionQuery;
 
@@ -227,10 +237,9 @@ impl QueriedPackage {
                 )
                 .map_err(|e| ExecuteError::ErrorInDataFromRegistry(e.to_string()))
                 .ok()?;
-                let manifest = wapm_toml::Manifest::parse(
-                    &response.package.as_ref()?.last_version.as_ref()?.manifest,
-                )
-                .ok()?;
+                let manifest =
+                    manifest_from_str(&response.package.as_ref()?.last_version.as_ref()?.manifest)
+                        .ok()?;
                 let command_to_exec = match command_name {
                     Some(s) => s,
                     None => manifest
@@ -266,8 +275,7 @@ impl QueriedPackage {
                         .map_err(|e| ExecuteError::ErrorInDataFromRegistry(e.to_string()))
                         .ok()?;
                 let manifest =
-                    wapm_toml::Manifest::parse(&response.package_version.as_ref()?.manifest)
-                        .ok()?;
+                    manifest_from_str(&response.package_version.as_ref()?.manifest).ok()?;
                 let command_to_exec = match command_name {
                     Some(s) => s,
                     None => manifest
@@ -314,6 +322,8 @@ fn transform_args(arg_stream: &[String]) -> Result<ExecuteOptInner, ExecuteArgPa
             "--verify" | "-v" => out.verify_signature = true,
             "--no-default-preopen" => out.no_default_preopen = true,
             "--offline" => out.offline = true,
+            "--print-timings" => out.print_timings = true,
+            "--keep-temp" => out.keep_temp = true,
             "--which" => {
                 out.which = Some(parse_which(arg_stream.get(idx + 1).cloned())?);
                 idx += 1;
@@ -371,6 +381,7 @@ pub fn execute(opt: ExecuteOpt) -> anyhow::Result<()> {
     }
     let opt = opt;
     trace!("Execute {:?}", &opt);
+    let mut timings = RunTimings::new(opt.print_timings);
     let current_dir = crate::config::Config::get_current_dir()?;
     let config = crate::config::Config::from_file()?;
     if let Some(which) = opt.which {
@@ -424,21 +435,28 @@ pub fn execute(opt: ExecuteOpt) -> anyhow::Result<()> {
             args: _,
             module_name,
             prehashed_cache_key,
+            package: _,
         } => {
             debug!(
                 "Wax command found locally in {}",
                 current_dir.to_string_lossy()
             );
             // run it and return
+            timings.end_phase("resolve");
             crate::commands::run::do_run(
                 current_dir,
                 source,
-                manifest_dir,
+                Some(manifest_dir),
                 command_name,
                 &module_name,
                 &opt.pre_opened_directories,
                 &opt.args,
                 prehashed_cache_key,
+                RunSettings {
+                    keep_temp: opt.keep_temp,
+                    timings,
+                    ..RunSettings::default()
+                },
             )?;
             return Ok(());
         }
@@ -488,11 +506,15 @@ pub fn execute(opt: ExecuteOpt) -> anyhow::Result<()> {
             // do execute unless it fails then continue
             wax_index.save()?;
 
+            let mut timings = timings.clone();
+            timings.end_phase("resolve");
             match run(
                 command_name,
                 location,
                 &opt.pre_opened_directories,
                 &opt.args,
+                opt.keep_temp,
+                timings,
             ) {
                 Ok(()) => return Ok(()),
                 Err(e) => {
@@ -513,11 +535,11 @@ pub fn execute(opt: ExecuteOpt) -> anyhow::Result<()> {
         let response: Result<wax_get_command_query::ResponseData, _> = execute_query(&q);
         if response.is_err() {
             info!("Failed to connect to the wapm registry. Continuning in offline mode.");
-            return do_offline_run(command_name, &opt);
+            return do_offline_run(command_name, &opt, timings);
         }
         response?
     } else {
-        return do_offline_run(command_name, &opt);
+        return do_offline_run(command_name, &opt, timings);
     };
 
     trace!("Wax get command query: {:?}", response);
@@ -566,11 +588,14 @@ pub fn execute(opt: ExecuteOpt) -> anyhow::Result<()> {
                 );
                 wax_index.save()?;
 
+                timings.end_phase("resolve");
                 run(
                     command_name,
                     location,
                     &opt.pre_opened_directories,
                     &opt.args,
+                    opt.keep_temp,
+                    timings,
                 )?;
                 return Ok(());
             }
@@ -658,6 +683,8 @@ pub fn execute(opt: ExecuteOpt) -> anyhow::Result<()> {
     let registry_version = reg_ver.context("no registry version")?;
     let package_name = package_name.context("no package name")?;
 
+    timings.end_phase("resolve");
+
     // perform the install and generate the lockfile (like a simpler version of dataflow::update updating without a manifest)
     let lockfile_result = LockfileResult::find_in_directory(&install_loc);
     let lockfile_packages = LockfilePackages::new_from_result(lockfile_result)
@@ -667,8 +694,11 @@ pub fn execute(opt: ExecuteOpt) -> anyhow::Result<()> {
         resolved_packages,
         !opt.verify_signature,
     )?;
-    let added_lockfile_data = LockfilePackages::from_installed_packages(&installed_packages)
-        .map_err(|e| ExecuteError::InstallationError(e.to_string()))?;
+    // a package installed again keeps its features
+    let requested_features = lockfile_packages.requested_features(&Default::default(), &[]);
+    let added_lockfile_data =
+        LockfilePackages::from_installed_packages(&installed_packages, &requested_features)
+            .map_err(|e| ExecuteError::InstallationError(e.to_string()))?;
 
     let retained_lockfile_packages =
         RetainedLockfilePackages::from_lockfile_packages(lockfile_packages);
@@ -704,11 +734,14 @@ pub fn execute(opt: ExecuteOpt) -> anyhow::Result<()> {
         );
     }
 
+    timings.end_phase("install");
     run(
         &run_command_name,
         install_loc,
         &opt.pre_opened_directories,
         &opt.args,
+        opt.keep_temp,
+        timings,
     )?;
 
     Ok(())
@@ -719,6 +752,8 @@ fn run(
     location: PathBuf,
     pre_opened_directories: &[String],
     args: &[OsString],
+    keep_temp: bool,
+    timings: RunTimings,
 ) -> anyhow::Result<()> {
     match FindCommandResult::find_command_in_directory(&location, command_name) {
         FindCommandResult::CommandNotFound { error, extended: _ } => {
@@ -738,24 +773,34 @@ fn run(
             args: _,
             module_name,
             prehashed_cache_key,
+            package: _,
         } => {
             crate::logging::clear_stdout()?;
             crate::commands::run::do_run(
                 location,
                 source,
-                manifest_dir,
+                Some(manifest_dir),
                 command_name,
                 &module_name,
                 pre_opened_directories,
                 args,
                 prehashed_cache_key,
+                RunSettings {
+                    keep_temp,
+                    timings,
+                    ..RunSettings::default()
+                },
             )
         }
         FindCommandResult::Error(e) => Err(e),
     }
 }
 
-fn do_offline_run(command_name: &str, opt: &ExecuteOptInner) -> anyhow::Result<()> {
+fn do_offline_run(
+    command_name: &str,
+    opt: &ExecuteOptInner,
+    mut timings: RunTimings,
+) -> anyhow::Result<()> {
     let mut wax_index = wax_index::WaxIndex::open()?;
     if let Ok((package_name, version, _)) = wax_index.search_for_entry(command_name.to_string()) {
         let package_version_str = format!("{}@{}", &package_name, &version);
@@ -764,11 +809,14 @@ fn do_offline_run(command_name: &str, opt: &ExecuteOptInner) -> anyhow::Result<(
         wax_index.save()?;
 
         crate::logging::clear_stdout()?;
+        timings.end_phase("resolve");
         run(
             command_name,
             location,
             &opt.pre_opened_directories,
             &opt.args,
+            opt.keep_temp,
+            timings,
         )
     } else if opt.offline {
         Err(ExecuteError::CommandNotFoundOfflineModeOfflineFlag(command_name.to_string()).into())
@@ -0,0 +1,50 @@
+//! Code pertaining to the `fmt` subcommand: it rewrites the manifest in the
+//! canonical format.
+
+use crate::data::manifest::MANIFEST_FILE_NAME;
+use crate::data::manifest_format::format_manifest;
+use crate::dataflow::manifest_packages::ManifestResult;
+use std::fs;
+use structopt::StructOpt;
+use thiserror::Error;
+
+/// Options for the `fmt` subcommand
+#[derive(StructOpt, Debug)]
+pub struct FmtOpt {
+    /// Exit with an error if the manifest is not formatted, instead of formatting it
+    #[structopt(long = "check")]
+    check: bool,
+}
+
+#[derive(Debug, Error)]
+enum FmtError {
+    #[error("Could not find a manifest in the current directory, try running `wapm init`")]
+    NoManifest,
+    #[error("The manifest is not formatted, run `wapm fmt` to format it")]
+    NotFormatted,
+}
+
+/// Run the fmt command
+pub fn fmt(options: FmtOpt) -> anyhow::Result<()> {
+    let cur_dir = crate::config::Config::get_current_dir()?;
+    let manifest_path = cur_dir.join(MANIFEST_FILE_NAME);
+    if !manifest_path.is_file() {
+        return Err(FmtError::NoManifest.into());
+    }
+    // only well-formed manifests are formatted
+    if let ManifestResult::ManifestError(e) = ManifestResult::find_in_directory(&cur_dir) {
+        return Err(e.into());
+    }
+
+    let source = fs::read_to_string(&manifest_path)?;
+    let formatted = format_manifest(&source)?;
+    if formatted == source {
+        return Ok(());
+    }
+    if options.check {
+        return Err(FmtError::NotFormatted.into());
+    }
+    fs::write(&manifest_path, formatted)?;
+    info!("Formatted {}", manifest_path.display());
+    Ok(())
+}
@@ -1,18 +1,37 @@
-use crate::init;
+use crate::init::{self, PackageFields};
+use semver::Version;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 pub struct InitOpt {
-    /// Agree to all prompts. Useful for non-interactive uses
-    #[structopt(long = "force-yes", short = "y")]
+    /// Agree to all prompts and take the defaults. Useful for non-interactive uses
+    #[structopt(long = "force-yes", short = "y", alias = "yes")]
     force_yes: bool,
     /// Initial project name to specify (`wapm init myproject`)
     project_name: Option<String>,
+    /// The name of the package, defaults to the name of the directory
+    #[structopt(long = "name")]
+    name: Option<String>,
+    /// The version of the package, defaults to 0.1.0
+    #[structopt(long = "version")]
+    version: Option<Version>,
+    /// The description of the package
+    #[structopt(long = "description")]
+    description: Option<String>,
+    /// The license of the package as an SPDX expression, defaults to MIT
+    #[structopt(long = "license")]
+    license: Option<String>,
 }
 
 pub fn init(opt: InitOpt) -> anyhow::Result<()> {
     let current_directory = crate::config::Config::get_current_dir()?;
-    init::init(current_directory, opt.force_yes, opt.project_name)
+    let fields = PackageFields {
+        name: opt.name,
+        version: opt.version,
+        description: opt.description,
+        license: opt.license,
+    };
+    init::init(current_directory, opt.force_yes, opt.project_name, fields)
 }
 
 #[cfg(feature = "integration_tests")]
@@ -21,6 +40,10 @@ impl InitOpt {
         InitOpt {
             force_yes,
             project_name: None,
+            name: None,
+            version: None,
+            description: None,
+            license: None,
         }
     }
 }
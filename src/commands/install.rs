@@ -43,6 +43,31 @@ pub struct InstallOpt {
     /// Add the Python bindings using "pip install".
     #[structopt(long, group = "bindings", conflicts_with = "global")]
     pip: bool,
+    /// Delete and download again the given packages, or all the packages of the lockfile
+    #[structopt(long, conflicts_with = "bindings")]
+    reinstall: bool,
+    /// Reconcile the lockfile with `wapm_packages`: install again the missing
+    /// dependencies of the manifest and drop the lockfile entries of the packages that
+    /// are neither installed nor in the manifest
+    #[structopt(
+        long,
+        conflicts_with_all = &["bindings", "global", "reinstall", "packages"]
+    )]
+    sync: bool,
+    /// Enable these features of the package(s), on top of their default features.
+    /// They are kept in the lockfile
+    #[structopt(
+        long,
+        value_name = "FEATURE",
+        use_delimiter = true,
+        requires = "packages",
+        conflicts_with_all = &["bindings", "reinstall"]
+    )]
+    features: Vec<String>,
+    /// Install the packages that were downloaded before from the package cache, and
+    /// only download the other ones
+    #[structopt(long, conflicts_with = "bindings")]
+    offline: bool,
 }
 
 #[derive(Debug, Error)]
@@ -62,6 +87,12 @@ enum InstallError {
     #[error("Failed to install packages in manifest. {0}")]
     FailureInstallingPackages(dataflow::Error),
 
+    #[error("Failed to reinstall packages. {0}")]
+    FailureReinstallingPackages(dataflow::Error),
+
+    #[error("Failed to sync the lockfile. {0}")]
+    FailureSyncingLockfile(dataflow::Error),
+
     #[error(
         "Failed to install package because package identifier {0} is invalid, expected <name>@<version> or <name>",
         name
@@ -143,6 +174,13 @@ fn install_bindings(
 }
 
 fn wapm_install(options: InstallOpt, current_directory: PathBuf) -> Result<(), anyhow::Error> {
+    dataflow::package_cache::set_prefer_cached_packages(options.offline);
+    if options.reinstall {
+        return reinstall_packages(&options.packages, options.global, current_directory);
+    }
+    if options.sync {
+        return sync_lockfile(&current_directory);
+    }
     match (options.global, options.packages.is_empty()) {
         (global_flag::GLOBAL_INSTALL, package_args::NO_PACKAGES) => {
             // install all global packages - unacceptable use case
@@ -151,14 +189,18 @@ fn wapm_install(options: InstallOpt, current_directory: PathBuf) -> Result<(), a
         (global_flag::LOCAL_INSTALL, package_args::NO_PACKAGES) => {
             local_install_from_lockfile(&current_directory)
         }
-        (_, package_args::SOME_PACKAGES) => {
-            install_packages(&options.packages, options.global, current_directory)
-        }
+        (_, package_args::SOME_PACKAGES) => install_packages(
+            &options.packages,
+            &options.features,
+            options.global,
+            current_directory,
+        ),
     }
 }
 
 fn install_packages(
     package_names: &[String],
+    features: &[String],
     global: bool,
     current_directory: PathBuf,
 ) -> Result<(), anyhow::Error> {
@@ -184,8 +226,13 @@ fn install_packages(
 
     std::fs::create_dir_all(install_directory.clone())
         .map_err(InstallError::CannotCreateInstallDirectory)?;
-    let changes_applied = dataflow::update(installed_packages.clone(), vec![], install_directory)
-        .map_err(InstallError::CannotRegenLockFile)?;
+    let changes_applied = dataflow::update_with_features(
+        installed_packages.clone(),
+        vec![],
+        features,
+        install_directory,
+    )
+    .map_err(InstallError::CannotRegenLockFile)?;
 
     if changes_applied {
         if global {
@@ -200,6 +247,45 @@ fn install_packages(
     Ok(())
 }
 
+fn reinstall_packages(
+    package_names: &[String],
+    global: bool,
+    current_directory: PathBuf,
+) -> Result<(), anyhow::Error> {
+    let install_directory = match global {
+        true => Config::get_globals_directory()?,
+        false => current_directory,
+    };
+    // packages are named without a version, the lockfile decides which one is reinstalled
+    let package_names: Vec<&str> = package_names
+        .iter()
+        .map(|name| name.split('@').next().unwrap_or(name))
+        .collect();
+    let changes_applied = dataflow::reinstall(package_names, install_directory)
+        .map_err(InstallError::FailureReinstallingPackages)?;
+
+    if changes_applied {
+        println!("Packages reinstalled successfully!");
+    } else {
+        println!("No packages to reinstall");
+    }
+
+    Ok(())
+}
+
+fn sync_lockfile(current_directory: &Path) -> Result<(), anyhow::Error> {
+    let dropped_packages =
+        dataflow::sync(current_directory).map_err(InstallError::FailureSyncingLockfile)?;
+    for package in dropped_packages.iter() {
+        println!(
+            "Dropped {} from the lockfile, it is neither installed nor in the manifest",
+            package
+        );
+    }
+    println!("The lockfile is in sync with wapm_packages!");
+    Ok(())
+}
+
 #[derive(Debug)]
 struct VersionedPackage<'a> {
     name: &'a str,
@@ -233,8 +319,8 @@ fn parse_package_and_version(package_specifier: &str) -> Result<(String, String)
     let name_and_version: Vec<_> = package_specifier.split('@').collect();
     let config = Config::from_file()?;
     match name_and_version.as_slice() {
-        [name, version] => Ok((name.to_string(), version.to_string())),
-        [name] => {
+        // the latest version is locked like any other, so it is resolved here
+        [name] | [name, "latest"] | [name, "*"] => {
             let q = GetPackageQuery::build_query(get_package_query::Variables {
                 name: name.to_string(),
             });
@@ -252,6 +338,7 @@ fn parse_package_and_version(package_specifier: &str) -> Result<(String, String)
 
             Ok((name.to_string(), version))
         }
+        [name, version] => Ok((name.to_string(), version.to_string())),
         _ => Err(InstallError::InvalidPackageIdentifier {
             name: package_specifier.to_string(),
         }
@@ -1,11 +1,19 @@
 //! Subcommand for inspecting installed packages and commands
 
 use crate::config;
-use crate::data::lock::lockfile::{CommandMap, ModuleMap};
+use crate::data::lock::lockfile::{CommandMap, Lockfile, ModuleMap};
+use crate::data::manifest::{dependency_version_req, PACKAGES_DIR_NAME};
 use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::dataflow::manifest_packages::ManifestResult;
+use crate::dataflow::resolved_packages::RegistryResolver;
+use console::style;
 use prettytable::{format, Table};
+use semver::{Version, VersionReq};
+use std::collections::BTreeMap;
 use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
+use thiserror::Error;
 
 #[derive(StructOpt, Debug)]
 pub struct ListOpt {
@@ -16,9 +24,46 @@ pub struct ListOpt {
     /// List both locally and globally installed packages
     #[structopt(short = "a", long = "all")]
     all: bool,
+
+    /// List the packages installed at more than one version and what depends on each
+    /// version. Fails if there are any
+    #[structopt(long = "duplicates", conflicts_with = "all")]
+    duplicates: bool,
+
+    /// Show the latest version of each package in the registry, highlighted when it is
+    /// newer than the installed one
+    #[structopt(long = "outdated", conflicts_with = "duplicates")]
+    outdated: bool,
+
+    /// Don't query the registry for `--outdated`
+    #[structopt(long = "offline", requires = "outdated")]
+    offline: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum ListError {
+    #[error("{0} package(s) are installed at more than one version")]
+    DuplicatePackages(usize),
+}
+
+/// A package installed at more than one version
+#[derive(Debug, PartialEq, Eq)]
+struct DuplicatePackage {
+    name: String,
+    /// The installed versions and the packages that depend on each of them
+    dependents: BTreeMap<Version, Vec<String>>,
 }
 
 pub fn list(options: ListOpt) -> anyhow::Result<()> {
+    if options.duplicates {
+        let directory = if options.global {
+            config::Config::get_globals_directory()?
+        } else {
+            config::Config::get_current_dir()?
+        };
+        return list_duplicates(directory);
+    }
+
     let mut local = false;
     let mut global = false;
     match (options.global, options.all) {
@@ -34,6 +79,10 @@ pub fn list(options: ListOpt) -> anyhow::Result<()> {
         }
     }
     let local_start_value = local;
+    let check_updates = options.outdated && !options.offline;
+    if options.outdated && options.offline {
+        info!("Not checking the registry for updates in offline mode");
+    }
 
     let mut handle = String::new();
     if local {
@@ -46,7 +95,12 @@ pub fn list(options: ListOpt) -> anyhow::Result<()> {
                 local = has_modules | has_commands;
                 if has_modules {
                     writeln!(handle, "LOCAL PACKAGES:")?;
-                    write!(handle, "{}", create_module_ascii_table(&lockfile.modules))?;
+                    let latest_versions = find_latest_versions(&lockfile.modules, check_updates);
+                    write!(
+                        handle,
+                        "{}",
+                        create_module_ascii_table(&lockfile.modules, latest_versions.as_ref())
+                    )?;
                 }
                 if has_modules && has_commands {
                     writeln!(handle)?;
@@ -86,7 +140,12 @@ pub fn list(options: ListOpt) -> anyhow::Result<()> {
                 global = has_modules || has_commands;
                 if has_modules {
                     writeln!(handle, "GLOBAL PACKAGES:")?;
-                    write!(handle, "{}", create_module_ascii_table(&lockfile.modules))?;
+                    let latest_versions = find_latest_versions(&lockfile.modules, check_updates);
+                    write!(
+                        handle,
+                        "{}",
+                        create_module_ascii_table(&lockfile.modules, latest_versions.as_ref())
+                    )?;
                 }
                 if has_modules && has_commands {
                     writeln!(handle)?;
@@ -123,30 +182,383 @@ pub fn list(options: ListOpt) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn create_module_ascii_table(modules: &ModuleMap) -> String {
+fn list_duplicates(directory: PathBuf) -> anyhow::Result<()> {
+    let lockfile = match LockfileResult::find_in_directory(&directory) {
+        LockfileResult::Lockfile(lockfile) => lockfile,
+        LockfileResult::NoLockfile => {
+            println!("No packages found");
+            return Ok(());
+        }
+        LockfileResult::LockfileError(e) => {
+            return Err(anyhow!(
+                "Failed to read lock file in {:?}: {}",
+                directory,
+                e
+            ));
+        }
+    };
+    let duplicates = find_duplicate_packages(&lockfile, &directory);
+    if duplicates.is_empty() {
+        println!("No package is installed at more than one version");
+        return Ok(());
+    }
+    println!("DUPLICATE PACKAGES:");
+    print!("{}", create_duplicates_ascii_table(&duplicates));
+    Err(ListError::DuplicatePackages(duplicates.len()).into())
+}
+
+/// The packages in the lockfile at more than one version, with the packages that
+/// depend on each version according to their manifests
+fn find_duplicate_packages(lockfile: &Lockfile, directory: &Path) -> Vec<DuplicatePackage> {
+    let mut duplicates: Vec<DuplicatePackage> = lockfile
+        .modules
+        .iter()
+        .filter(|(_, versions)| versions.len() > 1)
+        .map(|(name, versions)| DuplicatePackage {
+            name: name.clone(),
+            dependents: versions.keys().map(|v| (v.clone(), vec![])).collect(),
+        })
+        .collect();
+    if duplicates.is_empty() {
+        return duplicates;
+    }
+
+    // the root manifest, then the manifest of every installed package
+    let mut manifest_dirs = vec![directory.to_path_buf()];
+    for (name, versions) in lockfile.modules.iter() {
+        for version in versions.keys() {
+            manifest_dirs.push(
+                directory
+                    .join(PACKAGES_DIR_NAME)
+                    .join(format!("{}@{}", name, version)),
+            );
+        }
+    }
+    for manifest_dir in manifest_dirs {
+        let manifest = match ManifestResult::find_in_directory(&manifest_dir) {
+            ManifestResult::Manifest(manifest) => manifest,
+            _ => continue,
+        };
+        let dependent = format!("{}@{}", manifest.package.name, manifest.package.version);
+        for (dependency_name, version) in manifest.dependencies.iter().flatten() {
+            let version_req = match dependency_version_req(dependency_name, version) {
+                Ok(version_req) => version_req,
+                Err(_) => continue,
+            };
+            let duplicate = match duplicates.iter_mut().find(|d| &d.name == dependency_name) {
+                Some(duplicate) => duplicate,
+                None => continue,
+            };
+            for (installed_version, dependents) in duplicate.dependents.iter_mut() {
+                if version_matches(&version_req, installed_version) {
+                    dependents.push(dependent.clone());
+                }
+            }
+        }
+    }
+    for duplicate in duplicates.iter_mut() {
+        for dependents in duplicate.dependents.values_mut() {
+            dependents.sort();
+            dependents.dedup();
+        }
+    }
+    duplicates
+}
+
+/// Whether a manifest dependency on `version_req` can be `installed_version`
+fn version_matches(version_req: &VersionReq, installed_version: &Version) -> bool {
+    *version_req == VersionReq::STAR || version_req.matches(installed_version)
+}
+
+fn create_duplicates_ascii_table(duplicates: &[DuplicatePackage]) -> String {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.add_row(row!["PACKAGE", "VERSION", "REQUIRED BY"]);
+    for duplicate in duplicates {
+        for (version, dependents) in duplicate.dependents.iter() {
+            let required_by = if dependents.is_empty() {
+                "-".to_string()
+            } else {
+                dependents.join(", ")
+            };
+            table.add_row(row![duplicate.name, version, required_by]);
+        }
+    }
+    format!("{}", table)
+}
+
+/// The latest version of the packages in the registry, `None` when not checking or
+/// when the registry can't be reached
+fn find_latest_versions(
+    modules: &ModuleMap,
+    check_updates: bool,
+) -> Option<BTreeMap<String, Version>> {
+    if !check_updates {
+        return None;
+    }
+    match RegistryResolver::latest_versions(modules.keys().cloned().collect()) {
+        Ok(latest_versions) => Some(latest_versions),
+        Err(e) => {
+            warn!("Could not check the registry for updates: {}", e);
+            None
+        }
+    }
+}
+
+/// The latest version of a package when it is newer than the installed one
+fn available_update<'a>(
+    latest_versions: &'a BTreeMap<String, Version>,
+    package_name: &str,
+    version: &Version,
+) -> Option<&'a Version> {
+    latest_versions
+        .get(package_name)
+        .filter(|latest| *latest > version)
+}
+
+/// The table of the installed modules, with a `LATEST` column when the latest versions
+/// are known
+fn create_module_table(
+    modules: &ModuleMap,
+    latest_versions: Option<&BTreeMap<String, Version>>,
+) -> Table {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-    table.add_row(row!["PACKAGE", "VERSION", "MODULE", "ABI"]);
+    match latest_versions {
+        Some(_) => table.add_row(row!["PACKAGE", "VERSION", "MODULE", "ABI", "LATEST"]),
+        None => table.add_row(row!["PACKAGE", "VERSION", "MODULE", "ABI"]),
+    };
     for (package_name, version_info) in modules.iter() {
         for (version_number, module_info) in version_info.iter() {
             for (module_name, module) in module_info.iter() {
-                table.add_row(row![package_name, version_number, module_name, module.abi,]);
+                let mut row = row![package_name, version_number, module_name, module.abi,];
+                if let Some(latest_versions) = latest_versions {
+                    let latest =
+                        match available_update(latest_versions, package_name, version_number) {
+                            Some(update) => style(update).yellow().bold().to_string(),
+                            None => latest_versions
+                                .get(package_name)
+                                .map(|latest| latest.to_string())
+                                .unwrap_or_else(|| "-".to_string()),
+                        };
+                    row.add_cell(cell!(latest));
+                }
+                table.add_row(row);
             }
         }
     }
-    format!("{}", table)
+    table
 }
 
-fn create_command_ascii_table(commands: &CommandMap) -> String {
+fn create_module_ascii_table(
+    modules: &ModuleMap,
+    latest_versions: Option<&BTreeMap<String, Version>>,
+) -> String {
+    format!("{}", create_module_table(modules, latest_versions))
+}
+
+/// The table of the installed commands, with a `DESCRIPTION` column when any of them
+/// has one
+fn create_command_table(commands: &CommandMap) -> Table {
+    let has_descriptions = commands
+        .values()
+        .any(|command| command.description.is_some());
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-    table.add_row(row!["COMMAND", "PACKAGE", "VERSION"]);
+    if has_descriptions {
+        table.add_row(row!["COMMAND", "PACKAGE", "VERSION", "DESCRIPTION"]);
+    } else {
+        table.add_row(row!["COMMAND", "PACKAGE", "VERSION"]);
+    }
     for (command_name, command) in commands.iter() {
-        table.add_row(row![
-            command_name,
-            command.package_name,
-            command.package_version,
-        ]);
+        let mut row = row![command_name, command.package_name, command.package_version,];
+        if has_descriptions {
+            row.add_cell(cell!(command.description.as_deref().unwrap_or_default()));
+        }
+        table.add_row(row);
+    }
+    table
+}
+
+fn create_command_ascii_table(commands: &CommandMap) -> String {
+    format!("{}", create_command_table(commands))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::lock::lockfile_module::LockfileModule;
+    use crate::dataflow::directory_registry::test::publish_to_directory;
+    use crate::dataflow::directory_registry::DirectoryRegistry;
+    use crate::util::create_temp_dir;
+    use std::fs;
+
+    fn write_manifest(directory: &Path, name: &str, version: &str, dependencies: &str) {
+        fs::create_dir_all(directory).unwrap();
+        let manifest = format!(
+            "[package]\nname = \"{}\"\nversion = \"{}\"\ndescription = \"\"\n\n[dependencies]\n{}",
+            name, version, dependencies
+        );
+        fs::write(directory.join("wapm.toml"), manifest).unwrap();
+    }
+
+    #[test]
+    fn duplicates_list_what_requires_each_version() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let directory = tmp_dir.path();
+        let packages_dir = directory.join(PACKAGES_DIR_NAME);
+        write_manifest(
+            directory,
+            "_/app",
+            "1.0.0",
+            "\"_/sqlite\" = \"0.1.0\"\n\"_/python\" = \"^0.1\"\n",
+        );
+        write_manifest(
+            &packages_dir.join("_/python@0.1.2"),
+            "_/python",
+            "0.1.2",
+            "\"_/sqlite\" = \"^0.2\"\n",
+        );
+
+        let mut modules = ModuleMap::new();
+        for (name, version) in &[
+            ("_/sqlite", "0.1.0"),
+            ("_/sqlite", "0.2.1"),
+            ("_/python", "0.1.2"),
+        ] {
+            modules
+                .entry(name.to_string())
+                .or_default()
+                .entry(Version::parse(version).unwrap())
+                .or_default()
+                .insert(name.to_string(), LockfileModule::default());
+        }
+        let lockfile = Lockfile {
+            modules,
+            commands: CommandMap::new(),
+            features: Default::default(),
+        };
+
+        let duplicates = find_duplicate_packages(&lockfile, directory);
+        assert_eq!(
+            duplicates,
+            vec![DuplicatePackage {
+                name: "_/sqlite".to_string(),
+                dependents: vec![
+                    (
+                        Version::parse("0.1.0").unwrap(),
+                        vec!["_/app@1.0.0".to_string()]
+                    ),
+                    (
+                        Version::parse("0.2.1").unwrap(),
+                        vec!["_/python@0.1.2".to_string()]
+                    ),
+                ]
+                .into_iter()
+                .collect(),
+            }]
+        );
+    }
+
+    #[test]
+    fn no_duplicates_in_a_lockfile_with_one_version_per_package() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let mut modules = ModuleMap::new();
+        modules
+            .entry("_/sqlite".to_string())
+            .or_default()
+            .entry(Version::parse("0.1.0").unwrap())
+            .or_default()
+            .insert("sqlite".to_string(), LockfileModule::default());
+        let lockfile = Lockfile {
+            modules,
+            commands: CommandMap::new(),
+            features: Default::default(),
+        };
+        assert!(find_duplicate_packages(&lockfile, tmp_dir.path()).is_empty());
+    }
+
+    #[test]
+    fn outdated_packages_show_the_latest_version() {
+        let registry_dir = create_temp_dir().unwrap();
+        let root = registry_dir.path();
+        publish_to_directory(root, "_", "sqlite", "0.1.0");
+        publish_to_directory(root, "_", "sqlite", "0.2.1");
+        publish_to_directory(root, "_", "python", "0.1.2");
+
+        let mut modules = ModuleMap::new();
+        for (name, version) in &[
+            ("_/sqlite", "0.1.0"),
+            ("_/python", "0.1.2"),
+            ("_/unpublished", "1.0.0"),
+        ] {
+            modules
+                .entry(name.to_string())
+                .or_default()
+                .entry(Version::parse(version).unwrap())
+                .or_default()
+                .insert(name.to_string(), LockfileModule::default());
+        }
+        let latest_versions: BTreeMap<String, Version> =
+            DirectoryRegistry::versions_in(root, &modules.keys().cloned().collect::<Vec<_>>())
+                .into_iter()
+                .filter_map(|(name, versions)| Some((name, versions.into_iter().max()?)))
+                .collect();
+        assert_eq!(
+            available_update(&latest_versions, "_/sqlite", &Version::new(0, 1, 0)),
+            Some(&Version::new(0, 2, 1))
+        );
+        assert_eq!(
+            available_update(&latest_versions, "_/python", &Version::new(0, 1, 2)),
+            None
+        );
+
+        let table = create_module_table(&modules, Some(&latest_versions));
+        let latest_column: Vec<String> = table
+            .row_iter()
+            .map(|row| {
+                console::strip_ansi_codes(&row.get_cell(4).unwrap().get_content()).to_string()
+            })
+            .collect();
+        // the rows are sorted by package name
+        assert_eq!(latest_column, vec!["LATEST", "0.1.2", "0.2.1", "-"]);
+        assert_eq!(
+            create_module_table(&modules, None)
+                .get_row(0)
+                .unwrap()
+                .len(),
+            4
+        );
+    }
+    #[test]
+    fn command_descriptions_are_listed() {
+        use crate::data::lock::lockfile_command::LockfileCommand;
+        let mut commands = CommandMap::new();
+        for (name, description) in &[("sqlite", Some("An SQLite shell")), ("python", None)] {
+            commands.insert(
+                name.to_string(),
+                LockfileCommand {
+                    name: name.to_string(),
+                    package_name: format!("_/{}", name),
+                    package_version: Version::new(0, 1, 0),
+                    module: name.to_string(),
+                    is_top_level_dependency: true,
+                    main_args: None,
+                    description: description.map(str::to_string),
+                },
+            );
+        }
+        let table = create_command_table(&commands);
+        let description_column: Vec<String> = table
+            .row_iter()
+            .map(|row| row.get_cell(3).unwrap().get_content())
+            .collect();
+        assert_eq!(
+            description_column,
+            vec!["DESCRIPTION", "", "An SQLite shell"]
+        );
+
+        commands.remove("sqlite");
+        assert_eq!(create_command_table(&commands).get_row(0).unwrap().len(), 3);
     }
-    format!("{}", table)
 }
@@ -1,9 +1,11 @@
-use crate::config::{Config, UpdateRegistry};
-use crate::graphql::execute_query;
+use crate::config::{format_graphql, Config, UpdateRegistry};
+use crate::graphql::execute_query_custom_registry;
+use crate::util;
 use rpassword_wasi as rpassword;
 use std::io::prelude::*;
 use std::io::{stdin, stdout};
 use structopt::StructOpt;
+use thiserror::Error;
 
 use graphql_client::*;
 
@@ -20,6 +22,20 @@ pub struct LoginOpt {
     /// Password
     #[structopt(long)]
     password: Option<String>,
+    /// Check that the stored token is valid for the current registry, without logging in
+    #[structopt(long, conflicts_with_all = &["token", "user", "password"])]
+    check: bool,
+    /// Log into this registry instead of the current one. It becomes the current one
+    #[structopt(long = "registry", value_name = "URL", conflicts_with = "check")]
+    registry: Option<String>,
+}
+
+#[derive(Debug, Error)]
+enum LoginCheckError {
+    #[error("Not logged into registry {0:?}: no token is stored for it.")]
+    NoToken(String),
+    #[error("The token stored for registry {0:?} is invalid or expired. Run `wapm login` again.")]
+    InvalidToken(String),
 }
 
 #[derive(GraphQLQuery)]
@@ -30,17 +46,69 @@ pub struct LoginOpt {
 )]
 struct LoginMutation;
 
+/// Checks the stored token of the current registry with an authenticated query. The
+/// config is left untouched.
+fn check_login_token() -> anyhow::Result<()> {
+    let config = Config::from_file()?;
+    let registry = config.registry.get_current_registry();
+    if config
+        .registry
+        .get_login_token_for_registry(&registry)
+        .is_none()
+    {
+        return Err(LoginCheckError::NoToken(registry).into());
+    }
+    match crate::util::get_username() {
+        Ok(Some(username)) => {
+            println!(
+                "The token for registry {:?} is valid for user {:?}",
+                registry, username
+            );
+            Ok(())
+        }
+        Ok(None) => Err(LoginCheckError::InvalidToken(registry).into()),
+        Err(e) => Err(e.context(format!(
+            "Could not check the token for registry {:?}",
+            registry
+        ))),
+    }
+}
+
+/// Stores the token of `registry` and makes it the current registry
+fn store_login_token(config: &mut Config, registry: &str, token: &str) {
+    config
+        .registry
+        .set_login_token_for_registry(registry, token, UpdateRegistry::Update);
+}
+
 pub fn login(login_options: LoginOpt) -> anyhow::Result<()> {
+    if login_options.check {
+        return check_login_token();
+    }
+    let config = Config::from_file()?;
+    let registry = match &login_options.registry {
+        Some(registry) => format_graphql(registry),
+        None => config.registry.get_current_registry(),
+    };
+    if config
+        .registry
+        .get_login_token_for_registry(&registry)
+        .is_some()
+        && !util::prompt_user_for_yes(&format!(
+            "A token is already stored for registry {:?}. Do you want to replace it?",
+            registry
+        ))?
+    {
+        println!("The stored token was kept");
+        return Ok(());
+    }
+
     if let Some(token) = login_options.token {
         let mut config = Config::from_file()?;
-        config.registry.set_login_token_for_registry(
-            &config.registry.get_current_registry(),
-            &token,
-            UpdateRegistry::Update,
-        );
+        store_login_token(&mut config, &registry, &token);
         config.save()?;
         if !login_options.quiet {
-            if let Some(s) = crate::util::get_username().ok().and_then(|o| o) {
+            if let Some(s) = util::get_username().ok().and_then(|o| o) {
                 println!("Login for WAPM user {:?} saved", s);
             } else {
                 println!("Login for WAPM user saved");
@@ -67,7 +135,7 @@ pub fn login(login_options: LoginOpt) -> anyhow::Result<()> {
     };
 
     let q = LoginMutation::build_query(login_mutation::Variables { username, password });
-    let response: login_mutation::ResponseData = execute_query(&q)?;
+    let response: login_mutation::ResponseData = execute_query_custom_registry(&registry, &q)?;
     let token = match response.token_auth {
         Some(token_auth) => Some(token_auth.refresh_token),
         None => None,
@@ -75,15 +143,11 @@ pub fn login(login_options: LoginOpt) -> anyhow::Result<()> {
     if let Some(token) = token {
         // Save the token
         let mut config = Config::from_file()?;
-        config.registry.set_login_token_for_registry(
-            &config.registry.get_current_registry(),
-            &token,
-            UpdateRegistry::Update,
-        );
+        store_login_token(&mut config, &registry, &token);
         config.save()?;
 
         if !login_options.quiet {
-            if let Some(u) = crate::util::get_username().ok().and_then(|o| o) {
+            if let Some(u) = util::get_username().ok().and_then(|o| o) {
                 println!(
                     "Successfully logged into registry {:?} as user {:?}",
                     config.registry.get_current_registry(),
@@ -94,3 +158,43 @@ pub fn login(login_options: LoginOpt) -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn login_stores_the_token_and_switches_registry() {
+        let mut config = Config::default();
+        let default_registry = config.registry.get_current_registry();
+        store_login_token(&mut config, &default_registry, "default-token");
+        assert_eq!(
+            config
+                .registry
+                .get_login_token_for_registry(&default_registry),
+            Some("default-token".to_string())
+        );
+
+        let registry = format_graphql("https://registry.example.com");
+        store_login_token(&mut config, &registry, "example-token");
+        assert_eq!(config.registry.get_current_registry(), registry);
+        assert_eq!(
+            config.registry.get_login_token_for_registry(&registry),
+            Some("example-token".to_string())
+        );
+        // the token of the other registry is kept
+        assert_eq!(
+            config
+                .registry
+                .get_login_token_for_registry(&default_registry),
+            Some("default-token".to_string())
+        );
+
+        // logging in again replaces the token
+        store_login_token(&mut config, &registry, "new-token");
+        assert_eq!(
+            config.registry.get_login_token_for_registry(&registry),
+            Some("new-token".to_string())
+        );
+    }
+}
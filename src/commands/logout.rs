@@ -1,8 +1,10 @@
+use crate::commands::whoami::clear_whoami_cache;
 use crate::config::Config;
 
 pub fn logout() -> anyhow::Result<()> {
     let mut config = Config::from_file()?;
     config.registry.clear_current_registry_token();
     config.save()?;
+    clear_whoami_cache();
     Ok(())
 }
@@ -2,9 +2,14 @@
 
 mod add;
 mod bin;
+#[cfg(feature = "full")]
+mod cache;
 mod completions;
 mod config;
+#[cfg(feature = "full")]
+mod doctor;
 mod execute;
+mod fmt;
 mod init;
 mod install;
 #[cfg(feature = "full")]
@@ -14,20 +19,29 @@ mod list;
 mod login;
 mod logout;
 #[cfg(feature = "full")]
+mod outdated;
+#[cfg(feature = "full")]
 mod publish;
 mod remove;
 mod run;
 #[cfg(feature = "full")]
 mod search;
 mod uninstall;
+mod upgrade;
 mod validate;
+mod verify_manifest;
 mod whoami;
 
 pub use self::add::{add, AddOpt};
 pub use self::bin::{bin, BinOpt};
+#[cfg(feature = "full")]
+pub use self::cache::{cache, CacheOpt};
 pub use self::completions::CompletionOpt;
 pub use self::config::{config, ConfigOpt};
+#[cfg(feature = "full")]
+pub use self::doctor::{doctor, DoctorOpt};
 pub use self::execute::{execute, ExecuteOpt};
+pub use self::fmt::{fmt, FmtOpt};
 pub use self::init::{init, InitOpt};
 pub use self::install::{install, InstallOpt};
 #[cfg(feature = "full")]
@@ -37,11 +51,15 @@ pub use self::list::{list, ListOpt};
 pub use self::login::{login, LoginOpt};
 pub use self::logout::logout;
 #[cfg(feature = "full")]
+pub use self::outdated::{outdated, OutdatedOpt};
+#[cfg(feature = "full")]
 pub use self::publish::{publish, PublishOpt};
 pub use self::remove::{remove, RemoveOpt};
-pub use self::run::{run, RunOpt};
+pub use self::run::{error_exit_code, run, RunOpt};
 #[cfg(feature = "full")]
 pub use self::search::{search, SearchOpt};
 pub use self::uninstall::{uninstall, UninstallOpt};
+pub use self::upgrade::{upgrade, UpgradeOpt};
 pub use self::validate::{validate, ValidateOpt};
-pub use self::whoami::whoami;
+pub use self::verify_manifest::{verify_manifest, VerifyManifestOpt};
+pub use self::whoami::{whoami, WhoAmIOpt};
@@ -0,0 +1,190 @@
+//! Subcommand for finding the dependencies of the manifest that have newer versions in
+//! the registry
+
+use crate::config::Config;
+use crate::data::manifest::{dependency_version_req, Manifest};
+use crate::dataflow::manifest_packages::ManifestResult;
+use crate::dataflow::normalize_global_namespace_package_name;
+use crate::dataflow::resolved_packages::RegistryResolver;
+use prettytable::{format, Table};
+use semver::{Version, VersionReq};
+use std::collections::BTreeMap;
+use std::fmt;
+use structopt::StructOpt;
+use thiserror::Error;
+
+#[derive(StructOpt, Debug)]
+pub struct OutdatedOpt {
+    /// Print the dependencies as JSON
+    #[structopt(long = "json")]
+    json: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum OutdatedError {
+    #[error("There is no manifest in the current directory.")]
+    NoManifest,
+    #[error("Could not read the manifest. {0}")]
+    Manifest(String),
+}
+
+/// Whether a dependency can be updated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum DependencyStatus {
+    UpToDate,
+    Outdated,
+    NotInRegistry,
+}
+
+impl fmt::Display for DependencyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = match self {
+            DependencyStatus::UpToDate => "up to date",
+            DependencyStatus::Outdated => "outdated",
+            DependencyStatus::NotInRegistry => "not in the registry",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+/// A dependency of the manifest with the latest version in the registry
+#[derive(Debug, PartialEq, Eq, Serialize)]
+struct DependencyUpdate {
+    name: String,
+    /// The version or version requirement in the manifest
+    current: String,
+    latest: Option<Version>,
+    status: DependencyStatus,
+}
+
+pub fn outdated(options: OutdatedOpt) -> anyhow::Result<()> {
+    let manifest = match ManifestResult::find_in_directory(Config::get_current_dir()?) {
+        ManifestResult::Manifest(manifest) => manifest,
+        ManifestResult::NoManifest => return Err(OutdatedError::NoManifest.into()),
+        ManifestResult::ManifestError(e) => {
+            return Err(OutdatedError::Manifest(e.to_string()).into())
+        }
+    };
+    let names = manifest
+        .dependencies
+        .iter()
+        .flatten()
+        .map(|(name, _)| normalize_global_namespace_package_name(name.into()).to_string())
+        .collect();
+    let latest_versions = RegistryResolver::latest_versions(names)?;
+    let updates = find_dependency_updates(&manifest, &latest_versions)?;
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&updates)?);
+    } else if updates.is_empty() {
+        println!("The manifest has no dependencies");
+    } else {
+        print!("{}", create_updates_table(&updates));
+    }
+    Ok(())
+}
+
+/// The dependencies of the manifest, by name, with their latest version in
+/// `latest_versions`
+fn find_dependency_updates(
+    manifest: &Manifest,
+    latest_versions: &BTreeMap<String, Version>,
+) -> anyhow::Result<Vec<DependencyUpdate>> {
+    let mut updates = vec![];
+    for (name, version) in manifest.dependencies.iter().flatten() {
+        let version_req = dependency_version_req(name, version)?;
+        let latest = latest_versions
+            .get(normalize_global_namespace_package_name(name.into()).as_ref())
+            .cloned();
+        let status = match &latest {
+            None => DependencyStatus::NotInRegistry,
+            Some(latest) if version_req == VersionReq::STAR || version_req.matches(latest) => {
+                DependencyStatus::UpToDate
+            }
+            Some(_) => DependencyStatus::Outdated,
+        };
+        updates.push(DependencyUpdate {
+            name: name.clone(),
+            current: version.clone(),
+            latest,
+            status,
+        });
+    }
+    updates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(updates)
+}
+
+fn create_updates_table(updates: &[DependencyUpdate]) -> String {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.add_row(row!["PACKAGE", "CURRENT", "LATEST", "STATUS"]);
+    for update in updates {
+        let latest = match &update.latest {
+            Some(latest) => latest.to_string(),
+            None => "-".to_string(),
+        };
+        table.add_row(row![update.name, update.current, latest, update.status]);
+    }
+    format!("{}", table)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dependencies_are_compared_with_the_latest_versions() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[dependencies]
+"_/sqlite" = "0.1.1"
+"_/cowsay" = "^0.2"
+"syrusakbary/gzip" = "^1"
+"_/private" = "1.0.0"
+"#,
+        )
+        .unwrap();
+        let latest_versions: BTreeMap<String, Version> = vec![
+            ("_/sqlite", "0.2.0"),
+            ("_/cowsay", "0.2.5"),
+            ("syrusakbary/gzip", "1.0.0"),
+        ]
+        .into_iter()
+        .map(|(name, version)| (name.to_string(), Version::parse(version).unwrap()))
+        .collect();
+
+        let updates = find_dependency_updates(&manifest, &latest_versions).unwrap();
+        let statuses: Vec<(&str, DependencyStatus)> = updates
+            .iter()
+            .map(|update| (update.name.as_str(), update.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("_/cowsay", DependencyStatus::UpToDate),
+                ("_/private", DependencyStatus::NotInRegistry),
+                ("_/sqlite", DependencyStatus::Outdated),
+                ("syrusakbary/gzip", DependencyStatus::UpToDate),
+            ]
+        );
+
+        let json = serde_json::to_value(&updates[2]).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "name": "_/sqlite",
+                "current": "0.1.1",
+                "latest": "0.2.0",
+                "status": "outdated",
+            })
+        );
+        let table = create_updates_table(&updates);
+        assert!(table.contains("up to date"));
+    }
+}
@@ -1,25 +1,37 @@
 //! The publish command uploads the package specified in the Manifest (`wapm.toml`)
 //! to the wapm registry.
-use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::config::{format_graphql, Config};
+use crate::data::manifest::{
+    bindings_referenced_files, module_bindings, Manifest, Module, MANIFEST_FILE_NAME,
+};
+use crate::data::manifest_extensions::{
+    manifest_in_directory, manifest_to_string, ManifestExtensions, PackageExtensions,
+};
 use crate::database;
-use crate::graphql::execute_query_modifier;
+use crate::graphql::{
+    execute_query_custom_registry, execute_runtime_query_custom_registry, registry_input_fields,
+};
 use crate::keys;
-use crate::util::create_temp_dir;
+use crate::remote_source::{resolved_module_source, source_url, REMOTE_SOURCES_DIR_NAME};
+use crate::util::{create_temp_dir, validate_license};
 use crate::validate;
 
 use console::{style, Emoji};
-use flate2::{write::GzEncoder, Compression};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use graphql_client::*;
 use rpassword_wasi as rpassword;
 use structopt::StructOpt;
-use tar::Builder;
+use tar::{Archive, Builder, HeaderMode};
 use thiserror::Error;
 
-use std::collections::BTreeMap;
-use std::fmt::Write;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
 use std::fs;
-use std::io::{BufRead, Write as IoWrite};
-use std::path::{Path, PathBuf};
+use std::io::{self, BufRead, Read, Write as IoWrite};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::Duration;
 
 use wapm_toml::Package;
 
@@ -31,8 +43,82 @@ pub struct PublishOpt {
     /// Run the publish logic without sending anything to the registry server
     #[structopt(long = "dry-run")]
     dry_run: bool,
+    /// With `--dry-run`, copy the package archive to this path
+    #[structopt(
+        long = "out",
+        value_name = "PATH",
+        parse(from_os_str),
+        requires = "dry-run"
+    )]
+    out: Option<PathBuf>,
     #[structopt(long = "quiet")]
     quiet: bool,
+    /// Run a build command before bundling the package and abort if it fails. Without
+    /// a value, the `build` command of the `[package]` section is run
+    #[structopt(long = "build", value_name = "COMMAND", min_values = 0, max_values = 1)]
+    build: Option<Option<String>>,
+    /// Publish this package archive (`.tar.gz`) instead of bundling the current
+    /// directory. The archive must have the `wapm.toml` at its root
+    #[structopt(
+        long = "from",
+        value_name = "ARCHIVE",
+        parse(from_os_str),
+        conflicts_with = "build"
+    )]
+    from: Option<PathBuf>,
+    /// Fail instead of warning when the package has neither a `license` nor a
+    /// `license-file`, or when its `license` is not a valid SPDX expression
+    #[structopt(long = "strict")]
+    strict: bool,
+    /// Publish to this registry, with the token stored for it, instead of the active
+    /// one. The active registry is left as is
+    #[structopt(long = "registry", value_name = "URL")]
+    registry: Option<String>,
+    /// Publish the package as `public` or `private`. By default, a new package gets the
+    /// registry's default visibility and a published one keeps its own
+    #[structopt(long = "access", possible_values = &["public", "private"])]
+    access: Option<Access>,
+}
+
+/// Who can see a published package
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Access {
+    Public,
+    Private,
+}
+
+impl Access {
+    fn from_private(private: bool) -> Self {
+        if private {
+            Access::Private
+        } else {
+            Access::Public
+        }
+    }
+}
+
+impl FromStr for Access {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(Access::Public),
+            "private" => Ok(Access::Private),
+            _ => Err(format!(
+                "\"{}\" is not an access level, it must be public or private",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Public => write!(f, "public"),
+            Access::Private => write!(f, "private"),
+        }
+    }
 }
 
 #[derive(GraphQLQuery)]
@@ -75,106 +161,536 @@ fn normalize_path(cwd: &Path, path: &Path) -> PathBuf {
     out
 }
 
+/// The path of a file referenced by the manifest, relative to the base directory
+fn relative_path(path: &Path) -> PathBuf {
+    normalize_path(Path::new(""), path)
+}
+
+/// Where the directory of an `fs` mapping is placed in the archive: relative to the
+/// package directory, even if the manifest has it as an absolute path in it
+fn archive_fs_path(package_directory: &Path, path: &Path) -> PathBuf {
+    let path = path.strip_prefix(package_directory).unwrap_or(path);
+    relative_path(path)
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect()
+}
+
+/// Where the source of a module downloaded from a URL is placed in the archive
+fn archive_remote_source_path(module: &Module) -> PathBuf {
+    Path::new(REMOTE_SOURCES_DIR_NAME).join(format!("{}.wasm", module.name))
+}
+
+/// The manifest as it is published, with the `fs` mappings pointing at the archive
+/// paths of their directories rather than at the author's filesystem, and the modules
+/// downloaded from a URL at their archive path
+fn published_manifest(manifest: &Manifest, package_directory: &Path) -> Manifest {
+    let mut published = manifest.clone();
+    if let Some(fs) = published.fs.as_mut() {
+        for path in fs.values_mut() {
+            *path = archive_fs_path(package_directory, path);
+        }
+    }
+    for module in published.module.iter_mut().flatten() {
+        if source_url(&module.source).is_some() {
+            module.source = archive_remote_source_path(module);
+        }
+    }
+    published
+}
+
+/// All the files in `directory`, recursively, relative to `base_directory`
+fn files_in_directory(base_directory: &Path, directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(files_in_directory(base_directory, &path)?);
+        } else if let Ok(relative) = path.strip_prefix(base_directory) {
+            files.push(relative.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// The `include` and `exclude` file lists of the `[package]` section
+struct FileSelection {
+    include: Option<Vec<glob::Pattern>>,
+    exclude: Vec<glob::Pattern>,
+}
+
+impl FileSelection {
+    fn new(package: &PackageExtensions) -> Result<Self, PublishError> {
+        let parse = |pattern: &String| {
+            glob::Pattern::new(pattern.trim_end_matches('/'))
+                .map_err(|e| PublishError::InvalidFilePattern(pattern.clone(), e.to_string()))
+        };
+        Ok(Self {
+            include: package
+                .include
+                .as_ref()
+                .map(|patterns| patterns.iter().map(parse).collect())
+                .transpose()?,
+            exclude: package
+                .exclude
+                .iter()
+                .map(parse)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Whether the path or one of its parent directories matches one of the patterns
+    fn matches(patterns: &[glob::Pattern], path: &Path) -> bool {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        path.ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .any(|p| patterns.iter().any(|pat| pat.matches_path_with(p, options)))
+    }
+
+    /// Picks the files to bundle in the package. `include` replaces the files
+    /// referenced by the manifest, then `exclude` removes files from the selection.
+    fn select(
+        &self,
+        base_directory: &Path,
+        referenced_files: BTreeSet<PathBuf>,
+    ) -> Result<BTreeSet<PathBuf>, PublishError> {
+        let files = match &self.include {
+            Some(include) => files_in_directory(base_directory, base_directory)
+                .map_err(|e| {
+                    PublishError::ErrorListingFiles(base_directory.to_path_buf(), e.to_string())
+                })?
+                .into_iter()
+                .filter(|path| Self::matches(include, path))
+                .collect(),
+            None => referenced_files,
+        };
+        Ok(files
+            .into_iter()
+            .filter(|path| path != Path::new(MANIFEST_FILE_NAME))
+            .filter(|path| !Self::matches(&self.exclude, path))
+            .collect())
+    }
+}
+
+/// The bytes every wasm binary starts with
+const WASM_MAGIC: &[u8; 4] = b"\0asm";
+
+/// Checks that the source of a module is a wasm binary, or a `.wat` file that compiles
+/// to one. A wrong `source` would otherwise only fail for whoever runs the package.
+fn check_module_source(module: &str, path: &Path) -> Result<(), PublishError> {
+    let is_wasm = if path.extension().is_some_and(|extension| extension == "wat") {
+        wat::parse_file(path).is_ok()
+    } else {
+        let mut magic = [0; 4];
+        fs::File::open(path)
+            .and_then(|mut file| file.read_exact(&mut magic))
+            .is_ok()
+            && &magic == WASM_MAGIC
+    };
+    if is_wasm {
+        Ok(())
+    } else {
+        Err(PublishError::InvalidModuleSource {
+            module: module.to_string(),
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+/// Runs the build command in `directory` through the shell and shows its output. A
+/// failing build is an error.
+fn run_build_command(command: &str, directory: &Path) -> Result<(), PublishError> {
+    info!("Running build command `{}`", command);
+    #[cfg(windows)]
+    let mut shell_command = {
+        let mut c = Command::new("cmd");
+        c.arg("/C");
+        c
+    };
+    #[cfg(not(windows))]
+    let mut shell_command = {
+        let mut c = Command::new("sh");
+        c.arg("-c");
+        c
+    };
+    // the build inherits the stdio of wapm, so its output shows as it runs
+    let status = shell_command
+        .arg(command)
+        .current_dir(directory)
+        .status()
+        .map_err(|e| PublishError::BuildCouldNotStart(command.to_string(), e.to_string()))?;
+    if !status.success() {
+        return Err(PublishError::BuildFailed(
+            command.to_string(),
+            status.to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Warns when the package has neither a `license` nor a `license-file`, or when its
+/// `license` is not a valid SPDX expression. Both are errors with `--strict`
+fn check_license(package: &Package, strict: bool) -> Result<(), PublishError> {
+    if let Some(license) = &package.license {
+        return match validate_license(license) {
+            Ok(_) => Ok(()),
+            Err(e) if strict => Err(PublishError::InvalidLicense(license.clone(), e.to_string())),
+            Err(e) => {
+                warn!(
+                    "The `license` of the package, \"{}\", is invalid: {}",
+                    license, e
+                );
+                Ok(())
+            }
+        };
+    }
+    if package.license_file.is_some() {
+        return Ok(());
+    }
+    if strict {
+        return Err(PublishError::NoLicense);
+    }
+    warn!("The package has neither a `license` nor a `license-file` in its manifest");
+    Ok(())
+}
+
+/// The contents of the `license-file`. A license file that can't be read is an
+/// error, the package is not published without it.
+fn read_license_file(
+    base_directory: &Path,
+    package: &Package,
+) -> Result<Option<String>, PublishError> {
+    package
+        .license_file
+        .as_ref()
+        .map(|path| {
+            fs::read_to_string(normalize_path(base_directory, path))
+                .map_err(|e| PublishError::CannotReadLicenseFile(path.clone(), e.to_string()))
+        })
+        .transpose()
+}
+
+/// The GraphQL endpoint to publish to: `registry` if given, else the active registry
+fn publish_registry(config: &Config, registry: Option<&str>) -> String {
+    match registry {
+        Some(registry) => format_graphql(registry),
+        None => config.registry.get_graphql_url(),
+    }
+}
+
+/// A published package can't depend on a git repository
+fn check_no_git_dependencies(directory: &Path) -> anyhow::Result<()> {
+    let extensions = ManifestExtensions::find_in_directory(directory)?;
+    let git_dependencies: Vec<&str> = extensions.git_dependencies().keys().copied().collect();
+    if !git_dependencies.is_empty() {
+        return Err(PublishError::GitDependencies(git_dependencies.join(", ")).into());
+    }
+    Ok(())
+}
+
 pub fn publish(publish_opts: PublishOpt) -> anyhow::Result<()> {
-    let mut builder = Builder::new(Vec::new());
+    if let Some(archive_path) = &publish_opts.from {
+        return publish_archive(archive_path, &publish_opts);
+    }
+
     let cwd = crate::config::Config::get_current_dir()?;
 
+    // the build only runs when asked for, even if the manifest has a build command
+    if let Some(build) = publish_opts.build.as_ref() {
+        let command = match build {
+            Some(command) => command.clone(),
+            None => ManifestExtensions::find_in_directory(&cwd)?
+                .package
+                .build
+                .ok_or(PublishError::NoBuildCommand)?,
+        };
+        run_build_command(&command, &cwd)?;
+    }
+
+    check_no_git_dependencies(&cwd)?;
     validate::validate_directory(cwd.clone())?;
 
-    let manifest = Manifest::find_in_directory(&cwd)?;
+    let manifest = manifest_in_directory(&cwd)?;
+    check_license(&manifest.package, publish_opts.strict)?;
 
-    let manifest_path_buf = cwd.join(MANIFEST_FILE_NAME);
-    builder.append_path_with_name(&manifest_path_buf, MANIFEST_FILE_NAME)?;
     let package = &manifest.package;
     let modules = manifest.module.as_ref().ok_or(PublishError::NoModule)?;
-    let manifest_string = toml::to_string(&manifest)?;
+    let manifest_string = manifest_to_string(&published_manifest(&manifest, &cwd))?;
+    let base_directory_path = &manifest.base_directory_path;
+
+    // the files referenced by the manifest, relative to the base directory
+    let mut referenced_files = BTreeSet::new();
 
     let readme = package.readme.as_ref().and_then(|readme_path| {
-        let normalized_path = normalize_path(&manifest.base_directory_path, readme_path);
-        if builder.append_path(&normalized_path).is_err() {
-            // TODO: Maybe do something here
-        }
-        fs::read_to_string(normalized_path).ok()
-    });
-    let license_file = package.license_file.as_ref().and_then(|license_file_path| {
-        let normalized_path = normalize_path(&manifest.base_directory_path, license_file_path);
-        if builder.append_path(&normalized_path).is_err() {
-            // TODO: Maybe do something here
-        }
-        fs::read_to_string(normalized_path).ok()
+        let normalized_path = normalize_path(base_directory_path, readme_path);
+        let contents = fs::read_to_string(normalized_path).ok()?;
+        referenced_files.insert(relative_path(readme_path));
+        Some(contents)
     });
+    let license_file = read_license_file(base_directory_path, package)?;
+    if let Some(license_file_path) = &package.license_file {
+        referenced_files.insert(relative_path(license_file_path));
+    }
 
+    // the downloaded module sources, by their path in the archive
+    let mut remote_sources = BTreeMap::new();
     for module in modules {
-        let normalized_path = normalize_path(&manifest.base_directory_path, &module.source);
+        if source_url(&module.source).is_some() {
+            let downloaded_path = resolved_module_source(module, base_directory_path)?;
+            check_module_source(&module.name, &downloaded_path)?;
+            remote_sources.insert(archive_remote_source_path(module), downloaded_path);
+            continue;
+        }
+        let normalized_path = normalize_path(base_directory_path, &module.source);
         normalized_path
             .metadata()
             .map_err(|_| PublishError::SourceMustBeFile {
                 module: module.name.clone(),
                 path: normalized_path.clone(),
             })?;
-        builder
-            .append_path(normalized_path)
-            .map_err(|_| PublishError::ErrorBuildingPackage(module.name.clone()))?;
-
-        if let Some(bindings) = &module.bindings {
-            for path in bindings.referenced_files(&manifest.base_directory_path)? {
-                let normalized_path = normalize_path(&manifest.base_directory_path, &path);
-                normalized_path
-                    .metadata()
-                    .map_err(|_| PublishError::MissingBindings {
-                        module: module.name.clone(),
-                        path: normalized_path.clone(),
-                    })?;
-                builder
-                    .append_path(normalized_path)
-                    .map_err(|_| PublishError::ErrorBuildingPackage(module.name.clone()))?;
-            }
+        check_module_source(&module.name, &normalized_path)?;
+        referenced_files.insert(relative_path(&module.source));
+    }
+    for (module, bindings) in module_bindings(&manifest) {
+        for path in bindings_referenced_files(bindings, base_directory_path)? {
+            let normalized_path = normalize_path(base_directory_path, &path);
+            normalized_path
+                .metadata()
+                .map_err(|_| PublishError::MissingBindings {
+                    module: module.name.clone(),
+                    path: normalized_path.clone(),
+                })?;
+            referenced_files.insert(relative_path(&path));
         }
     }
 
     // bundle the package filesystem
-    for (_alias, path) in manifest.fs.unwrap_or_default().iter() {
-        let normalized_path = normalize_path(&cwd, path);
+    for (_alias, path) in manifest.fs.clone().unwrap_or_default().iter() {
+        let normalized_path = cwd.join(archive_fs_path(&cwd, path));
         let path_metadata = normalized_path.metadata().map_err(|_| {
             PublishError::MissingManifestFsPath(normalized_path.to_string_lossy().to_string())
         })?;
-        if path_metadata.is_dir() {
-            builder.append_dir_all(path, &normalized_path)
-        } else {
+        if !path_metadata.is_dir() {
             return Err(PublishError::PackageFileSystemEntryMustBeDirectory(
                 path.to_string_lossy().to_string(),
             )
             .into());
         }
-        .map_err(|_| {
+        let files = files_in_directory(&cwd, &normalized_path).map_err(|_| {
             PublishError::MissingManifestFsPath(normalized_path.to_string_lossy().to_string())
         })?;
+        referenced_files.extend(files);
     }
 
-    builder.finish().ok();
-    let tar_archive_data = builder.into_inner().map_err(|_|
-                                                        // TODO:
-                                                        PublishError::NoModule)?;
+    let extensions = ManifestExtensions::find_in_directory(base_directory_path)?;
+    let package_files =
+        FileSelection::new(&extensions.package)?.select(base_directory_path, referenced_files)?;
+
+    for module in modules {
+        if source_url(&module.source).is_none()
+            && !package_files.contains(&relative_path(&module.source))
+        {
+            return Err(PublishError::ModuleSourceNotIncluded {
+                module: module.name.clone(),
+                path: module.source.clone(),
+            }
+            .into());
+        }
+    }
     let archive_name = "package.tar.gz".to_string();
     let archive_dir = create_temp_dir()?;
     let archive_dir_path: &std::path::Path = archive_dir.as_ref();
     fs::create_dir(archive_dir_path.join("wapm_package"))?;
     let archive_path = archive_dir_path.join("wapm_package").join(&archive_name);
-    let mut compressed_archive = fs::File::create(&archive_path).unwrap();
-    let mut gz_enc = GzEncoder::new(&mut compressed_archive, Compression::best());
+    write_package_archive(
+        &archive_path,
+        &manifest_string,
+        base_directory_path,
+        &package_files,
+        &remote_sources,
+    )?;
+
+    assert!(archive_path.exists());
+    assert!(archive_path.is_file());
+
+    upload_package_archive(
+        package,
+        &extensions.package,
+        &manifest_string,
+        &license_file,
+        &readme,
+        &archive_name,
+        &archive_path,
+        &publish_opts,
+    )
+}
+
+/// Adds the manifest as it is published to the archive
+fn append_manifest<W: IoWrite>(builder: &mut Builder<W>, manifest: &str) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, MANIFEST_FILE_NAME, manifest.as_bytes())
+}
+
+/// A tar builder that leaves out what differs between two checkouts of the same
+/// sources: the files get a fixed mtime, no owner and a `0o644` or `0o755` mode
+fn package_archive_builder<W: IoWrite>(writer: W) -> Builder<W> {
+    let mut builder = Builder::new(writer);
+    builder.mode(HeaderMode::Deterministic);
+    builder
+}
+
+/// Writes the gzipped tar of the manifest, of the package files, relative to
+/// `base_directory`, and of the downloaded module sources, by their archive path. The
+/// archive is streamed to `archive_path` as it is built, so a large package is never
+/// held in memory. The files are added sorted by their archive path, so the same
+/// sources always give the same archive.
+fn write_package_archive(
+    archive_path: &Path,
+    manifest: &str,
+    base_directory: &Path,
+    package_files: &BTreeSet<PathBuf>,
+    remote_sources: &BTreeMap<PathBuf, PathBuf>,
+) -> anyhow::Result<()> {
+    let archive = io::BufWriter::new(fs::File::create(archive_path)?);
+    let mut builder = package_archive_builder(GzEncoder::new(archive, Compression::best()));
+    append_manifest(&mut builder, manifest)?;
+    let mut entries: BTreeMap<&PathBuf, PathBuf> = package_files
+        .iter()
+        .map(|path| (path, base_directory.join(path)))
+        .collect();
+    entries.extend(
+        remote_sources
+            .iter()
+            .map(|(path, downloaded_path)| (path, downloaded_path.clone())),
+    );
+    for (path, file_path) in entries {
+        builder
+            .append_path_with_name(file_path, path)
+            .map_err(|_| PublishError::ErrorAddingFile(path.clone()))?;
+    }
+    let mut archive = builder.into_inner()?.finish()?;
+    archive.flush()?;
+    Ok(())
+}
+
+/// The files of a package archive, in the archive order, with their size in bytes
+fn archive_listing(archive_path: &Path) -> anyhow::Result<Vec<(PathBuf, u64)>> {
+    let mut archive = Archive::new(GzDecoder::new(fs::File::open(archive_path)?));
+    let mut listing = vec![];
+    for entry in archive.entries()? {
+        let entry = entry?;
+        listing.push((entry.path()?.into_owned(), entry.header().size()?));
+    }
+    Ok(listing)
+}
+
+/// Publishes an archive that was bundled by something else. The archive is checked
+/// like a package directory: the manifest must parse and the files it references
+/// must be in the archive.
+fn publish_archive(archive_path: &Path, publish_opts: &PublishOpt) -> anyhow::Result<()> {
+    let archive_error =
+        |e: io::Error| PublishError::CannotReadArchive(archive_path.to_path_buf(), e.to_string());
+    let unpacked_dir = create_temp_dir()?;
+    let unpacked_dir_path: &Path = unpacked_dir.as_ref();
+    let archive_file = fs::File::open(archive_path).map_err(archive_error)?;
+    Archive::new(GzDecoder::new(archive_file))
+        .unpack(unpacked_dir_path)
+        .map_err(archive_error)?;
+    if !unpacked_dir_path.join(MANIFEST_FILE_NAME).is_file() {
+        return Err(PublishError::ArchiveWithoutManifest(archive_path.to_path_buf()).into());
+    }
+
+    check_no_git_dependencies(unpacked_dir_path)?;
+    validate::validate_directory(unpacked_dir_path.to_path_buf())?;
+
+    let manifest = manifest_in_directory(unpacked_dir_path)?;
+    let package = &manifest.package;
+    check_license(package, publish_opts.strict)?;
+    let modules = manifest.module.as_ref().ok_or(PublishError::NoModule)?;
+    for module in modules {
+        let normalized_path = normalize_path(unpacked_dir_path, &module.source);
+        if !normalized_path.is_file() {
+            return Err(PublishError::ModuleSourceNotIncluded {
+                module: module.name.clone(),
+                path: module.source.clone(),
+            }
+            .into());
+        }
+        check_module_source(&module.name, &normalized_path)?;
+    }
+    let read_package_file = |path: &Option<PathBuf>| {
+        path.as_ref()
+            .and_then(|path| fs::read_to_string(normalize_path(unpacked_dir_path, path)).ok())
+    };
+    let readme = read_package_file(&package.readme);
+    let license_file = read_license_file(unpacked_dir_path, package)?;
+    let manifest_string = manifest_to_string(&manifest)?;
+    let extensions = ManifestExtensions::find_in_directory(unpacked_dir_path)?;
+    let archive_name = archive_path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "package.tar.gz".to_string());
 
-    gz_enc.write_all(&tar_archive_data).unwrap();
-    let _compressed_archive = gz_enc.finish().unwrap();
-    let mut compressed_archive_reader = fs::File::open(&archive_path)?;
+    upload_package_archive(
+        package,
+        &extensions.package,
+        &manifest_string,
+        &license_file,
+        &readme,
+        &archive_name,
+        archive_path,
+        publish_opts,
+    )
+}
 
+/// Signs the archive and uploads it. In dry-run mode, publishing stops after signing.
+#[allow(clippy::too_many_arguments)]
+fn upload_package_archive(
+    package: &Package,
+    package_extensions: &PackageExtensions,
+    manifest_string: &str,
+    license_file: &Option<String>,
+    readme: &Option<String>,
+    archive_name: &str,
+    archive_path: &Path,
+    publish_opts: &PublishOpt,
+) -> anyhow::Result<()> {
+    let mut compressed_archive_reader = fs::File::open(archive_path)?;
     let maybe_signature_data = sign_compressed_archive(&mut compressed_archive_reader)?;
     let archived_data_size = archive_path.metadata()?.len();
-
-    assert!(archive_path.exists());
-    assert!(archive_path.is_file());
+    let registry_url = publish_registry(&Config::from_file()?, publish_opts.registry.as_deref());
+    if !publish_opts.quiet {
+        println!("Publishing to {}", registry_url);
+    }
 
     if publish_opts.dry_run {
         // dry run: publish is done here
+        if let Some(access) = publish_opts.access {
+            info!("The package would be published as {}", access);
+        }
+        if !publish_opts.quiet {
+            println!("The package archive contains:");
+            for (path, size) in archive_listing(archive_path)? {
+                println!("  {:>10}  {}", size, path.display());
+            }
+        }
+        if let Some(out) = &publish_opts.out {
+            fs::copy(archive_path, out).map_err(|e| {
+                anyhow!(
+                    "could not copy the package archive to {}: {}",
+                    out.display(),
+                    e
+                )
+            })?;
+            if !publish_opts.quiet {
+                println!("Wrote the package archive to {}", out.display());
+            }
+        }
 
         println!(
             "Successfully published package `{}@{}`",
@@ -189,29 +705,184 @@ pub fn publish(publish_opts: PublishOpt) -> anyhow::Result<()> {
     }
 
     try_chunked_uploading(
+        &registry_url,
         package,
-        &manifest_string,
-        &license_file,
-        &readme,
-        &archive_name,
-        &archive_path,
+        package_extensions,
+        manifest_string,
+        license_file,
+        readme,
+        archive_name,
+        archive_path,
         &maybe_signature_data,
         archived_data_size,
+        publish_opts.access,
         publish_opts.quiet,
     )
     .map_err(on_error)
 }
 
+/// How many times a chunk of the archive is sent before the upload fails
+const UPLOAD_ATTEMPTS: u32 = 3;
+/// The wait before sending a chunk again, doubled after each failed attempt
+const UPLOAD_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Network errors and server errors can go away when the chunk is sent again, client
+/// errors can't
+fn is_retryable_upload_error(error: &reqwest::Error) -> bool {
+    match error.status() {
+        Some(status) => status.is_server_error(),
+        None => error.is_connect() || error.is_timeout() || error.is_request(),
+    }
+}
+
+/// Calls `attempt` until it succeeds, at most `attempts` times, with an exponential
+/// backoff starting at `delay`. An error that is not retryable is returned at once.
+fn with_retries<T, E: fmt::Display>(
+    attempts: u32,
+    delay: Duration,
+    is_retryable: impl Fn(&E) -> bool,
+    mut attempt: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut delay = delay;
+    let mut attempted = 1;
+    loop {
+        match attempt() {
+            Err(e) if attempted < attempts && is_retryable(&e) => {
+                warn!(
+                    "Upload failed ({}), retrying in {:?} ({}/{})",
+                    e, delay, attempted, attempts
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempted += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The variables of the publish mutation, without the signature and the signed URL of
+/// the upload
+fn publish_mutation_variables(
+    package: &Package,
+    manifest_string: &str,
+    license_file: &Option<String>,
+    readme: &Option<String>,
+    archive_name: &str,
+) -> publish_package_mutation_chunked::Variables {
+    publish_package_mutation_chunked::Variables {
+        name: package.name.to_string(),
+        version: package.version.to_string(),
+        description: package.description.clone(),
+        manifest: manifest_string.to_string(),
+        license: package.license.clone(),
+        license_file: license_file.to_owned(),
+        readme: readme.to_owned(),
+        repository: package.repository.clone(),
+        homepage: package.homepage.clone(),
+        file_name: Some(archive_name.to_string()),
+        signature: None,
+        signed_url: None,
+    }
+}
+
+/// A field of `PublishPackageInput` that `graphql/schema.graphql` doesn't have. It is
+/// only sent when it is set and the schema of the registry has it
+#[derive(Debug, PartialEq)]
+struct OptionalInputField {
+    name: &'static str,
+    graphql_type: &'static str,
+    value: serde_json::Value,
+}
+
+/// The optional input fields set by the publish options and the package
+fn optional_input_fields(
+    package_extensions: &PackageExtensions,
+    access: Option<Access>,
+) -> Vec<OptionalInputField> {
+    let mut fields = vec![];
+    if let Some(access) = access {
+        fields.push(OptionalInputField {
+            name: "private",
+            graphql_type: "Boolean",
+            value: serde_json::Value::Bool(access == Access::Private),
+        });
+    }
+    let lists = [
+        ("keywords", &package_extensions.keywords),
+        ("categories", &package_extensions.categories),
+    ];
+    for (name, list) in lists {
+        if let Some(list) = list {
+            fields.push(OptionalInputField {
+                name,
+                graphql_type: "[String!]",
+                value: serde_json::json!(list),
+            });
+        }
+    }
+    fields
+}
+
+/// Keeps the optional input fields that the registry has. Without the `private` field,
+/// `--access` can't be honored, so it fails before anything is uploaded. The other
+/// fields are left out with a warning
+fn supported_input_fields(
+    registry_url: &str,
+    fields: Vec<OptionalInputField>,
+    registry_fields: &BTreeSet<String>,
+) -> Result<Vec<OptionalInputField>, PublishError> {
+    if fields
+        .iter()
+        .any(|field| field.name == "private" && !registry_fields.contains(field.name))
+    {
+        return Err(PublishError::AccessUnavailable(registry_url.to_string()));
+    }
+    let (supported, unsupported): (Vec<_>, Vec<_>) = fields
+        .into_iter()
+        .partition(|field| registry_fields.contains(field.name));
+    for field in unsupported {
+        warn!(
+            "The registry {} can't publish the {} of a package yet, they are left out",
+            registry_url, field.name
+        );
+    }
+    Ok(supported)
+}
+
+/// The publish mutation `query` with a variable and an input field for each of the
+/// optional `fields`
+fn with_input_fields(query: &str, fields: &[OptionalInputField]) -> String {
+    let declarations: String = fields
+        .iter()
+        .map(|field| format!(", ${}: {}", field.name, field.graphql_type))
+        .collect();
+    let input_fields: String = fields
+        .iter()
+        .map(|field| format!("{0}: ${0},\n    ", field.name))
+        .collect();
+    query
+        .replacen(") {", &format!("{}) {{", declarations), 1)
+        .replacen(
+            "clientMutationId:",
+            &format!("{}clientMutationId:", input_fields),
+            1,
+        )
+}
+
 #[allow(clippy::too_many_arguments)]
 fn try_chunked_uploading(
+    registry_url: &str,
     package: &Package,
-    manifest_string: &String,
+    package_extensions: &PackageExtensions,
+    manifest_string: &str,
     license_file: &Option<String>,
     readme: &Option<String>,
-    archive_name: &String,
-    archive_path: &PathBuf,
+    archive_name: &str,
+    archive_path: &Path,
     maybe_signature_data: &SignArchiveResult,
     archived_data_size: u64,
+    access: Option<Access>,
     quiet: bool,
 ) -> Result<(), anyhow::Error> {
     let maybe_signature_data = match maybe_signature_data {
@@ -235,6 +906,16 @@ fn try_chunked_uploading(
         }
     };
 
+    // the registry is asked for the optional fields before the upload, which is lost
+    // when they can't be published
+    let optional_fields = optional_input_fields(package_extensions, access);
+    let optional_fields = if optional_fields.is_empty() {
+        optional_fields
+    } else {
+        let registry_fields = registry_input_fields(registry_url, "PublishPackageInput")?;
+        supported_input_fields(registry_url, optional_fields, &registry_fields)?
+    };
+
     if !quiet {
         println!("{} {} Uploading...", style("[1/2]").bold().dim(), UPLOAD);
     }
@@ -246,7 +927,7 @@ fn try_chunked_uploading(
     });
 
     let _response: get_signed_url::ResponseData =
-        execute_query_modifier(&get_google_signed_url, |f| f)?;
+        execute_query_custom_registry(registry_url, &get_google_signed_url)?;
 
     let url = _response.url.ok_or_else(|| {
         anyhow!(
@@ -329,24 +1010,30 @@ fn try_chunked_uploading(
         let end = file_pointer + chunk.len().saturating_sub(1);
         let content_range = format!("bytes {start}-{end}/{total}");
 
-        let res = client
-            .put(&session_uri)
-            .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
-            .header(reqwest::header::CONTENT_LENGTH, format!("{}", chunk.len()))
-            .header("Content-Range".to_string(), content_range)
-            .body(chunk.to_vec());
-
         pb.set_position(file_pointer as u64);
 
-        res.send()
-            .map(|response| response.error_for_status())
-            .map_err(|e| {
-                anyhow!(
-                    "cannot send request to {session_uri} (chunk {}..{}): {e}",
-                    file_pointer,
-                    file_pointer + chunk_size
-                )
-            })??;
+        with_retries(
+            UPLOAD_ATTEMPTS,
+            UPLOAD_RETRY_DELAY,
+            is_retryable_upload_error,
+            || {
+                client
+                    .put(&session_uri)
+                    .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+                    .header(reqwest::header::CONTENT_LENGTH, format!("{}", chunk.len()))
+                    .header("Content-Range".to_string(), content_range.clone())
+                    .body(chunk.to_vec())
+                    .send()
+                    .and_then(|response| response.error_for_status())
+            },
+        )
+        .map_err(|e| {
+            anyhow!(
+                "cannot send request to {session_uri} (chunk {}..{}): {e}",
+                file_pointer,
+                file_pointer + chunk_size
+            )
+        })?;
 
         if n < chunk_size {
             break;
@@ -362,29 +1049,45 @@ fn try_chunked_uploading(
         println!("{} {}Publishing...", style("[2/2]").bold().dim(), PACKAGE,);
     }
 
-    let q =
-        PublishPackageMutationChunked::build_query(publish_package_mutation_chunked::Variables {
-            name: package.name.to_string(),
-            version: package.version.to_string(),
-            description: package.description.clone(),
-            manifest: manifest_string.to_string(),
-            license: package.license.clone(),
-            license_file: license_file.to_owned(),
-            readme: readme.to_owned(),
-            repository: package.repository.clone(),
-            homepage: package.homepage.clone(),
-            file_name: Some(archive_name.to_string()),
-            signature: maybe_signature_data,
-            signed_url: Some(signed_url),
-        });
-
-    let _response: publish_package_mutation_chunked::ResponseData =
-        crate::graphql::execute_query(&q)?;
+    let variables = publish_package_mutation_chunked::Variables {
+        signature: maybe_signature_data,
+        signed_url: Some(signed_url),
+        ..publish_mutation_variables(package, manifest_string, license_file, readme, archive_name)
+    };
+    let response: publish_package_mutation_chunked::ResponseData = if optional_fields.is_empty() {
+        let q = PublishPackageMutationChunked::build_query(variables);
+        execute_query_custom_registry(registry_url, &q)?
+    } else {
+        let mut variables = serde_json::to_value(variables)?;
+        for field in &optional_fields {
+            variables[field.name] = field.value.clone();
+        }
+        execute_runtime_query_custom_registry(
+            registry_url,
+            &with_input_fields(publish_package_mutation_chunked::QUERY, &optional_fields),
+            publish_package_mutation_chunked::OPERATION_NAME,
+            &variables,
+        )?
+    };
 
     println!(
         "Successfully published package `{}@{}`",
         package.name, package.version
     );
+    if let Some(published) = response.publish_package {
+        let published_access = Access::from_private(published.package_version.package.private);
+        if let Some(access) = access.filter(|access| *access != published_access) {
+            return Err(PublishError::AccessNotSupported(
+                registry_url.to_string(),
+                access,
+                published_access,
+            )
+            .into());
+        }
+        if !quiet {
+            println!("The package is {}", published_access);
+        }
+    }
 
     Ok(())
 }
@@ -404,14 +1107,44 @@ enum PublishError {
     SourceMustBeFile { module: String, path: PathBuf },
     #[error("Unable to load the bindings for \"{module}\" because \"{}\" doesn't exist", path.display())]
     MissingBindings { module: String, path: PathBuf },
-    #[error("Error building package when parsing module \"{0}\".")]
-    ErrorBuildingPackage(String),
+    #[error("Module \"{module}\" source \"{}\" is not a valid wasm binary", path.display())]
+    InvalidModuleSource { module: String, path: PathBuf },
+    #[error("Error building package when adding \"{}\".", .0.display())]
+    ErrorAddingFile(PathBuf),
+    #[error("Could not list the files in \"{}\": {1}", .0.display())]
+    ErrorListingFiles(PathBuf, String),
+    #[error("Invalid file pattern \"{0}\" in the package's include or exclude list: {1}")]
+    InvalidFilePattern(String, String),
+    #[error("The source of the \"{module}\" module, \"{}\", is not included in the package", path.display())]
+    ModuleSourceNotIncluded { module: String, path: PathBuf },
     #[error(
         "Path \"{0}\", specified in the manifest as part of the package file system does not exist.",
     )]
     MissingManifestFsPath(String),
     #[error("When processing the package filesystem, found path \"{0}\" which is not a directory")]
     PackageFileSystemEntryMustBeDirectory(String),
+    #[error("`--build` was given without a command and the manifest has no `build` command in `[package]`.")]
+    NoBuildCommand,
+    #[error("Could not run the build command `{0}`: {1}")]
+    BuildCouldNotStart(String, String),
+    #[error("The build command `{0}` failed ({1}). The package was not published.")]
+    BuildFailed(String, String),
+    #[error("Cannot publish a package with git dependencies ({0}). Depend on published versions instead.")]
+    GitDependencies(String),
+    #[error("Could not read the package archive \"{}\": {1}", .0.display())]
+    CannotReadArchive(PathBuf, String),
+    #[error("The package archive \"{}\" has no `wapm.toml` at its root", .0.display())]
+    ArchiveWithoutManifest(PathBuf),
+    #[error("The package has neither a `license` nor a `license-file`. Add one to publish with `--strict`.")]
+    NoLicense,
+    #[error("The `license` of the package, \"{0}\", is invalid: {1}. Fix it to publish with `--strict`.")]
+    InvalidLicense(String, String),
+    #[error("The registry {0} can't publish {1} packages, the package was published as {2}.")]
+    AccessNotSupported(String, Access, Access),
+    #[error("The registry {0} can't set the access of a package. Publish without `--access`.")]
+    AccessUnavailable(String),
+    #[error("Could not read the license file \"{}\": {1}", .0.display())]
+    CannotReadLicenseFile(PathBuf, String),
 }
 
 #[derive(Debug, Clone)]
@@ -470,3 +1203,687 @@ pub fn sign_compressed_archive(
         .to_string()),
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    fn package_dir() -> tempfile::TempDir {
+        let dir = tempfile::TempDir::new().unwrap();
+        for file in &[
+            "wapm.toml",
+            "README.md",
+            "module.wasm",
+            "static/index.html",
+            "static/tmp/cache.bin",
+            "tests/test.wasm",
+        ] {
+            let path = dir.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        dir
+    }
+
+    fn selection(include: Option<&[&str]>, exclude: &[&str]) -> FileSelection {
+        let to_strings = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect();
+        FileSelection::new(&PackageExtensions {
+            include: include.map(to_strings),
+            exclude: to_strings(exclude),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn paths(paths: &[&str]) -> BTreeSet<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn exclude_removes_referenced_files() {
+        let dir = package_dir();
+        let referenced = paths(&[
+            "README.md",
+            "module.wasm",
+            "static/index.html",
+            "static/tmp/cache.bin",
+        ]);
+        let selected = selection(None, &["static/tmp", "*.md"])
+            .select(dir.path(), referenced)
+            .unwrap();
+        assert_eq!(selected, paths(&["module.wasm", "static/index.html"]));
+    }
+
+    #[test]
+    fn include_replaces_referenced_files_and_exclude_applies_after() {
+        let dir = package_dir();
+        let referenced = paths(&["README.md", "module.wasm"]);
+        let selected = selection(Some(&["*.wasm", "static/"]), &["static/tmp/*"])
+            .select(dir.path(), referenced)
+            .unwrap();
+        assert_eq!(selected, paths(&["module.wasm", "static/index.html"]));
+    }
+
+    #[test]
+    fn invalid_patterns_are_an_error() {
+        assert!(FileSelection::new(&PackageExtensions {
+            include: Some(vec!["[".to_string()]),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn failing_build_blocks_publish() {
+        let dir = package_dir();
+        run_build_command("echo built > build.txt", dir.path()).unwrap();
+        assert!(dir.path().join("build.txt").is_file());
+
+        match run_build_command("exit 3", dir.path()) {
+            Err(PublishError::BuildFailed(command, _)) => assert_eq!(command, "exit 3"),
+            otherwise => panic!("expected the build to fail, got {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn streamed_archive_matches_the_buffered_one() {
+        let dir = package_dir();
+        let asset: Vec<u8> = (0..16 * 1024 * 1024u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect();
+        fs::create_dir(dir.path().join("assets")).unwrap();
+        fs::write(dir.path().join("assets/large.bin"), &asset).unwrap();
+        let package_files: BTreeSet<PathBuf> = vec![PathBuf::from("assets/large.bin")]
+            .into_iter()
+            .collect();
+        let manifest = fs::read_to_string(dir.path().join(MANIFEST_FILE_NAME)).unwrap();
+
+        let archive_dir = create_temp_dir().unwrap();
+        let archive_path = archive_dir.path().join("package.tar.gz");
+        write_package_archive(
+            &archive_path,
+            &manifest,
+            dir.path(),
+            &package_files,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        let mut streamed = vec![];
+        GzDecoder::new(fs::File::open(&archive_path).unwrap())
+            .read_to_end(&mut streamed)
+            .unwrap();
+
+        let mut builder = package_archive_builder(Vec::new());
+        append_manifest(&mut builder, &manifest).unwrap();
+        builder
+            .append_path_with_name(dir.path().join("assets/large.bin"), "assets/large.bin")
+            .unwrap();
+        let buffered = builder.into_inner().unwrap();
+        assert!(streamed == buffered, "the streamed archive differs");
+    }
+
+    #[test]
+    fn package_archives_are_reproducible() {
+        let dir = package_dir();
+        fs::write(dir.path().join("module.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        let package_files = paths(&["module.wasm", "README.md", "static/index.html"]);
+        let manifest = fs::read_to_string(dir.path().join(MANIFEST_FILE_NAME)).unwrap();
+        let archive_dir = create_temp_dir().unwrap();
+        let archive_bytes = |name: &str| {
+            let archive_path = archive_dir.path().join(name);
+            write_package_archive(
+                &archive_path,
+                &manifest,
+                dir.path(),
+                &package_files,
+                &BTreeMap::new(),
+            )
+            .unwrap();
+            fs::read(archive_path).unwrap()
+        };
+
+        let first = archive_bytes("first.tar.gz");
+        // touching the sources doesn't change the archive
+        let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(86_400);
+        for path in &package_files {
+            fs::File::options()
+                .write(true)
+                .open(dir.path().join(path))
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        assert!(
+            archive_bytes("second.tar.gz") == first,
+            "the archive changed"
+        );
+    }
+
+    #[test]
+    fn failed_uploads_are_retried() {
+        #[derive(Debug)]
+        enum MockError {
+            Network,
+            Client,
+        }
+        impl fmt::Display for MockError {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{:?}", self)
+            }
+        }
+        let is_retryable = |e: &MockError| matches!(e, MockError::Network);
+        let upload = |failures: Vec<MockError>| {
+            let mut failures = failures.into_iter();
+            let mut calls = 0;
+            let result = with_retries(
+                UPLOAD_ATTEMPTS,
+                Duration::from_millis(1),
+                is_retryable,
+                || {
+                    calls += 1;
+                    match failures.next() {
+                        Some(e) => Err(e),
+                        None => Ok(()),
+                    }
+                },
+            );
+            (result, calls)
+        };
+
+        let (result, calls) = upload(vec![MockError::Network, MockError::Network]);
+        assert!(result.is_ok());
+        assert_eq!(calls, 3);
+
+        let (result, calls) = upload(vec![
+            MockError::Network,
+            MockError::Network,
+            MockError::Network,
+        ]);
+        assert!(matches!(result, Err(MockError::Network)));
+        assert_eq!(calls, 3);
+
+        let (result, calls) = upload(vec![MockError::Client]);
+        assert!(matches!(result, Err(MockError::Client)));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn dry_run_lists_and_writes_the_archive() {
+        let dir = package_dir();
+        fs::write(dir.path().join("module.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        let manifest = "[package]\nname = \"test\"\n";
+        let archive_dir = create_temp_dir().unwrap();
+        let archive_path = archive_dir.path().join("package.tar.gz");
+        write_package_archive(
+            &archive_path,
+            manifest,
+            dir.path(),
+            &paths(&["module.wasm", "static/index.html"]),
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            archive_listing(&archive_path).unwrap(),
+            vec![
+                (PathBuf::from("wapm.toml"), manifest.len() as u64),
+                (PathBuf::from("module.wasm"), 8),
+                (PathBuf::from("static/index.html"), 0),
+            ]
+        );
+
+        let out = archive_dir.path().join("out.tar.gz");
+        write_archive(
+            &archive_path,
+            &[
+                ("wapm.toml", ARCHIVE_MANIFEST),
+                ("prebuilt.wasm", b"\0asm\x01\0\0\0"),
+            ],
+        );
+        publish(PublishOpt {
+            out: Some(out.clone()),
+            ..publish_opts_from(&archive_path)
+        })
+        .unwrap();
+        let listed: Vec<PathBuf> = archive_listing(&out)
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(
+            listed,
+            vec![PathBuf::from("wapm.toml"), PathBuf::from("prebuilt.wasm")]
+        );
+    }
+
+    /// A `.tar.gz` with the files at its root
+    fn write_archive(path: &Path, files: &[(&str, &[u8])]) {
+        let mut builder = Builder::new(GzEncoder::new(
+            fs::File::create(path).unwrap(),
+            Compression::default(),
+        ));
+        for (name, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *contents).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn publish_opts_from(archive_path: &Path) -> PublishOpt {
+        PublishOpt {
+            dry_run: true,
+            out: None,
+            quiet: true,
+            build: None,
+            from: Some(archive_path.to_path_buf()),
+            strict: false,
+            registry: None,
+            access: None,
+        }
+    }
+
+    const ARCHIVE_MANIFEST: &[u8] = br#"
+[package]
+name = "_/prebuilt"
+version = "0.1.0"
+description = "A package bundled by another build system"
+
+[[module]]
+name = "prebuilt"
+source = "prebuilt.wasm"
+abi = "none"
+"#;
+
+    #[test]
+    fn fs_mappings_point_inside_the_archive() {
+        let dir = package_dir();
+        fs::write(
+            dir.path().join("wapm.toml"),
+            format!(
+                r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[[module]]
+name = "module"
+source = "module.wasm"
+abi = "wasi"
+
+[fs]
+"/static" = "./static"
+"/cache" = "{}"
+"#,
+                dir.path().join("static/tmp").display()
+            ),
+        )
+        .unwrap();
+        let manifest = Manifest::find_in_directory(dir.path()).unwrap();
+
+        let published = published_manifest(&manifest, dir.path());
+        let fs = published.fs.as_ref().unwrap();
+        assert_eq!(fs["/static"], PathBuf::from("static"));
+        assert_eq!(fs["/cache"], PathBuf::from("static/tmp"));
+        // the directories are in the archive under these paths
+        let mut files = BTreeSet::new();
+        for path in fs.values() {
+            files.extend(files_in_directory(dir.path(), &dir.path().join(path)).unwrap());
+        }
+        assert!(files.contains(Path::new("static/index.html")));
+        assert!(files.contains(Path::new("static/tmp/cache.bin")));
+
+        let manifest_string = manifest_to_string(&published).unwrap();
+        assert!(!manifest_string.contains(&dir.path().to_string_lossy().to_string()));
+    }
+
+    #[test]
+    fn module_sources_must_be_wasm() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        fs::write(path("module.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        fs::write(path("module.wat"), "(module)").unwrap();
+        // an object file referenced by mistake
+        fs::write(path("module.o"), b"\x7fELF\x02\x01\x01\0").unwrap();
+        fs::write(path("broken.wat"), "(module").unwrap();
+        fs::write(path("empty.wasm"), b"").unwrap();
+
+        assert!(check_module_source("module", &path("module.wasm")).is_ok());
+        assert!(check_module_source("module", &path("module.wat")).is_ok());
+        for name in &["module.o", "broken.wat", "empty.wasm"] {
+            assert_eq!(
+                check_module_source("module", &path(name))
+                    .unwrap_err()
+                    .to_string(),
+                format!(
+                    "Module \"module\" source \"{}\" is not a valid wasm binary",
+                    path(name).display()
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn remote_sources_point_inside_the_archive() {
+        let dir = package_dir();
+        fs::write(
+            dir.path().join("wapm.toml"),
+            r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[[module]]
+name = "local"
+source = "module.wasm"
+abi = "wasi"
+
+[[module]]
+name = "remote"
+source = "https://example.com/releases/remote.wasm"
+abi = "wasi"
+"#,
+        )
+        .unwrap();
+        let manifest = Manifest::find_in_directory(dir.path()).unwrap();
+
+        let published = published_manifest(&manifest, dir.path());
+        let sources: Vec<&Path> = published
+            .module
+            .iter()
+            .flatten()
+            .map(|module| module.source.as_path())
+            .collect();
+        assert_eq!(
+            sources,
+            vec![
+                Path::new("module.wasm"),
+                Path::new("remote-sources/remote.wasm")
+            ]
+        );
+    }
+
+    #[test]
+    fn publish_prebuilt_archive_dry_run() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let archive_path = dir.path().join("prebuilt.tar.gz");
+        write_archive(
+            &archive_path,
+            &[
+                ("wapm.toml", ARCHIVE_MANIFEST),
+                ("prebuilt.wasm", b"\0asm\x01\0\0\0"),
+            ],
+        );
+        publish(publish_opts_from(&archive_path)).unwrap();
+    }
+
+    #[test]
+    fn archive_must_contain_manifest_and_modules() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let archive_path = dir.path().join("prebuilt.tar.gz");
+
+        write_archive(&archive_path, &[("prebuilt.wasm", b"\0asm\x01\0\0\0")]);
+        let error = publish(publish_opts_from(&archive_path)).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PublishError>(),
+            Some(PublishError::ArchiveWithoutManifest(_))
+        ));
+
+        write_archive(&archive_path, &[("wapm.toml", ARCHIVE_MANIFEST)]);
+        assert!(publish(publish_opts_from(&archive_path)).is_err());
+    }
+
+    #[test]
+    fn missing_license_file_is_an_error() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let archive_path = dir.path().join("prebuilt.tar.gz");
+        let manifest = r#"
+[package]
+name = "_/prebuilt"
+version = "0.1.0"
+description = "A package bundled by another build system"
+license-file = "LICENSE"
+
+[[module]]
+name = "prebuilt"
+source = "prebuilt.wasm"
+abi = "none"
+"#;
+        write_archive(
+            &archive_path,
+            &[
+                ("wapm.toml", manifest.as_bytes()),
+                ("prebuilt.wasm", b"\0asm\x01\0\0\0"),
+            ],
+        );
+        let error = publish(publish_opts_from(&archive_path)).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PublishError>(),
+            Some(PublishError::CannotReadLicenseFile(path, _)) if path == Path::new("LICENSE")
+        ));
+
+        write_archive(
+            &archive_path,
+            &[
+                ("wapm.toml", manifest.as_bytes()),
+                ("prebuilt.wasm", b"\0asm\x01\0\0\0"),
+                ("LICENSE", b"MIT"),
+            ],
+        );
+        publish(publish_opts_from(&archive_path)).unwrap();
+    }
+
+    #[test]
+    fn no_license_is_an_error_only_with_strict() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let archive_path = dir.path().join("prebuilt.tar.gz");
+        write_archive(
+            &archive_path,
+            &[
+                ("wapm.toml", ARCHIVE_MANIFEST),
+                ("prebuilt.wasm", b"\0asm\x01\0\0\0"),
+            ],
+        );
+        let strict = PublishOpt {
+            strict: true,
+            ..publish_opts_from(&archive_path)
+        };
+        let error = publish(strict).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<PublishError>(),
+            Some(PublishError::NoLicense)
+        ));
+        publish(publish_opts_from(&archive_path)).unwrap();
+    }
+
+    #[test]
+    fn license_must_be_an_spdx_expression() {
+        let package_with = |license: &str| -> Package {
+            let manifest: Manifest = toml::from_str(&format!(
+                r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+{}
+"#,
+                license
+            ))
+            .unwrap();
+            manifest.package
+        };
+
+        let valid = package_with(r#"license = "Apache-2.0 OR MIT""#);
+        check_license(&valid, true).unwrap();
+
+        let invalid = package_with(r#"license = "MIT license""#);
+        check_license(&invalid, false).unwrap();
+        assert!(matches!(
+            check_license(&invalid, true),
+            Err(PublishError::InvalidLicense(license, _)) if license == "MIT license"
+        ));
+
+        let missing = package_with("");
+        check_license(&missing, false).unwrap();
+        assert!(matches!(
+            check_license(&missing, true),
+            Err(PublishError::NoLicense)
+        ));
+
+        // the license file is enough without a license
+        let license_file = package_with(r#"license-file = "LICENSE""#);
+        check_license(&license_file, true).unwrap();
+    }
+
+    #[test]
+    fn registry_override_uses_its_own_token() {
+        use crate::config::{MultiRegistry, Registries};
+        use crate::graphql::registry_token;
+
+        let config = Config {
+            registry: Registries::Multi(MultiRegistry {
+                current: "https://registry.wapm.io/graphql".to_string(),
+                tokens: vec![
+                    ("https://registry.wapm.io/graphql", "active-token"),
+                    ("https://registry.wapm.dev/graphql", "dev-token"),
+                ]
+                .into_iter()
+                .map(|(url, token)| (url.to_string(), token.to_string()))
+                .collect(),
+            }),
+            ..Default::default()
+        };
+
+        let registry_url = publish_registry(&config, Some("https://registry.wapm.dev"));
+        assert_eq!(registry_url, "https://registry.wapm.dev/graphql");
+        if std::env::var("WAPM_REGISTRY_TOKEN").is_err() {
+            assert_eq!(registry_token(&config, &registry_url), "dev-token");
+        }
+        assert_eq!(
+            config.registry.get_current_registry(),
+            "https://registry.wapm.io/graphql"
+        );
+        assert_eq!(
+            publish_registry(&config, None),
+            "https://registry.wapm.io/graphql"
+        );
+
+        // a dry run with the override goes through without touching the active registry
+        let dir = tempfile::TempDir::new().unwrap();
+        let archive_path = dir.path().join("prebuilt.tar.gz");
+        write_archive(
+            &archive_path,
+            &[
+                ("wapm.toml", ARCHIVE_MANIFEST),
+                ("prebuilt.wasm", b"\0asm\x01\0\0\0"),
+            ],
+        );
+        let before = Config::from_file().unwrap().registry.get_current_registry();
+        publish(PublishOpt {
+            registry: Some("https://registry.wapm.dev".to_string()),
+            ..publish_opts_from(&archive_path)
+        })
+        .unwrap();
+        assert_eq!(
+            Config::from_file().unwrap().registry.get_current_registry(),
+            before
+        );
+    }
+
+    #[test]
+    fn keywords_and_categories_are_sent_to_registries_that_have_them() {
+        let package_extensions = PackageExtensions {
+            keywords: Some(vec!["prebuilt".to_string()]),
+            categories: Some(vec!["testing".to_string()]),
+            ..Default::default()
+        };
+        let fields = optional_input_fields(&package_extensions, None);
+        assert_eq!(
+            fields
+                .iter()
+                .map(|field| (field.name, field.value.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("keywords", serde_json::json!(["prebuilt"])),
+                ("categories", serde_json::json!(["testing"])),
+            ]
+        );
+        assert!(optional_input_fields(&PackageExtensions::default(), None).is_empty());
+
+        let registry_url = "https://registry.wapm.io/graphql";
+        let with_keywords = ["name", "keywords"]
+            .iter()
+            .map(ToString::to_string)
+            .collect::<BTreeSet<_>>();
+        let supported = supported_input_fields(registry_url, fields, &with_keywords).unwrap();
+        assert_eq!(
+            supported.iter().map(|field| field.name).collect::<Vec<_>>(),
+            vec!["keywords"]
+        );
+        let query = with_input_fields(publish_package_mutation_chunked::QUERY, &supported);
+        assert!(query.contains(", $signedUrl:String, $keywords: [String!]) {"));
+        assert!(!query.contains("categories"));
+    }
+
+    #[test]
+    fn access_is_only_sent_to_registries_that_have_it() {
+        let private_field = |access| {
+            optional_input_fields(&PackageExtensions::default(), Some(access))
+                .pop()
+                .unwrap()
+        };
+        assert_eq!(
+            private_field(Access::Private).value,
+            serde_json::json!(true)
+        );
+        assert_eq!(
+            private_field(Access::Public).value,
+            serde_json::json!(false)
+        );
+        // the registry keeps the visibility it has
+        assert!(optional_input_fields(&PackageExtensions::default(), None).is_empty());
+        assert!("internal".parse::<Access>().is_err());
+
+        let registry_url = "https://registry.wapm.io/graphql";
+        let with_private = ["name", "private"]
+            .iter()
+            .map(ToString::to_string)
+            .collect::<BTreeSet<_>>();
+        let fields = optional_input_fields(&PackageExtensions::default(), Some(Access::Private));
+        assert_eq!(
+            supported_input_fields(registry_url, fields, &with_private).unwrap(),
+            optional_input_fields(&PackageExtensions::default(), Some(Access::Private))
+        );
+        let fields = optional_input_fields(&PackageExtensions::default(), Some(Access::Private));
+        assert!(matches!(
+            supported_input_fields(registry_url, fields, &BTreeSet::new()),
+            Err(PublishError::AccessUnavailable(_))
+        ));
+
+        let query = with_input_fields(
+            publish_package_mutation_chunked::QUERY,
+            &optional_input_fields(&PackageExtensions::default(), Some(Access::Private)),
+        );
+        assert!(query.contains(", $signedUrl:String, $private: Boolean) {"));
+        assert!(query.contains("    private: $private,\n    clientMutationId: \"\""));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let archive_path = dir.path().join("prebuilt.tar.gz");
+        write_archive(
+            &archive_path,
+            &[
+                ("wapm.toml", ARCHIVE_MANIFEST),
+                ("prebuilt.wasm", b"\0asm\x01\0\0\0"),
+            ],
+        );
+        publish(PublishOpt {
+            access: Some(Access::Private),
+            ..publish_opts_from(&archive_path)
+        })
+        .unwrap();
+    }
+}
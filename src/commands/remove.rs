@@ -2,6 +2,8 @@
 //! from the manifest.
 
 use crate::data::manifest::Manifest;
+use crate::data::manifest_extensions::save_manifest;
+use crate::dataflow::manifest_packages::ManifestResult;
 use structopt::StructOpt;
 use thiserror::Error;
 
@@ -26,7 +28,10 @@ pub fn remove(options: RemoveOpt) -> anyhow::Result<()> {
     let mut error = false;
     let mut manifest: Manifest = {
         let cur_dir = crate::config::Config::get_current_dir()?;
-        Manifest::find_in_directory(cur_dir).map_err(|_| RemoveError::NoManifest)?
+        match ManifestResult::find_in_directory(cur_dir) {
+            ManifestResult::Manifest(manifest) => manifest,
+            _ => return Err(RemoveError::NoManifest.into()),
+        }
     };
 
     if options.packages.is_empty() {
@@ -48,7 +53,7 @@ pub fn remove(options: RemoveOpt) -> anyhow::Result<()> {
         }
     }
 
-    manifest.save()?;
+    save_manifest(&manifest)?;
 
     if error {
         Err(RemoveError::GenericError.into())
@@ -1,37 +1,222 @@
+use crate::abi::Abi;
 use crate::config::Config;
 use crate::constants::DEFAULT_RUNTIME;
 use crate::data::lock::is_lockfile_out_of_date;
+use crate::data::manifest_extensions::{
+    ManifestExtensions, ModuleWasmFeature, RequiredFeature, WasiVersion,
+};
 use crate::dataflow;
 use crate::dataflow::find_command_result;
-use crate::dataflow::find_command_result::get_command_from_anywhere;
+use crate::dataflow::find_command_result::{
+    get_available_commands, get_command_from_anywhere, AvailableCommand, CommandPackage,
+};
+use crate::dataflow::installed_packages::PackageFormat;
 use crate::dataflow::manifest_packages::ManifestResult;
-use crate::util::get_runtime_with_args;
+#[cfg(not(target_os = "wasi"))]
+use crate::fs_trace::{
+    module_writes, preopens_from_flags, read_trace, traced_command, tracer_is_available,
+};
+#[cfg(not(target_os = "wasi"))]
+use crate::memory_limit::ran_out_of_memory;
+use crate::memory_limit::{format_memory_size, limit_memory, parse_memory_size};
+#[cfg(all(feature = "full", not(target_os = "wasi")))]
+use crate::module_cache::{ModuleCache, ModuleCacheKey};
+#[cfg(not(target_os = "wasi"))]
+use crate::run_failure::{explain_failure, tee_stderr};
+#[cfg(not(target_os = "wasi"))]
+use crate::signals::{interrupted_exit_code, ForwardSignals};
+use crate::util::{create_temp_dir, get_runtime_with_args};
+use semver::Version;
+use std::collections::BTreeMap;
 use std::ffi::OsString;
+use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 #[cfg(not(target_os = "wasi"))]
 use std::process::Command;
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
 use thiserror::Error;
-use wapm_toml::Manifest;
+use wapm_toml::{Manifest, Package};
 #[cfg(target_os = "wasi")]
 use wasm_bus_process::prelude::Command;
 
 #[derive(StructOpt, Debug)]
 pub struct RunOpt {
-    /// Command name
-    command: String,
+    /// Command name. Defaults to the command marked `default = true` in the manifest
+    command: Option<String>,
     /// WASI pre-opened directory
     #[structopt(long = "dir", multiple = true, group = "wasi")]
     pre_opened_directories: Vec<String>,
+    /// The working directory of the module. It is pre-opened as `.`
+    #[structopt(long = "cwd", parse(from_os_str))]
+    cwd: Option<PathBuf>,
+    /// Don't expose the package name, version and command name to the module as
+    /// `WAPM_PACKAGE_NAME`, `WAPM_PACKAGE_VERSION` and `WAPM_COMMAND_NAME`
+    #[structopt(long = "no-inject-metadata")]
+    no_inject_metadata: bool,
+    /// Print how long each phase of the run took to stderr
+    #[structopt(long = "print-timings")]
+    print_timings: bool,
+    /// Compile the module again instead of loading it from the module cache
+    #[structopt(long = "no-cache")]
+    no_cache: bool,
+    /// A module to link with the command's module. Its exports satisfy the imports of
+    /// the command's module from the namespace named like the file
+    #[structopt(long = "preload", parse(from_os_str), number_of_values = 1)]
+    preload: Vec<PathBuf>,
+    /// Don't give the module the terminal as its stdin, pass the input through a pipe
+    /// instead. By default an interactive module gets the terminal, with raw mode and
+    /// the window size
+    #[structopt(long = "no-tty")]
+    no_tty: bool,
+    /// Give the module an empty stdin, so that a module reading its input gets the end
+    /// of it right away. It is the default when stdin is a terminal nobody types into:
+    /// the `CI` variable is set, or neither stdout nor stderr is a terminal
+    #[structopt(long = "stdin-null", conflicts_with_all = &["no-tty", "interactive"])]
+    stdin_null: bool,
+    /// Give the module the terminal as its stdin even when nobody seems to type into it
+    #[structopt(long = "interactive")]
+    interactive: bool,
+    /// Expose a single file to the module, read-only, at `/run/secrets/KEY`. The other
+    /// files of its directory stay hidden
+    #[structopt(
+        long = "mount-secret",
+        value_name = "KEY=path",
+        parse(try_from_str = parse_secret),
+        number_of_values = 1
+    )]
+    secrets: Vec<(String, PathBuf)>,
+    /// Let the module see this variable of the host environment. The module sees none
+    /// of them by default
+    #[structopt(long = "inherit-env", value_name = "VAR", number_of_values = 1)]
+    inherit_env: Vec<String>,
+    /// Let the module see the variables of the host environment whose names start
+    /// with this prefix
+    #[structopt(
+        long = "env-passthrough-prefix",
+        value_name = "PREFIX",
+        number_of_values = 1
+    )]
+    env_passthrough_prefixes: Vec<String>,
+    /// Let the module see the whole host environment
+    #[structopt(
+        long = "inherit-all-env",
+        conflicts_with_all = &["inherit-env", "env-passthrough-prefixes"]
+    )]
+    inherit_all_env: bool,
+    /// Set an environment variable of the module. It overrides the host environment
+    /// and the env file
+    #[structopt(
+        long = "env",
+        value_name = "KEY=VALUE",
+        parse(try_from_str = parse_env_var),
+        number_of_values = 1
+    )]
+    env: Vec<(String, String)>,
+    /// Set the environment variables of a file, one `KEY=VALUE` per line. Empty lines
+    /// and lines starting with `#` are skipped
+    #[structopt(long = "env-file", parse(from_os_str))]
+    env_file: Option<PathBuf>,
+    /// When the module fails, explain why: which trap it hit or that it picked its
+    /// exit code itself. The runtime is asked for backtraces
+    #[structopt(long = "explain-error")]
+    explain_error: bool,
+    /// Exit codes of the module that wapm exits with 0 for, like `1,2`. Any other
+    /// nonzero code of the module is the exit code of wapm
+    #[structopt(long = "allow-exit-code", value_name = "CODES", use_delimiter = true)]
+    allow_exit_code: Vec<i32>,
+    /// Use this version of WASI instead of the one the module asks for, to try the
+    /// module with another snapshot of WASI. The runtime must support it
+    #[structopt(
+        long = "wasi-version",
+        value_name = "VERSION",
+        possible_values = WasiVersion::NAMES
+    )]
+    wasi_version: Option<WasiVersion>,
+    /// Cap the memory of the module, like `256MB` or `1GB`. The module fails to
+    /// allocate past it instead of exhausting the memory of the host
+    #[structopt(long = "max-memory", value_name = "SIZE", parse(try_from_str = parse_memory_size))]
+    max_memory: Option<u64>,
+    /// Once the module exits, print the result of the run to stderr as JSON: the exit
+    /// code, how long it ran, the command, the package and its version. The output of
+    /// the module is left as it is
+    #[structopt(long = "json-output")]
+    json_output: bool,
+    /// Write the JSON result of the run to this file instead of stderr
+    #[structopt(long = "json-output-file", value_name = "FILE", parse(from_os_str))]
+    json_output_file: Option<PathBuf>,
+    /// Once the module exits, list its writes to the files of its preopened directories
+    /// to stderr: the file descriptor, the path in the module and the byte count. The
+    /// runtime is run under `strace` for it, so it needs Linux with strace installed.
+    /// Writes to other files, like stdout, are left out
+    #[structopt(long = "trace-fs")]
+    trace_fs: bool,
+    /// Write the `--trace-fs` trace to this file instead of stderr
+    #[structopt(long = "trace-fs-file", value_name = "FILE", parse(from_os_str))]
+    trace_fs_file: Option<PathBuf>,
+    /// Run another command under this name, `NAME=COMMAND`. The command can be the
+    /// command of a package, `namespace/package:command`. It overrides the aliases of
+    /// the `[run.aliases]` section of the config
+    #[structopt(
+        long = "map-command",
+        value_name = "NAME=COMMAND",
+        parse(try_from_str = parse_command_alias),
+        number_of_values = 1
+    )]
+    map_command: Vec<(String, String)>,
+    /// Fail instead of warning when the installed package of the command is not the
+    /// version of the lockfile, like after its `wapm.toml` was edited by hand
+    #[structopt(long = "strict")]
+    strict: bool,
+    /// Keep the temporary files of the run, like the copies of the secrets or the
+    /// module with the memory limit, and print where they are. They are removed once
+    /// the module exits by default
+    #[structopt(long = "keep-temp")]
+    keep_temp: bool,
+    /// Print the package and the module the command resolves to, without running it
+    #[structopt(long = "dry-run", conflicts_with = "wasm")]
+    dry_run: bool,
+    /// Run this wasm file directly, without a package. `-` reads the wasm from stdin.
+    /// All the positional arguments are passed to the module
+    #[structopt(long = "wasm", parse(from_os_str))]
+    wasm: Option<PathBuf>,
+    /// The ABI of the `--wasm` file. Detected from its imports by default
+    #[structopt(
+        long = "abi",
+        requires = "wasm",
+        possible_values = &["none", "wasi", "emscripten", "wasm4"]
+    )]
+    abi: Option<String>,
     /// Application arguments
     #[structopt(multiple = true, parse(from_os_str))]
     args: Vec<OsString>,
 }
 
 pub fn run(run_options: RunOpt) -> anyhow::Result<()> {
-    let command_name = run_options.command.as_str();
-    let args = &run_options.args;
+    let mut timings = RunTimings::new(run_options.print_timings);
+    if let Some(wasm_path) = run_options.wasm.clone() {
+        return run_wasm_file(wasm_path, run_options, timings);
+    }
     let current_dir = crate::config::Config::get_current_dir()?;
+    let command_name = match run_options.command {
+        Some(ref command_name) => command_name.clone(),
+        None => get_default_command_name(&current_dir).or_else(|error| {
+            match error.downcast_ref::<RunError>() {
+                Some(RunError::NoDefaultCommand(_)) => choose_command(&current_dir)?.ok_or(error),
+                _ => Err(error),
+            }
+        })?,
+    };
+    let aliases = Config::from_file()
+        .map(|config| config.run.aliases)
+        .unwrap_or_default();
+    let target = resolve_command_alias(&command_name, &run_options.map_command, &aliases);
+    if target.command != command_name {
+        debug!("Running \"{}\" as \"{}\"", command_name, target.command);
+    }
+    let command_name = target.command.as_str();
+    let args = &run_options.args;
 
     // always update the local lockfile if the manifest has changed
     match is_lockfile_out_of_date(&current_dir) {
@@ -66,10 +251,11 @@ pub fn run(run_options: RunOpt) -> anyhow::Result<()> {
     let find_command_result::Command {
         source: source_path_buf,
         manifest_dir,
-        args: _,
+        args: main_args,
         module_name,
         is_global,
         prehashed_cache_key,
+        package,
     } = match find_command_result {
         Err(find_command_result::Error::CommandNotFound {
             command,
@@ -101,6 +287,20 @@ pub fn run(run_options: RunOpt) -> anyhow::Result<()> {
         },
         otherwise => otherwise?,
     };
+    debug!(
+        "Command \"{}\" resolved to the module \"{}\" of {}",
+        command_name, module_name, package
+    );
+    if let Some(package_name) = target.package.as_ref() {
+        if *package_name != package.name {
+            return Err(RunError::AliasedCommandNotInPackage(
+                command_name.to_string(),
+                package_name.clone(),
+                package.to_string(),
+            )
+            .into());
+        }
+    }
 
     let run_dir = if is_global {
         Config::get_globals_directory().unwrap()
@@ -109,30 +309,434 @@ pub fn run(run_options: RunOpt) -> anyhow::Result<()> {
     };
 
     let manifest_dir = run_dir.join(manifest_dir);
+    if let Some(mismatch) = check_installed_version(&manifest_dir, &package) {
+        if run_options.strict {
+            return Err(mismatch.into());
+        }
+        warn!("{}", mismatch);
+    }
+    if run_options.dry_run {
+        println!(
+            "The command \"{}\" runs the module \"{}\" of {}{}",
+            command_name,
+            module_name,
+            package,
+            if is_global {
+                ", installed globally"
+            } else {
+                ""
+            }
+        );
+        println!("Source: {}", run_dir.join(&source_path_buf).display());
+        return Ok(());
+    }
+
+    let command_args = ManifestExtensions::find_in_directory(&manifest_dir)
+        .ok()
+        .and_then(|extensions| extensions.get_command(command_name).map(|c| c.args.clone()))
+        .unwrap_or_default();
+    let command_args = get_command_args(main_args.as_deref(), command_args);
+    let env = read_module_env(run_options.env_file.as_deref(), run_options.env)?;
+    timings.end_phase("resolve");
 
     do_run(
         run_dir,
         source_path_buf,
-        manifest_dir,
+        Some(manifest_dir),
         command_name,
         &module_name,
         &run_options.pre_opened_directories,
         args,
         prehashed_cache_key,
+        RunSettings {
+            cwd: run_options.cwd,
+            no_inject_metadata: run_options.no_inject_metadata,
+            no_cache: run_options.no_cache,
+            preload: run_options.preload,
+            no_tty: run_options.no_tty,
+            stdin_null: run_options.stdin_null,
+            interactive: run_options.interactive,
+            secrets: run_options.secrets,
+            inherit_env: run_options.inherit_env,
+            env_passthrough_prefixes: run_options.env_passthrough_prefixes,
+            inherit_all_env: run_options.inherit_all_env,
+            env,
+            explain_error: run_options.explain_error,
+            allowed_exit_codes: run_options.allow_exit_code,
+            max_memory: run_options.max_memory,
+            wasi_version: run_options.wasi_version,
+            json_output: ReportOutput::from_options(
+                run_options.json_output,
+                run_options.json_output_file,
+            ),
+            trace_fs: ReportOutput::from_options(run_options.trace_fs, run_options.trace_fs_file),
+            keep_temp: run_options.keep_temp,
+            command_args,
+            package: Some(package),
+            timings,
+        },
     )
 }
 
+/// Runs a wasm file given with `--wasm`, outside of any package
+fn run_wasm_file(
+    wasm_path: PathBuf,
+    run_options: RunOpt,
+    mut timings: RunTimings,
+) -> anyhow::Result<()> {
+    // holds the wasm read from stdin until the run is over
+    let mut temp_artifacts = TempArtifacts::new(run_options.keep_temp);
+    let stdin_dir = if wasm_path == Path::new("-") {
+        Some(temp_artifacts.create_dir()?)
+    } else {
+        None
+    };
+    let wasm_path = match stdin_dir {
+        Some(stdin_dir) => {
+            let mut wasm = vec![];
+            std::io::stdin().read_to_end(&mut wasm)?;
+            let path = stdin_dir.join("stdin.wasm");
+            fs::write(&path, wasm)?;
+            path
+        }
+        None => wasm_path,
+    };
+    let (run_dir, source_path_buf, name) = get_wasm_file_location(&wasm_path)?;
+    let abi = match run_options.abi.as_deref() {
+        Some(abi) => Abi::from_name(abi),
+        None => detect_abi(&fs::read(&wasm_path)?),
+    };
+    let uses_preopens = !run_options.pre_opened_directories.is_empty() || run_options.cwd.is_some();
+    if uses_preopens && !matches!(abi, Abi::Wasi) {
+        return Err(RunError::PreopenWithoutWasi(name).into());
+    }
+    // there is no command, so the first positional argument is an argument of the module
+    let args: Vec<OsString> = run_options
+        .command
+        .map(OsString::from)
+        .into_iter()
+        .chain(run_options.args)
+        .collect();
+    let env = read_module_env(run_options.env_file.as_deref(), run_options.env)?;
+    timings.end_phase("resolve");
+
+    do_run(
+        run_dir,
+        source_path_buf,
+        None,
+        &name,
+        &name,
+        &run_options.pre_opened_directories,
+        &args,
+        None,
+        RunSettings {
+            cwd: run_options.cwd,
+            no_inject_metadata: run_options.no_inject_metadata,
+            no_cache: run_options.no_cache,
+            preload: run_options.preload,
+            no_tty: run_options.no_tty,
+            stdin_null: run_options.stdin_null,
+            interactive: run_options.interactive,
+            secrets: run_options.secrets,
+            inherit_env: run_options.inherit_env,
+            env_passthrough_prefixes: run_options.env_passthrough_prefixes,
+            inherit_all_env: run_options.inherit_all_env,
+            env,
+            explain_error: run_options.explain_error,
+            allowed_exit_codes: run_options.allow_exit_code,
+            max_memory: run_options.max_memory,
+            wasi_version: run_options.wasi_version,
+            json_output: ReportOutput::from_options(
+                run_options.json_output,
+                run_options.json_output_file,
+            ),
+            trace_fs: ReportOutput::from_options(run_options.trace_fs, run_options.trace_fs_file),
+            keep_temp: run_options.keep_temp,
+            // a wasm file run on its own has no command
+            command_args: vec![],
+            package: None,
+            timings,
+        },
+    )
+}
+
+/// Splits the path of a wasm file in the directory it is run from, the file name and
+/// the name the module is run as
+fn get_wasm_file_location(wasm_path: &Path) -> anyhow::Result<(PathBuf, PathBuf, String)> {
+    let wasm_path = wasm_path
+        .canonicalize()
+        .map_err(|_| RunError::WasmFileNotFound(wasm_path.to_string_lossy().to_string()))?;
+    let (run_dir, file_name) = match (wasm_path.parent(), wasm_path.file_name()) {
+        (Some(run_dir), Some(file_name)) => (run_dir.to_path_buf(), PathBuf::from(file_name)),
+        _ => {
+            return Err(RunError::WasmFileNotFound(wasm_path.to_string_lossy().to_string()).into())
+        }
+    };
+    let name = file_name
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok((run_dir, file_name, name))
+}
+
+/// Guesses the ABI of a module from the names of its imports
+fn detect_abi(wasm: &[u8]) -> Abi {
+    use wasmparser::WasmDecoder;
+    let mut parser = wasmparser::Parser::new(wasm);
+    let mut abi = Abi::None;
+    loop {
+        match parser.read() {
+            wasmparser::ParserState::ImportSectionEntry { module, field, .. } => {
+                if module.starts_with("wasi") {
+                    return Abi::Wasi;
+                }
+                if *module == "env" && field.contains("emscripten") {
+                    abi = Abi::Emscripten;
+                }
+            }
+            wasmparser::ParserState::EndWasm | wasmparser::ParserState::Error(_) => return abi,
+            _ => {}
+        }
+    }
+}
+
+/// Settings of `wapm run` that only affect how the module is started
+#[derive(Debug, Default)]
+pub(crate) struct RunSettings {
+    /// The directory the module sees as its current directory
+    pub cwd: Option<PathBuf>,
+    /// Don't set the package metadata env vars
+    pub no_inject_metadata: bool,
+    /// Don't load or store the compiled module in the module cache
+    pub no_cache: bool,
+    /// Modules the runtime links with the main module
+    pub preload: Vec<PathBuf>,
+    /// Pass stdin through a pipe even when it is a terminal
+    pub no_tty: bool,
+    /// Give the module an empty stdin
+    pub stdin_null: bool,
+    /// Keep the terminal as stdin even when nobody seems to type into it
+    pub interactive: bool,
+    /// The files exposed in `/run/secrets`, by name
+    pub secrets: Vec<(String, PathBuf)>,
+    /// The host environment variables the module sees
+    pub inherit_env: Vec<String>,
+    /// The prefixes of the host environment variables the module sees
+    pub env_passthrough_prefixes: Vec<String>,
+    /// Let the module see the whole host environment
+    pub inherit_all_env: bool,
+    /// The environment variables set for the module, later ones win
+    pub env: Vec<(String, String)>,
+    /// Explain why the module failed, if it does
+    pub explain_error: bool,
+    /// The nonzero exit codes of the module that are not a failure of the run
+    pub allowed_exit_codes: Vec<i32>,
+    /// The most memory the module can use, in bytes
+    pub max_memory: Option<u64>,
+    /// The version of WASI the runtime gives the module, instead of the detected one
+    pub wasi_version: Option<WasiVersion>,
+    /// Where to write the result of the run as JSON, if anywhere
+    pub json_output: Option<ReportOutput>,
+    /// Where to write the writes of the module, if anywhere
+    pub trace_fs: Option<ReportOutput>,
+    /// Keep the temporary directories of the run instead of removing them
+    pub keep_temp: bool,
+    /// The arguments of the command in the manifest, see [`compose_args`]
+    pub command_args: Vec<String>,
+    /// The package the command comes from, `None` for a wasm file run on its own
+    pub package: Option<CommandPackage>,
+    /// The phases measured so far, `do_run` adds its own
+    pub timings: RunTimings,
+}
+
+/// Where a report on the run is written, like the one of `--json-output`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ReportOutput {
+    Stderr,
+    File(PathBuf),
+}
+
+impl ReportOutput {
+    /// The output of a report enabled with `flag`, or with a file to write it to
+    fn from_options(flag: bool, file: Option<PathBuf>) -> Option<Self> {
+        match file {
+            Some(path) => Some(ReportOutput::File(path)),
+            None if flag => Some(ReportOutput::Stderr),
+            None => None,
+        }
+    }
+
+    fn write(&self, report: &str) -> anyhow::Result<()> {
+        match self {
+            ReportOutput::Stderr => eprint!("{}", report),
+            ReportOutput::File(path) => fs::write(path, report).map_err(|e| {
+                anyhow!(
+                    "Could not write the report of the run to \"{}\": {}",
+                    path.display(),
+                    e
+                )
+            })?,
+        }
+        Ok(())
+    }
+}
+
+/// The temporary directories of a run. They are removed when it is dropped, once the
+/// run is over, or kept and listed on stderr with `--keep-temp`.
+struct TempArtifacts {
+    keep: bool,
+    dirs: Vec<PathBuf>,
+}
+
+impl TempArtifacts {
+    fn new(keep: bool) -> Self {
+        Self { keep, dirs: vec![] }
+    }
+
+    /// A new temporary directory, removed with the others
+    fn create_dir(&mut self) -> std::io::Result<PathBuf> {
+        #[cfg(not(target_os = "wasi"))]
+        let dir = create_temp_dir()?.into_path();
+        #[cfg(target_os = "wasi")]
+        let dir = create_temp_dir()?;
+        fs::create_dir_all(&dir)?;
+        self.dirs.push(dir.clone());
+        Ok(dir)
+    }
+}
+
+impl Drop for TempArtifacts {
+    fn drop(&mut self) {
+        for dir in &self.dirs {
+            if self.keep {
+                eprintln!("Kept the temporary directory {}", dir.display());
+            } else if let Err(e) = fs::remove_dir_all(dir) {
+                debug!(
+                    "Could not remove the temporary directory {}: {}",
+                    dir.display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// The result of a run, written by `--json-output` once the module exits
+#[derive(Debug, Serialize)]
+struct RunResult<'a> {
+    /// `None` when the module was killed by a signal
+    exit_code: Option<i32>,
+    duration_ms: u64,
+    command: &'a str,
+    package: Option<&'a str>,
+    version: Option<&'a Version>,
+}
+
+/// The time spent in each phase of a run, printed with `--print-timings`
+#[derive(Debug, Clone)]
+pub(crate) struct RunTimings {
+    /// `None` when the timings are not printed
+    phases: Option<Vec<(&'static str, Duration)>>,
+    phase_start: Instant,
+}
+
+impl Default for RunTimings {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl RunTimings {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            phases: if enabled { Some(vec![]) } else { None },
+            phase_start: Instant::now(),
+        }
+    }
+
+    /// Records the time since the end of the previous phase as `phase`
+    pub fn end_phase(&mut self, phase: &'static str) {
+        let now = Instant::now();
+        if let Some(phases) = self.phases.as_mut() {
+            phases.push((phase, now - self.phase_start));
+        }
+        self.phase_start = now;
+    }
+
+    fn table(&self) -> Option<String> {
+        let phases = self.phases.as_ref()?;
+        let total: Duration = phases.iter().map(|(_, duration)| *duration).sum();
+        let mut table = String::new();
+        for (phase, duration) in phases.iter().chain(std::iter::once(&("total", total))) {
+            table.push_str(&format!(
+                "{:<20}{:>10.1}ms\n",
+                phase,
+                duration.as_secs_f64() * 1000.0
+            ));
+        }
+        Some(table)
+    }
+
+    /// Prints the timings to stderr, if they are enabled
+    pub fn print(&self) {
+        if let Some(table) = self.table() {
+            eprint!("{}", table);
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn do_run(
     run_dir: PathBuf,
     source_path_buf: PathBuf,
-    manifest_dir: PathBuf,
+    manifest_dir: Option<PathBuf>,
     command_name: &str,
     module_name: &str,
     pre_opened_directories: &[String],
     args: &[OsString],
     prehashed_cache_key: Option<String>,
+    settings: RunSettings,
 ) -> anyhow::Result<()> {
+    let RunSettings {
+        cwd,
+        no_inject_metadata,
+        no_cache,
+        preload,
+        no_tty,
+        stdin_null,
+        interactive,
+        secrets,
+        inherit_env,
+        env_passthrough_prefixes,
+        inherit_all_env,
+        env,
+        explain_error,
+        allowed_exit_codes,
+        max_memory,
+        wasi_version,
+        json_output,
+        trace_fs,
+        keep_temp,
+        command_args,
+        package,
+        mut timings,
+    } = settings;
+    let args = &compose_args(&command_args, args);
+    // a webc container goes to the runtime as-is, there is no wasm module to work on
+    let is_webc = matches!(
+        package.as_ref().map(|package| package.format),
+        Some(PackageFormat::Webc)
+    );
+    if is_webc && max_memory.is_some() {
+        return Err(RunError::NotSupportedForWebc(module_name.to_string(), "--max-memory").into());
+    }
+    if is_webc && !preload.is_empty() {
+        return Err(RunError::NotSupportedForWebc(module_name.to_string(), "--preload").into());
+    }
+    // the runtime keys the compiled modules of a container itself
+    let prehashed_cache_key = prehashed_cache_key.filter(|_| !is_webc);
+    // removed once the run is over, after everything else of this function
+    let mut temp_artifacts = TempArtifacts::new(keep_temp);
     debug!(
         "Running module located at {:?}",
         &run_dir.join(&source_path_buf)
@@ -146,26 +750,55 @@ pub(crate) fn do_run(
         )
     })?;
 
-    let wasmer_extra_flags: Option<Vec<OsString>> =
-        match ManifestResult::find_in_directory(&manifest_dir) {
-            ManifestResult::Manifest(manifest) => manifest
-                .package
-                .wasmer_extra_flags
-                .map(|extra_flags| extra_flags.split_whitespace().map(OsString::from).collect()),
-            _ => None,
-        };
+    // a wasm file run on its own has no manifest
+    let manifest_result = manifest_dir
+        .as_ref()
+        .map(ManifestResult::find_in_directory)
+        .unwrap_or(ManifestResult::NoManifest);
+    let mut wasmer_extra_flags: Option<Vec<OsString>> = match &manifest_result {
+        ManifestResult::Manifest(manifest) => manifest
+            .package
+            .wasmer_extra_flags
+            .as_ref()
+            .map(|extra_flags| extra_flags.split_whitespace().map(OsString::from).collect()),
+        _ => None,
+    };
 
     let mut wasi_preopened_dir_flags: Vec<OsString> = pre_opened_directories
         .iter()
         .map(|entry| OsString::from(format!("--dir={}", entry)))
         .collect();
 
+    if let Some(cwd) = cwd.as_ref() {
+        wasi_preopened_dir_flags.push(get_cwd_preopen_flag(cwd)?);
+    }
+
+    // holds the copies of the secrets until the run is over
+    if !secrets.is_empty() {
+        let secrets_dir = temp_artifacts.create_dir()?;
+        wasi_preopened_dir_flags.push(mount_secrets(&secrets, &secrets_dir)?);
+    }
+
+    // a wasm file run on its own has no required features, so its ABI doesn't matter
+    let module_is_wasi = match &manifest_result {
+        ManifestResult::Manifest(manifest) => manifest
+            .module
+            .iter()
+            .flatten()
+            .find(|module| module.name == module_name)
+            .is_none_or(|module| matches!(module.abi, Abi::Wasi)),
+        _ => true,
+    };
     let mut disable_command_rename = false;
+    let mut metadata_env_flags = vec![];
 
-    if let ManifestResult::Manifest(Manifest { package, fs, .. }) =
-        ManifestResult::find_in_directory(&manifest_dir)
+    if let (Some(manifest_dir), ManifestResult::Manifest(Manifest { package, fs, .. })) =
+        (manifest_dir.as_ref(), manifest_result)
     {
         disable_command_rename = package.disable_command_rename;
+        if !no_inject_metadata {
+            metadata_env_flags = get_metadata_env_flags(&package, command_name);
+        }
 
         if let Some(ref fs) = fs {
             // todo: normalize (rm `:` and newline, etc) these paths if we haven't yet
@@ -179,9 +812,82 @@ pub(crate) fn do_run(
         }
     }
 
+    let (required_wasm_features, required_features) = manifest_dir
+        .as_ref()
+        .and_then(|manifest_dir| ManifestExtensions::find_in_directory(manifest_dir).ok())
+        .and_then(|extensions| {
+            extensions.get_module(module_name).map(|module| {
+                (
+                    module.wasm_features.clone(),
+                    module.required_features.clone(),
+                )
+            })
+        })
+        .unwrap_or_default();
+    check_required_abi(module_name, module_is_wasi, &required_features)?;
+
+    // holds the module with the memory limit until the run is over
+    let (source_path_buf, prehashed_cache_key) = match max_memory {
+        Some(max_memory) => {
+            let wasm = fs::read(run_dir.join(&source_path_buf))?;
+            let limited_wasm = limit_memory(&wasm, max_memory).map_err(|e| {
+                RunError::CannotLimitMemory(
+                    module_name.to_string(),
+                    format_memory_size(max_memory),
+                    e.to_string(),
+                )
+            })?;
+            let limited_path = temp_artifacts.create_dir()?.join(
+                source_path_buf
+                    .file_name()
+                    .unwrap_or_else(|| "module.wasm".as_ref()),
+            );
+            fs::write(&limited_path, limited_wasm)?;
+            // the prehashed key is the one of the module without the limit
+            (limited_path, None)
+        }
+        None => (source_path_buf, prehashed_cache_key),
+    };
+
     let (runtime, runtime_args) = get_runtime_with_args();
 
+    // holds the trace files of `--trace-fs` until the run is over
+    #[cfg(not(target_os = "wasi"))]
+    let fs_trace_dir = match trace_fs {
+        Some(_) if !cfg!(target_os = "linux") => {
+            return Err(RunError::TracerNotAvailable("only runs on Linux").into())
+        }
+        Some(_) if !tracer_is_available() => {
+            return Err(RunError::TracerNotAvailable("could not be found in the PATH").into())
+        }
+        Some(_) => Some(temp_artifacts.create_dir()?),
+        None => None,
+    };
+    // the trace only keeps the writes to these, by their path in the module
+    #[cfg(not(target_os = "wasi"))]
+    let fs_trace_preopens = match fs_trace_dir {
+        Some(_) => preopens_from_flags(&wasi_preopened_dir_flags, &std::env::current_dir()?),
+        None => vec![],
+    };
+
+    #[cfg(target_os = "wasi")]
+    if trace_fs.is_some() {
+        return Err(RunError::TracerNotAvailable("only runs on Linux").into());
+    }
+    #[cfg(target_os = "wasi")]
+    if !preload.is_empty() {
+        return Err(RunError::PreloadNotSupported(runtime).into());
+    }
+    #[cfg(target_os = "wasi")]
+    if let Some(wasi_version) = wasi_version {
+        return Err(RunError::UnsupportedWasiVersion(wasi_version.to_string(), runtime).into());
+    }
+    // the module always gets wapm's stdin under WASI
+    #[cfg(target_os = "wasi")]
+    let _ = (no_tty, stdin_null, interactive);
+
     let mut cmd;
+    let mut compiled_at_run_time = true;
     if cfg!(target_os = "wasi") {
         debug!("Running wapm process: {:?}", source_path_buf);
         cmd = Command::new(source_path_buf.to_string_lossy().as_ref());
@@ -205,6 +911,83 @@ pub(crate) fn do_run(
         } else {
             Some(command_name.to_string())
         };
+        // the metadata can't be overridden, the module relies on it
+        let reserved_env_vars: &[&str] = if metadata_env_flags.is_empty() {
+            &[]
+        } else {
+            &METADATA_ENV_VARS
+        };
+        let env_flags = get_env_flags(
+            host_env(),
+            &inherit_env,
+            &env_passthrough_prefixes,
+            inherit_all_env,
+            &env,
+            reserved_env_vars,
+        );
+        if !metadata_env_flags.is_empty() || !env_flags.is_empty() {
+            wasmer_extra_flags
+                .get_or_insert_with(Vec::new)
+                .extend(metadata_env_flags.into_iter().chain(env_flags));
+        }
+        #[cfg(not(target_os = "wasi"))]
+        if using_default_runtime
+            && (!required_wasm_features.is_empty() || !required_features.is_empty())
+        {
+            let help_text = get_runtime_run_help(&runtime, &runtime_args)?;
+            let mut feature_flags =
+                get_wasm_feature_flags(&help_text, &runtime, module_name, &required_wasm_features)?;
+            for flag in
+                get_required_feature_flags(&help_text, &runtime, module_name, &required_features)?
+            {
+                // `threads` can be both a wasm feature and a required feature
+                if !feature_flags.contains(&flag) {
+                    feature_flags.push(flag);
+                }
+            }
+            wasmer_extra_flags
+                .get_or_insert_with(Vec::new)
+                .extend(feature_flags);
+        }
+        #[cfg(not(target_os = "wasi"))]
+        if let Some(wasi_version) = wasi_version {
+            // only the help of the default runtime tells whether it has the version
+            if using_default_runtime {
+                let help_text = get_runtime_run_help(&runtime, &runtime_args)?;
+                check_wasi_version(&help_text, &runtime, wasi_version)?;
+            }
+            wasmer_extra_flags
+                .get_or_insert_with(Vec::new)
+                .push(OsString::from(wasi_version.runtime_flag()));
+        }
+        #[cfg(not(target_os = "wasi"))]
+        if !preload.is_empty() {
+            let main_wasm = fs::read(run_dir.join(&source_path_buf))?;
+            check_preloaded_exports(module_name, &main_wasm, &preload)?;
+            let preload_flags = get_preload_flags(&runtime, &runtime_args, &preload)?;
+            wasmer_extra_flags
+                .get_or_insert_with(Vec::new)
+                .extend(preload_flags);
+        }
+        let compiled_module = if using_default_runtime && !no_cache && !is_webc {
+            get_compiled_module(
+                &runtime,
+                &runtime_args,
+                &run_dir.join(&source_path_buf),
+                wasmer_extra_flags.as_deref().unwrap_or_default(),
+            )
+        } else {
+            None
+        };
+        // the artifact path is absolute, so it replaces `run_dir` in the command
+        let (source_path_buf, prehashed_cache_key) = match compiled_module {
+            Some(artifact_path) => {
+                timings.end_phase("compile");
+                compiled_at_run_time = false;
+                (artifact_path, None)
+            }
+            None => (source_path_buf, prehashed_cache_key),
+        };
         let command_vec = create_run_command(
             args,
             wasmer_extra_flags,
@@ -218,96 +1001,1777 @@ pub(crate) fn do_run(
         cmd = Command::new(&runtime);
         cmd.args(&runtime_args);
         cmd.args(&command_vec);
+        #[cfg(not(target_os = "wasi"))]
+        if let Some(fs_trace_dir) = fs_trace_dir.as_ref() {
+            cmd = traced_command(&cmd, fs_trace_dir);
+        }
+        // the runtime inherits the terminal, so that the module sees stdin as a
+        // character device and can use raw mode, unless asked not to
+        #[cfg(not(target_os = "wasi"))]
+        if stdin_null || (!interactive && stdin_is_unattended_terminal()) {
+            debug!("The module gets an empty stdin");
+            cmd.stdin(std::process::Stdio::null());
+        } else if no_tty && atty::is(atty::Stream::Stdin) {
+            cmd.stdin(std::process::Stdio::piped());
+        }
+        // the trap is in what the runtime prints, so stderr only leaves the terminal
+        // when it has to be read
+        #[cfg(not(target_os = "wasi"))]
+        if explain_error || max_memory.is_some() {
+            cmd.stderr(std::process::Stdio::piped());
+        }
+        #[cfg(not(target_os = "wasi"))]
+        if explain_error {
+            cmd.env("RUST_BACKTRACE", "1");
+        }
     };
 
-    let mut child = cmd
-        .spawn()
-        .map_err(|e| -> RunError { RunError::ProcessFailed(runtime, format!("{:?}", e)) })?;
+    timings.end_phase("prepare");
 
-    child.wait()?;
+    #[cfg(not(target_os = "wasi"))]
+    let forward_signals = ForwardSignals::install()?;
+    let started = Instant::now();
+    let mut child = cmd.spawn().map_err(|e| -> RunError {
+        RunError::ProcessFailed(runtime.clone(), format!("{:?}", e))
+    })?;
+    #[cfg(not(target_os = "wasi"))]
+    forward_signals.forward_to(&child);
+    #[cfg(not(target_os = "wasi"))]
+    if let Some(mut child_stdin) = child.stdin.take() {
+        // the thread ends at the end of the input, or with wapm
+        std::thread::spawn(move || std::io::copy(&mut std::io::stdin(), &mut child_stdin));
+    }
+    #[cfg(not(target_os = "wasi"))]
+    let stderr_tail = child.stderr.take().map(tee_stderr);
+
+    let status = child.wait()?;
+    // without a compiled module, the runtime compiles it in the process that executes it
+    timings.end_phase(if compiled_at_run_time {
+        "compile and execute"
+    } else {
+        "execute"
+    });
+    timings.print();
+    #[cfg(not(target_os = "wasi"))]
+    if let Some(json_output) = json_output.as_ref() {
+        let result = RunResult {
+            exit_code: status.code(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            command: command_name,
+            package: package.as_ref().map(|package| package.name.as_str()),
+            version: package.as_ref().map(|package| &package.version),
+        };
+        json_output.write(&format!("{}\n", serde_json::to_string(&result)?))?;
+    }
+    #[cfg(not(target_os = "wasi"))]
+    if let (Some(trace_fs), Some(fs_trace_dir)) = (trace_fs.as_ref(), fs_trace_dir.as_ref()) {
+        let trace: String = module_writes(read_trace(fs_trace_dir)?, &fs_trace_preopens)
+            .iter()
+            .map(|write| format!("{}\n", write))
+            .collect();
+        trace_fs.write(&trace)?;
+    }
+    #[cfg(not(target_os = "wasi"))]
+    if let Some(exit_code) = interrupted_exit_code(status, forward_signals.received()) {
+        return Err(RunError::Interrupted(runtime, exit_code).into());
+    }
+    #[cfg(not(target_os = "wasi"))]
+    if let Some(stderr_tail) = stderr_tail {
+        let stderr = stderr_tail.join().unwrap_or_default();
+        if let Some(max_memory) = max_memory {
+            if !status.success() && ran_out_of_memory(&stderr) {
+                return Err(RunError::MemoryLimitExceeded(
+                    module_name.to_string(),
+                    format_memory_size(max_memory),
+                )
+                .into());
+            }
+        }
+        if explain_error {
+            if let Some(explanation) = explain_failure(module_name, status.code(), &stderr) {
+                eprintln!("{}", explanation);
+            }
+        }
+    }
+    #[cfg(not(target_os = "wasi"))]
+    match status.code() {
+        Some(exit_code) if exit_code != 0 && !allowed_exit_codes.contains(&exit_code) => {
+            return Err(RunError::ModuleFailed(module_name.to_string(), exit_code).into());
+        }
+        _ => {}
+    }
+    #[cfg(target_os = "wasi")]
+    let _ = (
+        status,
+        explain_error,
+        json_output,
+        package,
+        started,
+        trace_fs,
+        allowed_exit_codes,
+    );
     Ok(())
 }
 
-fn create_run_command<P: AsRef<Path>, P2: AsRef<Path>>(
-    args: &[OsString],
-    wasmer_extra_flags: Option<Vec<OsString>>,
-    wasi_preopened_dir_flags: Vec<OsString>,
-    directory: P,
-    wasm_file_path: P2,
-    override_command_name: Option<String>,
-    prehashed_cache_key: Option<String>,
-) -> anyhow::Result<Vec<OsString>> {
-    let mut path = PathBuf::new();
-    path.push(directory);
-    path.push(wasm_file_path);
-    let path_string = path.into_os_string();
-    let command_vec = vec![path_string];
-    let override_command_name = override_command_name
-        .map(|cn| vec![OsString::from(format!("--command-name={}", cn))])
-        .unwrap_or_default();
-    let prehashed_cache_key_flag = prehashed_cache_key
-        .map(|pck| vec![OsString::from(format!("--cache-key=\"{}\"", pck))])
-        .unwrap_or_default();
+/// Whether stdin is a terminal that nobody types into, like the one of a CI job: the
+/// `CI` variable is set, or neither stdout nor stderr is a terminal. A module reading
+/// it would wait forever.
+#[cfg(not(target_os = "wasi"))]
+fn stdin_is_unattended_terminal() -> bool {
+    atty::is(atty::Stream::Stdin)
+        && (std::env::var_os("CI").is_some()
+            || (!atty::is(atty::Stream::Stdout) && !atty::is(atty::Stream::Stderr)))
+}
 
-    // NOTE:
-    // for optional types, use an empty vec here:
-    // an empty OsString may pass empty args to the child program which can cause issues
-    Ok([
-        command_vec.as_slice(),
-        &override_command_name,
-        &wasi_preopened_dir_flags,
-        wasmer_extra_flags.as_deref().unwrap_or_default(),
-        &prehashed_cache_key_flag,
-        &[OsString::from("--")],
-        args,
-    ]
-    .concat())
+/// The code wapm exits with after `error`. A run that was interrupted exits with a
+/// code derived from the signal, a module that failed with its exit code, anything
+/// else with -1
+pub fn error_exit_code(error: &anyhow::Error) -> i32 {
+    match error.downcast_ref::<RunError>() {
+        Some(RunError::Interrupted(_, exit_code)) => *exit_code,
+        Some(RunError::ModuleFailed(_, exit_code)) => *exit_code,
+        _ => -1,
+    }
 }
 
-#[cfg(test)]
-mod test {
-    use crate::commands::run::create_run_command;
-    use crate::data::manifest::PACKAGES_DIR_NAME;
-    use crate::util::create_temp_dir;
-    use std::ffi::OsString;
-    use std::fs;
-    use std::path::PathBuf;
+/// The imports of a module, as `(namespace, name)`
+fn get_module_imports(wasm: &[u8]) -> Vec<(String, String)> {
+    use wasmparser::WasmDecoder;
+    let mut parser = wasmparser::Parser::new(wasm);
+    let mut imports = vec![];
+    loop {
+        match parser.read() {
+            wasmparser::ParserState::ImportSectionEntry { module, field, .. } => {
+                imports.push((module.to_string(), field.to_string()));
+            }
+            wasmparser::ParserState::EndWasm | wasmparser::ParserState::Error(_) => return imports,
+            _ => {}
+        }
+    }
+}
 
-    #[test]
-    fn create_run_command_vec() {
-        let args: Vec<OsString> = vec![OsString::from("arg1"), OsString::from("arg2")];
-        let tmp_dir = create_temp_dir().unwrap();
-        let dir: &std::path::Path = tmp_dir.as_ref();
-        let wapm_module_dir = dir.join(
-            [PACKAGES_DIR_NAME, "_", "foo@1.0.2"]
-                .iter()
-                .collect::<PathBuf>(),
+/// The names of the exports of a module
+fn get_module_exports(wasm: &[u8]) -> Vec<String> {
+    use wasmparser::WasmDecoder;
+    let mut parser = wasmparser::Parser::new(wasm);
+    let mut exports = vec![];
+    loop {
+        match parser.read() {
+            wasmparser::ParserState::ExportSectionEntry { field, .. } => {
+                exports.push(field.to_string());
+            }
+            wasmparser::ParserState::EndWasm | wasmparser::ParserState::Error(_) => return exports,
+            _ => {}
+        }
+    }
+}
+
+/// Checks that the preloaded modules export everything the main module imports from
+/// them. A preloaded module provides the namespace named like its file, so
+/// `libruby.wasm` provides the `libruby` imports. The WASI and `env` imports are left
+/// to the runtime.
+fn check_preloaded_exports(
+    module_name: &str,
+    main_wasm: &[u8],
+    preload: &[PathBuf],
+) -> anyhow::Result<()> {
+    let mut provided = BTreeMap::new();
+    for path in preload {
+        let wasm = fs::read(path)
+            .map_err(|_| RunError::WasmFileNotFound(path.to_string_lossy().to_string()))?;
+        let namespace = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        provided.insert(namespace, get_module_exports(&wasm));
+    }
+    let missing: Vec<String> = get_module_imports(main_wasm)
+        .into_iter()
+        .filter(|(namespace, _)| !namespace.starts_with("wasi") && namespace != "env")
+        .filter(|(namespace, name)| {
+            !provided
+                .get(namespace)
+                .map(|exports| exports.contains(name))
+                .unwrap_or(false)
+        })
+        .map(|(namespace, name)| format!("{}.{}", namespace, name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(
+            RunError::UnresolvedImports(module_name.to_string(), missing.join(", ")).into(),
         );
-        fs::create_dir_all(&wapm_module_dir).unwrap();
-        let expected_dir = wapm_module_dir.join("foo_entry.wasm");
-        let expected_command = vec![
-            expected_dir.into_os_string(),
-            OsString::from("--"),
-            OsString::from("arg1"),
-            OsString::from("arg2"),
-        ];
-        let wasm_relative_path: PathBuf = ["wapm_packages", "_", "foo@1.0.2", "foo_entry.wasm"]
-            .iter()
-            .collect();
-        let actual_command =
-            create_run_command(&args, None, vec![], dir, wasm_relative_path, None, None).unwrap();
-        assert_eq!(expected_command, actual_command);
     }
+    Ok(())
 }
 
-#[derive(Debug, Error)]
-enum RunError {
-    #[error("Failed to run command \"{0}\". {1}")]
-    CannotRegenLockfile(String, dataflow::Error),
-    #[error(
-        "The command \"{0}\" for module \"{1}\" is defined but the source at \"{2}\" does not exist.",
-    )]
-    SourceForCommandNotFound(String, String, String),
-    #[error("Failed to run {0}: {1}")]
-    ProcessFailed(String, String),
+/// Checks that the runtime can link modules and returns the flags that preload them
+#[cfg(not(target_os = "wasi"))]
+fn get_preload_flags(
+    runtime: &str,
+    runtime_args: &[String],
+    preload: &[PathBuf],
+) -> anyhow::Result<Vec<OsString>> {
+    let output = Command::new(runtime)
+        .args(runtime_args)
+        .args(["run", "--help"])
+        .output()
+        .map_err(|e| RunError::ProcessFailed(runtime.to_string(), format!("{:?}", e)))?;
+    if !String::from_utf8_lossy(&output.stdout).contains("--preload") {
+        return Err(RunError::PreloadNotSupported(runtime.to_string()).into());
+    }
+    preload
+        .iter()
+        .map(|path| {
+            let path = path
+                .canonicalize()
+                .map_err(|_| RunError::WasmFileNotFound(path.to_string_lossy().to_string()))?;
+            Ok(OsString::from(format!(
+                "--preload={}",
+                path.to_string_lossy()
+            )))
+        })
+        .collect()
+}
+
+/// Flags of the runtime that change the compiled code
+#[cfg(all(feature = "full", not(target_os = "wasi")))]
+static COMPILE_FLAG_PREFIXES: &[&str] = &["--llvm", "--cranelift", "--singlepass", "--enable-"];
+
+/// The module compiled by the runtime, from the module cache when it was compiled
+/// before. `None` when it can't be compiled ahead of time, the wasm is run instead.
+#[cfg(all(feature = "full", not(target_os = "wasi")))]
+fn get_compiled_module(
+    runtime: &str,
+    runtime_args: &[String],
+    wasm_path: &Path,
+    extra_flags: &[OsString],
+) -> Option<PathBuf> {
+    let compile_flags: Vec<String> = runtime_args
+        .iter()
+        .cloned()
+        .chain(extra_flags.iter().map(|f| f.to_string_lossy().to_string()))
+        .filter(|flag| {
+            COMPILE_FLAG_PREFIXES
+                .iter()
+                .any(|prefix| flag.starts_with(prefix))
+        })
+        .collect();
+    let backend = if compile_flags.is_empty() {
+        "default".to_string()
+    } else {
+        compile_flags.join(" ")
+    };
+
+    let result = (|| -> anyhow::Result<PathBuf> {
+        let version_output = Command::new(runtime)
+            .args(runtime_args)
+            .arg("--version")
+            .output()?;
+        if !version_output.status.success() {
+            return Err(anyhow!("`{} --version` failed", runtime));
+        }
+        let runtime_version = String::from_utf8_lossy(&version_output.stdout);
+        let wasm = fs::read(wasm_path)?;
+        let key = ModuleCacheKey::new(&wasm, runtime_version.trim(), &backend);
+        let artifact_path = ModuleCache::in_config_folder()?.get_or_compile(&key, |path| {
+            debug!("Compiling {:?} to {:?}", wasm_path, path);
+            let output = Command::new(runtime)
+                .args(runtime_args)
+                .arg("compile")
+                .arg(wasm_path)
+                .arg("-o")
+                .arg(path)
+                .args(&compile_flags)
+                .output()
+                .map_err(|e| e.to_string())?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+            }
+        })?;
+        Ok(artifact_path)
+    })();
+    match result {
+        Ok(artifact_path) => Some(artifact_path),
+        Err(e) => {
+            debug!("Not using the module cache: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(not(all(feature = "full", not(target_os = "wasi"))))]
+fn get_compiled_module(
+    _runtime: &str,
+    _runtime_args: &[String],
+    _wasm_path: &Path,
+    _extra_flags: &[OsString],
+) -> Option<PathBuf> {
+    None
+}
+
+/// Maps the chosen working directory to `.` in the module's file system
+fn get_cwd_preopen_flag(cwd: &Path) -> anyhow::Result<OsString> {
+    if !cwd.is_dir() {
+        return Err(RunError::WorkingDirectoryNotFound(cwd.to_string_lossy().to_string()).into());
+    }
+    let cwd = cwd.canonicalize()?;
+    Ok(OsString::from(format!(
+        "--mapdir=.:{}",
+        cwd.to_string_lossy()
+    )))
+}
+
+/// Where the files of `--mount-secret` are in the module's file system
+static SECRETS_GUEST_DIR: &str = "/run/secrets";
+
+/// Parses a `--mount-secret` value, `KEY=path`. The key is the name of the file in
+/// the module's file system.
+fn parse_secret(value: &str) -> Result<(String, PathBuf), String> {
+    let (key, path) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=path, got \"{}\"", value))?;
+    if key.is_empty() || key == "." || key == ".." || key.contains(&['/', '\\'][..]) {
+        return Err(format!("\"{}\" can't be used as a file name", key));
+    }
+    Ok((key.to_string(), PathBuf::from(path)))
+}
+
+/// Copies the secrets into `secrets_dir` and returns the flag that maps it to
+/// `/run/secrets`. Mapping a directory of copies doesn't expose the files next to the
+/// secrets, and writes never reach them.
+fn mount_secrets(secrets: &[(String, PathBuf)], secrets_dir: &Path) -> anyhow::Result<OsString> {
+    fs::create_dir_all(secrets_dir)?;
+    for (key, host_path) in secrets {
+        if !host_path.is_file() {
+            return Err(RunError::SecretNotFound(
+                key.clone(),
+                host_path.to_string_lossy().to_string(),
+            )
+            .into());
+        }
+        let copy_path = secrets_dir.join(key);
+        if copy_path.exists() {
+            return Err(RunError::DuplicateSecret(key.clone()).into());
+        }
+        fs::copy(host_path, &copy_path)?;
+        // Windows can't delete a read-only file with its temporary directory
+        #[cfg(unix)]
+        {
+            let mut permissions = fs::metadata(&copy_path)?.permissions();
+            permissions.set_readonly(true);
+            fs::set_permissions(&copy_path, permissions)?;
+        }
+    }
+    Ok(OsString::from(format!(
+        "--mapdir={}:{}",
+        SECRETS_GUEST_DIR,
+        secrets_dir.to_string_lossy()
+    )))
+}
+
+/// The mismatch between the version of the package installed in `manifest_dir` and
+/// the version of the lockfile, if any. The package of the directory itself is not
+/// installed, so it is not checked.
+fn check_installed_version(manifest_dir: &Path, package: &CommandPackage) -> Option<RunError> {
+    if package.is_local_package {
+        return None;
+    }
+    match ManifestResult::find_in_directory(manifest_dir) {
+        ManifestResult::Manifest(manifest) if manifest.package.version != package.version => {
+            Some(RunError::InstalledVersionMismatch(
+                package.name.clone(),
+                manifest.package.version.to_string(),
+                package.version.to_string(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// The placeholders in the arguments of a command for the arguments of `wapm run`
+const ARGS_PLACEHOLDERS: &[&str] = &["$@", "$ARGS"];
+
+/// The arguments of a command: its `args` list, or its `main_args` string split on
+/// whitespace for the manifests written before `args`
+fn get_command_args(main_args: Option<&str>, args: Vec<String>) -> Vec<String> {
+    if !args.is_empty() {
+        return args;
+    }
+    main_args
+        .map(|main_args| main_args.split_whitespace().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// The arguments the module runs with: the arguments of the command, with the ones
+/// given to `wapm run` in place of the placeholders or after them all if there is none
+fn compose_args(command_args: &[String], args: &[OsString]) -> Vec<OsString> {
+    let has_placeholder = command_args
+        .iter()
+        .any(|arg| ARGS_PLACEHOLDERS.contains(&arg.as_str()));
+    let mut composed = vec![];
+    for arg in command_args {
+        if ARGS_PLACEHOLDERS.contains(&arg.as_str()) {
+            composed.extend_from_slice(args);
+        } else {
+            composed.push(OsString::from(arg));
+        }
+    }
+    if !has_placeholder {
+        composed.extend_from_slice(args);
+    }
+    composed
+}
+
+/// The command marked `default = true` in the manifest in `directory`
+fn get_default_command_name(directory: &Path) -> anyhow::Result<String> {
+    let extensions = ManifestExtensions::find_in_directory(directory)?;
+    match extensions.default_command()? {
+        Some(command_name) => Ok(command_name.to_string()),
+        None => Err(
+            RunError::NoDefaultCommand(command_choices(&get_available_commands(directory)?)).into(),
+        ),
+    }
+}
+
+/// Asks which of the available commands to run, when no command was given and none is
+/// the default. Only asks when someone is at the terminal to answer, `None` otherwise.
+#[cfg(not(target_os = "wasi"))]
+fn choose_command(directory: &Path) -> anyhow::Result<Option<String>> {
+    if crate::util::wapm_should_accept_all_prompts()
+        || !atty::is(atty::Stream::Stdin)
+        || !atty::is(atty::Stream::Stderr)
+    {
+        return Ok(None);
+    }
+    let commands = get_available_commands(directory)?;
+    if commands.is_empty() {
+        return Ok(None);
+    }
+    let chosen = dialoguer::Select::new()
+        .with_prompt("Which command do you want to run?")
+        .items(&command_lines(&commands))
+        .default(0)
+        .interact()?;
+    Ok(Some(commands[chosen].name.clone()))
+}
+
+#[cfg(target_os = "wasi")]
+fn choose_command(_directory: &Path) -> anyhow::Result<Option<String>> {
+    Ok(None)
+}
+
+/// The commands of a package to choose from, with their descriptions. Empty when the
+/// package has no commands.
+fn command_choices(commands: &[AvailableCommand]) -> String {
+    if commands.is_empty() {
+        return String::new();
+    }
+    let mut choices = String::from(" Run one of the commands of the package:");
+    for line in command_lines(commands) {
+        choices.push_str(&format!("\n    {}", line));
+    }
+    choices
+}
+
+/// One line per command: its name, then its description aligned with the others
+fn command_lines(commands: &[AvailableCommand]) -> Vec<String> {
+    let name_width = commands.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    commands
+        .iter()
+        .map(|command| match &command.description {
+            Some(description) => format!(
+                "{:width$}  {}",
+                command.name,
+                description,
+                width = name_width
+            ),
+            None => command.name.clone(),
+        })
+        .collect()
+}
+
+/// The environment variables that expose the package metadata to the module
+static METADATA_ENV_VARS: [&str; 3] = [
+    "WAPM_PACKAGE_NAME",
+    "WAPM_PACKAGE_VERSION",
+    "WAPM_COMMAND_NAME",
+];
+
+/// The `--env` flags that expose the package metadata to the module
+fn get_metadata_env_flags(package: &Package, command_name: &str) -> Vec<OsString> {
+    METADATA_ENV_VARS
+        .iter()
+        .zip(&[
+            package.name.clone(),
+            package.version.to_string(),
+            command_name.to_string(),
+        ])
+        .map(|(key, value)| OsString::from(format!("--env={}={}", key, value)))
+        .collect()
+}
+
+/// The command an alias points to
+#[derive(Debug, PartialEq, Eq)]
+struct CommandTarget {
+    /// The package the command must come from, if any
+    package: Option<String>,
+    command: String,
+}
+
+impl CommandTarget {
+    /// Parses `command` or `namespace/package:command`
+    fn parse(target: &str) -> Self {
+        match target.rsplit_once(':') {
+            Some((package, command)) if !package.is_empty() && !command.is_empty() => Self {
+                package: Some(package.to_string()),
+                command: command.to_string(),
+            },
+            _ => Self {
+                package: None,
+                command: target.to_string(),
+            },
+        }
+    }
+}
+
+/// Parses a `--map-command` value, `NAME=COMMAND`
+fn parse_command_alias(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, command)) if !name.is_empty() && !command.is_empty() => {
+            Ok((name.to_string(), command.to_string()))
+        }
+        _ => Err(format!("expected NAME=COMMAND, got \"{}\"", value)),
+    }
+}
+
+/// The command to run for `command_name`. The `--map-command` aliases win over the
+/// aliases of the config, and a name that is not an alias is run as is.
+fn resolve_command_alias(
+    command_name: &str,
+    map_command: &[(String, String)],
+    aliases: &BTreeMap<String, String>,
+) -> CommandTarget {
+    let target = map_command
+        .iter()
+        .rev()
+        .find(|(name, _)| name == command_name)
+        .map(|(_, target)| target)
+        .or_else(|| aliases.get(command_name));
+    match target {
+        Some(target) => CommandTarget::parse(target),
+        None => CommandTarget {
+            package: None,
+            command: command_name.to_string(),
+        },
+    }
+}
+
+/// Parses a `--env` value, `KEY=VALUE`
+fn parse_env_var(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got \"{}\"", value)),
+    }
+}
+
+/// The variables of `--env-file` followed by the ones of `--env`
+fn read_module_env(
+    env_file: Option<&Path>,
+    env: Vec<(String, String)>,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let mut module_env = vec![];
+    if let Some(env_file) = env_file {
+        let file_name = env_file.to_string_lossy().to_string();
+        let contents = fs::read_to_string(env_file)
+            .map_err(|e| RunError::CannotReadEnvFile(file_name.clone(), e.to_string()))?;
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let var = parse_env_var(line)
+                .map_err(|e| RunError::InvalidEnvFile(file_name.clone(), line_number + 1, e))?;
+            module_env.push(var);
+        }
+    }
+    module_env.extend(env);
+    Ok(module_env)
+}
+
+/// The host environment, without the variables that aren't unicode
+fn host_env() -> impl Iterator<Item = (String, String)> {
+    std::env::vars_os().filter_map(
+        |(key, value)| match (key.into_string(), value.into_string()) {
+            (Ok(key), Ok(value)) => Some((key, value)),
+            (key, _) => {
+                debug!("Not passing the host variable {:?}, it is not unicode", key);
+                None
+            }
+        },
+    )
+}
+
+/// The `--env` flags of the variables the module sees. It sees none of the host
+/// environment unless it is inherited, by name or by prefix, and `env` overrides the
+/// inherited variables. The `reserved` variables are never set.
+fn get_env_flags(
+    host_env: impl Iterator<Item = (String, String)>,
+    inherit_env: &[String],
+    inherit_prefixes: &[String],
+    inherit_all_env: bool,
+    env: &[(String, String)],
+    reserved: &[&str],
+) -> Vec<OsString> {
+    let mut module_env: BTreeMap<String, String> = host_env
+        .filter(|(key, _)| {
+            inherit_all_env
+                || inherit_env.contains(key)
+                || inherit_prefixes
+                    .iter()
+                    .any(|prefix| key.starts_with(prefix.as_str()))
+        })
+        .collect();
+    module_env.extend(env.iter().cloned());
+    module_env
+        .into_iter()
+        .filter(|(key, _)| {
+            if reserved.contains(&key.as_str()) {
+                warn!("Not setting {}, it is set from the package metadata", key);
+                return false;
+            }
+            true
+        })
+        .map(|(key, value)| OsString::from(format!("--env={}={}", key, value)))
+        .collect()
+}
+
+/// The help of `wasmer run`, which lists the flags the runtime takes
+#[cfg(not(target_os = "wasi"))]
+fn get_runtime_run_help(runtime: &str, runtime_args: &[String]) -> Result<String, RunError> {
+    let output = Command::new(runtime)
+        .args(runtime_args)
+        .args(["run", "--help"])
+        .output()
+        .map_err(|e| RunError::ProcessFailed(runtime.to_string(), format!("{:?}", e)))?;
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Checks that the runtime can enable every wasm feature the module requires and
+/// returns the flags that enable them.
+#[cfg(not(target_os = "wasi"))]
+fn get_wasm_feature_flags(
+    help_text: &str,
+    runtime: &str,
+    module_name: &str,
+    required_wasm_features: &[ModuleWasmFeature],
+) -> Result<Vec<OsString>, RunError> {
+    let mut flags = vec![];
+    for feature in required_wasm_features {
+        let flag = feature.runtime_flag();
+        if !help_text.contains(flag) {
+            return Err(RunError::UnsupportedWasmFeature(
+                module_name.to_string(),
+                feature.to_string(),
+                runtime.to_string(),
+            ));
+        }
+        flags.push(OsString::from(flag));
+    }
+    Ok(flags)
+}
+
+/// Checks that the runtime can give the module `wasi_version`
+#[cfg(not(target_os = "wasi"))]
+fn check_wasi_version(
+    help_text: &str,
+    runtime: &str,
+    wasi_version: WasiVersion,
+) -> Result<(), RunError> {
+    if help_text.contains("--wasi-version") && help_text.contains(wasi_version.name()) {
+        Ok(())
+    } else {
+        Err(RunError::UnsupportedWasiVersion(
+            wasi_version.to_string(),
+            runtime.to_string(),
+        ))
+    }
+}
+
+/// Checks that the ABI of the module allows every capability it requires, before
+/// anything is started
+fn check_required_abi(
+    module_name: &str,
+    module_is_wasi: bool,
+    required_features: &[RequiredFeature],
+) -> Result<(), RunError> {
+    match required_features
+        .iter()
+        .find(|feature| feature.needs_wasi())
+    {
+        Some(feature) if !module_is_wasi => Err(RunError::CapabilityNeedsWasi(
+            module_name.to_string(),
+            feature.to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the runtime has every capability the module requires and returns the
+/// flags that enable them.
+#[cfg(not(target_os = "wasi"))]
+fn get_required_feature_flags(
+    help_text: &str,
+    runtime: &str,
+    module_name: &str,
+    required_features: &[RequiredFeature],
+) -> Result<Vec<OsString>, RunError> {
+    let mut flags = vec![];
+    for feature in required_features {
+        let flag = feature.runtime_flag();
+        if !help_text.contains(flag) {
+            return Err(RunError::MissingCapability(
+                module_name.to_string(),
+                feature.to_string(),
+                runtime.to_string(),
+            ));
+        }
+        flags.push(OsString::from(flag));
+    }
+    Ok(flags)
+}
+
+fn create_run_command<P: AsRef<Path>, P2: AsRef<Path>>(
+    args: &[OsString],
+    wasmer_extra_flags: Option<Vec<OsString>>,
+    wasi_preopened_dir_flags: Vec<OsString>,
+    directory: P,
+    wasm_file_path: P2,
+    override_command_name: Option<String>,
+    prehashed_cache_key: Option<String>,
+) -> anyhow::Result<Vec<OsString>> {
+    let mut path = PathBuf::new();
+    path.push(directory);
+    path.push(wasm_file_path);
+    let path_string = path.into_os_string();
+    let command_vec = vec![path_string];
+    let override_command_name = override_command_name
+        .map(|cn| vec![OsString::from(format!("--command-name={}", cn))])
+        .unwrap_or_default();
+    let prehashed_cache_key_flag = prehashed_cache_key
+        .map(|pck| vec![OsString::from(format!("--cache-key=\"{}\"", pck))])
+        .unwrap_or_default();
+
+    // NOTE:
+    // for optional types, use an empty vec here:
+    // an empty OsString may pass empty args to the child program which can cause issues
+    Ok([
+        command_vec.as_slice(),
+        &override_command_name,
+        &wasi_preopened_dir_flags,
+        wasmer_extra_flags.as_deref().unwrap_or_default(),
+        &prehashed_cache_key_flag,
+        &[OsString::from("--")],
+        args,
+    ]
+    .concat())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::abi::Abi;
+    use crate::commands::run::{
+        check_installed_version, check_preloaded_exports, check_required_abi, check_wasi_version,
+        compose_args, create_run_command, detect_abi, do_run, error_exit_code, get_command_args,
+        get_cwd_preopen_flag, get_default_command_name, get_env_flags, get_metadata_env_flags,
+        get_required_feature_flags, get_wasm_file_location, mount_secrets, parse_command_alias,
+        parse_env_var, parse_secret, read_module_env, resolve_command_alias, CommandTarget,
+        ReportOutput, RunError, RunOpt, RunSettings, RunTimings, METADATA_ENV_VARS,
+    };
+    use crate::config::Config;
+    use crate::data::manifest::{Manifest, PACKAGES_DIR_NAME};
+    use crate::data::manifest_extensions::{RequiredFeature, WasiVersion};
+    use crate::dataflow::find_command_result::CommandPackage;
+    use crate::dataflow::installed_packages::PackageFormat;
+    #[cfg(unix)]
+    use crate::signals::test::SIGNAL_HANDLERS;
+    use crate::util::create_temp_dir;
+    use lazy_static::lazy_static;
+    use semver::Version;
+    use std::ffi::OsString;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+    use structopt::clap::ErrorKind;
+    use structopt::StructOpt;
+
+    lazy_static! {
+        /// Held by the tests that stand in for the runtime with `WAPM_RUNTIME_ENV_KEY`
+        static ref RUNTIME_ENV: Mutex<()> = Mutex::new(());
+    }
+
+    #[test]
+    fn create_run_command_vec() {
+        let args: Vec<OsString> = vec![OsString::from("arg1"), OsString::from("arg2")];
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &std::path::Path = tmp_dir.as_ref();
+        let wapm_module_dir = dir.join(
+            [PACKAGES_DIR_NAME, "_", "foo@1.0.2"]
+                .iter()
+                .collect::<PathBuf>(),
+        );
+        fs::create_dir_all(&wapm_module_dir).unwrap();
+        let expected_dir = wapm_module_dir.join("foo_entry.wasm");
+        let expected_command = vec![
+            expected_dir.into_os_string(),
+            OsString::from("--"),
+            OsString::from("arg1"),
+            OsString::from("arg2"),
+        ];
+        let wasm_relative_path: PathBuf = ["wapm_packages", "_", "foo@1.0.2", "foo_entry.wasm"]
+            .iter()
+            .collect();
+        let actual_command =
+            create_run_command(&args, None, vec![], dir, wasm_relative_path, None, None).unwrap();
+        assert_eq!(expected_command, actual_command);
+    }
+
+    #[test]
+    fn package_metadata_is_passed_as_env_vars() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "_/cowsay"
+version = "0.2.1"
+description = "cowsay"
+"#,
+        )
+        .unwrap();
+        let flags = get_metadata_env_flags(&manifest.package, "cowthink");
+        assert_eq!(
+            flags,
+            vec![
+                OsString::from("--env=WAPM_PACKAGE_NAME=_/cowsay"),
+                OsString::from("--env=WAPM_PACKAGE_VERSION=0.2.1"),
+                OsString::from("--env=WAPM_COMMAND_NAME=cowthink"),
+            ]
+        );
+    }
+
+    fn parse_error_kind(args: &[&str]) -> Option<ErrorKind> {
+        RunOpt::from_iter_safe(["run", "cowsay"].iter().chain(args).copied())
+            .err()
+            .map(|e| e.kind)
+    }
+
+    #[test]
+    fn stdin_null_conflicts_with_the_terminal_flags() {
+        assert_eq!(parse_error_kind(&["--stdin-null"]), None);
+        assert_eq!(
+            parse_error_kind(&["--stdin-null", "--no-tty"]),
+            Some(ErrorKind::ArgumentConflict)
+        );
+        assert_eq!(
+            parse_error_kind(&["--stdin-null", "--interactive"]),
+            Some(ErrorKind::ArgumentConflict)
+        );
+    }
+
+    #[test]
+    fn inherit_all_env_conflicts_with_the_other_env_flags() {
+        assert_eq!(parse_error_kind(&["--inherit-all-env"]), None);
+        assert_eq!(
+            parse_error_kind(&["--inherit-all-env", "--env-passthrough-prefix", "AWS_"]),
+            Some(ErrorKind::ArgumentConflict)
+        );
+        assert_eq!(
+            parse_error_kind(&["--inherit-all-env", "--inherit-env", "HOME"]),
+            Some(ErrorKind::ArgumentConflict)
+        );
+    }
+
+    #[test]
+    fn only_inherited_host_vars_reach_the_module() {
+        let host_env = || {
+            vec![
+                ("HOME".to_string(), "/home/user".to_string()),
+                ("AWS_SECRET_ACCESS_KEY".to_string(), "hunter2".to_string()),
+                ("TERM".to_string(), "xterm".to_string()),
+                ("WAPM_COMMAND_NAME".to_string(), "other".to_string()),
+            ]
+            .into_iter()
+        };
+        let env = |flags: Vec<OsString>| -> Vec<String> {
+            flags
+                .into_iter()
+                .map(|flag| flag.into_string().unwrap())
+                .collect()
+        };
+
+        assert!(get_env_flags(host_env(), &[], &[], false, &[], &[]).is_empty());
+        assert_eq!(
+            env(get_env_flags(
+                host_env(),
+                &["TERM".to_string()],
+                &[],
+                false,
+                &[],
+                &[]
+            )),
+            vec!["--env=TERM=xterm"]
+        );
+        // the explicit variables override the inherited ones
+        let explicit = vec![
+            parse_env_var("HOME=/tmp").unwrap(),
+            parse_env_var("EMPTY=").unwrap(),
+        ];
+        assert_eq!(
+            env(get_env_flags(
+                host_env(),
+                &["HOME".to_string(), "MISSING".to_string()],
+                &[],
+                false,
+                &explicit,
+                &[]
+            )),
+            vec!["--env=EMPTY=", "--env=HOME=/tmp"]
+        );
+        // but never the package metadata
+        assert_eq!(
+            env(get_env_flags(
+                host_env(),
+                &[],
+                &[],
+                true,
+                &[],
+                &METADATA_ENV_VARS
+            )),
+            vec![
+                "--env=AWS_SECRET_ACCESS_KEY=hunter2",
+                "--env=HOME=/home/user",
+                "--env=TERM=xterm",
+            ]
+        );
+        assert!(parse_env_var("=value").is_err());
+        assert!(parse_env_var("KEY").is_err());
+    }
+
+    #[test]
+    fn config_aliases_and_map_command() {
+        let config: Config = toml::from_str(
+            r#"
+[registry]
+url = "https://registry.wapm.io/graphql"
+
+[run.aliases]
+py = "python"
+build = "_/toolchain:compile"
+"#,
+        )
+        .unwrap();
+        let aliases = &config.run.aliases;
+        assert_eq!(
+            resolve_command_alias("py", &[], aliases),
+            CommandTarget {
+                package: None,
+                command: "python".to_string(),
+            }
+        );
+        assert_eq!(
+            resolve_command_alias("build", &[], aliases),
+            CommandTarget {
+                package: Some("_/toolchain".to_string()),
+                command: "compile".to_string(),
+            }
+        );
+        assert_eq!(
+            resolve_command_alias("ls", &[], aliases).command,
+            "ls".to_string()
+        );
+
+        // a one-shot alias wins over the config
+        let map_command = vec![parse_command_alias("build=other/toolchain:make").unwrap()];
+        assert_eq!(
+            resolve_command_alias("build", &map_command, aliases),
+            CommandTarget {
+                package: Some("other/toolchain".to_string()),
+                command: "make".to_string(),
+            }
+        );
+        assert_eq!(
+            resolve_command_alias("py", &map_command, aliases).command,
+            "python"
+        );
+        assert!(parse_command_alias("build=").is_err());
+        assert!(parse_command_alias("build").is_err());
+    }
+
+    #[test]
+    fn env_passthrough_prefixes_filter_host_env() {
+        let host_env = vec![
+            ("APP_LEVEL".to_string(), "debug".to_string()),
+            ("APP_NAME".to_string(), "app".to_string()),
+            ("APPLE".to_string(), "pie".to_string()),
+            ("HOME".to_string(), "/home/user".to_string()),
+            ("OTHER_PORT".to_string(), "80".to_string()),
+        ];
+        let flags = get_env_flags(
+            host_env.into_iter(),
+            &[],
+            &["APP_".to_string(), "OTHER_".to_string()],
+            false,
+            &[parse_env_var("APP_NAME=override").unwrap()],
+            &[],
+        );
+        assert_eq!(
+            flags,
+            vec![
+                OsString::from("--env=APP_LEVEL=debug"),
+                OsString::from("--env=APP_NAME=override"),
+                OsString::from("--env=OTHER_PORT=80"),
+            ]
+        );
+    }
+
+    #[test]
+    fn env_file_is_overridden_by_env_flags() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let env_file = tmp_dir.path().join(".env");
+        fs::write(&env_file, "# comment\nLEVEL=debug\n\nNAME=a=b\n").unwrap();
+        let env =
+            read_module_env(Some(&env_file), vec![parse_env_var("LEVEL=info").unwrap()]).unwrap();
+        let flags = get_env_flags(std::iter::empty(), &[], &[], false, &env, &[]);
+        assert_eq!(
+            flags,
+            vec![
+                OsString::from("--env=LEVEL=info"),
+                OsString::from("--env=NAME=a=b"),
+            ]
+        );
+
+        fs::write(&env_file, "LEVEL=debug\nverbose\n").unwrap();
+        let error = read_module_env(Some(&env_file), vec![]).unwrap_err();
+        assert!(error.to_string().starts_with("Line 2 of the env file"));
+    }
+
+    #[test]
+    fn timings_table_lists_phases_and_total() {
+        let mut timings = RunTimings::new(true);
+        timings.end_phase("resolve");
+        timings.end_phase("install");
+        let table = timings.table().unwrap();
+        let phases: Vec<&str> = table
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(phases, vec!["resolve", "install", "total"]);
+        assert!(table.lines().all(|line| line.ends_with("ms")));
+
+        let mut timings = RunTimings::new(false);
+        timings.end_phase("resolve");
+        assert!(timings.table().is_none());
+    }
+
+    #[test]
+    fn cwd_is_mapped_to_the_module_current_directory() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &std::path::Path = tmp_dir.as_ref();
+        let module_cwd = dir.join("data");
+        fs::create_dir_all(&module_cwd).unwrap();
+
+        let flag = get_cwd_preopen_flag(&module_cwd).unwrap();
+        let expected = format!(
+            "--mapdir=.:{}",
+            module_cwd.canonicalize().unwrap().to_string_lossy()
+        );
+        assert_eq!(flag, OsString::from(expected));
+
+        assert!(get_cwd_preopen_flag(&dir.join("missing")).is_err());
+    }
+
+    #[test]
+    fn installed_version_must_match_the_lockfile() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let manifest_dir = tmp_dir.path();
+        let write_version = |version: &str| {
+            fs::write(
+                manifest_dir.join("wapm.toml"),
+                format!(
+                    "[package]\nname = \"_/cowsay\"\nversion = \"{}\"\ndescription = \"\"\n",
+                    version
+                ),
+            )
+            .unwrap()
+        };
+        let package = CommandPackage {
+            name: "_/cowsay".to_string(),
+            version: Version::new(0, 2, 1),
+            is_local_package: false,
+            format: PackageFormat::TarGz,
+        };
+
+        write_version("0.2.1");
+        assert!(check_installed_version(manifest_dir, &package).is_none());
+        // the manifest of the installed package was tampered with
+        write_version("0.3.0");
+        let mismatch = check_installed_version(manifest_dir, &package).unwrap();
+        assert_eq!(
+            mismatch.to_string(),
+            "The installed package \"_/cowsay\" is at version 0.3.0, but the lockfile has \
+             0.2.1. Run `wapm install` to install it again."
+        );
+        // the package being worked on isn't installed
+        let local_package = CommandPackage {
+            is_local_package: true,
+            ..package
+        };
+        assert!(check_installed_version(manifest_dir, &local_package).is_none());
+    }
+
+    #[test]
+    fn command_args_are_composed_with_the_run_args() {
+        let strings =
+            |args: &[&str]| -> Vec<String> { args.iter().map(|a| a.to_string()).collect() };
+        let os_strings =
+            |args: &[&str]| -> Vec<OsString> { args.iter().map(OsString::from).collect() };
+        let run_args = os_strings(&["input.txt", "-v"]);
+
+        // the run arguments come after the ones of the command
+        assert_eq!(
+            compose_args(&strings(&["--color", "never"]), &run_args),
+            os_strings(&["--color", "never", "input.txt", "-v"])
+        );
+        // or in place of the placeholder
+        assert_eq!(
+            compose_args(&strings(&["--in", "$@", "--out", "out.txt"]), &run_args),
+            os_strings(&["--in", "input.txt", "-v", "--out", "out.txt"])
+        );
+        assert_eq!(
+            compose_args(&strings(&["$ARGS", "--quiet"]), &run_args),
+            os_strings(&["input.txt", "-v", "--quiet"])
+        );
+        assert_eq!(compose_args(&[], &run_args), run_args);
+
+        // `main_args` is the fallback of `args`
+        assert_eq!(
+            get_command_args(Some("--flag $@"), vec![]),
+            strings(&["--flag", "$@"])
+        );
+        assert_eq!(
+            get_command_args(Some("--flag $@"), strings(&["--other"])),
+            strings(&["--other"])
+        );
+        assert!(get_command_args(None, vec![]).is_empty());
+    }
+
+    #[test]
+    fn default_command_name() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir = tmp_dir.as_ref();
+        let write_manifest = |defaults: &[bool]| {
+            let mut manifest = String::from(
+                "[package]\nname = \"test\"\nversion = \"1.0.0\"\ndescription = \"test\"\n",
+            );
+            for (i, default) in defaults.iter().enumerate() {
+                manifest.push_str(&format!(
+                    "\n[[command]]\nname = \"cmd{}\"\nmodule = \"m\"\ndefault = {}\n",
+                    i, default
+                ));
+            }
+            fs::write(dir.join("wapm.toml"), manifest).unwrap();
+        };
+
+        write_manifest(&[false, false]);
+        assert!(get_default_command_name(dir).is_err());
+
+        write_manifest(&[false, true]);
+        assert_eq!(get_default_command_name(dir).unwrap(), "cmd1");
+
+        write_manifest(&[true, true]);
+        assert!(get_default_command_name(dir).is_err());
+    }
+
+    #[test]
+    fn commands_to_choose_from_have_their_descriptions() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir = tmp_dir.as_ref();
+        fs::write(
+            dir.join("wapm.toml"),
+            r#"[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[[command]]
+name = "serve"
+module = "m"
+description = "Serves the current directory"
+
+[[command]]
+name = "sh"
+module = "m"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            get_default_command_name(dir).unwrap_err().to_string(),
+            "No command name was given and no command in the manifest is marked `default = true`. \
+             Run one of the commands of the package:\n    \
+             serve  Serves the current directory\n    \
+             sh"
+        );
+    }
+
+    #[test]
+    fn available_commands_are_listed_without_a_command_name() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir = tmp_dir.as_ref();
+        fs::write(
+            dir.join("wapm.toml"),
+            r#"[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[[command]]
+name = "build"
+module = "m"
+description = "Builds the site"
+
+[[command]]
+name = "serve"
+module = "m"
+description = "Serves the site"
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("wapm.lock"),
+            r#"# Lockfile v4
+[modules]
+
+[commands.cowsay]
+name = "cowsay"
+package_name = "_/cowsay"
+package_version = "0.2.1"
+module = "cowsay"
+is_top_level_dependency = true
+description = "Prints a cow"
+
+[commands.serve]
+name = "serve"
+package_name = "_/http-server"
+package_version = "1.0.0"
+module = "server"
+is_top_level_dependency = true
+"#,
+        )
+        .unwrap();
+        // without a terminal to choose on, the commands are listed in the error
+        assert_eq!(
+            get_default_command_name(dir).unwrap_err().to_string(),
+            "No command name was given and no command in the manifest is marked `default = true`. \
+             Run one of the commands of the package:\n    \
+             build   Builds the site\n    \
+             serve   Serves the site\n    \
+             cowsay  Prints a cow"
+        );
+    }
+
+    #[test]
+    fn run_bare_wasm_file_with_args() {
+        // a module importing `"wasi_snapshot_preview1" "proc_exit"` with the signature `(param i32)`
+        let wasm: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x05, 0x01, 0x60, 0x01, 0x7f, 0x00, // type section
+            0x02, 0x24, 0x01, 0x16, b'w', b'a', b's', b'i', b'_', b's', b'n', b'a', b'p', b's',
+            b'h', b'o', b't', b'_', b'p', b'r', b'e', b'v', b'i', b'e', b'w', b'1', 0x09, b'p',
+            b'r', b'o', b'c', b'_', b'e', b'x', b'i', b't', 0x00, 0x00, // import section
+        ];
+        assert!(matches!(detect_abi(wasm), Abi::Wasi));
+        assert!(matches!(detect_abi(&wasm[..15]), Abi::None));
+
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir = tmp_dir.as_ref().canonicalize().unwrap();
+        fs::write(dir.join("hello.wasm"), wasm).unwrap();
+        let (run_dir, source, name) = get_wasm_file_location(&dir.join("hello.wasm")).unwrap();
+        assert_eq!(run_dir, dir);
+        assert_eq!(source, PathBuf::from("hello.wasm"));
+        assert_eq!(name, "hello");
+        assert!(get_wasm_file_location(&dir.join("missing.wasm")).is_err());
+
+        let args = vec![OsString::from("--flag"), OsString::from("value")];
+        let command =
+            create_run_command(&args, None, vec![], &run_dir, &source, None, None).unwrap();
+        assert_eq!(
+            command,
+            vec![
+                dir.join("hello.wasm").into_os_string(),
+                OsString::from("--"),
+                OsString::from("--flag"),
+                OsString::from("value"),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn json_output_reports_the_result_of_the_run() {
+        use crate::constants::WAPM_RUNTIME_ENV_KEY;
+        use crate::dataflow::find_command_result::CommandPackage;
+
+        let _runtime_env = RUNTIME_ENV.lock().unwrap();
+        let _signal_handlers = SIGNAL_HANDLERS.lock().unwrap();
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir = tmp_dir.path().to_path_buf();
+        fs::write(dir.join("hello.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        let result_path = dir.join("result.json");
+        // `true` stands in for the runtime, so the module exits with 0
+        std::env::set_var(WAPM_RUNTIME_ENV_KEY, "true");
+        let run = do_run(
+            dir,
+            PathBuf::from("hello.wasm"),
+            None,
+            "hello",
+            "hello",
+            &[],
+            &[],
+            None,
+            RunSettings {
+                json_output: Some(ReportOutput::File(result_path.clone())),
+                package: Some(CommandPackage {
+                    name: "_/hello".to_string(),
+                    version: semver::Version::new(1, 2, 0),
+                    is_local_package: false,
+                    format: PackageFormat::TarGz,
+                }),
+                ..RunSettings::default()
+            },
+        );
+        std::env::remove_var(WAPM_RUNTIME_ENV_KEY);
+        run.unwrap();
+
+        let result: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&result_path).unwrap()).unwrap();
+        assert_eq!(result["exit_code"], 0);
+        assert!(result["duration_ms"].is_u64());
+        assert_eq!(result["command"], "hello");
+        assert_eq!(result["package"], "_/hello");
+        assert_eq!(result["version"], "1.2.0");
+
+        assert_eq!(
+            ReportOutput::from_options(true, None),
+            Some(ReportOutput::Stderr)
+        );
+        assert_eq!(ReportOutput::from_options(false, None), None);
+    }
+
+    #[test]
+    fn secrets_are_mounted_without_their_directory() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let host_dir = tmp_dir.path().join("host");
+        fs::create_dir(&host_dir).unwrap();
+        fs::write(host_dir.join("secret.txt"), "hunter2").unwrap();
+        fs::write(host_dir.join("other-secret.txt"), "hunter3").unwrap();
+        let secrets_dir = tmp_dir.path().join("secrets");
+
+        let secret = parse_secret(&format!("token={}", host_dir.join("secret.txt").display()));
+        let flag = mount_secrets(&[secret.unwrap()], &secrets_dir).unwrap();
+        assert_eq!(
+            flag,
+            OsString::from(format!("--mapdir=/run/secrets:{}", secrets_dir.display()))
+        );
+        // the module sees the secret and only the secret
+        let visible: Vec<_> = fs::read_dir(&secrets_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(visible, vec![OsString::from("token")]);
+        assert_eq!(
+            fs::read_to_string(secrets_dir.join("token")).unwrap(),
+            "hunter2"
+        );
+        #[cfg(unix)]
+        assert!(fs::metadata(secrets_dir.join("token"))
+            .unwrap()
+            .permissions()
+            .readonly());
+
+        let missing = ("missing".to_string(), host_dir.join("missing.txt"));
+        assert!(mount_secrets(&[missing], &tmp_dir.path().join("other")).is_err());
+        assert!(parse_secret("token").is_err());
+        assert!(parse_secret("../token=/etc/passwd").is_err());
+    }
+
+    #[test]
+    fn interrupted_runs_exit_with_the_signal_code() {
+        let interrupted = RunError::Interrupted("wasmer".to_string(), 130).into();
+        assert_eq!(error_exit_code(&interrupted), 130);
+        let failed = RunError::NoDefaultCommand(String::new()).into();
+        assert_eq!(error_exit_code(&failed), -1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stdin_null_gives_the_module_the_end_of_its_input() {
+        use crate::constants::WAPM_RUNTIME_ENV_KEY;
+
+        let _runtime_env = RUNTIME_ENV.lock().unwrap();
+        let _signal_handlers = SIGNAL_HANDLERS.lock().unwrap();
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir = tmp_dir.path().to_path_buf();
+        // `sh` stands in for the runtime and runs the module as a script that reads
+        // stdin to the end
+        fs::write(
+            dir.join("reader.wasm"),
+            "cat > \"$(dirname \"$0\")/input.txt\"\n",
+        )
+        .unwrap();
+        std::env::set_var(WAPM_RUNTIME_ENV_KEY, "sh");
+        let run = do_run(
+            dir.clone(),
+            PathBuf::from("reader.wasm"),
+            None,
+            "reader",
+            "reader",
+            &[],
+            &[],
+            None,
+            RunSettings {
+                stdin_null: true,
+                ..RunSettings::default()
+            },
+        );
+        std::env::remove_var(WAPM_RUNTIME_ENV_KEY);
+        run.unwrap();
+        assert_eq!(fs::read(dir.join("input.txt")).unwrap(), b"");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn temp_dirs_are_removed_unless_kept() {
+        use crate::constants::WAPM_RUNTIME_ENV_KEY;
+
+        let _runtime_env = RUNTIME_ENV.lock().unwrap();
+        let _signal_handlers = SIGNAL_HANDLERS.lock().unwrap();
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir = tmp_dir.path().to_path_buf();
+        fs::write(dir.join("secret.txt"), "hunter2").unwrap();
+        // `sh` stands in for the runtime and runs the module as a script that notes
+        // where the secrets were copied to
+        fs::write(
+            dir.join("secrets.wasm"),
+            "for arg in \"$@\"; do\n\
+             case \"$arg\" in --mapdir=/run/secrets:*) \
+             printf %s \"${arg#--mapdir=/run/secrets:}\" > \"$(dirname \"$0\")/secrets_dir.txt\";; \
+             esac\n\
+             done\n",
+        )
+        .unwrap();
+        std::env::set_var(WAPM_RUNTIME_ENV_KEY, "sh");
+        let run = |keep_temp| {
+            do_run(
+                dir.clone(),
+                PathBuf::from("secrets.wasm"),
+                None,
+                "secrets",
+                "secrets",
+                &[],
+                &[],
+                None,
+                RunSettings {
+                    secrets: vec![("token".to_string(), dir.join("secret.txt"))],
+                    keep_temp,
+                    ..RunSettings::default()
+                },
+            )
+            .map(|_| PathBuf::from(fs::read_to_string(dir.join("secrets_dir.txt")).unwrap()))
+        };
+        let removed = run(false);
+        let kept = run(true);
+        std::env::remove_var(WAPM_RUNTIME_ENV_KEY);
+
+        assert!(!removed.unwrap().exists());
+        let kept = kept.unwrap();
+        assert_eq!(fs::read_to_string(kept.join("token")).unwrap(), "hunter2");
+        fs::remove_dir_all(kept).unwrap();
+    }
+
+    #[test]
+    fn webc_containers_are_run_as_they_are() {
+        let webc_package = || {
+            Some(CommandPackage {
+                name: "_/bar".to_string(),
+                version: Version::new(1, 0, 0),
+                is_local_package: false,
+                format: PackageFormat::Webc,
+            })
+        };
+        let run = |settings| {
+            do_run(
+                PathBuf::from("."),
+                PathBuf::from("package.webc"),
+                None,
+                "bar",
+                "bar",
+                &[],
+                &[],
+                None,
+                settings,
+            )
+            .unwrap_err()
+            .downcast::<RunError>()
+            .unwrap()
+            .to_string()
+        };
+        assert_eq!(
+            run(RunSettings {
+                max_memory: Some(1 << 20),
+                package: webc_package(),
+                ..RunSettings::default()
+            }),
+            "The module \"bar\" is a webc container, which can't be run with `--max-memory`."
+        );
+        assert_eq!(
+            run(RunSettings {
+                preload: vec![PathBuf::from("lib.wasm")],
+                package: webc_package(),
+                ..RunSettings::default()
+            }),
+            "The module \"bar\" is a webc container, which can't be run with `--preload`."
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn allowed_exit_codes_are_not_failures() {
+        use crate::constants::WAPM_RUNTIME_ENV_KEY;
+
+        let _runtime_env = RUNTIME_ENV.lock().unwrap();
+        let _signal_handlers = SIGNAL_HANDLERS.lock().unwrap();
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir = tmp_dir.path().to_path_buf();
+        fs::write(dir.join("grep.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        let run = |allowed_exit_codes: Vec<i32>| {
+            do_run(
+                dir.clone(),
+                PathBuf::from("grep.wasm"),
+                None,
+                "grep",
+                "grep",
+                &[],
+                &[],
+                None,
+                RunSettings {
+                    allowed_exit_codes,
+                    ..RunSettings::default()
+                },
+            )
+        };
+        // `false` stands in for the runtime, so the module exits with 1
+        std::env::set_var(WAPM_RUNTIME_ENV_KEY, "false");
+        let failed = run(vec![]);
+        let allowed = run(vec![1, 2]);
+        let other_code = run(vec![2]);
+        std::env::remove_var(WAPM_RUNTIME_ENV_KEY);
+
+        assert_eq!(error_exit_code(&failed.unwrap_err()), 1);
+        allowed.unwrap();
+        assert_eq!(error_exit_code(&other_code.unwrap_err()), 1);
+    }
+
+    #[test]
+    fn preloaded_modules_must_export_the_imports() {
+        let type_section: &[u8] = &[0x01, 0x04, 0x01, 0x60, 0x00, 0x00];
+        // imports `"libruby" "rb_eval"` and `"wasi_unstable" "proc_exit"`
+        let main_wasm = [
+            &b"\0asm\x01\0\0\0"[..],
+            type_section,
+            &[0x02, 0x2d, 0x02, 0x07],
+            b"libruby",
+            &[0x07],
+            b"rb_eval",
+            &[0x00, 0x00, 0x0d],
+            b"wasi_unstable",
+            &[0x09],
+            b"proc_exit",
+            &[0x00, 0x00],
+        ]
+        .concat();
+        // exports `rb_eval`
+        let library_wasm = [
+            &b"\0asm\x01\0\0\0"[..],
+            type_section,
+            &[0x03, 0x02, 0x01, 0x00],
+            &[0x07, 0x0b, 0x01, 0x07],
+            b"rb_eval",
+            &[0x00, 0x00],
+            &[0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b],
+        ]
+        .concat();
+
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &std::path::Path = tmp_dir.as_ref();
+        fs::write(dir.join("libruby.wasm"), &library_wasm).unwrap();
+        fs::write(dir.join("other.wasm"), &library_wasm).unwrap();
+
+        check_preloaded_exports("ruby", &main_wasm, &[dir.join("libruby.wasm")]).unwrap();
+        for preload in &[vec![], vec![dir.join("other.wasm")]] {
+            let error = check_preloaded_exports("ruby", &main_wasm, preload).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "The module \"ruby\" imports libruby.rb_eval, which the preloaded modules don't export."
+            );
+        }
+        assert!(check_preloaded_exports("ruby", &main_wasm, &[dir.join("missing.wasm")]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn wasi_version_override_is_given_to_the_runtime() {
+        use crate::constants::WAPM_RUNTIME_ENV_KEY;
+
+        let help_text =
+            "        --wasi-version <VERSION>    The WASI version [possible values: preview1]\n";
+        assert!(check_wasi_version(help_text, "wasmer", WasiVersion::Preview1).is_ok());
+        assert_eq!(
+            check_wasi_version(help_text, "wasmer", WasiVersion::Preview2)
+                .unwrap_err()
+                .to_string(),
+            "WASI preview2 is not supported by your wasmer build."
+        );
+        assert!(check_wasi_version("", "wasmer", WasiVersion::Preview1).is_err());
+
+        let _runtime_env = RUNTIME_ENV.lock().unwrap();
+        let _signal_handlers = SIGNAL_HANDLERS.lock().unwrap();
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir = tmp_dir.path().to_path_buf();
+        // `sh` stands in for the runtime and runs the module as a script that reports
+        // the WASI version it was given, `default` for the one of its imports
+        fs::write(
+            dir.join("version.wasm"),
+            "version=default\n\
+             for arg in \"$@\"; do\n\
+             case \"$arg\" in --wasi-version=*) version=\"${arg#--wasi-version=}\";; esac\n\
+             done\n\
+             printf %s \"$version\" > \"$(dirname \"$0\")/version.txt\"\n",
+        )
+        .unwrap();
+        std::env::set_var(WAPM_RUNTIME_ENV_KEY, "sh");
+        let run = |wasi_version| {
+            do_run(
+                dir.clone(),
+                PathBuf::from("version.wasm"),
+                None,
+                "version",
+                "version",
+                &[],
+                &[],
+                None,
+                RunSettings {
+                    wasi_version,
+                    ..RunSettings::default()
+                },
+            )
+            .map(|_| fs::read_to_string(dir.join("version.txt")).unwrap())
+        };
+        let detected = run(None);
+        let overridden = run(Some(WasiVersion::Preview2));
+        std::env::remove_var(WAPM_RUNTIME_ENV_KEY);
+
+        assert_eq!(detected.unwrap(), "default");
+        assert_eq!(overridden.unwrap(), "preview2");
+    }
+
+    #[test]
+    fn required_features_need_the_runtime_and_the_abi() {
+        let help_text = "OPTIONS:\n        --enable-threads    Enable support for the threads proposal\n        \
+                         --net               Enable networking with the host\n";
+        let required_features = [RequiredFeature::Threads, RequiredFeature::WasiNetworking];
+        assert_eq!(
+            get_required_feature_flags(help_text, "wasmer", "server", &required_features).unwrap(),
+            vec![OsString::from("--enable-threads"), OsString::from("--net")]
+        );
+
+        let error = get_required_feature_flags(
+            help_text,
+            "wasmer",
+            "server",
+            &[RequiredFeature::MultiMemory],
+        )
+        .unwrap_err();
+        assert!(matches!(error, RunError::MissingCapability(..)));
+        assert_eq!(
+            error.to_string(),
+            "The module \"server\" requires the multi-memory capability, which your wasmer build is missing."
+        );
+
+        check_required_abi("server", true, &required_features).unwrap();
+        check_required_abi("server", false, &[RequiredFeature::Threads]).unwrap();
+        let error = check_required_abi("server", false, &required_features).unwrap_err();
+        assert!(matches!(error, RunError::CapabilityNeedsWasi(..)));
+        assert_eq!(
+            error.to_string(),
+            "The module \"server\" requires the WASI networking capability, which only WASI modules can have."
+        );
+    }
+}
+
+#[derive(Debug, Error)]
+enum RunError {
+    #[error("Failed to run command \"{0}\". {1}")]
+    CannotRegenLockfile(String, dataflow::Error),
+    #[error(
+        "The command \"{0}\" for module \"{1}\" is defined but the source at \"{2}\" does not exist.",
+    )]
+    SourceForCommandNotFound(String, String, String),
+    #[error("Failed to run {0}: {1}")]
+    ProcessFailed(String, String),
+    #[error("The module \"{0}\" requires {1} which your {2} build doesn't support.")]
+    UnsupportedWasmFeature(String, String, String),
+    #[error("The module \"{0}\" requires the {1} capability, which your {2} build is missing.")]
+    MissingCapability(String, String, String),
+    #[error("{0} is not supported by your {1} build.")]
+    UnsupportedWasiVersion(String, String),
+    #[error("The module \"{0}\" requires the {1} capability, which only WASI modules can have.")]
+    CapabilityNeedsWasi(String, String),
+    #[error("The working directory \"{0}\" does not exist or is not a directory.")]
+    WorkingDirectoryNotFound(String),
+    #[error(
+        "No command name was given and no command in the manifest is marked `default = true`.{0}"
+    )]
+    NoDefaultCommand(String),
+    #[error("The module \"{0}\" imports {1}, which the preloaded modules don't export.")]
+    UnresolvedImports(String, String),
+    #[error("Your {0} build can't link preloaded modules.")]
+    PreloadNotSupported(String),
+    #[error("The module \"{0}\" is a webc container, which can't be run with `{1}`.")]
+    NotSupportedForWebc(String, &'static str),
+    #[error("The wasm file \"{0}\" does not exist.")]
+    WasmFileNotFound(String),
+    #[error("\"{0}\" is not a WASI module, so it can't be given directories. Pass `--abi wasi` if it is one.")]
+    PreopenWithoutWasi(String),
+    #[error("{0} was interrupted.")]
+    Interrupted(String, i32),
+    #[error("The module \"{0}\" exited with code {1}.")]
+    ModuleFailed(String, i32),
+    #[error("The secret \"{0}\" can't be mounted, \"{1}\" is not a file.")]
+    SecretNotFound(String, String),
+    #[error("The secret \"{0}\" is mounted more than once.")]
+    DuplicateSecret(String),
+    #[error("Could not read the env file \"{0}\". {1}")]
+    CannotReadEnvFile(String, String),
+    #[error("Line {1} of the env file \"{0}\" is invalid: {2}")]
+    InvalidEnvFile(String, usize, String),
+    #[error("The memory of the module \"{0}\" can't be limited to {1}: {2}.")]
+    CannotLimitMemory(String, String, String),
+    #[error("The module \"{0}\" exceeded the memory limit of {1}.")]
+    MemoryLimitExceeded(String, String),
+    #[error("The alias runs the command \"{0}\" of \"{1}\", but \"{0}\" is the command of {2}.")]
+    AliasedCommandNotInPackage(String, String, String),
+    #[error("`--trace-fs` runs the runtime under strace, which {0}. Run the command without `--trace-fs`.")]
+    TracerNotAvailable(&'static str),
+    #[error("The installed package \"{0}\" is at version {1}, but the lockfile has {2}. Run `wapm install` to install it again.")]
+    InstalledVersionMismatch(String, String, String),
 }
@@ -1,12 +1,21 @@
 //! Code pertaining to the `search` subcommand, which queries the server about
 //! the specified package.
 
+use crate::config::Config;
+use crate::dataflow::lockfile_packages::{LockfilePackages, LockfileResult};
+use crate::dataflow::{normalize_global_namespace_package_name, PackageKey};
 use crate::graphql::execute_query;
 
+use chrono::{Duration, Utc};
 use graphql_client::*;
 
 use prettytable::format;
 use prettytable::Table;
+use semver::Version;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
 use structopt::StructOpt;
 
 /// Options for the `search` subcommand
@@ -14,8 +23,21 @@ use structopt::StructOpt;
 pub struct SearchOpt {
     #[structopt(parse(from_str))]
     query: String,
+    /// Show the installed version of each result, read from the lockfile of the current directory
+    #[structopt(long = "installed")]
+    installed: bool,
+    /// Query the registry again instead of showing the results cached by a recent search
+    #[structopt(long = "refresh")]
+    refresh: bool,
 }
 
+/// The file in the config folder the results of recent searches are cached in
+static SEARCH_CACHE_FILE_NAME: &str = "search_cache.json";
+/// How long the results of a search are shown again without querying the registry
+const SEARCH_CACHE_SECONDS: i64 = 5 * 60;
+/// How many packages are suggested when a search finds nothing
+const MAX_SUGGESTIONS: usize = 3;
+
 type DateTime = String;
 
 #[derive(GraphQLQuery)]
@@ -28,34 +50,385 @@ struct SearchQuery;
 
 /// Run the search command
 pub fn search(options: SearchOpt) -> anyhow::Result<()> {
+    let installed_versions = if options.installed {
+        let current_directory = crate::config::Config::get_current_dir()?;
+        get_installed_versions(&current_directory)?
+    } else {
+        None
+    };
     let query = options.query;
+    let config = Config::from_file()?;
+    let cache_path = Config::get_folder()?.join(SEARCH_CACHE_FILE_NAME);
+    let refresh = options.refresh;
+    let search_registry = |query: &str| {
+        let cache_key = format!("{} {}", config.registry.get_graphql_url(), query);
+        search_with_cache(&cache_path, &cache_key, refresh, Utc::now(), || {
+            query_registry(query)
+        })
+    };
+    let results = search_registry(&query)?;
+
+    if results.is_empty() {
+        // a broader search finds the packages whose names are close to a misspelled one
+        let suggestions = match fallback_query(&query) {
+            Some(fallback) => suggest_packages(&query, &search_registry(&fallback)?),
+            None => vec![],
+        };
+        println!("{}", no_results_message(&query, &suggestions));
+        return Ok(());
+    }
+    results_table(&results, installed_versions.as_ref()).printstd();
+
+    Ok(())
+}
+
+/// A package version found by a search
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+struct SearchResult {
+    name: String,
+    display_name: String,
+    description: String,
+    created_at: String,
+    version: String,
+    /// The downloads of the package, when the registry counts them
+    downloads: Option<i64>,
+}
+
+/// The results of a search, as cached in the search cache
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedSearch {
+    searched_at: chrono::DateTime<Utc>,
+    results: Vec<SearchResult>,
+}
+
+fn query_registry(query: &str) -> anyhow::Result<Vec<SearchResult>> {
     let q = SearchQuery::build_query(search_query::Variables {
         query: query.to_string(),
     });
     let response: search_query::ResponseData = execute_query(&q)?;
+    Ok(response
+        .search
+        .edges
+        .into_iter()
+        .filter_map(|edge| match edge?.node? {
+            search_query::SearchQuerySearchEdgesNode::PackageVersion(version) => {
+                Some(SearchResult {
+                    name: version.package.name,
+                    display_name: version.package.display_name,
+                    description: version.description,
+                    created_at: version.created_at,
+                    version: version.version,
+                    downloads: version.package.downloads_count,
+                })
+            }
+            _ => None,
+        })
+        .collect())
+}
 
-    if response.search.edges.is_empty() {
-        println!("No packages found for \"{}\"", query);
-        return Ok(());
+/// The results cached for `cache_key` if they are recent enough and `refresh` is not
+/// set, otherwise the results of `search`, which are cached. A cache that can't be
+/// read or written is only logged, it never fails the search.
+fn search_with_cache<F>(
+    cache_path: &Path,
+    cache_key: &str,
+    refresh: bool,
+    now: chrono::DateTime<Utc>,
+    search: F,
+) -> anyhow::Result<Vec<SearchResult>>
+where
+    F: FnOnce() -> anyhow::Result<Vec<SearchResult>>,
+{
+    let mut cache: HashMap<String, CachedSearch> = fs::read_to_string(cache_path)
+        .ok()
+        .and_then(|cache| serde_json::from_str(&cache).ok())
+        .unwrap_or_default();
+    let is_recent = |searched_at: chrono::DateTime<Utc>| {
+        now.signed_duration_since(searched_at) < Duration::seconds(SEARCH_CACHE_SECONDS)
+    };
+    if !refresh {
+        if let Some(cached) = cache
+            .get(cache_key)
+            .filter(|cached| is_recent(cached.searched_at))
+        {
+            debug!("Using the results of the search at {}", cached.searched_at);
+            return Ok(cached.results.clone());
+        }
+    }
+
+    let results = search()?;
+    cache.retain(|_, cached| is_recent(cached.searched_at));
+    cache.insert(
+        cache_key.to_string(),
+        CachedSearch {
+            searched_at: now,
+            results: results.clone(),
+        },
+    );
+    let saved = serde_json::to_string(&cache)
+        .map_err(anyhow::Error::from)
+        .and_then(|cache| fs::write(cache_path, cache).map_err(anyhow::Error::from));
+    if let Err(e) = saved {
+        debug!("Could not save the search cache: {}", e);
+    }
+    Ok(results)
+}
+
+/// The broader query searched for suggestions when `query` finds nothing: the first
+/// half of it, which a typo at its end doesn't change. `None` when it is too short to
+/// shorten.
+fn fallback_query(query: &str) -> Option<String> {
+    let query = query.trim();
+    let length = query.chars().count();
+    let prefix: String = query.chars().take(length.div_ceil(2).max(2)).collect();
+    if prefix.chars().count() < length {
+        Some(prefix)
+    } else {
+        None
+    }
+}
+
+/// The names of the packages of `results` close enough to `query` to be what was meant,
+/// closest first. The namespace is left out of the comparison.
+fn suggest_packages(query: &str, results: &[SearchResult]) -> Vec<String> {
+    let query = query.trim().to_lowercase();
+    let max_distance = (query.chars().count() / 3).max(2);
+    let mut suggestions: Vec<(usize, &str)> = results
+        .iter()
+        .map(|result| {
+            let name = result.name.rsplit('/').next().unwrap_or(&result.name);
+            (
+                edit_distance(&query, &name.to_lowercase()),
+                result.name.as_str(),
+            )
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    suggestions.sort();
+    suggestions.dedup_by(|(_, a), (_, b)| a == b);
+    suggestions
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+/// The Levenshtein distance between `a` and `b`: the insertions, deletions and
+/// substitutions of characters it takes to turn one into the other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn no_results_message(query: &str, suggestions: &[String]) -> String {
+    let mut message = format!("No packages found for \"{}\"", query);
+    if !suggestions.is_empty() {
+        let suggestions: Vec<String> = suggestions
+            .iter()
+            .map(|name| format!("\"{}\"", name))
+            .collect();
+        message.push_str(&format!(", did you mean {}?", suggestions.join(" or ")));
     }
+    message
+}
+
+fn results_table(
+    results: &[SearchResult],
+    installed_versions: Option<&BTreeMap<String, Vec<Version>>>,
+) -> Table {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
 
     // Add a row per time
-    table.add_row(row!["NAME", "DESCRIPTION", "DATE", "VERSION"]);
-    for edge in response.search.edges.into_iter() {
-        let node = edge.unwrap().node;
-
-        if let Some(search_query::SearchQuerySearchEdgesNode::PackageVersion(version)) = node {
-            table.add_row(row![
-                version.package.display_name,
-                version.description,
-                version.created_at[..10],
-                version.version
-            ]);
+    let mut header = row!["NAME", "DESCRIPTION", "DATE", "VERSION", "DOWNLOADS"];
+    if installed_versions.is_some() {
+        header.add_cell(cell!("INSTALLED"));
+    }
+    table.add_row(header);
+    for result in results {
+        let downloads = result
+            .downloads
+            .map(|downloads| downloads.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let mut row = row![
+            result.display_name,
+            result.description,
+            result.created_at.get(..10).unwrap_or(&result.created_at),
+            result.version,
+            downloads
+        ];
+        if let Some(installed_versions) = installed_versions {
+            row.add_cell(cell!(installed_annotation(
+                installed_versions,
+                &result.name
+            )));
         }
+        table.add_row(row);
     }
-    table.printstd();
+    table
+}
 
-    Ok(())
+/// The installed versions of every package in the lockfile of `directory`, by package
+/// name. Returns `None` when there is no lockfile.
+fn get_installed_versions(
+    directory: &Path,
+) -> anyhow::Result<Option<BTreeMap<String, Vec<Version>>>> {
+    let lockfile_result = LockfileResult::find_in_directory(directory);
+    if let LockfileResult::NoLockfile = lockfile_result {
+        return Ok(None);
+    }
+    let lockfile_packages = LockfilePackages::new_from_result(lockfile_result)?;
+    let mut installed_versions: BTreeMap<String, Vec<Version>> = BTreeMap::new();
+    for key in lockfile_packages.packages.into_keys() {
+        if let PackageKey::WapmPackage(key) = key {
+            installed_versions
+                .entry(key.name.to_string())
+                .or_default()
+                .push(key.version);
+        }
+    }
+    for versions in installed_versions.values_mut() {
+        versions.sort();
+    }
+    Ok(Some(installed_versions))
+}
+
+fn installed_annotation(
+    installed_versions: &BTreeMap<String, Vec<Version>>,
+    package_name: &str,
+) -> String {
+    let package_name = normalize_global_namespace_package_name(Cow::Borrowed(package_name));
+    match installed_versions.get(package_name.as_ref()) {
+        Some(versions) => versions
+            .iter()
+            .map(Version::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        None => "not installed".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::create_temp_dir;
+
+    #[test]
+    fn installed_versions_are_read_from_the_lockfile() {
+        let tmp_dir = create_temp_dir().unwrap();
+        assert!(get_installed_versions(tmp_dir.as_ref()).unwrap().is_none());
+
+        let mut installed_versions = BTreeMap::new();
+        installed_versions.insert(
+            "_/sqlite".to_string(),
+            vec![Version::new(0, 1, 1), Version::new(0, 2, 0)],
+        );
+        installed_versions.insert(
+            "syrusakbary/cowsay".to_string(),
+            vec![Version::new(0, 2, 0)],
+        );
+        assert_eq!(
+            installed_annotation(&installed_versions, "sqlite"),
+            "0.1.1, 0.2.0"
+        );
+        assert_eq!(
+            installed_annotation(&installed_versions, "_/sqlite"),
+            "0.1.1, 0.2.0"
+        );
+        assert_eq!(
+            installed_annotation(&installed_versions, "syrusakbary/cowsay"),
+            "0.2.0"
+        );
+        assert_eq!(
+            installed_annotation(&installed_versions, "_/python"),
+            "not installed"
+        );
+    }
+
+    fn result(name: &str, downloads: Option<i64>) -> SearchResult {
+        SearchResult {
+            name: name.to_string(),
+            display_name: name.to_string(),
+            description: format!("the {} package", name),
+            created_at: "2022-09-01T10:00:00+00:00".to_string(),
+            version: "1.0.0".to_string(),
+            downloads,
+        }
+    }
+
+    #[test]
+    fn downloads_column_is_rendered() {
+        let table = results_table(
+            &[result("_/sqlite", Some(4242)), result("_/python", None)],
+            None,
+        );
+        let cell = |row, column| {
+            table
+                .get_row(row)
+                .and_then(|row| row.get_cell(column))
+                .map(|cell| cell.get_content())
+                .unwrap()
+        };
+        assert_eq!(cell(0, 4), "DOWNLOADS");
+        assert_eq!(cell(1, 2), "2022-09-01");
+        assert_eq!(cell(1, 4), "4242");
+        assert_eq!(cell(2, 4), "-");
+    }
+
+    #[test]
+    fn close_package_names_are_suggested() {
+        assert_eq!(edit_distance("cowsey", "cowsay"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(fallback_query("cowsey").as_deref(), Some("cow"));
+        assert_eq!(fallback_query("qj"), None);
+
+        let results = [
+            result("_/cowsay", None),
+            result("syrusakbary/cowsay", None),
+            result("_/cowthink", None),
+            result("_/coreutils", None),
+        ];
+        let suggestions = suggest_packages("cowsey", &results);
+        assert_eq!(suggestions, vec!["_/cowsay", "syrusakbary/cowsay"]);
+        assert_eq!(
+            no_results_message("cowsey", &suggestions),
+            "No packages found for \"cowsey\", did you mean \"_/cowsay\" or \"syrusakbary/cowsay\"?"
+        );
+        assert!(suggest_packages("sqlite", &results).is_empty());
+        assert_eq!(
+            no_results_message("sqlite", &[]),
+            "No packages found for \"sqlite\""
+        );
+    }
+
+    #[test]
+    fn recent_searches_are_cached_unless_refreshed() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let cache_path = tmp_dir.path().join(SEARCH_CACHE_FILE_NAME);
+        let now = Utc::now();
+        let search = |refresh, now, downloads| {
+            search_with_cache(&cache_path, "registry sqlite", refresh, now, || {
+                Ok(vec![result("_/sqlite", Some(downloads))])
+            })
+            .unwrap()[0]
+                .downloads
+        };
+
+        assert_eq!(search(false, now, 1), Some(1));
+        // paging back shows the same results without querying again
+        assert_eq!(search(false, now + Duration::seconds(10), 2), Some(1));
+        assert_eq!(search(true, now + Duration::seconds(20), 3), Some(3));
+        let later = now + Duration::seconds(20 + SEARCH_CACHE_SECONDS);
+        assert_eq!(search(false, later, 4), Some(4));
+    }
 }
@@ -0,0 +1,237 @@
+//! Code pertaining to the `upgrade` subcommand: it raises the versions of the
+//! dependencies in the manifest and installs them.
+
+use crate::config::Config;
+use crate::data::manifest::{add_dependency, dependency_version_req, Manifest};
+use crate::data::manifest_extensions::save_manifest;
+use crate::dataflow;
+use crate::dataflow::added_packages::AddedPackages;
+use crate::dataflow::manifest_packages::ManifestResult;
+use crate::dataflow::normalize_global_namespace_package_name;
+use crate::dataflow::removed_packages::RemovedPackages;
+use crate::dataflow::resolved_packages::RegistryResolver;
+use semver::{Op, Version, VersionReq};
+use std::collections::BTreeMap;
+use structopt::StructOpt;
+use thiserror::Error;
+
+/// Options for the `upgrade` subcommand
+#[derive(StructOpt, Debug)]
+pub struct UpgradeOpt {
+    /// The dependencies to upgrade, all of them when none are given
+    packages: Vec<String>,
+
+    /// Upgrade to the latest version even when it is outside of the version range in the
+    /// manifest, and rewrite the range for it
+    #[structopt(long = "latest")]
+    latest: bool,
+}
+
+#[derive(Debug, Error)]
+enum UpgradeError {
+    #[error("Could not find a manifest in the current directory, try running `wapm init`")]
+    NoManifest,
+    #[error("\"{0}\" is not a dependency in the manifest")]
+    NotADependency(String),
+}
+
+/// A dependency whose version in the manifest is raised
+#[derive(Debug, PartialEq, Eq)]
+struct DependencyUpgrade {
+    name: String,
+    from: String,
+    to: String,
+}
+
+/// Run the upgrade command
+pub fn upgrade(options: UpgradeOpt) -> anyhow::Result<()> {
+    let directory = Config::get_current_dir()?;
+    let mut manifest: Manifest = match ManifestResult::find_in_directory(&directory) {
+        ManifestResult::Manifest(manifest) => manifest,
+        _ => return Err(UpgradeError::NoManifest.into()),
+    };
+
+    let dependencies: BTreeMap<String, String> = manifest
+        .dependencies
+        .iter()
+        .flatten()
+        .filter(|(name, _)| options.packages.is_empty() || options.packages.contains(name))
+        .map(|(name, version)| (name.clone(), version.clone()))
+        .collect();
+    if let Some(missing) = options
+        .packages
+        .iter()
+        .find(|name| !dependencies.contains_key(*name))
+    {
+        return Err(UpgradeError::NotADependency(missing.clone()).into());
+    }
+
+    let names = dependencies
+        .keys()
+        .map(|name| normalize_global_namespace_package_name(name.into()).to_string())
+        .collect();
+    let versions = RegistryResolver::versions(names)?;
+    let upgrades = find_upgrades(&dependencies, &versions, options.latest)?;
+    if upgrades.is_empty() {
+        println!("All the dependencies are up to date");
+        return Ok(());
+    }
+    for upgrade in upgrades {
+        info!(
+            "Upgrading \"{}\" from {} to {}",
+            upgrade.name, upgrade.from, upgrade.to
+        );
+        add_dependency(&mut manifest, upgrade.name, upgrade.to)?;
+    }
+    save_manifest(&manifest)?;
+
+    dataflow::update_with_manifest(
+        &directory,
+        manifest,
+        AddedPackages::default(),
+        RemovedPackages::default(),
+        &[],
+    )?;
+    println!("Packages successfully upgraded!");
+    Ok(())
+}
+
+/// The new versions of the dependencies, among the published `versions`. Without
+/// `latest`, a dependency is only raised to a version its range already allows.
+fn find_upgrades(
+    dependencies: &BTreeMap<String, String>,
+    versions: &BTreeMap<String, Vec<Version>>,
+    latest: bool,
+) -> anyhow::Result<Vec<DependencyUpgrade>> {
+    let mut upgrades = vec![];
+    for (name, current) in dependencies {
+        let published =
+            match versions.get(normalize_global_namespace_package_name(name.into()).as_ref()) {
+                Some(published) => published,
+                None => {
+                    warn!("\"{}\" is not in the registry", name);
+                    continue;
+                }
+            };
+        let target = if latest {
+            published.iter().filter(|v| v.pre.is_empty()).max()
+        } else {
+            let version_req = dependency_version_req(name, current)?;
+            published.iter().filter(|v| version_req.matches(v)).max()
+        };
+        if let Some(to) = target.and_then(|target| raised_version(current, target, latest)) {
+            upgrades.push(DependencyUpgrade {
+                name: name.clone(),
+                from: current.clone(),
+                to,
+            });
+        }
+    }
+    Ok(upgrades)
+}
+
+/// The version of a dependency raised to `target`, keeping the operator of its range.
+/// `None` when it already allows nothing lower than `target`. Without `latest`, exact
+/// versions and ranges with more than one bound are kept as they are.
+fn raised_version(current: &str, target: &Version, latest: bool) -> Option<String> {
+    if current == "*" || current == "latest" {
+        return None;
+    }
+    if let Ok(version) = Version::parse(current) {
+        return Some(target.to_string()).filter(|_| latest && *target > version);
+    }
+    let version_req = VersionReq::parse(current).ok()?;
+    match version_req.comparators.as_slice() {
+        [comparator] if matches!(comparator.op, Op::Caret | Op::Tilde | Op::Exact) => {
+            let minimum = Version::new(
+                comparator.major,
+                comparator.minor.unwrap_or(0),
+                comparator.patch.unwrap_or(0),
+            );
+            if *target <= minimum {
+                return None;
+            }
+            let operator = match comparator.op {
+                Op::Tilde => "~",
+                Op::Exact => "=",
+                _ => "^",
+            };
+            Some(format!("{}{}", operator, target))
+        }
+        _ if latest => Some(format!("^{}", target)),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "integration_tests")]
+impl UpgradeOpt {
+    pub fn new(packages: Vec<String>, latest: bool) -> Self {
+        UpgradeOpt { packages, latest }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn upgrades(latest: bool) -> Vec<(String, String)> {
+        let dependencies: BTreeMap<String, String> = vec![
+            ("_/sqlite", "0.1.1"),
+            ("cowsay", "^0.2"),
+            ("syrusakbary/gzip", "~1.0.1"),
+            ("_/lolcat", "*"),
+            ("_/python", ">=0.1, <0.2"),
+        ]
+        .into_iter()
+        .map(|(name, version)| (name.to_string(), version.to_string()))
+        .collect();
+        let published = |versions: &[&str]| -> Vec<Version> {
+            versions
+                .iter()
+                .map(|v| Version::parse(v).unwrap())
+                .collect()
+        };
+        let versions: BTreeMap<String, Vec<Version>> = vec![
+            ("_/sqlite", published(&["0.1.1", "0.2.0"])),
+            (
+                "_/cowsay",
+                published(&["0.2.0", "0.2.5", "1.0.0", "2.0.0-rc.1"]),
+            ),
+            ("syrusakbary/gzip", published(&["1.0.1", "1.0.4", "1.1.0"])),
+            ("_/lolcat", published(&["0.1.1"])),
+            ("_/python", published(&["0.1.0", "0.1.2", "0.3.0"])),
+        ]
+        .into_iter()
+        .map(|(name, versions)| (name.to_string(), versions))
+        .collect();
+        find_upgrades(&dependencies, &versions, latest)
+            .unwrap()
+            .into_iter()
+            .map(|upgrade| (upgrade.name, upgrade.to))
+            .collect()
+    }
+
+    #[test]
+    fn dependencies_are_upgraded_within_their_range() {
+        assert_eq!(
+            upgrades(false),
+            vec![
+                ("cowsay".to_string(), "^0.2.5".to_string()),
+                ("syrusakbary/gzip".to_string(), "~1.0.4".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn latest_ignores_the_ranges() {
+        assert_eq!(
+            upgrades(true),
+            vec![
+                ("_/python".to_string(), "^0.3.0".to_string()),
+                ("_/sqlite".to_string(), "0.2.0".to_string()),
+                ("cowsay".to_string(), "^1.0.0".to_string()),
+                ("syrusakbary/gzip".to_string(), "~1.1.0".to_string()),
+            ]
+        );
+    }
+}
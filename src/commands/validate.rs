@@ -8,16 +8,41 @@ use tar::Archive;
 #[derive(StructOpt, Debug)]
 pub struct ValidateOpt {
     /// Directory or tar file to validate
-    package: String,
+    #[structopt(required_unless = "wasm")]
+    package: Option<String>,
+    /// Validate a single wasm file against the `--interface` files instead of a package
+    #[structopt(
+        long = "wasm",
+        parse(from_os_str),
+        conflicts_with = "package",
+        requires = "interfaces"
+    )]
+    wasm: Option<PathBuf>,
+    /// Interface file to validate the `--wasm` file against. Multiple interfaces are merged
+    #[structopt(long = "interface", parse(from_os_str), number_of_values = 1)]
+    interfaces: Vec<PathBuf>,
+    /// Fail on manifest keys wapm doesn't know, like misspelled ones, and on modules
+    /// that no command uses. The keys are ignored and the modules warned about otherwise
+    #[structopt(long = "strict", conflicts_with = "wasm")]
+    strict: bool,
 }
 
 pub fn validate(validate_opts: ValidateOpt) -> anyhow::Result<()> {
-    let pkg_path = PathBuf::from(&validate_opts.package);
-    validate_manifest_and_modules(pkg_path)
+    if let Some(wasm_path) = validate_opts.wasm {
+        validate_wasm_against_interface_files(&wasm_path, &validate_opts.interfaces)?;
+        info!("\"{}\" matches the interface", wasm_path.display());
+        return Ok(());
+    }
+    let pkg_path = PathBuf::from(validate_opts.package.unwrap_or_default());
+    validate_manifest_and_modules(pkg_path, validate_opts.strict)
 }
 
-pub fn validate_manifest_and_modules(pkg_path: PathBuf) -> anyhow::Result<()> {
+pub fn validate_manifest_and_modules(pkg_path: PathBuf, strict: bool) -> anyhow::Result<()> {
     if pkg_path.is_dir() {
+        if strict {
+            check_unknown_manifest_keys(&pkg_path)?;
+        }
+        check_orphan_modules(&pkg_path, strict)?;
         validate_directory(pkg_path)
     } else {
         //unzip then validate as dir
@@ -61,6 +86,10 @@ pub fn validate_manifest_and_modules(pkg_path: PathBuf) -> anyhow::Result<()> {
             ar_path
         };
 
+        if strict {
+            check_unknown_manifest_keys(&archive_path)?;
+        }
+        check_orphan_modules(&archive_path, strict)?;
         validate_directory(archive_path)
     }
 }
@@ -0,0 +1,270 @@
+//! Code pertaining to the `verify-manifest` subcommand: it checks a manifest file on
+//! its own, without the files of the package it describes.
+
+use crate::data::manifest_extensions::{manifest_from_value, ManifestExtensions};
+use crate::validate::{
+    find_unknown_manifest_keys, is_known_module_kind, unknown_module_kind_message,
+};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use thiserror::Error;
+
+/// Options for the `verify-manifest` subcommand
+#[derive(StructOpt, Debug)]
+pub struct VerifyManifestOpt {
+    /// The manifest file to check
+    #[structopt(parse(from_os_str))]
+    manifest: PathBuf,
+    /// Print the result as JSON
+    #[structopt(long = "json")]
+    json: bool,
+}
+
+#[derive(Debug, Error)]
+enum VerifyManifestError {
+    #[error("Could not read the manifest \"{0}\". {1}")]
+    CannotRead(String, String),
+    #[error("The manifest \"{0}\" has {1} error(s)")]
+    Invalid(String, usize),
+}
+
+/// What is wrong with a manifest. Warnings don't make it invalid.
+#[derive(Debug, Default, Serialize)]
+struct ManifestReport {
+    manifest: String,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+/// Run the verify-manifest command
+pub fn verify_manifest(options: VerifyManifestOpt) -> anyhow::Result<()> {
+    let manifest = options.manifest.to_string_lossy().to_string();
+    let source = fs::read_to_string(&options.manifest)
+        .map_err(|e| VerifyManifestError::CannotRead(manifest.clone(), e.to_string()))?;
+    let report = ManifestReport {
+        manifest: manifest.clone(),
+        ..check_manifest(&source)
+    };
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for error in report.errors.iter() {
+            println!("error: {}", error);
+        }
+        for warning in report.warnings.iter() {
+            println!("warning: {}", warning);
+        }
+    }
+    if !report.errors.is_empty() {
+        return Err(VerifyManifestError::Invalid(manifest, report.errors.len()).into());
+    }
+    Ok(())
+}
+
+/// Runs the checks of `wapm validate` that only need the manifest
+fn check_manifest(source: &str) -> ManifestReport {
+    let mut report = ManifestReport::default();
+    let document: toml::Value = match source.parse() {
+        Ok(document) => document,
+        Err(e) => {
+            report
+                .errors
+                .push(format!("could not parse the manifest: {}", e));
+            return report;
+        }
+    };
+
+    if let Err(e) = manifest_from_value(document.clone()) {
+        report.errors.push(e.to_string());
+    }
+    match ManifestExtensions::parse(source) {
+        Ok(extensions) => {
+            if let Err(e) = extensions.default_command() {
+                report.errors.push(e.to_string());
+            }
+            if let Err(e) = extensions.check_documentation_url() {
+                report.errors.push(e.to_string());
+            }
+        }
+        Err(e) => report.errors.push(e.to_string()),
+    }
+    report.errors.extend(check_names(&document));
+
+    report.warnings.extend(
+        find_unknown_manifest_keys(&document)
+            .into_iter()
+            .map(|key| format!("unknown key `{}`, it may be misspelled", key)),
+    );
+    report.warnings.extend(check_module_kinds(&document));
+    report
+}
+
+/// Warns about the modules whose `kind` wapm doesn't know
+fn check_module_kinds(document: &toml::Value) -> Vec<String> {
+    document
+        .get("module")
+        .and_then(|modules| modules.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|module| {
+            let kind = module.get("kind")?.as_str()?;
+            if is_known_module_kind(kind) {
+                return None;
+            }
+            let name = module.get("name").and_then(|name| name.as_str());
+            Some(unknown_module_kind_message(name.unwrap_or("?"), kind))
+        })
+        .collect()
+}
+
+/// Checks that module and command names are unique and that commands use modules of
+/// the manifest
+fn check_names(document: &toml::Value) -> Vec<String> {
+    fn entries<'a>(document: &'a toml::Value, section: &str) -> &'a [toml::Value] {
+        document
+            .get(section)
+            .and_then(|entries| entries.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+    fn string<'a>(entry: &'a toml::Value, key: &str) -> Option<&'a str> {
+        entry.get(key).and_then(|value| value.as_str())
+    }
+
+    let mut errors = vec![];
+    let mut module_names = BTreeSet::new();
+    for name in entries(document, "module")
+        .iter()
+        .filter_map(|module| string(module, "name"))
+    {
+        if !module_names.insert(name) {
+            errors.push(format!(
+                "the module \"{}\" is declared more than once",
+                name
+            ));
+        }
+    }
+    let mut command_names = BTreeSet::new();
+    for command in entries(document, "command") {
+        let name = string(command, "name").unwrap_or_default();
+        if !command_names.insert(name) {
+            errors.push(format!(
+                "the command \"{}\" is declared more than once",
+                name
+            ));
+        }
+        // a command of a dependency names a module of that package
+        if command.get("package").is_some() {
+            continue;
+        }
+        if let Some(module) = string(command, "module") {
+            if !module_names.contains(module) {
+                errors.push(format!(
+                    "the command \"{}\" uses the module \"{}\", which is not declared",
+                    name, module
+                ));
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::create_temp_dir;
+
+    #[test]
+    fn well_formed_manifest_without_module_files() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let manifest_path = tmp_dir.path().join("wapm.toml");
+        fs::write(
+            &manifest_path,
+            r#"[package]
+name = "test"
+version = "0.1.0"
+description = "test package"
+documentation = "https://docs.wasmer.io"
+
+[[module]]
+name = "test"
+source = "missing.wasm"
+abi = "wasi"
+
+[[command]]
+name = "test"
+module = "test"
+default = true
+"#,
+        )
+        .unwrap();
+
+        let report = check_manifest(&fs::read_to_string(&manifest_path).unwrap());
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        verify_manifest(VerifyManifestOpt {
+            manifest: manifest_path,
+            json: true,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn unknown_module_kinds_are_warnings() {
+        let manifest = |kind: &str| {
+            format!(
+                r#"[package]
+name = "test"
+version = "0.1.0"
+description = "test package"
+
+[[module]]
+name = "test"
+source = "test.wasm"
+kind = "{}"
+"#,
+                kind
+            )
+        };
+        let report = check_manifest(&manifest("tensorflow"));
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        let report = check_manifest(&manifest("wsam"));
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].starts_with("module \"test\" has the unknown kind \"wsam\""));
+    }
+
+    #[test]
+    fn malformed_manifest_is_reported() {
+        let report = check_manifest(
+            r#"[package]
+name = "test"
+version = "0.1.0"
+description = "test package"
+documentation = "docs"
+repostiory = "https://github.com/wasmerio/wapm-cli"
+
+[[module]]
+name = "test"
+source = "test.wasm"
+
+[[command]]
+name = "test"
+module = "tset"
+"#,
+        );
+        assert_eq!(report.errors.len(), 2, "{:?}", report.errors);
+        assert!(report.errors[1].contains("\"tset\""));
+        assert_eq!(
+            report.warnings,
+            vec!["unknown key `package.repostiory`, it may be misspelled"]
+        );
+
+        let report = check_manifest("[package\nname = \"test\"");
+        assert_eq!(report.errors.len(), 1);
+    }
+}
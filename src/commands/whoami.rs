@@ -1,9 +1,261 @@
+//! Code pertaining to the `whoami` subcommand. The user is cached for a short while,
+//! so that prompts calling it often don't query the registry every time.
 use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use thiserror::Error;
 
+use crate::config::{format_graphql, Config};
 use crate::util;
 
-pub fn whoami() -> anyhow::Result<()> {
-    let username = util::get_username()?.context("(not logged in)")?;
+/// Options for the `whoami` subcommand
+#[derive(StructOpt, Debug)]
+pub struct WhoAmIOpt {
+    /// Query the registry again instead of showing the user cached by a recent call
+    #[structopt(long = "refresh")]
+    refresh: bool,
+
+    /// Show the user logged into this registry instead of the current one
+    #[structopt(long = "registry", value_name = "URL")]
+    registry: Option<String>,
+}
+
+#[derive(Debug, Error)]
+enum WhoAmIError {
+    #[error("not logged in to {0}")]
+    NotLoggedIn(String),
+}
+
+/// The file in the config folder the current user is cached in
+static WHOAMI_CACHE_FILE_NAME: &str = "whoami_cache.json";
+/// How long the user is shown again without querying the registry
+const WHOAMI_CACHE_SECONDS: i64 = 60;
+
+/// The user the token of a registry logs in as, as cached in the whoami cache. The
+/// token itself is not written to the cache, only a hash of it.
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedUser {
+    registry: String,
+    token_hash: String,
+    username: String,
+    checked_at: DateTime<Utc>,
+}
+
+pub fn whoami(options: WhoAmIOpt) -> anyhow::Result<()> {
+    let config = Config::from_file()?;
+    if let Some(registry) = options.registry {
+        let username = registry_username(
+            &config,
+            &format_graphql(&registry),
+            &whoami_cache_path()?,
+            options.refresh,
+            Utc::now(),
+            util::get_username_for_registry,
+        )?;
+        println!("{username}");
+        return Ok(());
+    }
+    let registry = config.registry.get_current_registry();
+    let username = match config.registry.get_login_token_for_registry(&registry) {
+        Some(token) => username_with_cache(
+            &whoami_cache_path()?,
+            &registry,
+            &token,
+            options.refresh,
+            Utc::now(),
+            util::get_username,
+        )?,
+        None => util::get_username()?,
+    };
+    let username = username.context("(not logged in)")?;
     println!("{username}");
     Ok(())
 }
+
+/// The user the token stored for `registry` logs in as, found by `query`, cached like
+/// for the current registry
+fn registry_username<F>(
+    config: &Config,
+    registry: &str,
+    cache_path: &Path,
+    refresh: bool,
+    now: DateTime<Utc>,
+    query: F,
+) -> anyhow::Result<String>
+where
+    F: FnOnce(&str) -> anyhow::Result<Option<String>>,
+{
+    let not_logged_in = || WhoAmIError::NotLoggedIn(registry.to_string());
+    let token = config
+        .registry
+        .get_login_token_for_registry(registry)
+        .ok_or_else(not_logged_in)?;
+    let username = username_with_cache(cache_path, registry, &token, refresh, now, || {
+        query(registry)
+    })?;
+    Ok(username.ok_or_else(not_logged_in)?)
+}
+
+/// Forgets the cached user, for when the token is removed
+pub(crate) fn clear_whoami_cache() {
+    if let Ok(cache_path) = whoami_cache_path() {
+        if let Err(e) = fs::remove_file(cache_path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                debug!("Could not remove the whoami cache: {}", e);
+            }
+        }
+    }
+}
+
+fn whoami_cache_path() -> anyhow::Result<PathBuf> {
+    Ok(Config::get_folder()?.join(WHOAMI_CACHE_FILE_NAME))
+}
+
+fn hash_token(token: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    token.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// The user cached for `registry` and `token` if it is recent enough and `refresh` is
+/// not set, otherwise the user `query` finds, which is cached. A token that logs in
+/// as no user is not cached, and a cache that can't be read or written is only
+/// logged.
+fn username_with_cache<F>(
+    cache_path: &Path,
+    registry: &str,
+    token: &str,
+    refresh: bool,
+    now: DateTime<Utc>,
+    query: F,
+) -> anyhow::Result<Option<String>>
+where
+    F: FnOnce() -> anyhow::Result<Option<String>>,
+{
+    let token_hash = hash_token(token);
+    if !refresh {
+        let cached: Option<CachedUser> = fs::read_to_string(cache_path)
+            .ok()
+            .and_then(|cache| serde_json::from_str(&cache).ok());
+        if let Some(cached) = cached.filter(|cached| {
+            cached.registry == registry
+                && cached.token_hash == token_hash
+                && now.signed_duration_since(cached.checked_at)
+                    < Duration::seconds(WHOAMI_CACHE_SECONDS)
+        }) {
+            debug!("Using the user checked at {}", cached.checked_at);
+            return Ok(Some(cached.username));
+        }
+    }
+
+    let username = query()?;
+    let cached = username.clone().map(|username| CachedUser {
+        registry: registry.to_string(),
+        token_hash,
+        username,
+        checked_at: now,
+    });
+    let saved = match cached {
+        Some(cached) => serde_json::to_string(&cached)
+            .map_err(anyhow::Error::from)
+            .and_then(|cache| fs::write(cache_path, cache).map_err(anyhow::Error::from)),
+        None => match fs::remove_file(cache_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        },
+    };
+    if let Err(e) = saved {
+        debug!("Could not save the whoami cache: {}", e);
+    }
+    Ok(username)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::create_temp_dir;
+
+    #[test]
+    fn recent_users_are_cached_unless_refreshed() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let cache_path = tmp_dir.path().join(WHOAMI_CACHE_FILE_NAME);
+        let now = Utc::now();
+        let whoami = |token, refresh, now, username: &str| {
+            username_with_cache(&cache_path, "registry", token, refresh, now, || {
+                Ok(Some(username.to_string()))
+            })
+            .unwrap()
+            .unwrap()
+        };
+
+        assert_eq!(whoami("token", false, now, "alice"), "alice");
+        // a second call shows the same user without querying again
+        assert_eq!(
+            whoami("token", false, now + Duration::seconds(10), "bob"),
+            "alice"
+        );
+        assert_eq!(
+            whoami("token", true, now + Duration::seconds(20), "bob"),
+            "bob"
+        );
+        // another token is another user
+        assert_eq!(
+            whoami("other-token", false, now + Duration::seconds(20), "carol"),
+            "carol"
+        );
+        let later = now + Duration::seconds(20 + WHOAMI_CACHE_SECONDS);
+        assert_eq!(whoami("other-token", false, later, "dave"), "dave");
+        assert!(!fs::read_to_string(&cache_path)
+            .unwrap()
+            .contains("other-token"));
+
+        // an invalid token is not cached
+        let not_logged_in =
+            username_with_cache(&cache_path, "registry", "expired", true, later, || Ok(None));
+        assert_eq!(not_logged_in.unwrap(), None);
+        assert!(!cache_path.exists());
+    }
+
+    #[test]
+    fn users_are_shown_per_registry() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let cache_path = tmp_dir.path().join(WHOAMI_CACHE_FILE_NAME);
+        let mut config = Config::default();
+        let registry = format_graphql("https://registry.example.com");
+        config.registry.set_login_token_for_registry(
+            &registry,
+            "example-token",
+            crate::config::UpdateRegistry::LeaveAsIs,
+        );
+
+        let username = registry_username(&config, &registry, &cache_path, false, Utc::now(), |r| {
+            assert_eq!(r, registry);
+            Ok(Some("alice".to_string()))
+        });
+        assert_eq!(username.unwrap(), "alice");
+
+        // the current registry has no token
+        let current = config.registry.get_current_registry();
+        let not_logged_in =
+            registry_username(&config, &current, &cache_path, false, Utc::now(), |_| {
+                panic!("the registry is queried without a token")
+            });
+        assert_eq!(
+            not_logged_in.unwrap_err().to_string(),
+            format!("not logged in to {}", current)
+        );
+
+        // a token that logs in as no user
+        let expired = registry_username(&config, &registry, &cache_path, true, Utc::now(), |_| {
+            Ok(None)
+        });
+        assert_eq!(
+            expired.unwrap_err().to_string(),
+            format!("not logged in to {}", registry)
+        );
+    }
+}
@@ -20,8 +20,16 @@ pub static GLOBAL_WAX_INDEX_FILE_NAME: &str = ".wax_index.json";
 pub static GLOBAL_CONFIG_DATABASE_FILE_NAME: &str = "wapm.sqlite";
 pub static GLOBAL_CONFIG_FOLDER_ENV_VAR: &str = "WASMER_DIR";
 
+/// The version of the config layout. Configs written before versioning have no
+/// `config_version` and are version 0.
+pub const CURRENT_CONFIG_VERSION: i64 = 1;
+
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
 pub struct Config {
+    /// The version of the layout of this config, see [`CURRENT_CONFIG_VERSION`].
+    #[serde(default)]
+    pub config_version: i64,
+
     /// The number of seconds to wait before checking the registry for a new
     /// version of the package.
     #[serde(default = "wax_default_cooldown")]
@@ -43,6 +51,20 @@ pub struct Config {
     /// The proxy to use when connecting to the Internet.
     #[serde(default)]
     pub proxy: Proxy,
+
+    /// The `User-Agent` sent to the registry instead of the one naming the wapm
+    /// version and the platform. Set as `registry.user_agent`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+
+    /// Headers sent with every request to the registry, like the ones an auth gateway
+    /// in front of it expects. Set as `registry.headers.<name>`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub registry_headers: BTreeMap<String, String>,
+
+    /// The settings of `wapm run`.
+    #[serde(default, skip_serializing_if = "RunConfig::is_empty")]
+    pub run: RunConfig,
 }
 
 /// The default cooldown for wax.
@@ -100,7 +122,7 @@ fn test_registries_switch_token() {
     );
 }
 
-fn format_graphql(registry: &str) -> String {
+pub(crate) fn format_graphql(registry: &str) -> String {
     if registry.ends_with("/graphql") {
         registry.to_string()
     } else if registry.ends_with('/') {
@@ -140,7 +162,13 @@ impl Registries {
     /// Sets the current (active) registry URL
     pub fn set_current_registry(&mut self, registry: &str) {
         let registry = format_graphql(registry);
-        if let Err(e) = test_if_registry_present(&registry) {
+        // directory registries have no GraphQL endpoint to ping
+        let ping_result = if registry.starts_with("file://") {
+            Ok(())
+        } else {
+            test_if_registry_present(&registry)
+        };
+        if let Err(e) = ping_result {
             println!("Error when trying to ping registry {registry:?}: {e}");
             if registry.contains("wapm.dev") {
                 println!("Note: The correct URL for wapm.dev is https://registry.wapm.dev, not {registry}");
@@ -192,8 +220,12 @@ impl Registries {
                         map.insert(format_graphql(&s.url), token);
                     }
                     map.insert(format_graphql(registry), token.to_string());
+                    let current = match update_current_registry {
+                        UpdateRegistry::Update => format_graphql(registry),
+                        UpdateRegistry::LeaveAsIs => format_graphql(&s.url),
+                    };
                     Registries::Multi(MultiRegistry {
-                        current: format_graphql(&s.url),
+                        current,
                         tokens: map,
                     })
                 }
@@ -208,6 +240,62 @@ impl Registries {
         };
         *self = new_map;
     }
+
+    /// The login tokens, by registry URL
+    fn tokens(&self) -> BTreeMap<String, String> {
+        match self {
+            Registries::Single(s) => s
+                .token
+                .iter()
+                .map(|token| (format_graphql(&s.url), token.clone()))
+                .collect(),
+            Registries::Multi(m) => m.tokens.clone(),
+        }
+    }
+
+    /// The same current registry, without the login tokens
+    fn without_tokens(&self) -> Registries {
+        Registries::Single(Registry {
+            url: self.get_current_registry(),
+            token: None,
+        })
+    }
+
+    /// Merges `other` in: its current registry becomes the current one and its
+    /// tokens replace the ones of the same registries. Unlike
+    /// [`Registries::set_current_registry`], the registry is not pinged.
+    fn merged_with(&self, other: &Registries) -> Registries {
+        let mut tokens = self.tokens();
+        tokens.extend(other.tokens());
+        Self::from_tokens(other.get_current_registry(), tokens)
+    }
+
+    /// Removes a registry and its login token. When it was the current registry, the
+    /// default registry becomes the current one. Returns whether the registry was stored.
+    pub fn remove_registry(&mut self, url: &str) -> bool {
+        let registry = format_graphql(url);
+        let mut tokens = self.tokens();
+        let had_token = tokens.remove(&registry).is_some();
+        let mut current = self.get_current_registry();
+        let was_current = current == registry;
+        if was_current {
+            current = Registries::default().get_current_registry();
+        }
+        *self = Self::from_tokens(current, tokens);
+        had_token || was_current
+    }
+
+    /// A single registry when `tokens` has no other registry than `current`
+    fn from_tokens(current: String, mut tokens: BTreeMap<String, String>) -> Registries {
+        if tokens.keys().all(|registry| *registry == current) {
+            Registries::Single(Registry {
+                token: tokens.remove(&current),
+                url: current,
+            })
+        } else {
+            Registries::Multi(MultiRegistry { current, tokens })
+        }
+    }
 }
 
 #[derive(GraphQLQuery)]
@@ -281,16 +369,88 @@ pub struct Proxy {
     pub url: Option<String>,
 }
 
+/// The settings of `wapm run`, in the `[run]` section.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Default, Clone)]
+pub struct RunConfig {
+    /// Other names for commands, set as `run.aliases.<name>`. An alias points to a
+    /// command, or to the command of a package as `namespace/package:command`.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl RunConfig {
+    fn is_empty(&self) -> bool {
+        self.aliases.is_empty()
+    }
+}
+
+/// The prefix of the config keys of the command aliases
+static RUN_ALIASES_PREFIX: &str = "run.aliases.";
+
+/// The alias of a `run.aliases.<name>` key
+fn run_alias(key: &str) -> Option<&str> {
+    key.strip_prefix(RUN_ALIASES_PREFIX)
+        .filter(|alias| !alias.is_empty())
+}
+
+/// The prefix of the config keys of the headers sent to the registry
+static REGISTRY_HEADERS_PREFIX: &str = "registry.headers.";
+
+/// What the names of the headers carrying credentials contain, in lowercase
+static SENSITIVE_HEADER_PARTS: &[&str] = &["auth", "cookie", "token", "secret", "key", "password"];
+
+/// The header of a `registry.headers.<name>` key
+fn registry_header(key: &str) -> Option<&str> {
+    key.strip_prefix(REGISTRY_HEADERS_PREFIX)
+        .filter(|header| !header.is_empty())
+}
+
+/// Whether the value of the header is a credential, masked like the login tokens
+fn header_is_sensitive(header: &str) -> bool {
+    let header = header.to_ascii_lowercase();
+    SENSITIVE_HEADER_PARTS
+        .iter()
+        .any(|part| header.contains(part))
+}
+
+/// Checks that a header can be sent as it is: its name is an HTTP token and its value
+/// is printable ASCII, without the line breaks that would end the header early
+fn check_registry_header(header: &str, value: &str) -> Result<(), ConfigError> {
+    let invalid = |reason| ConfigError::InvalidHeader {
+        header: header.to_string(),
+        reason,
+    };
+    let is_token_char = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    if !header.chars().all(is_token_char) {
+        return Err(invalid(
+            "its name can only have letters, digits and !#$%&'*+-.^_`|~",
+        ));
+    }
+    if value
+        .chars()
+        .any(|c| !c.is_ascii() || (c.is_ascii_control() && c != '\t'))
+    {
+        return Err(invalid(
+            "its value can only have printable ASCII characters",
+        ));
+    }
+    Ok(())
+}
+
 impl Default for Config {
     fn default() -> Config {
         Config {
+            config_version: CURRENT_CONFIG_VERSION,
             registry: Registries::default(),
             #[cfg(feature = "telemetry")]
             telemetry: Telemetry::default(),
             #[cfg(feature = "update-notifications")]
             update_notifications: UpdateNotifications::default(),
             proxy: Proxy::default(),
+            user_agent: None,
+            registry_headers: BTreeMap::new(),
             wax_cooldown: wax_default_cooldown(),
+            run: RunConfig::default(),
         }
     }
 }
@@ -305,15 +465,20 @@ impl Config {
     }
 
     pub fn get_folder() -> Result<PathBuf, GlobalConfigError> {
+        let folder = Self::get_folder_path()?;
+        std::fs::create_dir_all(folder.clone())
+            .map_err(GlobalConfigError::CannotCreateConfigDirectory)?;
+        Ok(folder)
+    }
+
+    /// The config folder, without creating it
+    pub fn get_folder_path() -> Result<PathBuf, GlobalConfigError> {
         Ok(
             if let Some(folder_str) = env::var(GLOBAL_CONFIG_FOLDER_ENV_VAR)
                 .ok()
                 .filter(|s| !s.is_empty())
             {
-                let folder = PathBuf::from(folder_str);
-                std::fs::create_dir_all(folder.clone())
-                    .map_err(GlobalConfigError::CannotCreateConfigDirectory)?;
-                folder
+                PathBuf::from(folder_str)
             } else {
                 #[allow(unused_variables)]
                 let default_dir = Self::get_current_dir()
@@ -328,8 +493,6 @@ impl Config {
                     .unwrap_or_else(|| default_dir.to_string_lossy().to_string());
                 let mut folder = home_dir;
                 folder.push(GLOBAL_CONFIG_FOLDER_NAME);
-                std::fs::create_dir_all(folder.clone())
-                    .map_err(GlobalConfigError::CannotCreateConfigDirectory)?;
                 folder
             },
         )
@@ -358,7 +521,13 @@ impl Config {
                 let mut config_toml = String::new();
                 file.read_to_string(&mut config_toml)
                     .map_err(GlobalConfigError::Io)?;
-                toml::from_str(&config_toml).map_err(GlobalConfigError::Toml)
+                let (config, migrated) = Self::from_toml_str(&config_toml)?;
+                if migrated {
+                    if let Err(e) = config.save() {
+                        warn!("Could not save the migrated config: {}", e);
+                    }
+                }
+                Ok(config)
             }
             Err(_e) => Ok(Self::default()),
         }
@@ -369,13 +538,22 @@ impl Config {
     pub fn from_file() -> Result<Self, GlobalConfigError> {
         crate::integration_tests::data::RAW_CONFIG_DATA.with(|rcd| {
             if let Some(ref config_toml) = *rcd.borrow() {
-                toml::from_str(&config_toml).map_err(|e| GlobalConfigError::Toml(e))
+                Self::from_toml_str(&config_toml).map(|(config, _)| config)
             } else {
                 Ok(Self::default())
             }
         })
     }
 
+    /// Parses a config, migrating it to the current layout first. Returns whether
+    /// it was migrated, in which case it should be saved.
+    fn from_toml_str(config_toml: &str) -> Result<(Self, bool), GlobalConfigError> {
+        let mut value: toml::Value = config_toml.parse().map_err(GlobalConfigError::Toml)?;
+        let migrated = migrate_config(&mut value);
+        let config = value.try_into().map_err(GlobalConfigError::Toml)?;
+        Ok((config, migrated))
+    }
+
     pub fn get_globals_directory() -> Result<PathBuf, GlobalConfigError> {
         Self::get_folder().map(|p| p.join("globals"))
     }
@@ -402,6 +580,56 @@ impl Config {
         Ok(())
     }
 
+    /// The config as written by `wapm config export`. The login tokens and the
+    /// sensitive headers are left out unless `include_tokens` is set.
+    pub fn export(&self, include_tokens: bool) -> anyhow::Result<String> {
+        let mut value = toml::Value::try_from(self)?;
+        if !include_tokens {
+            if let Some(table) = value.as_table_mut() {
+                table.insert(
+                    "registry".to_string(),
+                    toml::Value::try_from(self.registry.without_tokens())?,
+                );
+                if let Some(headers) = table
+                    .get_mut("registry_headers")
+                    .and_then(toml::Value::as_table_mut)
+                {
+                    let sensitive_headers: Vec<String> = headers
+                        .keys()
+                        .filter(|header| header_is_sensitive(header))
+                        .cloned()
+                        .collect();
+                    for header in sensitive_headers {
+                        headers.remove(&header);
+                    }
+                }
+            }
+        }
+        Ok(toml::to_string(&value)?)
+    }
+
+    /// This config with an exported one merged in, as done by `wapm config import`.
+    /// The settings of the exported config replace these ones, the others are kept.
+    pub fn import(&self, exported: &str) -> anyhow::Result<Config> {
+        let mut imported: toml::Value = exported.parse().map_err(GlobalConfigError::Toml)?;
+        migrate_config(&mut imported);
+        // nothing is merged unless the exported config is valid on its own
+        let imported_config: Config = imported
+            .clone()
+            .try_into()
+            .map_err(GlobalConfigError::Toml)?;
+
+        let mut merged = toml::Value::try_from(self)?;
+        if let (Some(merged), Some(imported)) = (merged.as_table_mut(), imported.as_table_mut()) {
+            // the registries are merged below, a token-less registry would drop the tokens
+            imported.remove("registry");
+            merge_tables(merged, imported.clone());
+        }
+        let mut config: Config = merged.try_into().map_err(GlobalConfigError::Toml)?;
+        config.registry = self.registry.merged_with(&imported_config.registry);
+        Ok(config)
+    }
+
     #[cfg(feature = "update-notifications")]
     pub fn update_notifications_enabled() -> bool {
         Self::from_file()
@@ -410,6 +638,104 @@ impl Config {
     }
 }
 
+/// Copies the keys of `from` into `into`, merging the tables they both have
+fn merge_tables(into: &mut toml::value::Table, from: toml::value::Table) {
+    for (key, value) in from {
+        match (into.get_mut(&key), value) {
+            (Some(toml::Value::Table(into)), toml::Value::Table(from)) => merge_tables(into, from),
+            (_, value) => {
+                into.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Upgrades a config to the current layout, one version at a time. Returns whether
+/// anything was changed.
+fn migrate_config(config: &mut toml::Value) -> bool {
+    let table = match config.as_table_mut() {
+        Some(table) => table,
+        None => return false,
+    };
+    let version = table
+        .get("config_version")
+        .and_then(toml::Value::as_integer)
+        .unwrap_or(0);
+    if version > CURRENT_CONFIG_VERSION {
+        warn!(
+            "The config was written by a newer version of wapm (config version {}), some settings may be ignored",
+            version
+        );
+        return false;
+    }
+    if version == CURRENT_CONFIG_VERSION {
+        return false;
+    }
+    if version < 1 {
+        if let Some(registry) = table
+            .get_mut("registry")
+            .and_then(toml::Value::as_table_mut)
+        {
+            migrate_registry_v0_to_v1(registry);
+        }
+    }
+    table.insert(
+        "config_version".to_string(),
+        toml::Value::Integer(CURRENT_CONFIG_VERSION),
+    );
+    true
+}
+
+/// Version 0 could mix the `Single` keys (`url`, `token`) with the `Multi` keys
+/// (`current`, `tokens`), which parsed as `Single` and dropped the other tokens, and
+/// stored registry URLs with and without the `/graphql` suffix. Version 1 has the
+/// keys of only one of them, with the tokens of both, and only `/graphql` URLs.
+fn migrate_registry_v0_to_v1(registry: &mut toml::value::Table) {
+    let as_string = |value: toml::Value| value.as_str().map(str::to_string);
+    let url = registry.remove("url").and_then(as_string);
+    let token = registry.remove("token").and_then(as_string);
+    let current = registry.remove("current").and_then(as_string);
+    let tokens = registry
+        .remove("tokens")
+        .and_then(|tokens| tokens.try_into::<BTreeMap<String, String>>().ok())
+        .unwrap_or_default();
+    let is_multi = current.is_some() || !tokens.is_empty();
+
+    // when a registry has a token under both URLs, the one under the `/graphql` URL
+    // is the one that was used
+    let (graphql_tokens, other_tokens): (Vec<_>, Vec<_>) = tokens
+        .into_iter()
+        .partition(|(registry_url, _)| registry_url.ends_with("/graphql"));
+    let mut normalized_tokens = BTreeMap::new();
+    for (registry_url, registry_token) in graphql_tokens.into_iter().chain(other_tokens) {
+        normalized_tokens
+            .entry(format_graphql(&registry_url))
+            .or_insert(registry_token);
+    }
+    if let (Some(url), Some(token)) = (url.as_ref(), token.as_ref()) {
+        normalized_tokens
+            .entry(format_graphql(url))
+            .or_insert_with(|| token.clone());
+    }
+
+    let current = current
+        .or(url)
+        .map(|url| format_graphql(&url))
+        .unwrap_or_else(|| Registries::default().get_current_registry());
+    if is_multi {
+        registry.insert("current".to_string(), toml::Value::String(current));
+        registry.insert(
+            "tokens".to_string(),
+            toml::Value::try_from(normalized_tokens).expect("a map of strings is valid toml"),
+        );
+    } else {
+        registry.insert("url".to_string(), toml::Value::String(current));
+        if let Some(token) = token {
+            registry.insert("token".to_string(), toml::Value::String(token));
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum GlobalConfigError {
     #[error("Error while reading config: [{0}]")]
@@ -430,9 +756,33 @@ pub enum ConfigError {
     KeyNotFound { key: String },
     #[error("Failed to parse value `{value}` for key `{key}`")]
     CanNotParse { value: String, key: String },
+    #[error("The header `{header}` can't be sent to the registry: {reason}.")]
+    InvalidHeader {
+        header: String,
+        reason: &'static str,
+    },
+    #[error("The registry {registry:?} is not in the config")]
+    UnknownRegistry { registry: String },
 }
 
 pub fn set(config: &mut Config, key: String, value: String) -> anyhow::Result<()> {
+    if let Some(alias) = run_alias(&key) {
+        if value.is_empty() {
+            config.run.aliases.remove(alias);
+        } else {
+            config.run.aliases.insert(alias.to_string(), value);
+        }
+        return config.save();
+    }
+    if let Some(header) = registry_header(&key) {
+        if value.is_empty() {
+            config.registry_headers.remove(header);
+        } else {
+            check_registry_header(header, &value)?;
+            config.registry_headers.insert(header.to_string(), value);
+        }
+        return config.save();
+    }
     match key.as_ref() {
         "registry.url" => {
             let value = format_graphql(&value);
@@ -472,6 +822,9 @@ pub fn set(config: &mut Config, key: String, value: String) -> anyhow::Result<()
         "proxy.url" => {
             config.proxy.url = if value.is_empty() { None } else { Some(value) };
         }
+        "registry.user_agent" => {
+            config.user_agent = if value.is_empty() { None } else { Some(value) };
+        }
         "wax.cooldown" => {
             let num = value.parse::<i32>().map_err(|_| ConfigError::CanNotParse {
                 value: value.clone(),
@@ -488,6 +841,18 @@ pub fn set(config: &mut Config, key: String, value: String) -> anyhow::Result<()
 }
 
 pub fn get(config: &mut Config, key: String) -> anyhow::Result<String> {
+    if let Some(alias) = run_alias(&key) {
+        return match config.run.aliases.get(alias) {
+            Some(command) => Ok(command.clone()),
+            None => Err(ConfigError::KeyNotFound { key }.into()),
+        };
+    }
+    if let Some(header) = registry_header(&key) {
+        return match config.registry_headers.get(header) {
+            Some(value) => Ok(value.clone()),
+            None => Err(ConfigError::KeyNotFound { key }.into()),
+        };
+    }
     let value = match key.as_ref() {
         "registry.url" => config.registry.get_current_registry(),
         "registry.token" => config
@@ -510,6 +875,7 @@ pub fn get(config: &mut Config, key: String) -> anyhow::Result<String> {
                 "No proxy configured".to_owned()
             }
         }
+        "registry.user_agent" => crate::graphql::user_agent(config),
         "wax.cooldown" => format!("{}", config.wax_cooldown),
         _ => {
             return Err(ConfigError::KeyNotFound { key }.into());
@@ -518,9 +884,37 @@ pub fn get(config: &mut Config, key: String) -> anyhow::Result<String> {
     Ok(value)
 }
 
+/// The keys whose values `wapm config get` masks unless asked to show them, on top of
+/// the sensitive headers
+static MASKED_KEYS: &[&str] = &["registry.token"];
+
+/// Gets a value for printing. Secrets are masked unless `show` is set.
+pub fn get_for_display(config: &mut Config, key: String, show: bool) -> anyhow::Result<String> {
+    let masked = !show
+        && (MASKED_KEYS.contains(&key.as_str())
+            || registry_header(&key).is_some_and(header_is_sensitive));
+    let value = get(config, key)?;
+    Ok(if masked { mask_secret(&value) } else { value })
+}
+
+/// Hides all but the last 4 characters, enough to tell secrets apart. Short secrets
+/// are hidden entirely.
+fn mask_secret(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() < 12 {
+        return "****".to_string();
+    }
+    let last_4: String = chars[chars.len() - 4..].iter().collect();
+    format!("****{}", last_4)
+}
+
 #[cfg(test)]
 mod test {
-    use crate::config::{Config, GLOBAL_CONFIG_FILE_NAME, GLOBAL_CONFIG_FOLDER_ENV_VAR};
+    use crate::config::{
+        check_registry_header, get, get_for_display, Config, MultiRegistry, Proxy, Registries,
+        Registry, RunConfig, UpdateRegistry, CURRENT_CONFIG_VERSION, GLOBAL_CONFIG_FILE_NAME,
+        GLOBAL_CONFIG_FOLDER_ENV_VAR,
+    };
     use crate::util::create_temp_dir;
     use std::fs::*;
     use std::io::Write;
@@ -572,4 +966,263 @@ mod test {
         let config_result = Config::from_file();
         assert!(config_result.is_ok(), "Config not found.");
     }
+
+    #[test]
+    fn v0_config_is_migrated_without_data_loss() {
+        let v0_config = r#"
+wax_cooldown = 10
+
+[registry]
+url = "https://registry.wapm.dev"
+token = "dev-token"
+
+[proxy]
+url = "http://proxy.example.com"
+"#;
+        let (config, migrated) = Config::from_toml_str(v0_config).unwrap();
+        assert!(migrated);
+        assert_eq!(config.config_version, CURRENT_CONFIG_VERSION);
+        assert_eq!(config.wax_cooldown, 10);
+        assert_eq!(
+            config.registry,
+            Registries::Single(Registry {
+                url: "https://registry.wapm.dev/graphql".to_string(),
+                token: Some("dev-token".to_string()),
+            })
+        );
+        assert_eq!(
+            config.proxy.url,
+            Some("http://proxy.example.com".to_string())
+        );
+
+        // the migrated config is stable
+        let (reloaded, migrated) =
+            Config::from_toml_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert!(!migrated);
+        assert_eq!(reloaded, config);
+    }
+
+    #[test]
+    fn v0_mixed_registry_is_migrated_to_multi_with_all_tokens() {
+        // parsed as `Single` before the migration, which dropped `tokens`
+        let v0_config = r#"
+[registry]
+url = "https://registry.example.com"
+token = "example-token"
+current = "https://registry.wapm.io"
+
+[registry.tokens]
+"https://registry.wapm.io" = "stale-token"
+"https://registry.wapm.io/graphql" = "io-token"
+"https://registry.wapm.dev/graphql" = "dev-token"
+"#;
+        let (config, migrated) = Config::from_toml_str(v0_config).unwrap();
+        assert!(migrated);
+        assert_eq!(
+            config.registry,
+            Registries::Multi(MultiRegistry {
+                current: "https://registry.wapm.io/graphql".to_string(),
+                tokens: [
+                    ("https://registry.example.com/graphql", "example-token"),
+                    ("https://registry.wapm.dev/graphql", "dev-token"),
+                    ("https://registry.wapm.io/graphql", "io-token"),
+                ]
+                .iter()
+                .map(|(url, token)| (url.to_string(), token.to_string()))
+                .collect(),
+            })
+        );
+    }
+
+    #[test]
+    fn current_config_is_not_migrated() {
+        let config_toml = toml::to_string(&Config::default()).unwrap();
+        let (config, migrated) = Config::from_toml_str(&config_toml).unwrap();
+        assert!(!migrated);
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn removed_registries_lose_their_token() {
+        let default_registry = Registries::default().get_current_registry();
+        let mut registries = Registries::default();
+        registries.set_login_token_for_registry(
+            &default_registry,
+            "default-token",
+            UpdateRegistry::LeaveAsIs,
+        );
+        registries.set_login_token_for_registry(
+            "https://registry.wapm.dev",
+            "dev-token",
+            UpdateRegistry::Update,
+        );
+        registries.set_login_token_for_registry(
+            "https://registry.example.com",
+            "example-token",
+            UpdateRegistry::LeaveAsIs,
+        );
+        assert!(!registries.remove_registry("https://registry.unknown.com"));
+
+        // removing the current registry falls back to the default one
+        assert!(registries.remove_registry("https://registry.wapm.dev"));
+        assert_eq!(registries.get_current_registry(), default_registry);
+        assert_eq!(
+            registries.get_login_token_for_registry("https://registry.wapm.dev"),
+            None
+        );
+        assert!(matches!(registries, Registries::Multi(_)));
+
+        // with only one registry left, it is stored as a single one
+        assert!(registries.remove_registry("https://registry.example.com/graphql"));
+        assert_eq!(
+            registries,
+            Registries::Single(Registry {
+                url: default_registry.clone(),
+                token: Some("default-token".to_string()),
+            })
+        );
+
+        assert!(registries.remove_registry(&default_registry));
+        assert_eq!(registries, Registries::default());
+    }
+
+    #[test]
+    fn registry_token_is_masked_unless_shown() {
+        let mut config = Config::default();
+        let registry = config.registry.get_current_registry();
+        config.registry.set_login_token_for_registry(
+            &registry,
+            "wapm_0123456789abcdef",
+            UpdateRegistry::LeaveAsIs,
+        );
+        let key = || "registry.token".to_string();
+        assert_eq!(
+            get_for_display(&mut config, key(), false).unwrap(),
+            "****cdef"
+        );
+        assert_eq!(
+            get_for_display(&mut config, key(), true).unwrap(),
+            "wapm_0123456789abcdef"
+        );
+        // only secrets are masked
+        assert_eq!(
+            get_for_display(&mut config, "registry.url".to_string(), false).unwrap(),
+            registry
+        );
+    }
+
+    #[test]
+    fn registry_headers_are_checked_and_sensitive_ones_masked() {
+        assert!(check_registry_header("X-Org-Id", "myorg").is_ok());
+        assert!(check_registry_header("X Org", "myorg").is_err());
+        assert!(check_registry_header("X-Org-Id:", "myorg").is_err());
+        assert!(check_registry_header("X-Org-Id", "myorg\r\nX-Admin: 1").is_err());
+
+        let mut config = Config::default();
+        config
+            .registry_headers
+            .insert("X-Org-Id".to_string(), "myorg".to_string());
+        config
+            .registry_headers
+            .insert("X-Api-Key".to_string(), "0123456789abcdef".to_string());
+        let key = |header: &str| format!("registry.headers.{}", header);
+        assert_eq!(get(&mut config, key("X-Org-Id")).unwrap(), "myorg");
+        assert_eq!(
+            get_for_display(&mut config, key("X-Org-Id"), false).unwrap(),
+            "myorg"
+        );
+        assert_eq!(
+            get_for_display(&mut config, key("X-Api-Key"), false).unwrap(),
+            "****cdef"
+        );
+        assert!(get(&mut config, key("X-Missing")).is_err());
+
+        let exported = config.export(false).unwrap();
+        assert!(exported.contains("X-Org-Id"), "{}", exported);
+        assert!(!exported.contains("X-Api-Key"), "{}", exported);
+        assert!(config.export(true).unwrap().contains("X-Api-Key"));
+    }
+
+    /// A config with tokens for two registries, a proxy and an alias
+    fn config_to_export() -> Config {
+        Config {
+            registry: Registries::Multi(MultiRegistry {
+                current: "https://registry.wapm.dev/graphql".to_string(),
+                tokens: [
+                    ("https://registry.wapm.dev/graphql", "dev-token"),
+                    ("https://registry.wapm.io/graphql", "io-token"),
+                ]
+                .iter()
+                .map(|(url, token)| (url.to_string(), token.to_string()))
+                .collect(),
+            }),
+            proxy: Proxy {
+                url: Some("http://proxy.example.com".to_string()),
+            },
+            wax_cooldown: 60,
+            run: RunConfig {
+                aliases: vec![("fmt".to_string(), "wasmer/prettier:prettier".to_string())]
+                    .into_iter()
+                    .collect(),
+            },
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn export_and_import_without_tokens() {
+        let config = config_to_export();
+        let exported = config.export(false).unwrap();
+        assert!(!exported.contains("token"), "{}", exported);
+
+        let mut other = Config::default();
+        other.registry.set_login_token_for_registry(
+            "https://registry.wapm.io",
+            "other-token",
+            UpdateRegistry::LeaveAsIs,
+        );
+        other.wax_cooldown = 10;
+        let imported = other.import(&exported).unwrap();
+        assert_eq!(
+            imported.registry.get_current_registry(),
+            "https://registry.wapm.dev/graphql"
+        );
+        assert_eq!(imported.proxy, config.proxy);
+        assert_eq!(imported.wax_cooldown, 60);
+        assert_eq!(imported.run, config.run);
+        // the tokens of the current config are kept
+        assert_eq!(
+            imported.registry.tokens(),
+            vec![(
+                "https://registry.wapm.io/graphql".to_string(),
+                "other-token".to_string()
+            )]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn export_and_import_with_tokens() {
+        let config = config_to_export();
+        let exported = config.export(true).unwrap();
+        let imported = Config::default().import(&exported).unwrap();
+        assert_eq!(imported, config);
+
+        // a partial config only changes its own settings
+        let imported = config
+            .import("[registry]\nurl = \"https://registry.wapm.io\"\n")
+            .unwrap();
+        assert_eq!(
+            imported.registry.get_current_registry(),
+            "https://registry.wapm.io/graphql"
+        );
+        assert_eq!(imported.registry.tokens(), config.registry.tokens());
+        assert_eq!(imported.proxy, config.proxy);
+
+        assert!(config.import("wax_cooldown = 10").is_err());
+        assert!(config
+            .import("wax_cooldown = \"soon\"\n[registry]\nurl = \"https://registry.wapm.io\"")
+            .is_err());
+    }
 }
@@ -32,6 +32,7 @@ pub struct LockfileV3 {
     pub commands: CommandMapV3, // CommandName -> Command
 }
 
+pub type FeatureMap = BTreeMap<String, Vec<String>>;
 pub type ModuleMap = BTreeMap<String, BTreeMap<Version, BTreeMap<String, LockfileModuleV4>>>;
 pub type CommandMap = BTreeMap<String, LockfileCommand>;
 
@@ -40,6 +41,9 @@ pub type CommandMap = BTreeMap<String, LockfileCommand>;
 pub struct Lockfile {
     pub modules: ModuleMap, // PackageName -> VersionNumber -> ModuleName -> Module
     pub commands: CommandMap, // CommandName -> Command
+    /// The features each package was installed with, when it has any
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: FeatureMap, // PackageName -> Features
 }
 
 pub type LockfileV4 = Lockfile;
@@ -69,6 +73,18 @@ impl Lockfile {
             .and_then(|module| module.prehashed_module_key.clone())
     }
 
+    /// The integrity of the archive a package version was installed from
+    pub fn get_integrity(&self, package_name: &str, package_version: &Version) -> Option<&str> {
+        self.modules
+            .get(package_name)
+            .and_then(|version_map| version_map.get(package_version))
+            .and_then(|module_map| {
+                module_map
+                    .values()
+                    .find_map(|module| module.integrity.as_deref())
+            })
+    }
+
     pub fn get_command(&self, command_name: &str) -> Result<&LockfileCommand, LockfileError> {
         self.commands
             .get(command_name)
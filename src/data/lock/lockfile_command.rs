@@ -1,4 +1,5 @@
 use crate::data::manifest::Command;
+use crate::data::manifest_extensions::ManifestExtensions;
 use semver::Version;
 use thiserror::Error;
 
@@ -11,6 +12,9 @@ pub struct LockfileCommand {
     pub module: String,
     pub is_top_level_dependency: bool,
     pub main_args: Option<String>,
+    /// The `description` of the command in its manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
 }
 
 #[test]
@@ -34,10 +38,42 @@ fn test_lockfile_command_ok() {
             module: "wit-pack".to_string(),
             is_top_level_dependency: true,
             main_args: None,
+            description: None,
         })
     )
 }
 
+#[test]
+fn test_lockfile_command_description_round_trips() {
+    let extensions = ManifestExtensions::parse(
+        "[[command]]\nname = \"wit-pack\"\nmodule = \"wit-pack\"\ndescription = \"Packs WIT files\"\n",
+    )
+    .unwrap();
+    let command = LockfileCommand {
+        name: "wit-pack".to_string(),
+        package_name: "Micheal-F-Bryan/wit-pack".to_string(),
+        package_version: Version::new(1, 0, 0),
+        module: "wit-pack".to_string(),
+        is_top_level_dependency: true,
+        main_args: None,
+        description: None,
+    };
+    let serialized = toml::to_string(&command).unwrap();
+    assert!(!serialized.contains("description"));
+    assert_eq!(
+        toml::from_str::<LockfileCommand>(&serialized).unwrap(),
+        command
+    );
+
+    let command = command.with_description(&extensions);
+    assert_eq!(command.description.as_deref(), Some("Packs WIT files"));
+    let serialized = toml::to_string(&command).unwrap();
+    assert_eq!(
+        toml::from_str::<LockfileCommand>(&serialized).unwrap(),
+        command
+    );
+}
+
 impl<'a> LockfileCommand {
     pub fn from_command(
         local_package_name: &str,
@@ -77,9 +113,18 @@ impl<'a> LockfileCommand {
             module: command.get_module(),
             main_args: command.get_main_args(),
             is_top_level_dependency: true,
+            description: None,
         };
         Ok(lockfile_command)
     }
+
+    /// Takes the description of the command from the manifest of its package
+    pub fn with_description(self, extensions: &ManifestExtensions) -> Self {
+        LockfileCommand {
+            description: extensions.command_description(&self.name),
+            ..self
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Error)]
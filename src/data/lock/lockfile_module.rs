@@ -1,5 +1,6 @@
 use crate::abi::Abi;
 use crate::data::manifest::{Module, PACKAGES_DIR_NAME};
+use crate::dataflow::installed_packages::PackageFormat;
 use crate::util;
 use semver::Version;
 use std::path::{Path, PathBuf};
@@ -50,6 +51,14 @@ pub struct LockfileModule {
     pub source: String,
     /// The hash of the wasm module cached here for faster startup time
     pub prehashed_module_key: Option<String>,
+    /// The sha256 of the package archive the module was installed from. A download
+    /// of the same package version must have the same one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+    /// The format of the package archive. The module of a webc container is the
+    /// container itself
+    #[serde(default, skip_serializing_if = "PackageFormat::is_tar_gz")]
+    pub format: PackageFormat,
 }
 
 pub type LockfileModuleV4 = LockfileModule;
@@ -92,6 +101,8 @@ impl LockfileModule {
             abi: module.abi,
             prehashed_module_key: util::get_hashed_module_key(&path.join(&source)),
             source,
+            integrity: None,
+            format: PackageFormat::TarGz,
         }
     }
 
@@ -114,6 +125,8 @@ impl LockfileModule {
             abi: module.abi,
             source: module.source.to_string_lossy().to_string(),
             prehashed_module_key: util::get_hashed_module_key(&wasm_module_full_path),
+            integrity: None,
+            format: PackageFormat::TarGz,
         }
     }
 
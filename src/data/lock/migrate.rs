@@ -7,6 +7,7 @@ use crate::data::lock::lockfile::{LockfileV2, LockfileV3, LockfileV4};
 use crate::data::lock::lockfile_command::LockfileCommand;
 use crate::data::lock::lockfile_module::{LockfileModuleV2, LockfileModuleV3, LockfileModuleV4};
 use crate::data::manifest::PACKAGES_DIR_NAME;
+use crate::dataflow::installed_packages::PackageFormat;
 use crate::dataflow::lockfile_packages::LockfileError;
 use crate::dataflow::normalize_global_namespace_package_name;
 
@@ -160,6 +161,8 @@ pub fn convert_lockfilev3_to_v4(lockfile: LockfileV3, directory: &Path) -> Lockf
                     },
                     package_path,
                     prehashed_module_key: module_data.prehashed_module_key,
+                    integrity: None,
+                    format: PackageFormat::TarGz,
                 };
                 name_map.insert(k3, module);
             }
@@ -170,6 +173,7 @@ pub fn convert_lockfilev3_to_v4(lockfile: LockfileV3, directory: &Path) -> Lockf
     LockfileV4 {
         modules,
         commands: lockfile.commands,
+        features: Default::default(),
     }
 }
 
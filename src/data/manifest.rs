@@ -1,5 +1,279 @@
 //! The Manifest file is where the core metadata of a wapm package lives
 pub use wapm_toml::{
-    Command, CommandV1, CommandV2, Manifest, ManifestError, Module, Package, ValidationError,
-    MANIFEST_FILE_NAME, PACKAGES_DIR_NAME,
+    Bindings, Command, CommandV1, CommandV2, ImportsError, Manifest, ManifestError, Module,
+    Package, ValidationError, MANIFEST_FILE_NAME, PACKAGES_DIR_NAME,
 };
+
+use crate::data::manifest_extensions::ManifestExtensionsError;
+use crate::dataflow::parse_version_req;
+use semver::{Version, VersionReq};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The files the bindings reference, with the `.wit` files they `use`, transitively.
+/// [`Bindings::referenced_files`] only lists the top-level `.wit` file. A used file that
+/// doesn't exist is listed all the same, for the caller to report.
+pub fn bindings_referenced_files(
+    bindings: &Bindings,
+    base_directory: &Path,
+) -> Result<Vec<PathBuf>, ImportsError> {
+    let mut files = Vec::new();
+    let mut seen = BTreeSet::new();
+    let mut pending = bindings.referenced_files(base_directory)?;
+    pending.reverse();
+    while let Some(path) = pending.pop() {
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        if path.extension().is_some_and(|extension| extension == "wit") {
+            if let Ok(source) = fs::read_to_string(base_directory.join(&path)) {
+                let directory = path.parent().unwrap_or_else(|| Path::new(""));
+                let mut used: Vec<PathBuf> = wit_uses(&source)
+                    .into_iter()
+                    .map(|used| directory.join(used))
+                    .collect();
+                used.reverse();
+                pending.extend(used);
+            }
+        }
+        files.push(path);
+    }
+    Ok(files)
+}
+
+/// The files a `.wit` document uses, relative to its directory: `use { a } from b`
+/// uses `b.wit`.
+fn wit_uses(source: &str) -> Vec<PathBuf> {
+    source
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if !line.starts_with("use ") {
+                return None;
+            }
+            let from = line.rsplit(" from ").next().filter(|from| *from != line)?;
+            let name = from.trim().trim_end_matches(';').trim_matches('"');
+            if name.is_empty() {
+                None
+            } else if name.ends_with(".wit") {
+                Some(PathBuf::from(name))
+            } else {
+                Some(PathBuf::from(format!("{}.wit", name)))
+            }
+        })
+        .collect()
+}
+
+/// Parses the version of the dependency `name`. A bare version like `1.2.3` is that
+/// exact version, `=1.2.3`, as in the manifests written before ranges were allowed.
+pub fn dependency_version_req(
+    name: &str,
+    version: &str,
+) -> Result<VersionReq, ManifestExtensionsError> {
+    let version_req = if Version::parse(version).is_ok() {
+        VersionReq::parse(&format!("={}", version))
+    } else {
+        parse_version_req(version)
+    };
+    version_req.map_err(|_| {
+        ManifestExtensionsError::InvalidDependencyVersion(name.to_string(), version.to_string())
+    })
+}
+
+/// Adds the dependency `name` to the manifest, or replaces its version. `version` must be
+/// a version or a version requirement, see [`dependency_version_req`].
+pub fn add_dependency(
+    manifest: &mut Manifest,
+    name: String,
+    version: String,
+) -> Result<(), ManifestExtensionsError> {
+    dependency_version_req(&name, &version)?;
+    manifest.add_dependency(name, version);
+    Ok(())
+}
+
+/// The dependencies of the manifest with their version requirements
+pub fn get_dependencies(
+    manifest: &Manifest,
+) -> Result<Vec<(String, VersionReq)>, ManifestExtensionsError> {
+    manifest
+        .dependencies
+        .iter()
+        .flatten()
+        .map(|(name, version)| Ok((name.clone(), dependency_version_req(name, version)?)))
+        .collect()
+}
+
+/// The modules of the manifest that have bindings, with their bindings. The files the
+/// bindings reference are listed by [`Bindings::referenced_files`].
+pub fn module_bindings(manifest: &Manifest) -> impl Iterator<Item = (&Module, &Bindings)> {
+    manifest
+        .module
+        .iter()
+        .flatten()
+        .filter_map(|module| module.bindings.as_ref().map(|bindings| (module, bindings)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_modules_with_bindings_are_listed() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[[module]]
+name = "plain"
+source = "plain.wasm"
+
+[[module]]
+name = "with-bindings"
+source = "with-bindings.wasm"
+
+[module.bindings]
+wit-exports = "exports.wit"
+wit-bindgen = "0.1.0"
+"#,
+        )
+        .unwrap();
+        let bindings: Vec<(&Module, &Bindings)> = module_bindings(&manifest).collect();
+        assert_eq!(bindings.len(), 1);
+        let (module, bindings) = bindings[0];
+        assert_eq!(module.name, "with-bindings");
+        assert!(std::ptr::eq(bindings, module.bindings.as_ref().unwrap()));
+
+        let without_modules: Manifest = toml::from_str(
+            "[package]\nname = \"test\"\nversion = \"1.0.0\"\ndescription = \"test\"\n",
+        )
+        .unwrap();
+        assert_eq!(module_bindings(&without_modules).count(), 0);
+    }
+
+    /// A manifest with WIT bindings whose files are written to `directory`
+    fn manifest_with_wit(directory: &Path, files: &[(&str, &str)]) -> Manifest {
+        for (name, source) in files {
+            fs::write(directory.join(name), source).unwrap();
+        }
+        toml::from_str(
+            r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[[module]]
+name = "with-bindings"
+source = "with-bindings.wasm"
+
+[module.bindings]
+wit-exports = "exports.wit"
+wit-bindgen = "0.1.0"
+"#,
+        )
+        .unwrap()
+    }
+
+    fn referenced_file_names(manifest: &Manifest, directory: &Path) -> Vec<String> {
+        let (_, bindings) = module_bindings(manifest).next().unwrap();
+        bindings_referenced_files(bindings, directory)
+            .unwrap()
+            .into_iter()
+            .map(|path| {
+                let path = directory.join(path);
+                path.strip_prefix(directory).unwrap().display().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn used_wit_files_are_referenced() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let manifest = manifest_with_wit(
+            dir.path(),
+            &[
+                (
+                    "exports.wit",
+                    "use { point } from types\n\nmove: func(p: point) -> point\n",
+                ),
+                ("types.wit", "record point { x: u32, y: u32 }\n"),
+            ],
+        );
+        assert_eq!(
+            referenced_file_names(&manifest, dir.path()),
+            vec!["exports.wit", "types.wit"]
+        );
+    }
+
+    #[test]
+    fn cyclic_wit_uses_are_referenced_once() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let manifest = manifest_with_wit(
+            dir.path(),
+            &[
+                ("exports.wit", "use { a } from a\nuse { b } from b\n"),
+                ("a.wit", "use { b } from b\nrecord a { x: u32 }\n"),
+                ("b.wit", "use { a } from \"a.wit\"\nrecord b { y: u32 }\n"),
+            ],
+        );
+        assert_eq!(
+            referenced_file_names(&manifest, dir.path()),
+            vec!["exports.wit", "a.wit", "b.wit"]
+        );
+    }
+
+    #[test]
+    fn dependency_versions_are_version_requirements() {
+        let version_req = |version: &str| dependency_version_req("_/foo", version).unwrap();
+        let matches = |version: &str, candidate: &str| {
+            version_req(version).matches(&Version::parse(candidate).unwrap())
+        };
+        // a bare version is that exact version
+        assert_eq!(version_req("1.2.3"), VersionReq::parse("=1.2.3").unwrap());
+        assert!(matches("1.2.3", "1.2.3"));
+        assert!(!matches("1.2.3", "1.2.4"));
+        // caret
+        assert!(matches("^1.2", "1.9.0"));
+        assert!(!matches("^1.2", "2.0.0"));
+        // tilde
+        assert!(matches("~1.2", "1.2.7"));
+        assert!(!matches("~1.2", "1.3.0"));
+        // wildcard
+        assert_eq!(version_req("*"), VersionReq::STAR);
+        assert_eq!(version_req("latest"), VersionReq::STAR);
+        assert!(matches("1.*", "1.4.0"));
+        assert!(!matches("1.*", "2.0.0"));
+
+        let error = dependency_version_req("_/foo", "not a version").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The version \"not a version\" of the dependency \"_/foo\" is neither a version \
+             nor a version requirement."
+        );
+    }
+
+    #[test]
+    fn invalid_dependency_versions_are_not_added() {
+        let mut manifest: Manifest = toml::from_str(
+            "[package]\nname = \"test\"\nversion = \"1.0.0\"\ndescription = \"test\"\n",
+        )
+        .unwrap();
+        add_dependency(&mut manifest, "_/foo".to_string(), "^1.2".to_string()).unwrap();
+        add_dependency(&mut manifest, "_/bar".to_string(), "0.3.0".to_string()).unwrap();
+        assert!(add_dependency(&mut manifest, "_/baz".to_string(), ">>1".to_string()).is_err());
+        let mut dependencies = get_dependencies(&manifest).unwrap();
+        dependencies.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            dependencies,
+            vec![
+                ("_/bar".to_string(), VersionReq::parse("=0.3.0").unwrap()),
+                ("_/foo".to_string(), VersionReq::parse("^1.2").unwrap()),
+            ]
+        );
+    }
+}
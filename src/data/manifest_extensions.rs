@@ -0,0 +1,1293 @@
+//! Keys of the Manifest that wapm understands but that are not (yet) part of the
+//! `wapm_toml` schema. They are read from the same `wapm.toml` as the [`Manifest`]
+//! and ignored by the `wapm_toml` parser.
+//!
+//! [`Manifest`]: crate::data::manifest::Manifest
+use crate::data::manifest::{
+    Command, CommandV1, CommandV2, Manifest, ManifestError, MANIFEST_FILE_NAME,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+use wasmer_wasm_interface::validate::WasmFeature;
+
+#[derive(Debug, Error)]
+pub enum ManifestExtensionsError {
+    #[error("Could not read manifest at \"{0}\". {1}")]
+    Io(String, String),
+    #[error("Could not parse manifest because {0}.")]
+    Toml(String),
+    #[error("Only one command can be the default, but {} are marked `default = true`.", .0.join(", "))]
+    MultipleDefaultCommands(Vec<String>),
+    #[error("The `documentation` of the package, \"{0}\", is not a valid URL: {1}.")]
+    InvalidDocumentationUrl(String, String),
+    #[error("The package has no feature \"{0}\".")]
+    UnknownFeature(String),
+    #[error("The version \"{1}\" of the dependency \"{0}\" is neither a version nor a version requirement.")]
+    InvalidDependencyVersion(String, String),
+    #[error("More than one module is named \"{0}\".")]
+    DuplicateModule(String),
+    #[error("More than one command is named \"{0}\".")]
+    DuplicateCommand(String),
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ManifestExtensions {
+    #[serde(default)]
+    pub package: PackageExtensions,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub module: Vec<ModuleExtensions>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<CommandExtensions>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, DependencySource>,
+    /// The optional parts of the package, like Cargo features: each feature lists the
+    /// features it enables. The features of `default` are enabled unless installed
+    /// otherwise
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: BTreeMap<String, Vec<String>>,
+}
+
+/// Where a dependency comes from. `wapm_toml` only reads registry dependencies written
+/// as a version, the other ones are tables.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum DependencySource {
+    /// A version requirement on a registry package
+    Registry(String),
+    Git(GitDependency),
+    RegistryWithFeatures(RegistryDependency),
+}
+
+/// A registry dependency with features of the package enabled, like
+/// `"_/foo" = { version = "1", features = ["x"] }`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RegistryDependency {
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+}
+
+/// A dependency fetched from a git repository, like
+/// `"_/foo" = { git = "https://github.com/org/foo", rev = "v1.0.0" }`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GitDependency {
+    pub git: String,
+    /// The commit, tag or branch to fetch. The default branch when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+}
+
+/// The `repository` of a package. The table form points into a subdirectory of the
+/// repository, like
+/// `repository = { url = "https://github.com/org/repo", directory = "packages/foo" }`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Repository {
+    Url(String),
+    Table {
+        url: String,
+        /// The directory of the package in the repository
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        directory: Option<String>,
+    },
+}
+
+/// The extra keys of the `[package]` section.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PackageExtensions {
+    /// Globs of the files to bundle when publishing, instead of the files referenced
+    /// by the manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
+    /// Globs of the files to leave out when publishing
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// The command `wapm publish --build` runs before bundling the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<String>,
+    /// The URL of the package's documentation, next to `repository` and `homepage`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<String>,
+    /// The layout of the commands, see [`manifest_from_value`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<u32>,
+    /// Words the registry search finds the package by
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keywords: Option<Vec<String>>,
+    /// The registry categories the package is listed in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub categories: Option<Vec<String>>,
+    /// The `repository`, which `wapm_toml` only reads as a URL, see
+    /// [`normalize_repository`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<Repository>,
+}
+
+impl PackageExtensions {
+    /// The directory of the package in its repository, when the `repository` is a
+    /// table that has one
+    pub fn repository_directory(&self) -> Option<&str> {
+        match &self.repository {
+            Some(Repository::Table { directory, .. }) => directory.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// The keys of [`PackageExtensions`], as spelled in the manifest
+pub(crate) static PACKAGE_EXTENSION_KEYS: &[&str] = &[
+    "include",
+    "exclude",
+    "build",
+    "documentation",
+    "schema",
+    "keywords",
+    "categories",
+];
+/// The top-level keys of [`ManifestExtensions`] that are not in the [`Manifest`]
+pub(crate) static MANIFEST_EXTENSION_KEYS: &[&str] = &["features"];
+/// The keys of [`ModuleExtensions`], as spelled in the manifest
+pub(crate) static MODULE_EXTENSION_KEYS: &[&str] =
+    &["wasm-features", "required-features", "features"];
+/// The keys of [`CommandExtensions`], as spelled in the manifest
+pub(crate) static COMMAND_EXTENSION_KEYS: &[&str] = &["default", "description", "features"];
+
+/// The extra keys of a `[[module]]` entry.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ModuleExtensions {
+    pub name: String,
+    /// The post-MVP wasm features the module needs from the runtime
+    #[serde(rename = "wasm-features", default)]
+    pub wasm_features: Vec<ModuleWasmFeature>,
+    /// The capabilities of the host the module can't run without
+    #[serde(rename = "required-features", default)]
+    pub required_features: Vec<RequiredFeature>,
+    /// The features of the package the module is installed with. Unlike the
+    /// `required-features`, they are features of the `[features]` section
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+}
+
+/// The extra keys of a `[[command]]` entry.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CommandExtensions {
+    pub name: String,
+    /// Whether `wapm run` runs this command when no command name is given
+    #[serde(default)]
+    pub default: bool,
+    /// What the command does, shown by `wapm list` and `wapm run`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The features of the package the command is installed with
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    /// The arguments the command always runs with. `$@` or `$ARGS` marks where the
+    /// arguments given to `wapm run` go, they come after these otherwise. It replaces
+    /// the `main_args` string
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+}
+
+/// A wasm feature as spelled in the `wasm-features` array of a `[[module]]`.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum ModuleWasmFeature {
+    Threads,
+    ReferenceTypes,
+    Simd,
+    BulkMemory,
+    MultiValue,
+}
+
+impl ModuleWasmFeature {
+    /// The flag the default runtime takes to enable this feature
+    pub fn runtime_flag(self) -> &'static str {
+        match self {
+            ModuleWasmFeature::Threads => "--enable-threads",
+            ModuleWasmFeature::ReferenceTypes => "--enable-reference-types",
+            ModuleWasmFeature::Simd => "--enable-simd",
+            ModuleWasmFeature::BulkMemory => "--enable-bulk-memory",
+            ModuleWasmFeature::MultiValue => "--enable-multi-value",
+        }
+    }
+}
+
+impl fmt::Display for ModuleWasmFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ModuleWasmFeature::Threads => "threads",
+            ModuleWasmFeature::ReferenceTypes => "reference types",
+            ModuleWasmFeature::Simd => "SIMD",
+            ModuleWasmFeature::BulkMemory => "bulk memory",
+            ModuleWasmFeature::MultiValue => "multi-value",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A capability as spelled in the `required-features` array of a `[[module]]`. Unlike
+/// the `wasm-features`, some of them depend on the ABI of the module.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum RequiredFeature {
+    Threads,
+    MultiMemory,
+    WasiNetworking,
+}
+
+impl RequiredFeature {
+    /// The flag the default runtime takes to enable this capability
+    pub fn runtime_flag(self) -> &'static str {
+        match self {
+            RequiredFeature::Threads => "--enable-threads",
+            RequiredFeature::MultiMemory => "--enable-multi-memory",
+            RequiredFeature::WasiNetworking => "--net",
+        }
+    }
+
+    /// Whether only WASI modules can have this capability
+    pub fn needs_wasi(self) -> bool {
+        matches!(self, RequiredFeature::WasiNetworking)
+    }
+}
+
+impl fmt::Display for RequiredFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RequiredFeature::Threads => "threads",
+            RequiredFeature::MultiMemory => "multi-memory",
+            RequiredFeature::WasiNetworking => "WASI networking",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A version of WASI, the snapshot of its interfaces the runtime gives the module
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum WasiVersion {
+    Preview1,
+    Preview2,
+}
+
+impl WasiVersion {
+    pub const NAMES: &'static [&'static str] = &["preview1", "preview2"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WasiVersion::Preview1 => "preview1",
+            WasiVersion::Preview2 => "preview2",
+        }
+    }
+
+    /// The flag the default runtime takes to use this version instead of the one the
+    /// imports of the module ask for
+    pub fn runtime_flag(self) -> String {
+        format!("--wasi-version={}", self.name())
+    }
+}
+
+impl FromStr for WasiVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "preview1" => Ok(WasiVersion::Preview1),
+            "preview2" => Ok(WasiVersion::Preview2),
+            _ => Err(format!(
+                "unknown WASI version \"{}\", use one of {}",
+                s,
+                WasiVersion::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for WasiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WASI {}", self.name())
+    }
+}
+
+impl From<WasmFeature> for ModuleWasmFeature {
+    fn from(feature: WasmFeature) -> Self {
+        match feature {
+            WasmFeature::Threads => ModuleWasmFeature::Threads,
+            WasmFeature::ReferenceTypes => ModuleWasmFeature::ReferenceTypes,
+            WasmFeature::Simd => ModuleWasmFeature::Simd,
+            WasmFeature::BulkMemory => ModuleWasmFeature::BulkMemory,
+            WasmFeature::MultiValue => ModuleWasmFeature::MultiValue,
+        }
+    }
+}
+
+impl ManifestExtensions {
+    pub fn parse(source: &str) -> Result<Self, ManifestExtensionsError> {
+        let extensions: Self =
+            toml::from_str(source).map_err(|e| ManifestExtensionsError::Toml(e.to_string()))?;
+        extensions.check_unique_names()?;
+        Ok(extensions)
+    }
+
+    /// Checks that no two modules and no two commands share a name. `wapm_toml` keeps
+    /// only one of them and resolves the commands against whichever it kept.
+    fn check_unique_names(&self) -> Result<(), ManifestExtensionsError> {
+        if let Some(name) = first_duplicate(self.module.iter().map(|m| m.name.as_str())) {
+            return Err(ManifestExtensionsError::DuplicateModule(name.to_string()));
+        }
+        if let Some(name) = first_duplicate(self.command.iter().map(|c| c.name.as_str())) {
+            return Err(ManifestExtensionsError::DuplicateCommand(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Reads the extensions from the manifest in `directory`. A missing manifest has no
+    /// extensions.
+    pub fn find_in_directory<P: AsRef<Path>>(
+        directory: P,
+    ) -> Result<Self, ManifestExtensionsError> {
+        let manifest_path_buf = directory.as_ref().join(MANIFEST_FILE_NAME);
+        if !manifest_path_buf.is_file() {
+            return Ok(Self::default());
+        }
+        let source = fs::read_to_string(&manifest_path_buf).map_err(|e| {
+            ManifestExtensionsError::Io(
+                manifest_path_buf.to_string_lossy().to_string(),
+                e.to_string(),
+            )
+        })?;
+        Self::parse(&source)
+    }
+
+    pub fn get_module(&self, module_name: &str) -> Option<&ModuleExtensions> {
+        self.module.iter().find(|m| m.name == module_name)
+    }
+
+    pub fn get_command(&self, command_name: &str) -> Option<&CommandExtensions> {
+        self.command.iter().find(|c| c.name == command_name)
+    }
+
+    /// The `description` of a command, if it has one
+    pub fn command_description(&self, command_name: &str) -> Option<String> {
+        self.command
+            .iter()
+            .find(|c| c.name == command_name)
+            .and_then(|c| c.description.clone())
+    }
+
+    /// The dependencies fetched from a git repository, by package name
+    pub fn git_dependencies(&self) -> BTreeMap<&str, &GitDependency> {
+        self.dependencies
+            .iter()
+            .filter_map(|(name, source)| match source {
+                DependencySource::Git(git) => Some((name.as_str(), git)),
+                DependencySource::Registry(_) | DependencySource::RegistryWithFeatures(_) => None,
+            })
+            .collect()
+    }
+
+    /// The features the registry dependencies are installed with, by package name
+    pub fn dependency_features(&self) -> BTreeMap<String, Vec<String>> {
+        self.dependencies
+            .iter()
+            .filter_map(|(name, source)| match source {
+                DependencySource::RegistryWithFeatures(dependency)
+                    if !dependency.features.is_empty() =>
+                {
+                    Some((name.clone(), dependency.features.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Checks that the `documentation` of the package, if any, is a URL
+    pub fn check_documentation_url(&self) -> Result<(), ManifestExtensionsError> {
+        match &self.package.documentation {
+            Some(documentation) => url::Url::parse(documentation).map(|_| ()).map_err(|e| {
+                ManifestExtensionsError::InvalidDocumentationUrl(
+                    documentation.clone(),
+                    e.to_string(),
+                )
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The features enabled by installing the package with `requested`, on top of the
+    /// default ones, with the features they enable in turn
+    pub fn enabled_features(
+        &self,
+        requested: &[String],
+    ) -> Result<BTreeSet<String>, ManifestExtensionsError> {
+        let mut enabled = BTreeSet::new();
+        let mut pending: Vec<&str> = requested.iter().map(String::as_str).collect();
+        if self.features.contains_key("default") {
+            pending.push("default");
+        }
+        while let Some(feature) = pending.pop() {
+            let enables = self
+                .features
+                .get(feature)
+                .ok_or_else(|| ManifestExtensionsError::UnknownFeature(feature.to_string()))?;
+            if enabled.insert(feature.to_string()) {
+                pending.extend(enables.iter().map(String::as_str));
+            }
+        }
+        Ok(enabled)
+    }
+
+    /// Whether the module is installed with the `enabled` features
+    pub fn module_is_enabled(&self, module_name: &str, enabled: &BTreeSet<String>) -> bool {
+        self.get_module(module_name)
+            .is_none_or(|module| module.features.iter().all(|f| enabled.contains(f)))
+    }
+
+    /// Whether the command is installed with the `enabled` features
+    pub fn command_is_enabled(&self, command_name: &str, enabled: &BTreeSet<String>) -> bool {
+        self.command
+            .iter()
+            .find(|c| c.name == command_name)
+            .is_none_or(|command| command.features.iter().all(|f| enabled.contains(f)))
+    }
+
+    /// The name of the command marked `default = true`, if any. More than one default
+    /// command is an error.
+    pub fn default_command(&self) -> Result<Option<&str>, ManifestExtensionsError> {
+        let default_commands: Vec<&str> = self
+            .command
+            .iter()
+            .filter(|c| c.default)
+            .map(|c| c.name.as_str())
+            .collect();
+        match default_commands.as_slice() {
+            [] => Ok(None),
+            [name] => Ok(Some(name)),
+            names => Err(ManifestExtensionsError::MultipleDefaultCommands(
+                names.iter().map(|name| name.to_string()).collect(),
+            )),
+        }
+    }
+}
+
+/// Serializes the manifest with the extension keys of the manifest on disk, which
+/// serializing the [`Manifest`] alone drops.
+pub fn manifest_to_string(manifest: &Manifest) -> Result<String, ManifestExtensionsError> {
+    let manifest_path_buf = manifest.base_directory_path.join(MANIFEST_FILE_NAME);
+    let mut value = toml::Value::try_from(manifest)
+        .map_err(|e| ManifestExtensionsError::Toml(e.to_string()))?;
+    if let Some(original) = fs::read_to_string(&manifest_path_buf)
+        .ok()
+        .and_then(|source| source.parse::<toml::Value>().ok())
+    {
+        keep_extension_keys(&original, &mut value);
+    }
+    toml::to_string(&value).map_err(|e| ManifestExtensionsError::Toml(e.to_string()))
+}
+
+/// Saves the manifest like `Manifest::save` does, but keeps the extension keys that
+/// the manifest on disk already has.
+pub fn save_manifest(manifest: &Manifest) -> Result<(), ManifestExtensionsError> {
+    let manifest_path_buf = manifest.base_directory_path.join(MANIFEST_FILE_NAME);
+    let contents = manifest_to_string(manifest)?;
+    fs::write(&manifest_path_buf, contents).map_err(|e| {
+        ManifestExtensionsError::Io(
+            manifest_path_buf.to_string_lossy().to_string(),
+            e.to_string(),
+        )
+    })
+}
+
+/// Removes the dependencies that are not registry dependencies, which `wapm_toml`
+/// cannot parse. They are read with [`ManifestExtensions::git_dependencies`]. A
+/// registry dependency written as a table is replaced by its version, its features
+/// are read with [`ManifestExtensions::dependency_features`].
+pub fn remove_extension_dependencies(manifest: &mut toml::Value) {
+    if let Some(dependencies) = manifest
+        .get_mut("dependencies")
+        .and_then(|d| d.as_table_mut())
+    {
+        let extension_dependencies: Vec<(String, Option<String>)> = dependencies
+            .iter()
+            .filter(|(_, source)| source.is_table())
+            .map(|(name, source)| {
+                (
+                    name.clone(),
+                    registry_table_version(source).map(str::to_string),
+                )
+            })
+            .collect();
+        for (name, version) in extension_dependencies {
+            match version {
+                Some(version) => dependencies.insert(name, toml::Value::String(version)),
+                None => dependencies.remove(&name),
+            };
+        }
+    }
+}
+
+/// Replaces a `repository` written as a table by its `url`, the only form `wapm_toml`
+/// reads. The `directory` is read with [`PackageExtensions::repository_directory`].
+pub fn normalize_repository(manifest: &mut toml::Value) -> Result<(), toml::de::Error> {
+    use serde::de::Error;
+
+    let package = match manifest.get_mut("package").and_then(|p| p.as_table_mut()) {
+        Some(package) => package,
+        None => return Ok(()),
+    };
+    if let Some(toml::Value::Table(repository)) = package.get("repository") {
+        let url = repository
+            .get("url")
+            .and_then(|url| url.as_str())
+            .ok_or_else(|| toml::de::Error::custom("the `repository` table must have a `url`"))?
+            .to_string();
+        package.insert("repository".to_string(), toml::Value::String(url));
+    }
+    Ok(())
+}
+
+/// The first name that was already seen
+fn first_duplicate<'a>(mut names: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let mut seen = BTreeSet::new();
+    names.find(|name| !seen.insert(*name))
+}
+
+/// The version of a registry dependency written as a table
+fn registry_table_version(source: &toml::Value) -> Option<&str> {
+    if source.get("git").is_some() {
+        return None;
+    }
+    source.get("version").and_then(|version| version.as_str())
+}
+
+/// Reads a [`Manifest`] without the dependencies `wapm_toml` can't parse.
+///
+/// `wapm_toml` reads each `[[command]]` as the first command layout it fits, so a
+/// command meant as a V2 command can be read as a V1 command and lose its `runner`. A
+/// `schema` in `[package]` picks the layout instead: `1` for V1 commands and `2` for
+/// V2 commands. Without it, commands are guessed like before.
+pub fn manifest_from_value(mut manifest: toml::Value) -> Result<Manifest, toml::de::Error> {
+    use serde::de::Error;
+
+    remove_extension_dependencies(&mut manifest);
+    normalize_repository(&mut manifest)?;
+    let schema = match manifest.get("package").and_then(|p| p.get("schema")) {
+        None => return manifest.try_into(),
+        Some(toml::Value::Integer(schema @ 1..=2)) => *schema,
+        Some(schema) => {
+            return Err(toml::de::Error::custom(format!(
+                "the schema {} is unknown, it must be 1 or 2",
+                schema
+            )))
+        }
+    };
+    let commands = manifest
+        .as_table_mut()
+        .and_then(|manifest| manifest.remove("command"));
+    let mut parsed: Manifest = manifest.try_into()?;
+    if let Some(commands) = commands {
+        let commands: Vec<toml::Value> = commands.try_into()?;
+        parsed.command = Some(
+            commands
+                .into_iter()
+                .map(|command| match schema {
+                    1 => command.try_into::<CommandV1>().map(Command::V1),
+                    _ => command.try_into::<CommandV2>().map(Command::V2),
+                })
+                .collect::<Result<_, _>>()?,
+        );
+    }
+    Ok(parsed)
+}
+
+/// Parses and validates a manifest that isn't read from a directory, like one fetched
+/// from the registry. Its `base_directory_path` is empty.
+pub fn manifest_from_str(source: &str) -> Result<Manifest, ManifestError> {
+    parse_manifest(source, PathBuf::new())
+}
+
+/// Reads and validates the manifest in `directory` like `Manifest::find_in_directory`,
+/// with the keys `wapm_toml` can't parse handled like [`manifest_from_value`] does
+pub fn manifest_in_directory<P: AsRef<Path>>(directory: P) -> anyhow::Result<Manifest> {
+    let directory = directory.as_ref();
+    let manifest_path_buf = directory.join(MANIFEST_FILE_NAME);
+    let source = fs::read_to_string(&manifest_path_buf).map_err(|e| {
+        ManifestExtensionsError::Io(
+            manifest_path_buf.to_string_lossy().to_string(),
+            e.to_string(),
+        )
+    })?;
+    Ok(parse_manifest(&source, directory.to_path_buf())?)
+}
+
+fn parse_manifest(source: &str, base_directory_path: PathBuf) -> Result<Manifest, ManifestError> {
+    let mut manifest = source
+        .parse::<toml::Value>()
+        .and_then(manifest_from_value)
+        .map_err(|e| ManifestError::TomlParseError(e.to_string()))?;
+    manifest.base_directory_path = base_directory_path;
+    manifest.validate()?;
+    Ok(manifest)
+}
+
+/// Like [`manifest_from_str`], for a manifest that is still bytes
+pub fn manifest_from_slice(source: &[u8]) -> Result<Manifest, ManifestError> {
+    let source =
+        std::str::from_utf8(source).map_err(|e| ManifestError::TomlParseError(e.to_string()))?;
+    manifest_from_str(source)
+}
+
+/// Writes the `repository` of `package` back as a table when it is one in `original`,
+/// with the URL `package` has now
+fn keep_repository_table(original: &toml::Value, package: &mut toml::Value) {
+    let mut repository = match original.get("repository") {
+        Some(toml::Value::Table(repository)) => repository.clone(),
+        _ => return,
+    };
+    if let Some(package) = package.as_table_mut() {
+        if let Some(url) = package.get("repository").and_then(|url| url.as_str()) {
+            repository.insert("url".to_string(), toml::Value::String(url.to_string()));
+            package.insert("repository".to_string(), toml::Value::Table(repository));
+        }
+    }
+}
+
+/// Copies the extension keys of `original` that are missing from `manifest`.
+fn keep_extension_keys(original: &toml::Value, manifest: &mut toml::Value) {
+    fn copy_keys(keys: &[&str], from: &toml::Value, to: &mut toml::Value) {
+        if let (Some(from), Some(to)) = (from.as_table(), to.as_table_mut()) {
+            for key in keys {
+                if let Some(value) = from.get(*key) {
+                    to.entry(key.to_string()).or_insert_with(|| value.clone());
+                }
+            }
+        }
+    }
+
+    copy_keys(MANIFEST_EXTENSION_KEYS, original, manifest);
+    if let (Some(from), Some(to)) = (original.get("package"), manifest.get_mut("package")) {
+        copy_keys(PACKAGE_EXTENSION_KEYS, from, to);
+        keep_repository_table(from, to);
+    }
+    let extension_dependencies: Vec<(String, toml::Value)> = original
+        .get("dependencies")
+        .and_then(|d| d.as_table())
+        .map(|dependencies| {
+            dependencies
+                .iter()
+                .filter(|(_, source)| source.is_table())
+                .map(|(name, source)| (name.clone(), source.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+    if !extension_dependencies.is_empty() {
+        if let Some(manifest) = manifest.as_table_mut() {
+            let dependencies = manifest
+                .entry("dependencies".to_string())
+                .or_insert_with(|| toml::Value::Table(Default::default()));
+            if let Some(dependencies) = dependencies.as_table_mut() {
+                for (name, mut source) in extension_dependencies {
+                    if registry_table_version(&source).is_none() {
+                        dependencies.entry(name).or_insert(source);
+                        continue;
+                    }
+                    // a registry dependency with features keeps the version the
+                    // manifest has now, and is gone if the manifest dropped it
+                    let version = dependencies.get(&name).and_then(|v| v.as_str());
+                    if let (Some(version), Some(table)) = (version, source.as_table_mut()) {
+                        table.insert(
+                            "version".to_string(),
+                            toml::Value::String(version.to_string()),
+                        );
+                        dependencies.insert(name, source);
+                    }
+                }
+            }
+        }
+    }
+    for (section, keys) in [
+        ("module", MODULE_EXTENSION_KEYS),
+        ("command", COMMAND_EXTENSION_KEYS),
+    ] {
+        let original_entries = original
+            .get(section)
+            .and_then(|m| m.as_array())
+            .cloned()
+            .unwrap_or_default();
+        if let Some(entries) = manifest.get_mut(section).and_then(|m| m.as_array_mut()) {
+            for entry in entries {
+                let name = entry.get("name").cloned();
+                if let Some(from) = original_entries
+                    .iter()
+                    .find(|m| m.get("name") == name.as_ref())
+                {
+                    copy_keys(keys, from, entry);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_module_wasm_features() {
+        let manifest_str = r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[[module]]
+name = "fast"
+source = "fast.wasm"
+abi = "wasi"
+wasm-features = ["simd", "threads"]
+
+[[module]]
+name = "plain"
+source = "plain.wasm"
+"#;
+        let extensions = ManifestExtensions::parse(manifest_str).unwrap();
+        assert_eq!(
+            extensions.get_module("fast").unwrap().wasm_features,
+            vec![ModuleWasmFeature::Simd, ModuleWasmFeature::Threads]
+        );
+        assert!(extensions
+            .get_module("plain")
+            .unwrap()
+            .wasm_features
+            .is_empty());
+        assert!(extensions.get_module("missing").is_none());
+    }
+
+    #[test]
+    fn features_enable_the_features_they_list() {
+        let manifest_str = r#"
+[features]
+default = ["std"]
+std = []
+full = ["net", "std"]
+net = []
+
+[[module]]
+name = "client"
+features = ["net"]
+
+[[command]]
+name = "fetch"
+features = ["net", "std"]
+"#;
+        let extensions = ManifestExtensions::parse(manifest_str).unwrap();
+        let features = |enabled: &[&str]| -> BTreeSet<String> {
+            enabled.iter().map(|f| f.to_string()).collect()
+        };
+
+        let enabled = extensions.enabled_features(&[]).unwrap();
+        assert_eq!(enabled, features(&["default", "std"]));
+        assert!(!extensions.module_is_enabled("client", &enabled));
+        assert!(!extensions.command_is_enabled("fetch", &enabled));
+        // the modules and commands without features are always installed
+        assert!(extensions.module_is_enabled("core", &enabled));
+
+        let enabled = extensions.enabled_features(&["full".to_string()]).unwrap();
+        assert_eq!(enabled, features(&["default", "full", "net", "std"]));
+        assert!(extensions.module_is_enabled("client", &enabled));
+        assert!(extensions.command_is_enabled("fetch", &enabled));
+
+        assert!(matches!(
+            extensions.enabled_features(&["tls".to_string()]),
+            Err(ManifestExtensionsError::UnknownFeature(feature)) if feature == "tls"
+        ));
+        // without a `default` feature nothing is enabled by default
+        assert!(ManifestExtensions::default()
+            .enabled_features(&[])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn required_features_round_trip() {
+        let manifest_str = r#"
+[[module]]
+name = "server"
+source = "server.wasm"
+abi = "wasi"
+required-features = ["threads", "wasi-networking"]
+
+[[module]]
+name = "plain"
+source = "plain.wasm"
+"#;
+        let extensions = ManifestExtensions::parse(manifest_str).unwrap();
+        assert_eq!(
+            extensions.get_module("server").unwrap().required_features,
+            vec![RequiredFeature::Threads, RequiredFeature::WasiNetworking]
+        );
+        assert!(extensions
+            .get_module("plain")
+            .unwrap()
+            .required_features
+            .is_empty());
+
+        let serialized = toml::to_string(&extensions).unwrap();
+        assert!(serialized.contains(r#"required-features = ["threads", "wasi-networking"]"#));
+        assert_eq!(ManifestExtensions::parse(&serialized).unwrap(), extensions);
+
+        let manifest_str = r#"
+[[module]]
+name = "server"
+source = "server.wasm"
+required-features = ["gpu"]
+"#;
+        assert!(ManifestExtensions::parse(manifest_str).is_err());
+    }
+
+    #[test]
+    fn package_file_lists_round_trip() {
+        let manifest_str = r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+include = ["*.wasm", "static/"]
+exclude = ["static/tmp"]
+documentation = "https://docs.example.com/test"
+"#;
+        let extensions = ManifestExtensions::parse(manifest_str).unwrap();
+        assert_eq!(
+            extensions.package.include,
+            Some(vec!["*.wasm".to_string(), "static/".to_string()])
+        );
+        assert_eq!(extensions.package.exclude, vec!["static/tmp".to_string()]);
+        assert_eq!(
+            extensions.package.documentation.as_deref(),
+            Some("https://docs.example.com/test")
+        );
+        extensions.check_documentation_url().unwrap();
+
+        let serialized = toml::to_string(&extensions).unwrap();
+        assert_eq!(ManifestExtensions::parse(&serialized).unwrap(), extensions);
+
+        let extensions = ManifestExtensions::parse("[package]\nname = \"test\"").unwrap();
+        assert_eq!(extensions.package, PackageExtensions::default());
+    }
+
+    #[test]
+    fn keywords_and_categories_round_trip() {
+        let manifest_str = r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+keywords = ["compression", "gzip"]
+categories = ["utilities"]
+"#;
+        let extensions = ManifestExtensions::parse(manifest_str).unwrap();
+        assert_eq!(
+            extensions.package.keywords,
+            Some(vec!["compression".to_string(), "gzip".to_string()])
+        );
+        assert_eq!(
+            extensions.package.categories,
+            Some(vec!["utilities".to_string()])
+        );
+
+        let serialized = toml::to_string(&extensions).unwrap();
+        assert_eq!(ManifestExtensions::parse(&serialized).unwrap(), extensions);
+
+        // they are kept when the manifest is saved
+        let original: toml::Value = toml::from_str(manifest_str).unwrap();
+        let mut saved = toml::Value::try_from(manifest_from_str(manifest_str).unwrap()).unwrap();
+        keep_extension_keys(&original, &mut saved);
+        assert_eq!(
+            saved["package"]["categories"][0].as_str(),
+            Some("utilities")
+        );
+
+        // missing lists are left out rather than written empty
+        let serialized = toml::to_string(&PackageExtensions::default()).unwrap();
+        assert!(!serialized.contains("keywords"));
+        assert!(!serialized.contains("categories"));
+    }
+
+    #[test]
+    fn repository_string_and_table_round_trip() {
+        let manifest_str = |repository: &str| {
+            format!(
+                "[package]\nname = \"test\"\nversion = \"1.0.0\"\ndescription = \"test\"\nrepository = {}\n",
+                repository
+            )
+        };
+        let url = "https://github.com/org/repo";
+
+        let string_form = manifest_str(&format!("\"{}\"", url));
+        let extensions = ManifestExtensions::parse(&string_form).unwrap();
+        assert_eq!(
+            extensions.package.repository,
+            Some(Repository::Url(url.to_string()))
+        );
+        assert_eq!(extensions.package.repository_directory(), None);
+        let manifest = manifest_from_str(&string_form).unwrap();
+        assert_eq!(manifest.package.repository.as_deref(), Some(url));
+        let serialized = toml::to_string(&extensions).unwrap();
+        assert_eq!(ManifestExtensions::parse(&serialized).unwrap(), extensions);
+
+        let table_form = manifest_str(&format!(
+            "{{ url = \"{}\", directory = \"packages/foo\" }}",
+            url
+        ));
+        let extensions = ManifestExtensions::parse(&table_form).unwrap();
+        assert_eq!(
+            extensions.package.repository_directory(),
+            Some("packages/foo")
+        );
+        // `wapm_toml` reads the url of the table
+        let mut manifest = manifest_from_str(&table_form).unwrap();
+        assert_eq!(manifest.package.repository.as_deref(), Some(url));
+        let serialized = toml::to_string(&extensions).unwrap();
+        assert_eq!(ManifestExtensions::parse(&serialized).unwrap(), extensions);
+
+        // the table is kept when the manifest is saved, with the url it has now
+        manifest.package.repository = Some("https://github.com/org/other".to_string());
+        let original: toml::Value = toml::from_str(&table_form).unwrap();
+        let mut saved = toml::Value::try_from(&manifest).unwrap();
+        keep_extension_keys(&original, &mut saved);
+        let saved = toml::to_string(&saved).unwrap();
+        let extensions = ManifestExtensions::parse(&saved).unwrap();
+        assert_eq!(
+            extensions.package.repository,
+            Some(Repository::Table {
+                url: "https://github.com/org/other".to_string(),
+                directory: Some("packages/foo".to_string()),
+            })
+        );
+        assert!(manifest_from_str(&saved).is_ok());
+
+        let without_url = manifest_str("{ directory = \"packages/foo\" }");
+        assert!(manifest_from_str(&without_url).is_err());
+    }
+
+    #[test]
+    fn extension_keys_are_kept_when_saving() {
+        let original: toml::Value = toml::from_str(
+            r#"
+[package]
+name = "test"
+exclude = ["tests"]
+documentation = "https://docs.example.com/test"
+
+[[module]]
+name = "fast"
+wasm-features = ["simd"]
+
+[[command]]
+name = "run"
+default = true
+"#,
+        )
+        .unwrap();
+        let mut saved: toml::Value = toml::from_str(
+            r#"
+[package]
+name = "test"
+
+[[module]]
+name = "other"
+
+[[module]]
+name = "fast"
+
+[[command]]
+name = "run"
+"#,
+        )
+        .unwrap();
+        keep_extension_keys(&original, &mut saved);
+        let extensions: ManifestExtensions = saved.try_into().unwrap();
+        assert_eq!(extensions.package.exclude, vec!["tests".to_string()]);
+        assert_eq!(
+            extensions.package.documentation.as_deref(),
+            Some("https://docs.example.com/test")
+        );
+        assert!(extensions
+            .get_module("other")
+            .unwrap()
+            .wasm_features
+            .is_empty());
+        assert_eq!(
+            extensions.get_module("fast").unwrap().wasm_features,
+            vec![ModuleWasmFeature::Simd]
+        );
+        assert_eq!(extensions.default_command().unwrap(), Some("run"));
+    }
+
+    #[test]
+    fn default_command() {
+        let manifest_with_defaults = |defaults: &[bool]| {
+            let commands: String = defaults
+                .iter()
+                .enumerate()
+                .map(|(i, default)| {
+                    format!(
+                        "[[command]]\nname = \"cmd{}\"\nmodule = \"m\"\ndefault = {}\n",
+                        i, default
+                    )
+                })
+                .collect();
+            ManifestExtensions::parse(&commands).unwrap()
+        };
+
+        assert_eq!(manifest_with_defaults(&[]).default_command().unwrap(), None);
+        assert_eq!(
+            manifest_with_defaults(&[false, false])
+                .default_command()
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            manifest_with_defaults(&[false, true])
+                .default_command()
+                .unwrap(),
+            Some("cmd1")
+        );
+        match manifest_with_defaults(&[true, false, true]).default_command() {
+            Err(ManifestExtensionsError::MultipleDefaultCommands(names)) => {
+                assert_eq!(names, vec!["cmd0".to_string(), "cmd2".to_string()])
+            }
+            otherwise => panic!("expected an error, got {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn git_dependencies() {
+        let manifest_str = r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[dependencies]
+"_/sqlite" = "0.1.1"
+"_/foo" = { git = "https://github.com/org/foo", rev = "v1.0.0" }
+"org/bar" = { git = "https://github.com/org/bar" }
+"#;
+        let extensions = ManifestExtensions::parse(manifest_str).unwrap();
+        let git_dependencies = extensions.git_dependencies();
+        assert_eq!(git_dependencies.len(), 2);
+        assert_eq!(git_dependencies["_/foo"].rev.as_deref(), Some("v1.0.0"));
+        assert_eq!(
+            git_dependencies["org/bar"].git,
+            "https://github.com/org/bar"
+        );
+        assert_eq!(git_dependencies["org/bar"].rev, None);
+
+        let mut manifest: toml::Value = manifest_str.parse().unwrap();
+        remove_extension_dependencies(&mut manifest);
+        let dependencies = manifest["dependencies"].as_table().unwrap();
+        assert_eq!(dependencies.keys().collect::<Vec<_>>(), vec!["_/sqlite"]);
+
+        let original: toml::Value = manifest_str.parse().unwrap();
+        keep_extension_keys(&original, &mut manifest);
+        assert_eq!(manifest, original);
+    }
+
+    #[test]
+    fn dependency_features() {
+        let manifest_str = r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[dependencies]
+"_/sqlite" = "0.1.1"
+"_/tools" = { version = "1", features = ["fmt", "lint"] }
+"_/foo" = { git = "https://github.com/org/foo" }
+"#;
+        let extensions = ManifestExtensions::parse(manifest_str).unwrap();
+        let mut expected = BTreeMap::new();
+        expected.insert(
+            "_/tools".to_string(),
+            vec!["fmt".to_string(), "lint".to_string()],
+        );
+        assert_eq!(extensions.dependency_features(), expected);
+        assert_eq!(extensions.git_dependencies().len(), 1);
+
+        // `wapm_toml` sees the version of the dependency
+        let manifest = manifest_from_value(manifest_str.parse().unwrap()).unwrap();
+        let dependencies = manifest.dependencies.as_ref().unwrap();
+        assert_eq!(dependencies["_/tools"], "1");
+        assert!(!dependencies.contains_key("_/foo"));
+
+        // saving keeps the features, with the version the manifest has now
+        let mut value = toml::Value::try_from(&manifest).unwrap();
+        value["dependencies"]["_/tools"] = toml::Value::String("1.2.0".to_string());
+        let original: toml::Value = manifest_str.parse().unwrap();
+        keep_extension_keys(&original, &mut value);
+        let tools = &value["dependencies"]["_/tools"];
+        assert_eq!(tools["version"].as_str(), Some("1.2.0"));
+        assert_eq!(
+            tools["features"],
+            original["dependencies"]["_/tools"]["features"]
+        );
+    }
+
+    #[test]
+    fn unknown_wasm_feature_is_an_error() {
+        let manifest_str = r#"
+[[module]]
+name = "fast"
+source = "fast.wasm"
+wasm-features = ["teleportation"]
+"#;
+        assert!(ManifestExtensions::parse(manifest_str).is_err());
+    }
+
+    #[test]
+    fn module_and_command_names_are_unique() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(
+            dir.path().join(MANIFEST_FILE_NAME),
+            r#"[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[[module]]
+name = "mod"
+source = "a.wasm"
+
+[[module]]
+name = "mod"
+source = "b.wasm"
+"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            ManifestExtensions::find_in_directory(dir.path()),
+            Err(ManifestExtensionsError::DuplicateModule(name)) if name == "mod"
+        ));
+
+        let manifest_str = r#"
+[[module]]
+name = "mod"
+source = "a.wasm"
+
+[[command]]
+name = "run"
+module = "mod"
+
+[[command]]
+name = "run"
+module = "mod"
+"#;
+        assert_eq!(
+            ManifestExtensions::parse(manifest_str)
+                .unwrap_err()
+                .to_string(),
+            "More than one command is named \"run\"."
+        );
+    }
+
+    #[test]
+    fn manifests_are_parsed_without_a_directory() {
+        let manifest_str = |module: &str| {
+            format!(
+                r#"[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[[module]]
+name = "mod"
+source = "mod.wasm"
+abi = "wasi"
+
+[[command]]
+name = "run"
+module = "{}"
+"#,
+                module
+            )
+        };
+        let manifest = manifest_from_slice(manifest_str("mod").as_bytes()).unwrap();
+        assert_eq!(manifest.base_directory_path, PathBuf::new());
+        assert_eq!(manifest.command.unwrap()[0].get_module(), "mod");
+
+        assert!(matches!(
+            manifest_from_str(&manifest_str("missing")),
+            Err(ManifestError::ValidationError(_))
+        ));
+        assert!(matches!(
+            manifest_from_str("[package"),
+            Err(ManifestError::TomlParseError(_))
+        ));
+        assert!(matches!(
+            manifest_from_slice(b"\xff"),
+            Err(ManifestError::TomlParseError(_))
+        ));
+    }
+
+    #[test]
+    fn documentation_must_be_a_url() {
+        let extensions =
+            ManifestExtensions::parse("[package]\nname = \"test\"\ndocumentation = \"docs\"")
+                .unwrap();
+        assert!(matches!(
+            extensions.check_documentation_url(),
+            Err(ManifestExtensionsError::InvalidDocumentationUrl(..))
+        ));
+    }
+
+    #[test]
+    fn schema_picks_the_command_layout() {
+        let manifest_str = |schema: &str| {
+            format!(
+                r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+{}
+
+[[module]]
+name = "test"
+source = "test.wasm"
+
+[[command]]
+name = "test"
+module = "test"
+runner = "https://webc.org/runner/wasi"
+"#,
+                schema
+            )
+        };
+        let commands = |schema: &str| {
+            let manifest = manifest_from_value(manifest_str(schema).parse().unwrap())?;
+            Ok::<_, toml::de::Error>(manifest.command.unwrap())
+        };
+
+        match commands("schema = 2").unwrap().as_slice() {
+            [Command::V2(command)] => {
+                assert_eq!(command.module, "test");
+                assert_eq!(command.runner, "https://webc.org/runner/wasi");
+            }
+            otherwise => panic!("expected a V2 command, got {:?}", otherwise),
+        }
+        match commands("schema = 1").unwrap().as_slice() {
+            [Command::V1(command)] => assert_eq!(command.module, "test"),
+            otherwise => panic!("expected a V1 command, got {:?}", otherwise),
+        }
+        // without a schema the layout is guessed
+        assert_eq!(commands("").unwrap().len(), 1);
+        assert!(commands("schema = 3").is_err());
+        assert!(commands("schema = \"2\"").is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! Writes a Manifest in the canonical `wapm fmt` format: sections and keys in a
+//! fixed order, nested tables inline and one blank line between sections.
+//!
+//! Formatting works on the toml document rather than on [`Manifest`] so that keys
+//! `wapm_toml` doesn't know about (see [`manifest_extensions`]) are kept. Comments
+//! are not kept.
+//!
+//! [`Manifest`]: crate::data::manifest::Manifest
+//! [`manifest_extensions`]: crate::data::manifest_extensions
+use thiserror::Error;
+use toml::Value;
+
+#[derive(Debug, Error)]
+pub enum ManifestFormatError {
+    #[error("Could not parse manifest because {0}.")]
+    Toml(String),
+    #[error("Expected `{0}` to be a {1}.")]
+    UnexpectedType(String, &'static str),
+}
+
+/// The order of the top level sections
+pub(crate) static SECTION_ORDER: &[&str] = &["package", "dependencies", "module", "command", "fs"];
+/// The order of the keys of `[package]`
+pub(crate) static PACKAGE_KEY_ORDER: &[&str] = &[
+    "name",
+    "version",
+    "schema",
+    "description",
+    "license",
+    "license-file",
+    "readme",
+    "repository",
+    "homepage",
+    "documentation",
+    "wasmer-extra-flags",
+    "disable-command-rename",
+    "rename-commands-to-raw-command-name",
+];
+/// The order of the keys of a `[[module]]`
+pub(crate) static MODULE_KEY_ORDER: &[&str] =
+    &["name", "source", "abi", "kind", "interfaces", "bindings"];
+/// The order of the keys of a `[[command]]`
+pub(crate) static COMMAND_KEY_ORDER: &[&str] = &["name", "module", "main-args", "package"];
+
+/// Formats the manifest source in the canonical format. Formatting is idempotent.
+pub fn format_manifest(source: &str) -> Result<String, ManifestFormatError> {
+    let document: Value = source
+        .parse()
+        .map_err(|e: toml::de::Error| ManifestFormatError::Toml(e.to_string()))?;
+    let document = document
+        .as_table()
+        .ok_or_else(|| ManifestFormatError::UnexpectedType("manifest".to_string(), "table"))?;
+
+    let mut sections = vec![];
+    // keys that are not in a section must come before the first section
+    let top_level_values = document
+        .iter()
+        .filter(|(_, value)| !value.is_table() && !is_array_of_tables(value))
+        .map(|(key, value)| format_key_value(key, value))
+        .collect::<Vec<_>>();
+    if !top_level_values.is_empty() {
+        sections.push(top_level_values.join(""));
+    }
+
+    for name in ordered_keys(document, SECTION_ORDER) {
+        let value = &document[name];
+        let key_order: &[&str] = match name {
+            "package" => PACKAGE_KEY_ORDER,
+            "module" => MODULE_KEY_ORDER,
+            "command" => COMMAND_KEY_ORDER,
+            _ => &[],
+        };
+        match value {
+            Value::Table(table) => sections.push(format_section(
+                &format!("[{}]", format_key(name)),
+                table,
+                key_order,
+            )),
+            Value::Array(entries) if is_array_of_tables(value) => {
+                for entry in entries {
+                    let table = entry.as_table().expect("checked by is_array_of_tables");
+                    sections.push(format_section(
+                        &format!("[[{}]]", format_key(name)),
+                        table,
+                        key_order,
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(sections.join("\n"))
+}
+
+fn is_array_of_tables(value: &Value) -> bool {
+    match value {
+        Value::Array(entries) => !entries.is_empty() && entries.iter().all(Value::is_table),
+        _ => false,
+    }
+}
+
+/// The keys of the table, the ones in `order` first and then the others sorted
+fn ordered_keys<'a>(table: &'a toml::value::Table, order: &[&str]) -> Vec<&'a str> {
+    let mut keys: Vec<&str> = table.keys().map(String::as_str).collect();
+    keys.sort_by_key(|key| {
+        let position = order.iter().position(|k| k == key).unwrap_or(order.len());
+        (position, *key)
+    });
+    keys
+}
+
+fn format_section(header: &str, table: &toml::value::Table, key_order: &[&str]) -> String {
+    let mut section = format!("{}\n", header);
+    for key in ordered_keys(table, key_order) {
+        section.push_str(&format_key_value(key, &table[key]));
+    }
+    section
+}
+
+fn format_key_value(key: &str, value: &Value) -> String {
+    format!("{} = {}\n", format_key(key), format_value(value))
+}
+
+fn format_key(key: &str) -> String {
+    let is_bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if is_bare {
+        key.to_string()
+    } else {
+        Value::String(key.to_string()).to_string()
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Array(entries) => format!(
+            "[{}]",
+            entries
+                .iter()
+                .map(format_value)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Value::Table(table) if table.is_empty() => "{}".to_string(),
+        Value::Table(table) => format!(
+            "{{ {} }}",
+            ordered_keys(table, &[])
+                .into_iter()
+                .map(|key| format!("{} = {}", format_key(key), format_value(&table[key])))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MESSY_MANIFEST: &str = r#"
+[[command]]
+module = "cowsay"
+name = "cowsay"
+
+[fs]
+"/data" = "data"
+
+[[module]]
+source = "target/cowsay.wasm"
+abi = "wasi"
+name = "cowsay"
+wasm-features = ["simd"]
+[module.interfaces]
+wasi = "0.0.0-unstable"
+
+[dependencies]
+"_/sqlite" = "0.1.1"
+"_/python" = "0.1.0"
+
+[package]
+description = "cowsay is a program that generates ASCII pictures of a cow"
+version = "0.2.0"
+name = "_/cowsay"
+exclude = ["tests"]
+license = "MIT"
+"#;
+
+    const FORMATTED_MANIFEST: &str = r#"[package]
+name = "_/cowsay"
+version = "0.2.0"
+description = "cowsay is a program that generates ASCII pictures of a cow"
+license = "MIT"
+exclude = ["tests"]
+
+[dependencies]
+"_/python" = "0.1.0"
+"_/sqlite" = "0.1.1"
+
+[[module]]
+name = "cowsay"
+source = "target/cowsay.wasm"
+abi = "wasi"
+interfaces = { wasi = "0.0.0-unstable" }
+wasm-features = ["simd"]
+
+[[command]]
+name = "cowsay"
+module = "cowsay"
+
+[fs]
+"/data" = "data"
+"#;
+
+    #[test]
+    fn sections_and_keys_are_in_canonical_order() {
+        assert_eq!(format_manifest(MESSY_MANIFEST).unwrap(), FORMATTED_MANIFEST);
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let formatted = format_manifest(MESSY_MANIFEST).unwrap();
+        assert_eq!(format_manifest(&formatted).unwrap(), formatted);
+
+        let reparsed: Value = formatted.parse().unwrap();
+        let original: Value = MESSY_MANIFEST.parse().unwrap();
+        assert_eq!(reparsed, original);
+    }
+
+    #[test]
+    fn invalid_toml_is_an_error() {
+        assert!(format_manifest("[package\nname = ").is_err());
+    }
+}
@@ -3,4 +3,6 @@
 //! respectively.
 pub mod lock;
 pub mod manifest;
+pub mod manifest_extensions;
+pub mod manifest_format;
 pub mod wax_index;
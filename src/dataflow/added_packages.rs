@@ -1,4 +1,6 @@
-use crate::dataflow::{normalize_global_namespace, PackageKey};
+use crate::dataflow::{
+    normalize_global_namespace, PackageKey, Requirement, COMMAND_LINE_DEPENDENT,
+};
 use semver::Version;
 use std::collections::HashSet;
 use thiserror::Error;
@@ -44,6 +46,18 @@ impl<'a> AddedPackages<'a> {
         Self { packages }
     }
 
+    /// The packages, as asked for by the command line
+    pub fn requirements(&self) -> Vec<Requirement<'a>> {
+        self.packages
+            .iter()
+            .cloned()
+            .map(|key| Requirement {
+                dependent: COMMAND_LINE_DEPENDENT.to_string(),
+                key,
+            })
+            .collect()
+    }
+
     pub fn add_missing_packages(self, missing_package_keys: HashSet<PackageKey<'a>>) -> Self {
         let added_packages = self.packages;
         let packages = added_packages
@@ -116,6 +116,7 @@ mod test {
         let lockfile_package = LockfilePackage {
             modules: vec![],
             commands: vec![],
+            features: vec![],
         };
         lockfile_packages.insert(package_key, lockfile_package);
         let lockfile_data = LockfilePackages {
@@ -149,6 +150,7 @@ mod test {
         let lockfile_package = LockfilePackage {
             modules: vec![],
             commands: vec![],
+            features: vec![],
         };
         lockfile_packages.insert(package_key_2, lockfile_package);
         let lockfile_data = LockfilePackages {
@@ -180,6 +182,7 @@ mod test {
         let lockfile_package = LockfilePackage {
             modules: vec![],
             commands: vec![],
+            features: vec![],
         };
         lockfile_packages.insert(package_key_1, lockfile_package);
         let lockfile_data = LockfilePackages {
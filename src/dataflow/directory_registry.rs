@@ -0,0 +1,430 @@
+//! A package registry backed by a local directory of package archives, laid out as
+//! `<root>/<namespace>/<name>/<version>.tar.gz`.
+//!
+//! It is used instead of the wapm.io registry when the `WAPM_LOCAL_REGISTRY` env var is
+//! set, or when the registry URL is a `file://` URL. This allows the whole dataflow to
+//! run without a network connection.
+use crate::config::Config;
+use crate::dataflow::installed_packages::{
+    self, check_archive_integrity, get_key_sign_end_step, Install, InstalledPackage, PackageFormat,
+    RegistryInstaller,
+};
+use crate::dataflow::package_cache::package_cache_directory;
+use crate::dataflow::resolved_packages::{self, DownloadUrls, Resolve};
+use crate::dataflow::{PackageKey, WapmPackageKey};
+#[allow(unused_imports)]
+use crate::keys;
+use crate::util::{
+    create_package_dir, fully_qualified_package_display_name, get_package_namespace_and_name,
+};
+use semver::Version;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub static LOCAL_REGISTRY_ENV_VAR: &str = "WAPM_LOCAL_REGISTRY";
+
+static ARCHIVE_EXTENSION: &str = ".tar.gz";
+
+/// Returns the root of the local registry, if one is configured.
+pub fn local_registry_directory() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var(LOCAL_REGISTRY_ENV_VAR)
+        .ok()
+        .filter(|s| !s.is_empty())
+    {
+        return Some(PathBuf::from(dir));
+    }
+    let registry = Config::from_file().ok()?.registry.get_current_registry();
+    let registry = registry.trim_end_matches("/graphql");
+    url::Url::parse(registry)
+        .ok()
+        .filter(|url| url.scheme() == "file")
+        .and_then(|url| url.to_file_path().ok())
+}
+
+/// Whether the archive of a `file://` download URL is in a directory wapm installs
+/// from: the local registry or the package cache. A `file://` URL in a registry
+/// response or in a lockfile could point to any other archive.
+pub fn is_local_archive_url(download_url: &str) -> bool {
+    let roots = local_registry_directory()
+        .into_iter()
+        .chain(package_cache_directory());
+    archive_is_in(download_url, roots)
+}
+
+fn archive_is_in<I: IntoIterator<Item = PathBuf>>(download_url: &str, roots: I) -> bool {
+    let archive_path = match url::Url::parse(download_url)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .and_then(|path| path.canonicalize().ok())
+    {
+        Some(path) => path,
+        None => return false,
+    };
+    roots
+        .into_iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .any(|root| archive_path.starts_with(root))
+}
+
+pub struct DirectoryRegistry;
+
+impl DirectoryRegistry {
+    /// The directory holding all the archives of a package
+    fn package_directory(root: &Path, package_name: &str) -> Option<PathBuf> {
+        let (namespace, name) = get_package_namespace_and_name(package_name).ok()?;
+        Some(root.join(namespace).join(name))
+    }
+
+    /// All the versions of a package found in the registry
+    fn versions(root: &Path, package_name: &str) -> Vec<Version> {
+        let package_directory = match Self::package_directory(root, package_name) {
+            Some(dir) => dir,
+            None => return vec![],
+        };
+        let entries = match fs::read_dir(package_directory) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let version = file_name.strip_suffix(ARCHIVE_EXTENSION)?;
+                Version::parse(version).ok()
+            })
+            .collect()
+    }
+
+    fn archive_url(root: &Path, package_name: &str, version: &Version) -> Option<String> {
+        let archive_path = Self::package_directory(root, package_name)?
+            .join(format!("{}{}", version, ARCHIVE_EXTENSION));
+        let archive_path = archive_path.canonicalize().ok()?;
+        url::Url::from_file_path(archive_path)
+            .ok()
+            .map(|url| url.to_string())
+    }
+
+    /// All the versions of each package in the registry rooted at `root`. Packages that
+    /// are not in the registry are left out.
+    pub fn versions_in(root: &Path, package_names: &[String]) -> BTreeMap<String, Vec<Version>> {
+        package_names
+            .iter()
+            .map(|name| (name.clone(), Self::versions(root, name)))
+            .filter(|(_, versions)| !versions.is_empty())
+            .collect()
+    }
+
+    /// Resolves the packages against the registry rooted at `root`. Packages that are
+    /// not in the registry are skipped, like they are by the wapm.io resolver.
+    pub fn resolve_in<'a>(root: &Path, added_packages: Vec<PackageKey<'a>>) -> DownloadUrls<'a> {
+        added_packages
+            .into_iter()
+            .filter_map(|added_package| {
+                let (name, version) = match added_package {
+                    PackageKey::WapmPackage(WapmPackageKey { name, version }) => {
+                        if !Self::versions(root, &name).contains(&version) {
+                            return None;
+                        }
+                        (name, version)
+                    }
+                    PackageKey::WapmPackageRange(range) => {
+                        // a directory has no publication order, its latest version is
+                        // the highest one
+                        let version = Self::versions(root, &range.name)
+                            .into_iter()
+                            .filter(|v| range.matches_version(v))
+                            .max()?;
+                        (range.name, version)
+                    }
+                };
+                let download_url = Self::archive_url(root, &name, &version)?;
+                Some((WapmPackageKey { name, version }, (download_url, None)))
+            })
+            .collect()
+    }
+}
+
+impl<'a> Resolve<'a> for DirectoryRegistry {
+    fn sync_packages(
+        added_packages: Vec<PackageKey<'a>>,
+    ) -> Result<DownloadUrls<'a>, resolved_packages::Error> {
+        let root = local_registry_directory().ok_or_else(|| {
+            resolved_packages::Error::CouldNotResolvePackages(
+                "no local registry directory is configured".to_string(),
+            )
+        })?;
+        Ok(Self::resolve_in(&root, added_packages))
+    }
+}
+
+/// Packages in a local registry are unpacked straight from their `file://` URL. Their
+/// signature is checked like the one of a download, local archives are usually not
+/// signed.
+impl<'a> Install<'a> for DirectoryRegistry {
+    fn install_package(
+        directory: &Path,
+        key: WapmPackageKey<'a>,
+        download_url: &str,
+        #[cfg(feature = "full")] signature: Option<keys::WapmPackageSignature>,
+        force_insecure_install: bool,
+    ) -> Result<InstalledPackage<'a>, installed_packages::Error> {
+        let archive_path = url::Url::parse(download_url)
+            .ok()
+            .and_then(|url| url.to_file_path().ok())
+            .ok_or_else(|| {
+                installed_packages::Error::Download(
+                    key.to_string(),
+                    format!("\"{}\" is not a local file URL", download_url),
+                )
+            })?;
+        let (namespace, pkg_name) = get_package_namespace_and_name(&key.name).map_err(|e| {
+            installed_packages::Error::FailedToParsePackageName(key.to_string(), e.to_string())
+        })?;
+        let fully_qualified_package_name =
+            fully_qualified_package_display_name(pkg_name, &key.version);
+        let package_dir = create_package_dir(directory, namespace, &fully_qualified_package_name)
+            .map_err(|e| {
+            installed_packages::Error::IoErrorCreatingDirectory(key.to_string(), e.to_string())
+        })?;
+        let key_sign_end_step = get_key_sign_end_step(
+            force_insecure_install,
+            namespace,
+            fully_qualified_package_name,
+            signature,
+        )?;
+        let mut archive = fs::File::open(&archive_path)
+            .map_err(|e| installed_packages::Error::Download(key.to_string(), e.to_string()))?;
+        key_sign_end_step(&mut archive)?;
+        let integrity = check_archive_integrity(directory, &key, &mut archive)?;
+
+        let format = PackageFormat::from_download_url(download_url);
+        RegistryInstaller::unpack_archive(archive, &package_dir, &key, format)?;
+        Ok((key, package_dir, download_url.to_string(), integrity))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::dataflow::installed_packages::InstalledPackages;
+    use crate::dataflow::resolved_packages::ResolvedPackages;
+    use crate::util::create_temp_dir;
+    use flate2::{write::GzEncoder, Compression};
+
+    /// Writes a package archive containing a manifest and a single empty wasm module,
+    /// named after the package, into the registry
+    pub(crate) fn publish_to_directory(root: &Path, namespace: &str, name: &str, version: &str) {
+        let manifest = format!(
+            r#"[package]
+name = "{0}/{1}"
+version = "{2}"
+description = "test package"
+
+[[module]]
+name = "{1}"
+source = "{1}.wasm"
+"#,
+            namespace, name, version
+        );
+        publish_manifest_to_directory(root, namespace, name, version, &manifest, &[name]);
+    }
+
+    /// Publishes a package with its own manifest, and an empty module in
+    /// `<module>.wasm` for each of `modules`
+    pub(crate) fn publish_manifest_to_directory(
+        root: &Path,
+        namespace: &str,
+        name: &str,
+        version: &str,
+        manifest: &str,
+        modules: &[&str],
+    ) {
+        let module: &[u8] = b"\0asm\x01\0\0\0";
+        let mut builder = tar::Builder::new(Vec::new());
+        let files = modules
+            .iter()
+            .map(|module_name| (format!("{}.wasm", module_name), module));
+        for (path, data) in
+            std::iter::once(("wapm.toml".to_string(), manifest.as_bytes())).chain(files)
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, data).unwrap();
+        }
+        let tar_data = builder.into_inner().unwrap();
+
+        let package_directory = root.join(namespace).join(name);
+        fs::create_dir_all(&package_directory).unwrap();
+        let archive =
+            fs::File::create(package_directory.join(format!("{}.tar.gz", version))).unwrap();
+        let mut gz = GzEncoder::new(archive, Compression::default());
+        std::io::Write::write_all(&mut gz, &tar_data).unwrap();
+        gz.finish().unwrap();
+    }
+
+    #[test]
+    fn resolve_exact_versions_and_ranges() {
+        let registry_dir = create_temp_dir().unwrap();
+        let root: &Path = registry_dir.as_ref();
+        publish_to_directory(root, "_", "foo", "1.0.0");
+        publish_to_directory(root, "_", "foo", "1.2.0");
+        publish_to_directory(root, "_", "foo", "2.0.0");
+
+        let resolved = DirectoryRegistry::resolve_in(
+            root,
+            vec![PackageKey::new_registry_package_range(
+                "_/foo",
+                semver::VersionReq::parse("^1").unwrap(),
+            )],
+        );
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].0.version, Version::new(1, 2, 0));
+
+        let resolved = DirectoryRegistry::resolve_in(
+            root,
+            vec![
+                PackageKey::new_registry_package("_/foo", Version::new(2, 0, 0)),
+                PackageKey::new_registry_package("_/foo", Version::new(3, 0, 0)),
+                PackageKey::new_registry_package("_/bar", Version::new(1, 0, 0)),
+            ],
+        );
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].0.version, Version::new(2, 0, 0));
+    }
+
+    #[test]
+    fn only_archives_in_the_roots_are_local() {
+        let registry_dir = create_temp_dir().unwrap();
+        let root: &Path = registry_dir.as_ref();
+        publish_to_directory(root, "_", "foo", "1.0.0");
+        let elsewhere = create_temp_dir().unwrap();
+        fs::write(elsewhere.path().join("bar.tar.gz"), "").unwrap();
+        let url = |path: &Path| url::Url::from_file_path(path).unwrap().to_string();
+
+        let archive = root.join("_").join("foo").join("1.0.0.tar.gz");
+        assert!(archive_is_in(&url(&archive), vec![root.to_path_buf()]));
+        assert!(!archive_is_in(&url(&archive), vec![]));
+        let outside = elsewhere.path().join("bar.tar.gz");
+        assert!(!archive_is_in(&url(&outside), vec![root.to_path_buf()]));
+        // a path going up out of the root is outside of it
+        let escaping = format!(
+            "{}/_/../../{}/bar.tar.gz",
+            url(root),
+            elsewhere.path().file_name().unwrap().to_string_lossy()
+        );
+        assert!(!archive_is_in(&escaping, vec![root.to_path_buf()]));
+        assert!(!archive_is_in(
+            "https://example.com/_/foo/1.0.0.tar.gz",
+            vec![root.to_path_buf()]
+        ));
+    }
+
+    #[test]
+    fn install_from_directory_registry() {
+        let registry_dir = create_temp_dir().unwrap();
+        let root: &Path = registry_dir.as_ref();
+        publish_to_directory(root, "_", "foo", "1.0.0");
+        let install_dir = create_temp_dir().unwrap();
+        let install_dir: &Path = install_dir.as_ref();
+
+        let resolved = ResolvedPackages {
+            packages: DirectoryRegistry::resolve_in(
+                root,
+                vec![PackageKey::new_registry_package(
+                    "_/foo",
+                    Version::new(1, 0, 0),
+                )],
+            ),
+        };
+        let installed =
+            InstalledPackages::install::<DirectoryRegistry>(install_dir, resolved, true).unwrap();
+
+        assert_eq!(installed.packages.len(), 1);
+        let (key, manifest, _) = &installed.packages[0];
+        assert_eq!(key.version, Version::new(1, 0, 0));
+        assert_eq!(manifest.package.version, Version::new(1, 0, 0));
+        assert!(install_dir
+            .join("wapm_packages")
+            .join("_")
+            .join("foo@1.0.0")
+            .join("wapm.toml")
+            .is_file());
+    }
+
+    #[cfg(feature = "full")]
+    #[test]
+    fn changed_archives_do_not_match_the_lockfile() {
+        use crate::data::lock::lockfile::{FeatureMap, Lockfile};
+        use crate::dataflow::lockfile_packages::LockfilePackages;
+
+        let registry_dir = create_temp_dir().unwrap();
+        let root: &Path = registry_dir.as_ref();
+        publish_to_directory(root, "_", "foo", "1.0.0");
+        let install_dir = create_temp_dir().unwrap();
+        let install_dir: &Path = install_dir.as_ref();
+        let install = || {
+            let resolved = ResolvedPackages {
+                packages: DirectoryRegistry::resolve_in(
+                    root,
+                    vec![PackageKey::new_registry_package(
+                        "_/foo",
+                        Version::new(1, 0, 0),
+                    )],
+                ),
+            };
+            InstalledPackages::install::<DirectoryRegistry>(install_dir, resolved, true)
+        };
+
+        // lock the package with the integrity of its archive
+        let installed = install().unwrap();
+        let lockfile_packages =
+            LockfilePackages::from_installed_packages(&installed, &FeatureMap::new()).unwrap();
+        let modules = lockfile_packages
+            .packages
+            .values()
+            .flat_map(|package| package.modules.iter().cloned())
+            .collect::<Vec<_>>();
+        assert!(modules[0]
+            .integrity
+            .as_deref()
+            .unwrap()
+            .starts_with("sha256-"));
+        let mut lockfile = Lockfile {
+            modules: Default::default(),
+            commands: Default::default(),
+            features: Default::default(),
+        };
+        for module in modules {
+            lockfile
+                .modules
+                .entry(module.package_name.clone())
+                .or_default()
+                .entry(Version::new(1, 0, 0))
+                .or_default()
+                .insert(module.name.clone(), module);
+        }
+        lockfile.save(install_dir).unwrap();
+
+        // the same archive installs again
+        install().unwrap();
+
+        // the registry serves another archive for the same version
+        let manifest = r#"[package]
+name = "_/foo"
+version = "1.0.0"
+description = "a different package"
+
+[[module]]
+name = "foo"
+source = "foo.wasm"
+"#;
+        publish_manifest_to_directory(root, "_", "foo", "1.0.0", manifest, &["foo"]);
+        assert!(matches!(
+            install(),
+            Err(installed_packages::Error::IntegrityMismatch(..))
+        ));
+    }
+}
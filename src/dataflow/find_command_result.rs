@@ -1,8 +1,13 @@
 use crate::config::Config;
 use crate::data::lock::lockfile::{Lockfile, LockfileError};
+use crate::data::lock::lockfile_command::LockfileCommand;
 use crate::data::manifest::Manifest;
+use crate::data::manifest_extensions::ManifestExtensions;
+use crate::dataflow::installed_packages::PackageFormat;
 use crate::dataflow::lockfile_packages::LockfileResult;
 use crate::dataflow::manifest_packages::ManifestResult;
+use semver::Version;
+use std::fmt;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
@@ -86,6 +91,38 @@ pub enum Error {
     CouldNotOpenGlobalsDirectory(String, String),
 }
 
+/// The package a command was resolved from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandPackage {
+    pub name: String,
+    pub version: Version,
+    /// Whether it is the package of the directory rather than one installed in it
+    pub is_local_package: bool,
+    /// The format of the archive it was installed from
+    pub format: PackageFormat,
+}
+
+impl CommandPackage {
+    fn from_lockfile_command(lockfile_command: &LockfileCommand, is_local_package: bool) -> Self {
+        Self {
+            name: lockfile_command.package_name.clone(),
+            version: lockfile_command.package_version.clone(),
+            is_local_package,
+            format: PackageFormat::TarGz,
+        }
+    }
+}
+
+impl fmt::Display for CommandPackage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_local_package {
+            write!(f, "the local package {}@{}", self.name, self.version)
+        } else {
+            write!(f, "the installed package {}@{}", self.name, self.version)
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum FindCommandResult {
     CommandNotFound {
@@ -99,6 +136,7 @@ pub enum FindCommandResult {
         args: Option<String>,
         module_name: String,
         prehashed_cache_key: Option<String>,
+        package: CommandPackage,
     },
     Error(anyhow::Error),
 }
@@ -139,6 +177,7 @@ impl FindCommandResult {
                             module_name: module.name.clone(),
                             // don't use prehashed cache key for local modules
                             prehashed_cache_key: None,
+                            package: CommandPackage::from_lockfile_command(lockfile_command, true),
                         },
                         None => FindCommandResult::Error(
                             Error::CommandFoundButCorrespondingModuleIsMissing(
@@ -174,6 +213,10 @@ impl FindCommandResult {
                                 module_name: lockfile_module.name.clone(),
                                 prehashed_cache_key: lockfile
                                     .get_prehashed_cache_key_from_command(lockfile_command),
+                                package: CommandPackage {
+                                    format: lockfile_module.format,
+                                    ..CommandPackage::from_lockfile_command(lockfile_command, false)
+                                },
                             }
                         }
                         Err(e) => FindCommandResult::Error(e),
@@ -211,6 +254,10 @@ impl FindCommandResult {
                         module_name: lockfile_module.name.clone(),
                         prehashed_cache_key: lockfile
                             .get_prehashed_cache_key_from_command(lockfile_command),
+                        package: CommandPackage {
+                            format: lockfile_module.format,
+                            ..CommandPackage::from_lockfile_command(lockfile_command, false)
+                        },
                     };
                 }
                 Err(e) => {
@@ -298,6 +345,8 @@ pub struct Command {
     pub is_global: bool,
     /// the prehashed module key
     pub prehashed_cache_key: Option<String>,
+    /// the package the command was resolved from
+    pub package: CommandPackage,
 }
 
 /// Get a command from anywhere, where anywhere is the set of packages in the local lockfile and the global lockfile.
@@ -321,6 +370,7 @@ pub fn get_command_from_anywhere<S: AsRef<str>>(command_name: S) -> Result<Comma
             args,
             module_name,
             prehashed_cache_key,
+            package,
         } => {
             return Ok(Command {
                 source,
@@ -329,6 +379,7 @@ pub fn get_command_from_anywhere<S: AsRef<str>>(command_name: S) -> Result<Comma
                 module_name,
                 is_global: false,
                 prehashed_cache_key,
+                package,
             });
         }
         FindCommandResult::Error(e) => {
@@ -361,6 +412,7 @@ pub fn get_command_from_anywhere<S: AsRef<str>>(command_name: S) -> Result<Comma
             args,
             module_name,
             prehashed_cache_key,
+            package,
         } => {
             return Ok(Command {
                 source,
@@ -369,6 +421,7 @@ pub fn get_command_from_anywhere<S: AsRef<str>>(command_name: S) -> Result<Comma
                 module_name,
                 is_global: true,
                 prehashed_cache_key,
+                package,
             });
         }
         FindCommandResult::Error(e) => {
@@ -391,3 +444,156 @@ pub fn get_command_from_anywhere<S: AsRef<str>>(command_name: S) -> Result<Comma
         global_log,
     });
 }
+
+/// A command that can be run in a directory, for listing it to the user
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AvailableCommand {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// The commands that can be run in `directory`: the ones of its manifest first, then the
+/// ones of its installed dependencies
+pub fn get_available_commands(directory: &Path) -> anyhow::Result<Vec<AvailableCommand>> {
+    let mut commands: Vec<AvailableCommand> = ManifestExtensions::find_in_directory(directory)?
+        .command
+        .into_iter()
+        .map(|command| AvailableCommand {
+            name: command.name,
+            description: command.description,
+        })
+        .collect();
+    match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => {
+            for (name, command) in lockfile.commands {
+                if !commands.iter().any(|c| c.name == name) {
+                    commands.push(AvailableCommand {
+                        name,
+                        description: command.description,
+                    });
+                }
+            }
+        }
+        LockfileResult::NoLockfile => {}
+        LockfileResult::LockfileError(e) => return Err(e.into()),
+    }
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::lock::lockfile::{CommandMap, ModuleMap};
+    use crate::data::lock::lockfile_module::LockfileModule;
+    use crate::util::create_temp_dir;
+
+    fn lockfile_command(name: &str, package_name: &str, package_version: &str) -> LockfileCommand {
+        LockfileCommand {
+            name: name.to_string(),
+            package_name: package_name.to_string(),
+            package_version: Version::parse(package_version).unwrap(),
+            module: name.to_string(),
+            is_top_level_dependency: true,
+            main_args: None,
+            description: None,
+        }
+    }
+
+    fn found_package(result: FindCommandResult) -> CommandPackage {
+        match result {
+            FindCommandResult::CommandFound { package, .. } => package,
+            otherwise => panic!("the command was not found: {:?}", otherwise),
+        }
+    }
+
+    /// A lockfile with the command of the package `_/app` and the command of the
+    /// installed package `_/sqlite`
+    fn lockfile() -> Lockfile {
+        let mut modules = ModuleMap::new();
+        modules
+            .entry("_/sqlite".to_string())
+            .or_default()
+            .entry(Version::new(0, 1, 1))
+            .or_default()
+            .insert(
+                "sqlite".to_string(),
+                LockfileModule {
+                    name: "sqlite".to_string(),
+                    package_name: "_/sqlite".to_string(),
+                    package_version: "0.1.1".to_string(),
+                    package_path: "_/sqlite@0.1.1".to_string(),
+                    source: "package.webc".to_string(),
+                    format: PackageFormat::Webc,
+                    ..Default::default()
+                },
+            );
+        let mut commands = CommandMap::new();
+        commands.insert("app".to_string(), lockfile_command("app", "_/app", "1.0.0"));
+        commands.insert(
+            "sqlite".to_string(),
+            lockfile_command("sqlite", "_/sqlite", "0.1.1"),
+        );
+        Lockfile {
+            modules,
+            commands,
+            features: Default::default(),
+        }
+    }
+
+    #[test]
+    fn command_resolves_to_the_local_package_or_an_installed_one() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let mut manifest: Manifest = toml::from_str(
+            r#"[package]
+name = "_/app"
+version = "1.0.0"
+description = "test package"
+
+[[module]]
+name = "app"
+source = "app.wasm"
+"#,
+        )
+        .unwrap();
+        manifest.base_directory_path = tmp_dir.path().to_path_buf();
+
+        let local = FindCommandResult::find_command_in_manifest_and_lockfile(
+            "app",
+            manifest.clone(),
+            lockfile(),
+            tmp_dir.path(),
+        );
+        assert_eq!(
+            found_package(local),
+            CommandPackage {
+                name: "_/app".to_string(),
+                version: Version::new(1, 0, 0),
+                is_local_package: true,
+                format: PackageFormat::TarGz,
+            }
+        );
+
+        let installed = FindCommandResult::find_command_in_manifest_and_lockfile(
+            "sqlite",
+            manifest,
+            lockfile(),
+            tmp_dir.path(),
+        );
+        let package = found_package(installed);
+        assert_eq!(
+            package,
+            CommandPackage {
+                name: "_/sqlite".to_string(),
+                version: Version::new(0, 1, 1),
+                is_local_package: false,
+                format: PackageFormat::Webc,
+            }
+        );
+        assert_eq!(package.to_string(), "the installed package _/sqlite@0.1.1");
+
+        // without a manifest, every command is from an installed package
+        let installed =
+            FindCommandResult::find_command_in_lockfile("sqlite", lockfile(), tmp_dir.path());
+        assert!(!found_package(installed).is_local_package);
+    }
+}
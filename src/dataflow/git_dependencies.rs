@@ -0,0 +1,388 @@
+//! Dependencies fetched from a git repository instead of the registry, declared in the
+//! manifest as `"_/foo" = { git = "https://github.com/org/foo", rev = "abc123" }`.
+//!
+//! The repository is fetched at the pinned revision with a shallow fetch and its files
+//! are installed in `wapm_packages` like the files of a registry package. The lockfile
+//! resolves the modules of the package to `git+<url>?rev=<rev>#<commit>`, so later
+//! installs use the same commit until the `rev` in the manifest changes.
+use crate::data::manifest_extensions::GitDependency;
+use crate::dataflow::installed_packages::InstalledPackages;
+use crate::dataflow::lockfile_packages::LockfilePackages;
+use crate::dataflow::manifest_packages::ManifestResult;
+use crate::dataflow::{
+    normalize_global_namespace_package_name, PackageKey, WapmPackageKey, WapmPackageRange,
+};
+use crate::util::{
+    create_package_dir, create_temp_dir, fully_qualified_package_display_name,
+    get_package_namespace_and_name,
+};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error)]
+pub enum Error {
+    #[error("Could not fetch \"{0}\" from git. {1}")]
+    Fetch(String, String),
+    #[error("The git repository of \"{0}\" does not contain a valid manifest. {1}")]
+    Manifest(String, String),
+    #[error("The git repository of \"{0}\" contains the package \"{1}\" instead.")]
+    NameMismatch(String, String),
+    #[error("Could not install \"{0}\". {1}")]
+    Install(String, String),
+}
+
+/// The git dependencies of a manifest, installed in the project directory
+#[derive(Debug, Default)]
+pub struct GitPackages<'a> {
+    /// The keys of all the git dependencies, installed now or before
+    pub keys: HashSet<PackageKey<'a>>,
+    /// The git dependencies that were installed now
+    pub installed: InstalledPackages<'a>,
+}
+
+impl<'a> GitPackages<'a> {
+    /// Installs the git dependencies that are not installed at the locked commit yet
+    pub fn install(
+        directory: &Path,
+        dependencies: BTreeMap<&str, &GitDependency>,
+        lockfile_packages: &LockfilePackages<'a>,
+    ) -> Result<Self, Error> {
+        let missing_packages = lockfile_packages.find_missing_packages(directory);
+        let mut git_packages = Self::default();
+        for (name, dependency) in dependencies {
+            let name = normalize_global_namespace_package_name(Cow::Borrowed(name)).to_string();
+            let source = source_url(dependency);
+            let locked = lockfile_packages
+                .packages
+                .iter()
+                .find_map(|(key, data)| match key {
+                    PackageKey::WapmPackage(WapmPackageKey { name: n, .. }) if *n == name => data
+                        .modules
+                        .iter()
+                        .find_map(|module| locked_commit(&module.resolved, &source))
+                        .map(|commit| (key, commit)),
+                    _ => None,
+                });
+            let rev = match locked {
+                Some((key, _)) if !missing_packages.contains(key) => {
+                    git_packages.keys.insert(key.clone());
+                    continue;
+                }
+                Some((_, commit)) => commit,
+                None => dependency.rev.clone().unwrap_or_else(|| "HEAD".to_string()),
+            };
+            let installed_package = install_git_package(directory, name, dependency, &rev)?;
+            git_packages
+                .keys
+                .insert(PackageKey::WapmPackage(installed_package.0.clone()));
+            git_packages.installed.packages.push(installed_package);
+        }
+        Ok(git_packages)
+    }
+
+    /// Whether the key is any version of one of the git dependencies
+    pub fn is_git_package(&self, key: &PackageKey) -> bool {
+        let name = match key {
+            PackageKey::WapmPackage(WapmPackageKey { name, .. }) => name,
+            PackageKey::WapmPackageRange(WapmPackageRange { name, .. }) => name,
+        };
+        self.keys.iter().any(|git_key| match git_key {
+            PackageKey::WapmPackage(git_key) => git_key.name == *name,
+            _ => false,
+        })
+    }
+}
+
+/// The URL the modules of a git dependency are resolved to, without the commit
+fn source_url(dependency: &GitDependency) -> String {
+    match dependency.rev {
+        Some(ref rev) => format!("git+{}?rev={}", dependency.git, rev),
+        None => format!("git+{}", dependency.git),
+    }
+}
+
+/// The commit in a `resolved` lockfile URL, if it was resolved from `source`
+fn locked_commit(resolved: &str, source: &str) -> Option<String> {
+    let (resolved_source, commit) = resolved.rsplit_once('#')?;
+    if resolved_source == source && !commit.is_empty() {
+        Some(commit.to_string())
+    } else {
+        None
+    }
+}
+
+fn run_git(args: &[&str], directory: &Path) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(directory)
+        .output()
+        .map_err(|e| format!("Could not run git: {}", e))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// Fetches only the commit `rev` points to into the empty directory `checkout`, and
+/// returns the hash of that commit. The `url` and `rev` come from a manifest, so they
+/// can't be git options like `--upload-pack`.
+fn fetch(url: &str, rev: &str, checkout: &Path) -> Result<String, String> {
+    for value in &[url, rev] {
+        if value.starts_with('-') {
+            return Err(format!("\"{}\" is not a git repository or revision", value));
+        }
+    }
+    run_git(&["init", "--quiet"], checkout)?;
+    run_git(
+        &["fetch", "--quiet", "--depth", "1", "--", url, rev],
+        checkout,
+    )?;
+    run_git(&["checkout", "--quiet", "FETCH_HEAD"], checkout)?;
+    run_git(&["rev-parse", "HEAD"], checkout)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        if path.is_dir() {
+            copy_dir(&path, &to.join(entry.file_name()))?;
+        } else {
+            fs::copy(&path, to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+fn install_git_package<'a>(
+    directory: &Path,
+    name: String,
+    dependency: &GitDependency,
+    rev: &str,
+) -> Result<(WapmPackageKey<'a>, crate::data::manifest::Manifest, String), Error> {
+    info!("Fetching {} from {} at {}", name, dependency.git, rev);
+    let checkout_dir = create_temp_dir().map_err(|e| Error::Fetch(name.clone(), e.to_string()))?;
+    let checkout: &Path = checkout_dir.as_ref();
+    let commit =
+        fetch(&dependency.git, rev, checkout).map_err(|e| Error::Fetch(name.clone(), e))?;
+
+    let manifest = match ManifestResult::find_in_directory(checkout) {
+        ManifestResult::Manifest(manifest) => manifest,
+        ManifestResult::NoManifest => {
+            return Err(Error::Manifest(
+                name,
+                "The manifest was not found.".to_string(),
+            ))
+        }
+        ManifestResult::ManifestError(e) => return Err(Error::Manifest(name, e.to_string())),
+    };
+    let package_name =
+        normalize_global_namespace_package_name(Cow::Borrowed(manifest.package.name.as_str()));
+    if package_name != name {
+        return Err(Error::NameMismatch(name, package_name.to_string()));
+    }
+
+    let key = WapmPackageKey {
+        name: Cow::Owned(name.clone()),
+        version: manifest.package.version.clone(),
+    };
+    let (namespace, pkg_name) = get_package_namespace_and_name(&name)
+        .map_err(|e| Error::Install(key.to_string(), e.to_string()))?;
+    let package_dir = create_package_dir(
+        directory,
+        namespace,
+        &fully_qualified_package_display_name(pkg_name, &key.version),
+    )
+    .and_then(|package_dir| {
+        // files of a previous commit must not be left behind
+        fs::remove_dir_all(&package_dir)?;
+        copy_dir(checkout, &package_dir)?;
+        Ok(package_dir)
+    })
+    .map_err(|e| Error::Install(key.to_string(), e.to_string()))?;
+
+    let manifest = match ManifestResult::find_in_directory(&package_dir) {
+        ManifestResult::Manifest(manifest) => manifest,
+        _ => {
+            return Err(Error::Install(
+                key.to_string(),
+                "The manifest was not installed.".to_string(),
+            ))
+        }
+    };
+    let resolved = format!("{}#{}", source_url(dependency), commit);
+    Ok((key, manifest, resolved))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::lock::lockfile_module::LockfileModule;
+    use crate::dataflow::lockfile_packages::LockfilePackage;
+
+    fn git(args: &[&str], directory: &Path) -> String {
+        run_git(
+            &[
+                &["-c", "user.name=test", "-c", "user.email=test@example.com"],
+                args,
+            ]
+            .concat(),
+            directory,
+        )
+        .unwrap()
+    }
+
+    /// Creates a bare repository with two commits of the package `_/foo`, the second
+    /// one tagged `v2`. Returns the URL of the repository and the first commit.
+    fn bare_repository(root: &Path) -> (String, String) {
+        let work = root.join("work");
+        fs::create_dir_all(&work).unwrap();
+        git(&["init", "--quiet"], &work);
+        let mut first_commit = String::new();
+        for version in &["1.0.0", "2.0.0"] {
+            fs::write(
+                work.join("wapm.toml"),
+                format!(
+                    "[package]\nname = \"foo\"\nversion = \"{}\"\ndescription = \"test\"\n\n[[module]]\nname = \"foo\"\nsource = \"foo.wasm\"\n",
+                    version
+                ),
+            )
+            .unwrap();
+            fs::write(work.join("foo.wasm"), version).unwrap();
+            git(&["add", "."], &work);
+            git(&["commit", "--quiet", "-m", version], &work);
+            if first_commit.is_empty() {
+                first_commit = git(&["rev-parse", "HEAD"], &work);
+            }
+        }
+        git(&["tag", "v2"], &work);
+        git(&["clone", "--quiet", "--bare", "work", "foo.git"], root);
+        let url = url::Url::from_file_path(root.join("foo.git")).unwrap();
+        (url.to_string(), first_commit)
+    }
+
+    fn installed_module(directory: &Path, version: &str) -> String {
+        fs::read_to_string(
+            directory
+                .join("wapm_packages")
+                .join("_")
+                .join(format!("foo@{}", version))
+                .join("foo.wasm"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn install_git_dependency_at_tag_and_commit() {
+        let repository_dir = create_temp_dir().unwrap();
+        let (url, first_commit) = bare_repository(repository_dir.path());
+        let install_dir = create_temp_dir().unwrap();
+        let install_dir = install_dir.path();
+
+        for (rev, version) in &[("v2", "2.0.0"), (first_commit.as_str(), "1.0.0")] {
+            let dependency = GitDependency {
+                git: url.clone(),
+                rev: Some(rev.to_string()),
+            };
+            let mut dependencies = BTreeMap::new();
+            dependencies.insert("foo", &dependency);
+            let git_packages =
+                GitPackages::install(install_dir, dependencies, &LockfilePackages::default())
+                    .unwrap();
+            let (key, manifest, resolved) = &git_packages.installed.packages[0];
+            assert_eq!(key.name, "_/foo");
+            assert_eq!(key.version.to_string(), *version);
+            assert_eq!(manifest.package.version.to_string(), *version);
+            assert!(resolved.starts_with(&format!("git+{}?rev={}#", url, rev)));
+            assert_eq!(installed_module(install_dir, version), *version);
+        }
+    }
+
+    #[test]
+    fn git_options_are_not_fetched() {
+        let checkout = create_temp_dir().unwrap();
+        let marker = checkout.path().join("marker");
+        let upload_pack = format!("--upload-pack=touch {}", marker.display());
+        assert!(fetch(&upload_pack, "main", checkout.path()).is_err());
+        assert!(fetch(
+            "https://example.com/repo.git",
+            &upload_pack,
+            checkout.path()
+        )
+        .is_err());
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn locked_git_dependency_is_not_fetched_again() {
+        let repository_dir = create_temp_dir().unwrap();
+        let (url, first_commit) = bare_repository(repository_dir.path());
+        let install_dir = create_temp_dir().unwrap();
+        let install_dir = install_dir.path();
+        let dependency = GitDependency {
+            git: url.clone(),
+            rev: Some("v2".to_string()),
+        };
+        let dependencies = || {
+            let mut dependencies = BTreeMap::new();
+            dependencies.insert("_/foo", &dependency);
+            dependencies
+        };
+
+        let git_packages =
+            GitPackages::install(install_dir, dependencies(), &LockfilePackages::default())
+                .unwrap();
+        let (key, manifest, resolved) = &git_packages.installed.packages[0];
+        let module = &manifest.module.as_ref().unwrap()[0];
+        let lockfile_module = LockfileModule::from_module(
+            &manifest.base_directory_path,
+            &key.name,
+            &key.version,
+            module,
+            resolved,
+        );
+        let mut lockfile_packages = LockfilePackages::default();
+        lockfile_packages.packages.insert(
+            PackageKey::WapmPackage(key.clone()),
+            LockfilePackage {
+                modules: vec![lockfile_module.clone()],
+                commands: vec![],
+                features: vec![],
+            },
+        );
+        let git_packages =
+            GitPackages::install(install_dir, dependencies(), &lockfile_packages).unwrap();
+        assert!(git_packages.installed.packages.is_empty());
+        assert_eq!(git_packages.keys.len(), 1);
+
+        // a locked commit is fetched again when its files are missing
+        let mut lockfile_module = lockfile_module;
+        lockfile_module.resolved = format!("git+{}?rev=v2#{}", url, first_commit);
+        lockfile_packages.packages.insert(
+            PackageKey::WapmPackage(key.clone()),
+            LockfilePackage {
+                modules: vec![lockfile_module],
+                commands: vec![],
+                features: vec![],
+            },
+        );
+        fs::remove_dir_all(install_dir.join("wapm_packages")).unwrap();
+        let git_packages =
+            GitPackages::install(install_dir, dependencies(), &lockfile_packages).unwrap();
+        assert_eq!(
+            git_packages.installed.packages[0].0.version.to_string(),
+            "1.0.0"
+        );
+        assert_eq!(installed_module(install_dir, "1.0.0"), "1.0.0");
+    }
+}
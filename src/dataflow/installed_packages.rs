@@ -2,22 +2,30 @@
     not(feature = "full"),
     allow(dead_code, unused_imports, unused_variables)
 )]
+use crate::config::Config;
+use crate::data::lock::lockfile::Lockfile;
 use crate::data::manifest::Manifest;
+use crate::data::manifest_extensions::manifest_from_value;
 #[cfg(feature = "full")]
 use crate::database;
+use crate::dataflow::directory_registry::{is_local_archive_url, DirectoryRegistry};
+use crate::dataflow::lockfile_packages::LockfileResult;
 use crate::dataflow::manifest_packages::ManifestResult;
+use crate::dataflow::package_cache;
 use crate::dataflow::resolved_packages::ResolvedPackages;
 use crate::dataflow::WapmPackageKey;
-use crate::graphql::VERSION;
+use crate::graphql::user_agent;
 #[allow(unused_imports)]
 use crate::keys;
-use crate::util::whoami_distro;
 #[allow(unused_imports)]
 use crate::util::{
     self, create_package_dir, create_temp_dir, fully_qualified_package_display_name,
     get_package_namespace_and_name,
 };
 use flate2::read::GzDecoder;
+#[cfg(feature = "full")]
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fs::{self, OpenOptions};
 use std::io;
 use std::io::{Seek, SeekFrom};
@@ -51,13 +59,60 @@ pub enum Error {
     IoConnection(String),
     #[error("Failed to validate package {0} with key {1}: {2}")]
     FailedToValidateSignature(String, String, String),
+    #[error("The archive of package \"{0}\" does not match the lockfile: its integrity is {2} instead of {1}. Delete it from `wapm.lock` to install it anyway.")]
+    IntegrityMismatch(String, String, String),
+}
+
+/// The file a webc container is stored as in the directory of its package
+pub const WEBC_FILE_NAME: &str = "package.webc";
+
+/// The format of a package archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum PackageFormat {
+    /// A gzipped tarball of the package directory, with its manifest
+    #[default]
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    /// A webc (pirita) container, stored as-is
+    #[serde(rename = "webc")]
+    Webc,
+}
+
+impl PackageFormat {
+    /// The format of the archive at `download_url`, by the extension of its path or
+    /// by its `format` query parameter
+    pub fn from_download_url(download_url: &str) -> Self {
+        let is_webc = match url::Url::parse(download_url) {
+            Ok(url) => {
+                url.path().ends_with(".webc")
+                    || url
+                        .query_pairs()
+                        .any(|(name, value)| name == "format" && value == "webc")
+            }
+            Err(_) => download_url.ends_with(".webc"),
+        };
+        if is_webc {
+            PackageFormat::Webc
+        } else {
+            PackageFormat::TarGz
+        }
+    }
+
+    /// Whether it is the default format, which the lockfile leaves out
+    pub fn is_tar_gz(&self) -> bool {
+        *self == PackageFormat::TarGz
+    }
 }
 
 /// A structure containing installed packages. Currently contains the key, the deserialized
 /// manifest, and the download url.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct InstalledPackages<'a> {
     pub packages: Vec<(WapmPackageKey<'a>, Manifest, String)>,
+    /// The integrity of the archives the packages were installed from
+    pub integrity: HashMap<WapmPackageKey<'a>, String>,
+    /// The format of the archives the packages were installed from
+    pub formats: HashMap<WapmPackageKey<'a>, PackageFormat>,
 }
 
 impl<'a> InstalledPackages<'a> {
@@ -67,26 +122,40 @@ impl<'a> InstalledPackages<'a> {
         resolve_packages: ResolvedPackages<'a>,
         force_insecure_install: bool,
     ) -> Result<Self, Error> {
-        let packages_result: Result<Vec<(WapmPackageKey, PathBuf, String)>, Error> =
-            resolve_packages
-                .packages
-                .into_iter()
-                .map(|(key, (download_url, signature))| {
-                    info!("Installing {}@{}", key.name, key.version);
-                    Installer::install_package(
-                        directory,
-                        key,
-                        download_url.as_str(),
-                        #[cfg(feature = "full")]
-                        signature,
-                        force_insecure_install,
-                    )
-                })
-                .collect();
+        let packages_result: Result<Vec<InstalledPackage>, Error> = resolve_packages
+            .packages
+            .into_iter()
+            .map(|(key, (download_url, signature))| {
+                info!("Installing {}@{}", key.name, key.version);
+                Installer::install_package(
+                    directory,
+                    key,
+                    download_url.as_str(),
+                    #[cfg(feature = "full")]
+                    signature,
+                    force_insecure_install,
+                )
+            })
+            .collect();
+        let installed_packages = packages_result?;
+        let integrity = installed_packages
+            .iter()
+            .filter_map(|(key, _, _, integrity)| Some((key.clone(), integrity.clone()?)))
+            .collect();
+        let formats: HashMap<WapmPackageKey, PackageFormat> = installed_packages
+            .iter()
+            .map(|(key, _, download_url, _)| {
+                (key.clone(), PackageFormat::from_download_url(download_url))
+            })
+            .collect();
         let packages_result: Result<Vec<(WapmPackageKey, Manifest, String)>, Error> =
-            packages_result?
+            installed_packages
                 .into_iter()
-                .map(|(key, dir, download_url)| {
+                .map(|(key, dir, download_url, _)| {
+                    if formats[&key] == PackageFormat::Webc {
+                        let manifest = webc_manifest(&key, &dir)?;
+                        return Ok((key, manifest, download_url));
+                    }
                     let manifest = match ManifestResult::find_in_directory(dir) {
                         ManifestResult::ManifestError(e) => {
                             return Err(Error::InstalledDependencyIsMissingManifest(
@@ -106,10 +175,57 @@ impl<'a> InstalledPackages<'a> {
                 })
                 .collect();
         let packages = packages_result?;
-        Ok(Self { packages })
+        Ok(Self {
+            packages,
+            integrity,
+            formats,
+        })
     }
 }
 
+/// The manifest of a package installed from a webc container. The container is kept
+/// as-is, so the package has one module, the container, and one command named after
+/// the package that runs it.
+fn webc_manifest(key: &WapmPackageKey, package_dir: &Path) -> Result<Manifest, Error> {
+    let (_, name) = get_package_namespace_and_name(&key.name)
+        .map_err(|e| Error::FailedToParsePackageName(key.to_string(), e.to_string()))?;
+    let table = |entries: &[(&str, String)]| {
+        toml::Value::Table(
+            entries
+                .iter()
+                .map(|(name, value)| (name.to_string(), toml::Value::String(value.clone())))
+                .collect(),
+        )
+    };
+    let package = table(&[
+        ("name", key.name.to_string()),
+        ("version", key.version.to_string()),
+        ("description", format!("The webc container of {}", key)),
+    ]);
+    let module = table(&[
+        ("name", name.to_string()),
+        ("source", WEBC_FILE_NAME.to_string()),
+    ]);
+    let command = table(&[("name", name.to_string()), ("module", name.to_string())]);
+    let value = toml::Value::Table(
+        vec![
+            ("package".to_string(), package),
+            ("module".to_string(), toml::Value::Array(vec![module])),
+            ("command".to_string(), toml::Value::Array(vec![command])),
+        ]
+        .into_iter()
+        .collect(),
+    );
+    let mut manifest = manifest_from_value(value)
+        .map_err(|e| Error::InstalledDependencyIsMissingManifest(key.to_string(), e.to_string()))?;
+    manifest.base_directory_path = package_dir.to_path_buf();
+    Ok(manifest)
+}
+
+/// A package an installer unpacked: its key, its directory, its download url and the
+/// integrity of its archive, when it was checked.
+pub type InstalledPackage<'a> = (WapmPackageKey<'a>, PathBuf, String, Option<String>);
+
 /// A trait for injecting an installer for installing wapm packages.
 pub trait Install<'a> {
     fn install_package(
@@ -118,13 +234,63 @@ pub trait Install<'a> {
         download_url: &str,
         #[cfg(feature = "full")] signature: Option<keys::WapmPackageSignature>,
         force_insecure_install: bool,
-    ) -> Result<(WapmPackageKey<'a>, PathBuf, String), Error>;
+    ) -> Result<InstalledPackage<'a>, Error>;
+}
+
+/// The integrity of a package archive, its sha256. When the lockfile in `directory`
+/// has one for the same package version, the archive must have the same.
+#[cfg(feature = "full")]
+pub(crate) fn check_archive_integrity(
+    directory: &Path,
+    key: &WapmPackageKey,
+    archive: &mut fs::File,
+) -> Result<Option<String>, Error> {
+    archive
+        .seek(SeekFrom::Start(0))
+        .and_then(|_| {
+            let mut hasher = Sha256::new();
+            io::copy(archive, &mut hasher)?;
+            Ok(hasher)
+        })
+        .map_err(|e| Error::IoCopy(key.to_string(), e.to_string()))
+        .and_then(|hasher| {
+            let integrity = format!("sha256-{:x}", hasher.finalize());
+            if let LockfileResult::Lockfile(lockfile) = LockfileResult::find_in_directory(directory)
+            {
+                check_locked_integrity(&lockfile, key, &integrity)?;
+            }
+            Ok(Some(integrity))
+        })
+}
+
+#[cfg(not(feature = "full"))]
+pub(crate) fn check_archive_integrity(
+    _directory: &Path,
+    _key: &WapmPackageKey,
+    _archive: &mut fs::File,
+) -> Result<Option<String>, Error> {
+    Ok(None)
+}
+
+fn check_locked_integrity(
+    lockfile: &Lockfile,
+    key: &WapmPackageKey,
+    integrity: &str,
+) -> Result<(), Error> {
+    match lockfile.get_integrity(&key.name, &key.version) {
+        Some(locked) if locked != integrity => Err(Error::IntegrityMismatch(
+            key.to_string(),
+            locked.to_string(),
+            integrity.to_string(),
+        )),
+        _ => Ok(()),
+    }
 }
 
 pub struct RegistryInstaller;
 
 impl RegistryInstaller {
-    fn decompress_and_extract_archive<P: AsRef<Path>, F: io::Seek + io::Read>(
+    pub(crate) fn decompress_and_extract_archive<P: AsRef<Path>, F: io::Seek + io::Read>(
         mut compressed_archive: F,
         pkg_name: P,
         _key: &WapmPackageKey,
@@ -136,6 +302,26 @@ impl RegistryInstaller {
         archive.unpack(&pkg_name)?;
         Ok(())
     }
+
+    /// Puts a downloaded archive in `package_dir` the way its `format` needs: a tarball
+    /// is extracted, a webc container is stored as-is.
+    pub(crate) fn unpack_archive(
+        mut archive: fs::File,
+        package_dir: &Path,
+        key: &WapmPackageKey,
+        format: PackageFormat,
+    ) -> Result<(), Error> {
+        match format {
+            PackageFormat::TarGz => Self::decompress_and_extract_archive(archive, package_dir, key)
+                .map_err(|e| Error::Decompression(key.to_string(), e.to_string())),
+            PackageFormat::Webc => archive
+                .seek(SeekFrom::Start(0))
+                .and_then(|_| fs::File::create(package_dir.join(WEBC_FILE_NAME)))
+                .and_then(|mut dest| io::copy(&mut archive, &mut dest))
+                .map(|_| ())
+                .map_err(|e| Error::IoCopy(key.to_string(), e.to_string())),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -293,7 +479,28 @@ impl<'a> Install<'a> for RegistryInstaller {
         download_url: &str,
         #[cfg(feature = "full")] signature: Option<keys::WapmPackageSignature>,
         force_insecure_install: bool,
-    ) -> Result<(WapmPackageKey<'a>, PathBuf, String), Error> {
+    ) -> Result<InstalledPackage<'a>, Error> {
+        // only the archives of the local registry and of the package cache are installed
+        // from the file system, not any archive a registry or a lockfile points to
+        if download_url.starts_with("file://") {
+            if !is_local_archive_url(download_url) {
+                return Err(Error::Download(
+                    key.to_string(),
+                    format!(
+                        "\"{}\" is neither in the local registry nor in the package cache",
+                        download_url
+                    ),
+                ));
+            }
+            return DirectoryRegistry::install_package(
+                directory,
+                key,
+                download_url,
+                #[cfg(feature = "full")]
+                signature,
+                force_insecure_install,
+            );
+        }
         let (namespace, pkg_name) = get_package_namespace_and_name(&key.name)
             .map_err(|e| Error::FailedToParsePackageName(key.to_string(), e.to_string()))?;
         let fully_qualified_package_name: String =
@@ -314,25 +521,28 @@ impl<'a> Install<'a> for RegistryInstaller {
 
             builder.build().unwrap()
         };
-        let user_agent = format!(
-            "wapm/{} {} {}",
-            VERSION,
-            whoami::platform(),
-            whoami_distro(),
-        );
-        let mut response = client
+        let config = Config::from_file().unwrap_or_default();
+        let mut request = client
             .get(download_url)
-            .header(header::USER_AGENT, user_agent)
-            .send()
-            .map_err(|e| {
-                let error_message = e.to_string();
-                #[cfg(feature = "telemetry")]
-                {
-                    let e = e.into();
-                    sentry::integrations::anyhow::capture_anyhow(&e);
-                }
-                Error::Download(key.to_string(), error_message)
-            })?;
+            .header(header::USER_AGENT, user_agent(&config));
+        // the registry headers can carry credentials too, like the login token
+        if is_registry_download(&config, download_url) {
+            for (name, value) in &config.registry_headers {
+                request = request.header(name.as_str(), value.as_str());
+            }
+        }
+        if let Some(authorization) = download_authorization(&config, download_url) {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        let mut response = request.send().map_err(|e| {
+            let error_message = e.to_string();
+            #[cfg(feature = "telemetry")]
+            {
+                let e = e.into();
+                sentry::integrations::anyhow::capture_anyhow(&e);
+            }
+            Error::Download(key.to_string(), error_message)
+        })?;
 
         let key_sign_end_step = get_key_sign_end_step(
             force_insecure_install,
@@ -353,17 +563,48 @@ impl<'a> Install<'a> for RegistryInstaller {
             .read(true)
             .write(true)
             .create(true)
-            .open(temp_tar_gz_path)
+            .open(&temp_tar_gz_path)
             .map_err(|e| Error::IoCopy(key.to_string(), e.to_string()))?;
 
         io::copy(&mut response, &mut dest)
             .map_err(|e| Error::Download(key.to_string(), e.to_string()))?;
 
         key_sign_end_step(&mut dest)?;
+        let integrity = check_archive_integrity(directory, &key, &mut dest)?;
+        let format = PackageFormat::from_download_url(download_url);
+        // the package cache only has tarballs
+        if let (Some(cache), PackageFormat::TarGz) =
+            (package_cache::package_cache_directory(), format)
+        {
+            if let Err(e) = package_cache::store(&cache, &key, &temp_tar_gz_path) {
+                warn!("Could not add {} to the package cache: {}", key, e);
+            }
+        }
+
+        Self::unpack_archive(dest, &package_dir, &key, format)?;
+        Ok((key, package_dir, download_url.to_string(), integrity))
+    }
+}
+
+/// The `Authorization` header of a download from the active registry. The login token
+/// is only sent to the scheme, host and port of the registry, never to another host
+/// like the CDN the download URL may point to.
+fn download_authorization(config: &Config, download_url: &str) -> Option<String> {
+    if !is_registry_download(config, download_url) {
+        return None;
+    }
+    let registry = config.registry.get_current_registry();
+    let token = config.registry.get_login_token_for_registry(&registry)?;
+    Some(format!("Bearer {}", token))
+}
 
-        Self::decompress_and_extract_archive(dest, &package_dir, &key)
-            .map_err(|e| Error::Decompression(key.to_string(), e.to_string()))?;
-        Ok((key, package_dir, download_url.to_string()))
+/// Whether `download_url` has the scheme, host and port of the active registry
+fn is_registry_download(config: &Config, download_url: &str) -> bool {
+    let registry_url = url::Url::parse(&config.registry.get_current_registry());
+    let download_url = url::Url::parse(download_url);
+    match (registry_url, download_url) {
+        (Ok(registry_url), Ok(download_url)) => download_url.origin() == registry_url.origin(),
+        _ => false,
     }
 }
 
@@ -371,7 +612,7 @@ type KeySignEndStep = Box<dyn FnOnce(&mut fs::File) -> Result<(), Error>>;
 
 /// Get the step to perform after package is decompressed: may be a no-op or may
 /// execute side effects such as logging to the user.
-fn get_key_sign_end_step(
+pub(crate) fn get_key_sign_end_step(
     force_insecure_install: bool,
     namespace: &str,
     fully_qualified_package_name: String,
@@ -432,3 +673,199 @@ fn verify_signature_on_package(
     minisign::verify(&public_key, &sig_box, dest, true, false)
         .map_err(|e| anyhow!("Could not validate signature: {}", e.to_string()))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Registries, Registry};
+    use crate::data::lock::lockfile_module::LockfileModule;
+    use semver::Version;
+
+    #[test]
+    fn the_format_is_found_from_the_download_url() {
+        for url in &[
+            "https://registry.wapm.io/packages/_/foo/foo-1.0.0.tar.gz",
+            "https://cdn.example.com/_/foo/1.0.0?format=tar.gz",
+            "file:///registry/_/foo/1.0.0.tar.gz",
+        ] {
+            assert_eq!(
+                PackageFormat::from_download_url(url),
+                PackageFormat::TarGz,
+                "{}",
+                url
+            );
+        }
+        for url in &[
+            "https://registry.wapm.io/packages/_/foo/foo-1.0.0.webc",
+            "https://cdn.example.com/_/foo/1.0.0.webc?token=abc",
+            "https://cdn.example.com/_/foo/1.0.0?format=webc",
+            "https://cdn.example.com/_/foo/1.0.0?token=abc&format=webc",
+            "file:///registry/_/foo/1.0.0.webc",
+        ] {
+            assert_eq!(
+                PackageFormat::from_download_url(url),
+                PackageFormat::Webc,
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn archives_are_unpacked_by_their_format() {
+        use crate::data::lock::lockfile::FeatureMap;
+        use crate::dataflow::directory_registry::test::publish_to_directory;
+        use crate::dataflow::lockfile_packages::LockfilePackages;
+        use crate::dataflow::PackageKey;
+
+        let registry_dir = create_temp_dir().unwrap();
+        publish_to_directory(registry_dir.path(), "_", "foo", "1.0.0");
+        let webc = b"\0webc002 not a real container";
+        fs::create_dir_all(registry_dir.path().join("_/bar")).unwrap();
+        fs::write(registry_dir.path().join("_/bar/1.0.0.webc"), webc).unwrap();
+        let url = |path: &str| {
+            url::Url::from_file_path(registry_dir.path().join(path))
+                .unwrap()
+                .to_string()
+        };
+        let foo = WapmPackageKey {
+            name: "_/foo".into(),
+            version: Version::new(1, 0, 0),
+        };
+        let bar = WapmPackageKey {
+            name: "_/bar".into(),
+            version: Version::new(1, 0, 0),
+        };
+        let resolved = ResolvedPackages {
+            packages: vec![
+                (foo.clone(), (url("_/foo/1.0.0.tar.gz"), None)),
+                (bar.clone(), (url("_/bar/1.0.0.webc"), None)),
+            ],
+        };
+        let install_dir = create_temp_dir().unwrap();
+        let installed =
+            InstalledPackages::install::<DirectoryRegistry>(install_dir.path(), resolved, true)
+                .unwrap();
+        assert_eq!(installed.formats[&foo], PackageFormat::TarGz);
+        assert_eq!(installed.formats[&bar], PackageFormat::Webc);
+
+        // the tarball is extracted
+        let packages_dir = install_dir.path().join("wapm_packages/_");
+        assert!(packages_dir.join("foo@1.0.0/wapm.toml").is_file());
+        assert!(packages_dir.join("foo@1.0.0/foo.wasm").is_file());
+        // the container is stored as it was downloaded
+        assert_eq!(
+            fs::read(packages_dir.join("bar@1.0.0").join(WEBC_FILE_NAME)).unwrap(),
+            webc
+        );
+        assert!(!packages_dir.join("bar@1.0.0/wapm.toml").exists());
+
+        // the container is the module of the only command of its package
+        let lockfile_packages =
+            LockfilePackages::from_installed_packages(&installed, &FeatureMap::new()).unwrap();
+        let package = |name: &str| {
+            lockfile_packages
+                .packages
+                .iter()
+                .find(|(key, _)| matches!(key, PackageKey::WapmPackage(key) if key.name == name))
+                .map(|(_, package)| package)
+                .unwrap()
+        };
+        let bar_package = package("_/bar");
+        assert_eq!(bar_package.modules.len(), 1);
+        assert_eq!(bar_package.modules[0].name, "bar");
+        assert_eq!(bar_package.modules[0].source, WEBC_FILE_NAME);
+        assert_eq!(bar_package.modules[0].format, PackageFormat::Webc);
+        assert_eq!(bar_package.commands.len(), 1);
+        assert_eq!(bar_package.commands[0].name, "bar");
+        let foo_package = package("_/foo");
+        assert_eq!(foo_package.modules[0].source, "foo.wasm");
+        assert_eq!(foo_package.modules[0].format, PackageFormat::TarGz);
+    }
+
+    #[test]
+    fn file_urls_outside_the_local_directories_are_not_installed() {
+        use crate::dataflow::directory_registry::test::publish_to_directory;
+
+        let registry_dir = create_temp_dir().unwrap();
+        publish_to_directory(registry_dir.path(), "_", "foo", "1.0.0");
+        let key = WapmPackageKey {
+            name: "_/foo".into(),
+            version: Version::new(1, 0, 0),
+        };
+        let download_url = url::Url::from_file_path(registry_dir.path().join("_/foo/1.0.0.tar.gz"))
+            .unwrap()
+            .to_string();
+        let resolved = ResolvedPackages {
+            packages: vec![(key, (download_url, None))],
+        };
+        let install_dir = create_temp_dir().unwrap();
+        let result =
+            InstalledPackages::install::<RegistryInstaller>(install_dir.path(), resolved, true);
+        assert!(matches!(result, Err(Error::Download(..))));
+        assert!(!install_dir
+            .path()
+            .join("wapm_packages/_/foo@1.0.0")
+            .exists());
+    }
+
+    #[test]
+    fn the_format_is_only_in_the_lockfile_of_webc_packages() {
+        let module = LockfileModule {
+            name: "foo".to_string(),
+            ..Default::default()
+        };
+        let toml = toml::to_string(&module).unwrap();
+        assert!(!toml.contains("format"));
+        let webc_module = LockfileModule {
+            format: PackageFormat::Webc,
+            ..module
+        };
+        let toml = toml::to_string(&webc_module).unwrap();
+        assert!(toml.contains("format = \"webc\""));
+        let parsed: LockfileModule = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed, webc_module);
+    }
+
+    #[test]
+    fn the_token_is_only_sent_to_the_registry_host() {
+        let config = Config {
+            registry: Registries::Single(Registry {
+                url: "https://registry.example.com/graphql".to_string(),
+                token: Some("secret".to_string()),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            download_authorization(
+                &config,
+                "https://registry.example.com/packages/_/foo/1.0.0.tar.gz"
+            ),
+            Some("Bearer secret".to_string())
+        );
+        for url in &[
+            "https://cdn.example.com/_/foo/1.0.0.tar.gz",
+            "http://registry.example.com/packages/_/foo/1.0.0.tar.gz",
+            "https://registry.example.com:8443/packages/_/foo/1.0.0.tar.gz",
+            "https://registry.example.com.evil.com/packages/_/foo/1.0.0.tar.gz",
+        ] {
+            assert_eq!(download_authorization(&config, url), None, "{}", url);
+        }
+
+        // the registry headers follow the same rule
+        assert!(is_registry_download(
+            &config,
+            "https://registry.example.com/packages/_/foo/1.0.0.tar.gz"
+        ));
+        assert!(!is_registry_download(
+            &config,
+            "https://cdn.example.com/_/foo/1.0.0.tar.gz"
+        ));
+
+        let logged_out = Config::default();
+        assert_eq!(
+            download_authorization(&logged_out, "https://registry.wapm.io/_/foo/1.0.0.tar.gz"),
+            None
+        );
+    }
+}
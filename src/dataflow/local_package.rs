@@ -2,6 +2,7 @@ use crate::data::lock::lockfile_command;
 use crate::data::lock::lockfile_command::LockfileCommand;
 use crate::data::lock::lockfile_module::LockfileModule;
 use crate::data::manifest::Manifest;
+use crate::data::manifest_extensions::ManifestExtensions;
 use crate::dataflow::lockfile_packages::{LockfilePackage, LockfilePackages};
 use crate::dataflow::PackageKey;
 use std::collections::hash_map::HashMap;
@@ -37,17 +38,26 @@ impl<'a> LocalPackage<'a> {
                 )
             })
             .collect();
+        let extensions = ManifestExtensions::find_in_directory(&manifest.base_directory_path)
+            .unwrap_or_default();
         let commands = manifest
             .command
             .as_ref()
             .cloned()
             .unwrap_or_default()
             .into_iter()
-            .map(|c| LockfileCommand::from_command(package_name, package_version.clone(), &c))
+            .map(|c| {
+                LockfileCommand::from_command(package_name, package_version.clone(), &c)
+                    .map(|command| command.with_description(&extensions))
+            })
             .collect::<Result<Vec<LockfileCommand>, lockfile_command::Error>>()
             .map_err(Error::CouldNotExtractCommandsFromManifest)?;
         let key = PackageKey::new_registry_package(package_name, package_version.clone());
-        let data = LockfilePackage { modules, commands };
+        let data = LockfilePackage {
+            modules,
+            commands,
+            features: vec![],
+        };
         Ok(LocalPackage { key, data })
     }
 }
@@ -1,10 +1,11 @@
-use crate::data::lock::lockfile::Lockfile;
+use crate::data::lock::lockfile::{FeatureMap, Lockfile};
 use crate::data::lock::lockfile_command::{Error, LockfileCommand};
 use crate::data::lock::lockfile_module::LockfileModule;
 use crate::data::lock::migrate::{
     convert_lockfilev2_to_v3, convert_lockfilev3_to_v4, fix_up_v1_package_names, LockfileVersion,
 };
 use crate::data::lock::LOCKFILE_NAME;
+use crate::data::manifest_extensions::ManifestExtensions;
 use crate::dataflow::installed_packages::InstalledPackages;
 use crate::dataflow::removed_packages::RemovedPackages;
 use crate::dataflow::{PackageKey, WapmPackageKey};
@@ -26,6 +27,8 @@ pub enum LockfileError {
     InvalidOrMissingVersion,
     #[error("Lockfile version is too high, update wapm or delete `wapm.lock` and try again.")]
     VersionTooHigh,
+    #[error("Could not install the package \"{0}\". {1}")]
+    Features(String, String),
 }
 
 /// A ternary for a lockfile: Some, None, Error.
@@ -83,6 +86,8 @@ impl Default for LockfileResult {
 pub struct LockfilePackage {
     pub modules: Vec<LockfileModule>,
     pub commands: Vec<LockfileCommand>,
+    /// The features of the package that are enabled
+    pub features: Vec<String>,
 }
 
 /// A wrapper around a map of key -> lockfile package.
@@ -92,16 +97,36 @@ pub struct LockfilePackages<'a> {
 }
 
 impl<'a> LockfilePackages<'a> {
+    /// The lockfile data of the installed packages. Only the modules and commands of
+    /// the enabled features are kept: the default features of each package and the
+    /// ones `requested_features` has for it, by package name.
     pub fn from_installed_packages(
         installed_manifest_packages: &'a InstalledPackages<'a>,
+        requested_features: &FeatureMap,
     ) -> Result<Self, LockfileError> {
         let mut packages = HashMap::default();
         for (k, manifest, download_url) in installed_manifest_packages.packages.iter() {
+            let extensions = ManifestExtensions::find_in_directory(&manifest.base_directory_path)
+                .unwrap_or_default();
+            let requested = requested_features
+                .get(k.name.as_ref())
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let enabled_features = extensions
+                .enabled_features(requested)
+                .map_err(|e| LockfileError::Features(k.to_string(), e.to_string()))?;
             let modules: Vec<LockfileModule> = match manifest.module {
                 Some(ref modules) => modules
                     .iter()
-                    .map(|module| {
-                        LockfileModule::from_module(
+                    .filter(|module| extensions.module_is_enabled(&module.name, &enabled_features))
+                    .map(|module| LockfileModule {
+                        integrity: installed_manifest_packages.integrity.get(k).cloned(),
+                        format: installed_manifest_packages
+                            .formats
+                            .get(k)
+                            .copied()
+                            .unwrap_or_default(),
+                        ..LockfileModule::from_module(
                             &manifest.base_directory_path,
                             k.name.as_ref(),
                             &k.version,
@@ -115,14 +140,22 @@ impl<'a> LockfilePackages<'a> {
             let commands: Vec<LockfileCommand> = match manifest.command {
                 Some(ref modules) => modules
                     .iter()
-                    .map(|c| LockfileCommand::from_command(&k.name, k.version.clone(), c))
+                    .filter(|c| extensions.command_is_enabled(&c.get_name(), &enabled_features))
+                    .map(|c| {
+                        LockfileCommand::from_command(&k.name, k.version.clone(), c)
+                            .map(|command| command.with_description(&extensions))
+                    })
                     .collect::<Result<Vec<LockfileCommand>, Error>>()
                     .map_err(LockfileError::CommandPackageVersionParseError)?,
                 _ => vec![],
             };
             packages.insert(
                 PackageKey::WapmPackage(k.clone()),
-                LockfilePackage { modules, commands },
+                LockfilePackage {
+                    modules,
+                    commands,
+                    features: enabled_features.into_iter().collect(),
+                },
             );
         }
         Ok(Self { packages })
@@ -139,7 +172,8 @@ impl<'a> LockfilePackages<'a> {
     }
 
     fn new_from_lockfile(lockfile: Lockfile) -> LockfilePackages<'a> {
-        let (raw_lockfile_modules, raw_lockfile_commands) = (lockfile.modules, lockfile.commands);
+        let (raw_lockfile_modules, raw_lockfile_commands, mut raw_lockfile_features) =
+            (lockfile.modules, lockfile.commands, lockfile.features);
 
         let mut lockfile_commands_map: HashMap<PackageKey, Vec<LockfileCommand>> = HashMap::new();
         for (_name, command) in raw_lockfile_commands {
@@ -165,6 +199,7 @@ impl<'a> LockfilePackages<'a> {
                         let package_data = LockfilePackage {
                             modules: lockfile_modules,
                             commands: lockfile_commands,
+                            features: raw_lockfile_features.remove(&pkg_name).unwrap_or_default(),
                         };
                         (id, package_data)
                     })
@@ -179,6 +214,72 @@ impl<'a> LockfilePackages<'a> {
         self.packages.keys().cloned().collect()
     }
 
+    /// The features the packages are installed with, by package name, with `features`
+    /// added for the `added_packages`
+    pub fn requested_features(
+        &self,
+        added_packages: &HashSet<PackageKey<'a>>,
+        features: &[String],
+    ) -> FeatureMap {
+        let mut requested_features: FeatureMap = self
+            .packages
+            .iter()
+            .filter_map(|(key, data)| match key {
+                PackageKey::WapmPackage(key) if !data.features.is_empty() => {
+                    Some((key.name.to_string(), data.features.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        if !features.is_empty() {
+            for key in added_packages {
+                if let PackageKey::WapmPackage(key) = key {
+                    let requested = requested_features.entry(key.name.to_string()).or_default();
+                    requested.extend(features.iter().cloned());
+                    requested.sort();
+                    requested.dedup();
+                }
+            }
+        }
+        requested_features
+    }
+
+    /// The `added_packages` that are installed without some of `features`, so that
+    /// they are installed again with them
+    pub fn find_packages_missing_features(
+        &self,
+        added_packages: &HashSet<PackageKey<'a>>,
+        features: &[String],
+    ) -> HashSet<PackageKey<'a>> {
+        added_packages
+            .iter()
+            .filter(|key| match self.packages.get(key) {
+                Some(data) => features.iter().any(|f| !data.features.contains(f)),
+                None => false,
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The `packages` that are installed without some of the features `requested` for
+    /// them by package name, like the features of the dependencies of the manifest
+    pub fn find_packages_missing_requested_features(
+        &self,
+        packages: &HashSet<PackageKey<'a>>,
+        requested: &FeatureMap,
+    ) -> HashSet<PackageKey<'a>> {
+        packages
+            .iter()
+            .filter(|key| match (key, self.packages.get(key)) {
+                (PackageKey::WapmPackage(package), Some(data)) => requested
+                    .get(package.name.as_ref())
+                    .is_some_and(|features| features.iter().any(|f| !data.features.contains(f))),
+                _ => false,
+            })
+            .cloned()
+            .collect()
+    }
+
     pub fn find_missing_packages(&self, directory: &Path) -> HashSet<PackageKey<'a>> {
         let missing_packages: HashSet<PackageKey<'a>> = self
             .packages
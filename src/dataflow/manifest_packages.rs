@@ -1,8 +1,11 @@
 use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::data::manifest_extensions::manifest_from_value;
 use crate::dataflow::added_packages::AddedPackages;
 use crate::dataflow::removed_packages::RemovedPackages;
-use crate::dataflow::{normalize_global_namespace, PackageKey, WapmPackageKey};
-use semver::{Version, VersionReq};
+use crate::dataflow::{
+    normalize_global_namespace, parse_version_req, PackageKey, Requirement, WapmPackageKey,
+};
+use semver::Version;
 use std::collections::hash_set::HashSet;
 use std::fs;
 use std::path::Path;
@@ -41,7 +44,9 @@ impl ManifestResult {
             Ok(s) => s,
             Err(_) => return ManifestResult::NoManifest,
         };
-        match toml::from_str::<Manifest>(&source) {
+        // git dependencies are installed separately, see `git_dependencies`
+        let manifest = source.parse::<toml::Value>().and_then(manifest_from_value);
+        match manifest {
             Ok(mut m) => {
                 m.base_directory_path = directory.to_owned();
                 ManifestResult::Manifest(m)
@@ -74,6 +79,35 @@ impl<'a> ManifestPackages<'a> {
         Ok(Self { packages })
     }
 
+    /// Replaces the version ranges with the versions the lockfile has for them, so that
+    /// a locked range is kept as it is instead of being resolved again. Ranges without
+    /// a locked version are left to be resolved.
+    pub fn lock_ranges(&mut self, lockfile_keys: &HashSet<PackageKey>) {
+        self.packages = self
+            .packages
+            .drain()
+            .map(|package_key| {
+                let locked_key = match &package_key {
+                    PackageKey::WapmPackageRange(range) => lockfile_keys
+                        .iter()
+                        .filter(|key| key.matches(range))
+                        .filter_map(|key| match key {
+                            PackageKey::WapmPackage(key) => Some(key),
+                            PackageKey::WapmPackageRange(_) => None,
+                        })
+                        .max_by(|a, b| a.version.cmp(&b.version)),
+                    PackageKey::WapmPackage(_) => None,
+                };
+                match locked_key {
+                    Some(key) => {
+                        PackageKey::new_registry_package(key.name.to_string(), key.version.clone())
+                    }
+                    None => package_key,
+                }
+            })
+            .collect();
+    }
+
     pub fn keys(&self) -> HashSet<PackageKey<'a>> {
         self.packages.iter().cloned().collect()
     }
@@ -100,6 +134,23 @@ impl<'a> ManifestPackages<'a> {
         }
     }
 
+    /// The packages of [`ManifestPackages::new_from_manifest_and_added_packages`], with
+    /// what asks for each of them: the package of the manifest or the command line.
+    pub fn requirements(
+        manifest: &'a Manifest,
+        added_packages: &AddedPackages<'a>,
+    ) -> Result<Vec<Requirement<'a>>, Error> {
+        let mut requirements: Vec<Requirement> = ManifestPackages::extract_package_keys(manifest)?
+            .into_iter()
+            .map(|key| Requirement {
+                dependent: manifest.package.name.clone(),
+                key: normalize_global_namespace(key),
+            })
+            .collect();
+        requirements.extend(added_packages.requirements());
+        Ok(requirements)
+    }
+
     /// Extract package keys from the manifest
     fn extract_package_keys(manifest: &'a Manifest) -> Result<Vec<PackageKey<'a>>, Error> {
         match manifest.dependencies {
@@ -122,7 +173,7 @@ impl<'a> ManifestPackages<'a> {
     ) -> Result<PackageKey<'a>, Error> {
         if let Ok(version) = Version::parse(version) {
             Ok(PackageKey::new_registry_package(name, version))
-        } else if let Ok(version_req) = VersionReq::parse(version) {
+        } else if let Ok(version_req) = parse_version_req(version) {
             Ok(PackageKey::new_registry_package_range(name, version_req))
         } else {
             Err(Error::SemVer(name.to_string(), version.to_string()))
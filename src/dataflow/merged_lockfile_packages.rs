@@ -1,4 +1,4 @@
-use crate::data::lock::lockfile::{CommandMap, Lockfile, ModuleMap};
+use crate::data::lock::lockfile::{CommandMap, FeatureMap, Lockfile, ModuleMap};
 use crate::dataflow::bin_script::save_bin_script;
 use crate::dataflow::lockfile_packages::{LockfilePackage, LockfilePackages};
 use crate::dataflow::retained_lockfile_packages::RetainedLockfilePackages;
@@ -52,12 +52,16 @@ impl<'a> MergedLockfilePackages<'a> {
     pub fn generate_lockfile(self, directory: &'a Path) -> Result<(), Error> {
         let mut modules: ModuleMap = BTreeMap::new();
         let mut commands: CommandMap = BTreeMap::new();
+        let mut features: FeatureMap = BTreeMap::new();
         for (key, package) in self.packages {
             match key {
                 PackageKey::WapmPackage(WapmPackageKey {
                     name: package_name,
                     version: package_version,
                 }) => {
+                    if !package.features.is_empty() {
+                        features.insert(package_name.to_string(), package.features);
+                    }
                     let versions = modules.entry(package_name.into_owned()).or_default();
                     let modules = versions.entry(package_version).or_default();
                     for module in package.modules {
@@ -87,7 +91,11 @@ impl<'a> MergedLockfilePackages<'a> {
             }
         }
 
-        let lockfile = Lockfile { modules, commands };
+        let lockfile = Lockfile {
+            modules,
+            commands,
+            features,
+        };
 
         lockfile
             .save(directory)
@@ -114,6 +122,7 @@ mod test {
             LockfilePackage {
                 modules: vec![],
                 commands: vec![],
+                features: vec![],
             },
         );
         new_lockfile_packages_map.insert(
@@ -121,6 +130,7 @@ mod test {
             LockfilePackage {
                 modules: vec![],
                 commands: vec![],
+                features: vec![],
             },
         );
         let new_lockfile_packages = LockfilePackages {
@@ -135,6 +145,7 @@ mod test {
             LockfilePackage {
                 modules: vec![],
                 commands: vec![],
+                features: vec![],
             },
         );
         old_lockfile_packages_map.insert(
@@ -142,6 +153,7 @@ mod test {
             LockfilePackage {
                 modules: vec![],
                 commands: vec![],
+                features: vec![],
             },
         );
 
@@ -1,6 +1,8 @@
-use crate::data::manifest::Manifest;
+use crate::data::manifest::{add_dependency, Manifest, PACKAGES_DIR_NAME};
+use crate::data::manifest_extensions::{save_manifest, ManifestExtensions};
 use crate::dataflow::added_packages::AddedPackages;
 use crate::dataflow::changed_manifest_packages::ChangedManifestPackages;
+use crate::dataflow::git_dependencies::GitPackages;
 use crate::dataflow::installed_packages::{InstalledPackages, RegistryInstaller};
 use crate::dataflow::local_package::LocalPackage;
 use crate::dataflow::lockfile_packages::{LockfileError, LockfilePackages, LockfileResult};
@@ -10,6 +12,7 @@ use crate::dataflow::removed_lockfile_packages::RemovedLockfilePackages;
 use crate::dataflow::removed_packages::RemovedPackages;
 use crate::dataflow::resolved_packages::{RegistryResolver, ResolvedPackages};
 use crate::dataflow::retained_lockfile_packages::RetainedLockfilePackages;
+use crate::util::{fully_qualified_package_display_name, get_package_namespace_and_name};
 use semver::{Version, VersionReq};
 use std::borrow::{Borrow, Cow};
 use std::collections::{HashMap, HashSet};
@@ -20,13 +23,16 @@ pub mod added_packages;
 pub mod bin_script;
 pub mod bindings;
 pub mod changed_manifest_packages;
+pub mod directory_registry;
 pub mod find_command_result;
+pub mod git_dependencies;
 pub mod installed_packages;
 pub mod interfaces;
 pub mod local_package;
 pub mod lockfile_packages;
 pub mod manifest_packages;
 pub mod merged_lockfile_packages;
+pub mod package_cache;
 pub mod removed_lockfile_packages;
 pub mod removed_packages;
 pub mod resolved_packages;
@@ -53,8 +59,16 @@ pub enum Error {
     LocalPackage(local_package::Error),
     #[error("Could not cleanup old artifacts. {0}")]
     Cleanup(removed_lockfile_packages::Error),
-    #[error("Attempting to install multiple versions of package {0} ({1} and {2})")]
-    DuplicatePackage(String, String, String),
+    #[error("Attempting to install multiple versions of package {0}: {1}")]
+    DuplicatePackage(String, String),
+    #[error("Package \"{0}\" is not installed.")]
+    NotInstalled(String),
+    #[error("Could not read the dependencies of the manifest. {0}")]
+    ManifestDependencies(String),
+    #[error("Could not install git dependency. {0}")]
+    GitDependency(git_dependencies::Error),
+    #[error("Could not remove installed package \"{0}\". {1}")]
+    RemoveInstalledPackage(String, String),
 }
 
 /// A package key for a package in the wapm.io registry.
@@ -72,26 +86,78 @@ pub struct WapmPackageRange<'a> {
     pub version_req: VersionReq,
 }
 
+impl<'a> WapmPackageRange<'a> {
+    /// Whether this is a dependency on the latest version, `*` or `latest`
+    pub fn is_latest(&self) -> bool {
+        self.version_req == VersionReq::STAR
+    }
+
+    /// Whether `version` satisfies the range. Any version satisfies a dependency on the
+    /// latest version, even a prerelease, once it is locked.
+    pub fn matches_version(&self, version: &Version) -> bool {
+        self.is_latest() || self.version_req.matches(version)
+    }
+}
+
+/// Parses the version requirement of a dependency. `latest` is the same as `*`: the
+/// version the registry reports as the last one.
+pub fn parse_version_req(version: &str) -> Result<VersionReq, semver::Error> {
+    if version == "latest" {
+        return Ok(VersionReq::STAR);
+    }
+    VersionReq::parse(version)
+}
+
 impl<'a> fmt::Display for WapmPackageKey<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         write!(f, "{} {}", self.name, self.version)
     }
 }
 
-pub fn detect_duplicate_packages(packages: &HashSet<PackageKey>) -> Result<(), Error> {
-    let mut seen_pkg_versions = HashMap::new();
-
-    for pkg in packages.iter() {
-        if let PackageKey::WapmPackage(WapmPackageKey { name, version }) = pkg {
-            if let Some(old_version) = seen_pkg_versions.insert(name, version) {
-                // we sort the versions so that output is stable
-                let mut versions = [old_version.to_string(), version.to_string()];
-                versions.sort();
-                return Err(Error::DuplicatePackage(
-                    name.to_string(),
-                    versions[0].clone(),
-                    versions[1].clone(),
-                ));
+/// What asks for the packages given to `wapm install`
+pub const COMMAND_LINE_DEPENDENT: &str = "the command line";
+
+/// A package to install and what asks for it: the package of the manifest, or the
+/// command line. A conflict between two packages names both.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Requirement<'a> {
+    pub dependent: String,
+    pub key: PackageKey<'a>,
+}
+
+impl<'a> fmt::Display for Requirement<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match &self.key {
+            PackageKey::WapmPackage(WapmPackageKey { name, version }) => {
+                write!(f, "{} requires {} {}", self.dependent, name, version)
+            }
+            PackageKey::WapmPackageRange(WapmPackageRange { name, version_req }) => {
+                write!(f, "{} requires {} {}", self.dependent, name, version_req)
+            }
+        }
+    }
+}
+
+/// Fails when two versions of a package are asked for, with what asks for each of them
+pub fn detect_duplicate_packages(requirements: &[Requirement]) -> Result<(), Error> {
+    let mut seen_pkg_versions: HashMap<&str, (&Version, &Requirement)> = HashMap::new();
+
+    for requirement in requirements {
+        if let PackageKey::WapmPackage(WapmPackageKey { name, version }) = &requirement.key {
+            match seen_pkg_versions.get(name.as_ref()).copied() {
+                Some((old_version, old_requirement)) if old_version != version => {
+                    // we sort by version so that output is stable
+                    let mut conflict = [(old_version, old_requirement), (version, requirement)];
+                    conflict.sort_by_key(|(version, _)| *version);
+                    return Err(Error::DuplicatePackage(
+                        name.to_string(),
+                        format!("{}, {}", conflict[0].1, conflict[1].1),
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    seen_pkg_versions.insert(name.as_ref(), (version, requirement));
+                }
             }
         }
     }
@@ -132,7 +198,7 @@ impl<'a> PackageKey<'a> {
     pub fn matches(&self, range: &WapmPackageRange) -> bool {
         match self {
             PackageKey::WapmPackage(key) => {
-                key.name == range.name && range.version_req.matches(&key.version)
+                key.name == range.name && range.matches_version(&key.version)
             }
             _ => false,
         }
@@ -185,13 +251,14 @@ pub fn update_with_no_manifest<P: AsRef<Path>>(
     directory: P,
     added_packages: AddedPackages,
     removed_packages: RemovedPackages,
+    features: &[String],
 ) -> Result<bool, Error> {
     let directory = directory.as_ref();
     // get lockfile data
     let lockfile_result = LockfileResult::find_in_directory(directory);
     let mut lockfile_packages =
         LockfilePackages::new_from_result(lockfile_result).map_err(Error::Lockfile)?;
-    detect_duplicate_packages(&added_packages.packages)?;
+    detect_duplicate_packages(&added_packages.requirements())?;
 
     // capture the initial lockfile keys before any modifications
     let initial_package_keys: HashSet<_> = lockfile_packages.package_keys();
@@ -209,9 +276,18 @@ pub fn update_with_no_manifest<P: AsRef<Path>>(
     // remove/uninstall packages
     lockfile_packages.remove_packages(removed_packages);
 
-    // check that the added packages are not already installed
+    let requested_features =
+        lockfile_packages.requested_features(&added_packages.packages, features);
+    let refeatured_packages =
+        lockfile_packages.find_packages_missing_features(&added_packages.packages, features);
+    let features_changed = !refeatured_packages.is_empty();
+
+    // check that the added packages are not already installed, unless they are
+    // installed without the requested features
     let lockfile_package_keys = lockfile_packages.package_keys();
-    let added_packages = added_packages.prune_already_installed_packages(lockfile_package_keys);
+    let added_packages = added_packages
+        .prune_already_installed_packages(lockfile_package_keys)
+        .add_missing_packages(refeatured_packages);
     // check for missing packages e.g. deleting stuff from wapm_packages
     // install any missing or newly added packages
     let missing_packages = lockfile_packages.find_missing_packages(directory);
@@ -224,7 +300,8 @@ pub fn update_with_no_manifest<P: AsRef<Path>>(
         InstalledPackages::install::<RegistryInstaller>(directory, resolved_packages, false)
             .map_err(Error::Install)?;
     let added_lockfile_data =
-        LockfilePackages::from_installed_packages(&installed_packages).map_err(Error::Lockfile)?;
+        LockfilePackages::from_installed_packages(&installed_packages, &requested_features)
+            .map_err(Error::Lockfile)?;
 
     let retained_lockfile_packages =
         RetainedLockfilePackages::from_lockfile_packages(lockfile_packages);
@@ -233,7 +310,7 @@ pub fn update_with_no_manifest<P: AsRef<Path>>(
     let final_lockfile_data =
         MergedLockfilePackages::merge(added_lockfile_data, retained_lockfile_packages);
     let final_package_keys: HashSet<_> = final_lockfile_data.packages.keys().cloned().collect();
-    if final_package_keys != initial_package_keys {
+    if final_package_keys != initial_package_keys || features_changed {
         final_lockfile_data
             .generate_lockfile(directory)
             .map_err(Error::GenerateLockfile)?;
@@ -251,6 +328,7 @@ pub fn update_with_manifest<P: AsRef<Path>>(
     manifest: Manifest,
     added_packages: AddedPackages,
     removed_packages: RemovedPackages,
+    features: &[String],
 ) -> Result<bool, Error> {
     let directory = directory.as_ref();
 
@@ -258,7 +336,9 @@ pub fn update_with_manifest<P: AsRef<Path>>(
         ManifestPackages::new_from_manifest_and_added_packages(&manifest, &added_packages)
             .map_err(Error::Manifest)?;
 
-    detect_duplicate_packages(&manifest_packages.packages)?;
+    detect_duplicate_packages(
+        &ManifestPackages::requirements(&manifest, &added_packages).map_err(Error::Manifest)?,
+    )?;
 
     // remove/uninstall packages
     manifest_packages.remove_packages(&removed_packages);
@@ -269,6 +349,31 @@ pub fn update_with_manifest<P: AsRef<Path>>(
         LockfilePackages::new_from_result(lockfile_result).map_err(Error::Lockfile)?;
     // store lockfile package keys before updating it
     let initial_package_keys = lockfile_packages.package_keys();
+    // a range that is locked keeps its version, it is only resolved again when it
+    // changes in the manifest
+    manifest_packages.lock_ranges(&initial_package_keys);
+
+    let extensions = ManifestExtensions::find_in_directory(directory)
+        .map_err(|e| Error::ManifestDependencies(e.to_string()))?;
+    // the features of the dependencies of the manifest are enabled on top of the ones
+    // they are installed with
+    let dependency_features = extensions.dependency_features();
+    let mut requested_features =
+        lockfile_packages.requested_features(&added_packages.packages, features);
+    for (name, enabled) in &dependency_features {
+        let requested = requested_features.entry(name.clone()).or_default();
+        requested.extend(enabled.iter().cloned());
+        requested.sort();
+        requested.dedup();
+    }
+
+    // git dependencies are fetched here, the registry is never asked about them
+    let git_packages =
+        GitPackages::install(directory, extensions.git_dependencies(), &lockfile_packages)
+            .map_err(Error::GitDependency)?;
+    manifest_packages
+        .packages
+        .extend(git_packages.keys.iter().cloned());
 
     // get the local package modules and commands from the manifest
     let local_package =
@@ -285,7 +390,21 @@ pub fn update_with_manifest<P: AsRef<Path>>(
     };
 
     let missing_lockfile_packages = lockfile_packages.find_missing_packages(directory);
-    let new_added_packages = packages_to_install.add_missing_packages(missing_lockfile_packages);
+    let mut refeatured_packages =
+        lockfile_packages.find_packages_missing_features(&added_packages.packages, features);
+    refeatured_packages.extend(
+        lockfile_packages.find_packages_missing_requested_features(
+            &manifest_packages.keys(),
+            &dependency_features,
+        ),
+    );
+    let features_changed = !refeatured_packages.is_empty();
+    let mut new_added_packages = packages_to_install
+        .add_missing_packages(missing_lockfile_packages)
+        .add_missing_packages(refeatured_packages);
+    new_added_packages
+        .packages
+        .retain(|key| !git_packages.is_git_package(key));
 
     let removed_lockfile_packages =
         RemovedLockfilePackages::from_manifest_and_lockfile(&manifest_packages, &lockfile_packages);
@@ -307,11 +426,17 @@ pub fn update_with_manifest<P: AsRef<Path>>(
         false,
     )
     .map_err(Error::Install)?;
-    let mut manifest_lockfile_data =
-        LockfilePackages::from_installed_packages(&installed_manifest_packages)
-            .map_err(Error::Lockfile)?;
+    let mut manifest_lockfile_data = LockfilePackages::from_installed_packages(
+        &installed_manifest_packages,
+        &requested_features,
+    )
+    .map_err(Error::Lockfile)?;
 
     manifest_lockfile_data.extend(local_package.into());
+    manifest_lockfile_data.extend(
+        LockfilePackages::from_installed_packages(&git_packages.installed, &requested_features)
+            .map_err(Error::Lockfile)?,
+    );
 
     // merge the lockfile data, and generate the new lockfile
     let final_lockfile_data =
@@ -323,7 +448,7 @@ pub fn update_with_manifest<P: AsRef<Path>>(
         .map_err(Error::GenerateLockfile)?;
 
     // update the manifest, if applicable
-    if final_package_keys != initial_package_keys {
+    if final_package_keys != initial_package_keys || features_changed {
         update_manifest(manifest.clone(), &added_packages, &removed_packages)?;
         Ok(true)
     } else {
@@ -337,6 +462,17 @@ pub fn update<P: AsRef<Path>>(
     added_packages: Vec<(&str, &str)>,
     removed_packages: Vec<&str>,
     directory: P,
+) -> Result<bool, Error> {
+    update_with_features(added_packages, removed_packages, &[], directory)
+}
+
+/// Like [`update`], with `features` of the added packages enabled on top of their default
+/// ones. The lockfile keeps the features of each package for the next updates.
+pub fn update_with_features<P: AsRef<Path>>(
+    added_packages: Vec<(&str, &str)>,
+    removed_packages: Vec<&str>,
+    features: &[String],
+    directory: P,
 ) -> Result<bool, Error> {
     let directory = directory.as_ref();
     let added_packages = AddedPackages::new_from_str_pairs(added_packages).map_err(Error::Add)?;
@@ -344,15 +480,161 @@ pub fn update<P: AsRef<Path>>(
     let manifest_result = ManifestResult::find_in_directory(directory);
     match manifest_result {
         ManifestResult::NoManifest => {
-            update_with_no_manifest(directory, added_packages, removed_packages)
-        }
-        ManifestResult::Manifest(manifest) => {
-            update_with_manifest(directory, manifest, added_packages, removed_packages)
+            update_with_no_manifest(directory, added_packages, removed_packages, features)
         }
+        ManifestResult::Manifest(manifest) => update_with_manifest(
+            directory,
+            manifest,
+            added_packages,
+            removed_packages,
+            features,
+        ),
         ManifestResult::ManifestError(e) => Err(Error::Manifest(e)),
     }
 }
 
+/// Deletes the installed files of the given lockfile packages, or of all of them when no
+/// names are given, and downloads them again. The lockfile keeps the same versions.
+/// This function returns a bool on success indicating if any package was reinstalled
+pub fn reinstall<P: AsRef<Path>>(package_names: Vec<&str>, directory: P) -> Result<bool, Error> {
+    let directory = directory.as_ref();
+    let lockfile_result = LockfileResult::find_in_directory(directory);
+    let lockfile_packages =
+        LockfilePackages::new_from_result(lockfile_result).map_err(Error::Lockfile)?;
+
+    let package_names: Vec<Cow<str>> = package_names
+        .into_iter()
+        .map(|name| normalize_global_namespace_package_name(Cow::Borrowed(name)))
+        .collect();
+    // the lockfile also holds the modules of the local package, which are not installed
+    let local_package_name = match ManifestResult::find_in_directory(directory) {
+        ManifestResult::Manifest(manifest) => Some(manifest.package.name),
+        _ => None,
+    };
+    let installed_keys: Vec<WapmPackageKey> = lockfile_packages
+        .packages
+        .keys()
+        .filter_map(|key| match key {
+            PackageKey::WapmPackage(key) => Some(key.clone()),
+            _ => None,
+        })
+        .filter(|key| Some(key.name.as_ref()) != local_package_name.as_deref())
+        .collect();
+    if let Some(name) = package_names
+        .iter()
+        .find(|name| !installed_keys.iter().any(|key| key.name == **name))
+    {
+        return Err(Error::NotInstalled(name.to_string()));
+    }
+    let reinstalled_keys: Vec<WapmPackageKey> = installed_keys
+        .into_iter()
+        .filter(|key| package_names.is_empty() || package_names.contains(&key.name))
+        .collect();
+    if reinstalled_keys.is_empty() {
+        return Ok(false);
+    }
+
+    for key in reinstalled_keys.iter() {
+        remove_installed_package(directory, key)?;
+    }
+
+    let added_packages = AddedPackages {
+        packages: reinstalled_keys
+            .into_iter()
+            .map(PackageKey::WapmPackage)
+            .collect(),
+    };
+    let resolved_packages =
+        ResolvedPackages::new_from_added_packages::<RegistryResolver>(added_packages)
+            .map_err(Error::Resolve)?;
+    let installed_packages =
+        InstalledPackages::install::<RegistryInstaller>(directory, resolved_packages, false)
+            .map_err(Error::Install)?;
+    let requested_features = lockfile_packages.requested_features(&HashSet::new(), &[]);
+    let reinstalled_lockfile_data =
+        LockfilePackages::from_installed_packages(&installed_packages, &requested_features)
+            .map_err(Error::Lockfile)?;
+
+    // packages that could not be resolved again keep their old lockfile entry
+    let retained_lockfile_packages =
+        RetainedLockfilePackages::from_lockfile_packages(lockfile_packages);
+    MergedLockfilePackages::merge(reinstalled_lockfile_data, retained_lockfile_packages)
+        .generate_lockfile(directory)
+        .map_err(Error::GenerateLockfile)?;
+    Ok(true)
+}
+
+/// Reconciles the lockfile with `wapm_packages`. The entries of the packages that are
+/// neither installed nor dependencies in the manifest are dropped, then the missing
+/// dependencies are installed again.
+/// This function returns the dropped packages
+pub fn sync<P: AsRef<Path>>(directory: P) -> Result<Vec<String>, Error> {
+    let directory = directory.as_ref();
+    let lockfile_result = LockfileResult::find_in_directory(directory);
+    let mut lockfile_packages =
+        LockfilePackages::new_from_result(lockfile_result).map_err(Error::Lockfile)?;
+    let manifest = match ManifestResult::find_in_directory(directory) {
+        ManifestResult::Manifest(manifest) => Some(manifest),
+        ManifestResult::NoManifest => None,
+        ManifestResult::ManifestError(e) => return Err(Error::Manifest(e)),
+    };
+
+    // the lockfile also holds the modules of the local package, which are not installed
+    let mut kept_names: HashSet<String> = manifest
+        .iter()
+        .map(|manifest| manifest.package.name.clone())
+        .collect();
+    if let Some(manifest) = manifest.as_ref() {
+        let no_added_packages = AddedPackages::default();
+        let manifest_packages =
+            ManifestPackages::new_from_manifest_and_added_packages(manifest, &no_added_packages)
+                .map_err(Error::Manifest)?;
+        kept_names.extend(manifest_packages.packages.iter().map(|key| match key {
+            PackageKey::WapmPackage(key) => key.name.to_string(),
+            PackageKey::WapmPackageRange(range) => range.name.to_string(),
+        }));
+    }
+    let mut dropped_keys: Vec<WapmPackageKey> = lockfile_packages
+        .find_missing_packages(directory)
+        .into_iter()
+        .filter_map(|key| match key {
+            PackageKey::WapmPackage(key) if !kept_names.contains(key.name.as_ref()) => Some(key),
+            _ => None,
+        })
+        .collect();
+    dropped_keys.sort_by(|a, b| a.name.cmp(&b.name).then(a.version.cmp(&b.version)));
+
+    if !dropped_keys.is_empty() {
+        for key in dropped_keys.iter() {
+            lockfile_packages
+                .packages
+                .remove(&PackageKey::WapmPackage(key.clone()));
+        }
+        let retained_lockfile_packages =
+            RetainedLockfilePackages::from_lockfile_packages(lockfile_packages);
+        MergedLockfilePackages::merge(LockfilePackages::default(), retained_lockfile_packages)
+            .generate_lockfile(directory)
+            .map_err(Error::GenerateLockfile)?;
+    }
+    update(vec![], vec![], directory)?;
+    Ok(dropped_keys.iter().map(ToString::to_string).collect())
+}
+
+/// Deletes the directory of an installed package in `wapm_packages`, if there is one
+fn remove_installed_package(directory: &Path, key: &WapmPackageKey) -> Result<(), Error> {
+    let (namespace, name) = get_package_namespace_and_name(&key.name)
+        .map_err(|e| Error::RemoveInstalledPackage(key.to_string(), e.to_string()))?;
+    let package_dir = directory
+        .join(PACKAGES_DIR_NAME)
+        .join(namespace)
+        .join(fully_qualified_package_display_name(name, &key.version));
+    if package_dir.exists() {
+        std::fs::remove_dir_all(&package_dir)
+            .map_err(|e| Error::RemoveInstalledPackage(key.to_string(), e.to_string()))?;
+    }
+    Ok(())
+}
+
 /// Updates the manifest and saves it
 pub fn update_manifest(
     manifest: Manifest,
@@ -367,10 +649,12 @@ pub fn update_manifest(
     for key in added_packages.packages.iter().cloned() {
         match key {
             PackageKey::WapmPackageRange(WapmPackageRange { name, version_req }) => {
-                manifest.add_dependency(name.to_string(), version_req.to_string());
+                add_dependency(&mut manifest, name.to_string(), version_req.to_string())
+                    .map_err(|e| Error::Save(e.to_string()))?;
             }
             PackageKey::WapmPackage(WapmPackageKey { name, version }) => {
-                manifest.add_dependency(name.to_string(), version.to_string());
+                add_dependency(&mut manifest, name.to_string(), version.to_string())
+                    .map_err(|e| Error::Save(e.to_string()))?;
             }
         }
     }
@@ -379,7 +663,398 @@ pub fn update_manifest(
         manifest.remove_dependency(package_name.borrow());
     }
 
-    manifest.save().map_err(|e| Error::Save(e.to_string()))?;
+    save_manifest(&manifest).map_err(|e| Error::Save(e.to_string()))?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataflow::directory_registry::test::{
+        publish_manifest_to_directory, publish_to_directory,
+    };
+    use crate::dataflow::directory_registry::LOCAL_REGISTRY_ENV_VAR;
+    use crate::util::create_temp_dir;
+    use lazy_static::lazy_static;
+    use std::fs;
+    use std::sync::Mutex;
+
+    lazy_static! {
+        /// Held by the tests that point the local registry variable at their registry
+        static ref LOCAL_REGISTRY: Mutex<()> = Mutex::new(());
+    }
+
+    #[test]
+    fn conflicts_name_what_requires_each_version() {
+        let requirement = |dependent: &str, version| Requirement {
+            dependent: dependent.to_string(),
+            key: PackageKey::new_registry_package("_/foo", Version::parse(version).unwrap()),
+        };
+        let range = Requirement {
+            dependent: "_/app".to_string(),
+            key: PackageKey::new_registry_package_range("_/foo", VersionReq::parse("^1").unwrap()),
+        };
+        assert_eq!(range.to_string(), "_/app requires _/foo ^1");
+
+        // the same version asked twice is no conflict
+        let requirements = vec![
+            requirement("_/app", "1.0.0"),
+            requirement(COMMAND_LINE_DEPENDENT, "1.0.0"),
+            range,
+        ];
+        assert!(detect_duplicate_packages(&requirements).is_ok());
+
+        let requirements = vec![
+            requirement(COMMAND_LINE_DEPENDENT, "2.0.0"),
+            requirement("_/app", "1.0.0"),
+        ];
+        let error = detect_duplicate_packages(&requirements).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Attempting to install multiple versions of package _/foo: _/app requires _/foo \
+             1.0.0, the command line requires _/foo 2.0.0"
+        );
+    }
+
+    #[test]
+    fn reinstall_replaces_corrupted_package() {
+        let _local_registry = LOCAL_REGISTRY.lock().unwrap();
+        let registry_dir = create_temp_dir().unwrap();
+        let registry_root: &Path = registry_dir.as_ref();
+        publish_to_directory(registry_root, "_", "foo", "1.0.0");
+        std::env::set_var(LOCAL_REGISTRY_ENV_VAR, registry_root);
+
+        let install_dir = create_temp_dir().unwrap();
+        let install_dir: &Path = install_dir.as_ref();
+        assert!(update(vec![("_/foo", "1.0.0")], vec![], install_dir).unwrap());
+        let module_path = install_dir
+            .join(PACKAGES_DIR_NAME)
+            .join("_")
+            .join("foo@1.0.0")
+            .join("foo.wasm");
+        let original_module = fs::read(&module_path).unwrap();
+
+        // a plain install sees the package as installed and leaves it alone
+        fs::write(&module_path, b"corrupted").unwrap();
+        assert!(!update(vec![("_/foo", "1.0.0")], vec![], install_dir).unwrap());
+        assert_eq!(fs::read(&module_path).unwrap(), b"corrupted");
+
+        assert!(reinstall(vec!["foo"], install_dir).unwrap());
+        assert_eq!(fs::read(&module_path).unwrap(), original_module);
+
+        assert!(matches!(
+            reinstall(vec!["_/bar"], install_dir),
+            Err(Error::NotInstalled(_))
+        ));
+        std::env::remove_var(LOCAL_REGISTRY_ENV_VAR);
+    }
+
+    #[test]
+    fn sync_drops_stale_entries_and_reinstalls_dependencies() {
+        let _local_registry = LOCAL_REGISTRY.lock().unwrap();
+        let registry_dir = create_temp_dir().unwrap();
+        let registry_root: &Path = registry_dir.as_ref();
+        publish_to_directory(registry_root, "_", "foo", "1.0.0");
+        publish_to_directory(registry_root, "_", "bar", "1.0.0");
+        std::env::set_var(LOCAL_REGISTRY_ENV_VAR, registry_root);
+
+        let install_dir = create_temp_dir().unwrap();
+        let install_dir: &Path = install_dir.as_ref();
+        assert!(update(
+            vec![("_/foo", "1.0.0"), ("_/bar", "1.0.0")],
+            vec![],
+            install_dir
+        )
+        .unwrap());
+        let package_dir = |name: &str| {
+            install_dir
+                .join(PACKAGES_DIR_NAME)
+                .join("_")
+                .join(format!("{}@1.0.0", name))
+        };
+        let locked_packages = || match LockfileResult::find_in_directory(install_dir) {
+            LockfileResult::Lockfile(lockfile) => {
+                lockfile.modules.keys().cloned().collect::<Vec<_>>()
+            }
+            _ => panic!("the lockfile is missing"),
+        };
+
+        // without a manifest, nothing is installed again
+        fs::remove_dir_all(package_dir("bar")).unwrap();
+        assert_eq!(sync(install_dir).unwrap(), vec!["_/bar 1.0.0".to_string()]);
+        assert_eq!(locked_packages(), vec!["_/foo".to_string()]);
+        assert!(!package_dir("bar").exists());
+
+        // a dependency in the manifest is installed again
+        fs::write(
+            install_dir.join("wapm.toml"),
+            r#"[package]
+name = "_/app"
+version = "0.1.0"
+description = "app"
+
+[dependencies]
+"_/foo" = "1.0.0"
+"#,
+        )
+        .unwrap();
+        fs::remove_dir_all(package_dir("foo")).unwrap();
+        assert!(sync(install_dir).unwrap().is_empty());
+        assert!(package_dir("foo").join("foo.wasm").is_file());
+        assert!(locked_packages().contains(&"_/foo".to_string()));
+        std::env::remove_var(LOCAL_REGISTRY_ENV_VAR);
+    }
+
+    #[test]
+    fn features_pick_the_installed_modules_and_commands() {
+        let _local_registry = LOCAL_REGISTRY.lock().unwrap();
+        let registry_dir = create_temp_dir().unwrap();
+        let registry_root: &Path = registry_dir.as_ref();
+        let manifest = r#"[package]
+name = "_/tools"
+version = "1.0.0"
+description = "test package"
+
+[features]
+default = ["fmt"]
+fmt = []
+extra = ["lint"]
+lint = []
+
+[[module]]
+name = "fmt"
+source = "fmt.wasm"
+features = ["fmt"]
+
+[[module]]
+name = "lint"
+source = "lint.wasm"
+features = ["lint"]
+
+[[command]]
+name = "fmt"
+module = "fmt"
+
+[[command]]
+name = "lint"
+module = "lint"
+"#;
+        publish_manifest_to_directory(
+            registry_root,
+            "_",
+            "tools",
+            "1.0.0",
+            manifest,
+            &["fmt", "lint"],
+        );
+        std::env::set_var(LOCAL_REGISTRY_ENV_VAR, registry_root);
+
+        let install_dir = create_temp_dir().unwrap();
+        let install_dir: &Path = install_dir.as_ref();
+        let locked = || match LockfileResult::find_in_directory(install_dir) {
+            LockfileResult::Lockfile(lockfile) => (
+                lockfile.commands.keys().cloned().collect::<Vec<_>>(),
+                lockfile
+                    .features
+                    .get("_/tools")
+                    .cloned()
+                    .unwrap_or_default(),
+            ),
+            _ => panic!("the lockfile is missing"),
+        };
+
+        // the default install leaves the optional command out
+        assert!(update(vec![("_/tools", "1.0.0")], vec![], install_dir).unwrap());
+        assert_eq!(
+            locked(),
+            (
+                vec!["fmt".to_string()],
+                vec!["default".to_string(), "fmt".to_string()]
+            )
+        );
+
+        let extra = vec!["extra".to_string()];
+        assert!(
+            update_with_features(vec![("_/tools", "1.0.0")], vec![], &extra, install_dir).unwrap()
+        );
+        let (commands, features) = locked();
+        assert_eq!(commands, vec!["fmt".to_string(), "lint".to_string()]);
+        assert_eq!(features, vec!["default", "extra", "fmt", "lint"]);
+
+        // the features are kept by the next updates
+        assert!(!update(vec![], vec![], install_dir).unwrap());
+        assert!(
+            !update_with_features(vec![("_/tools", "1.0.0")], vec![], &extra, install_dir).unwrap()
+        );
+        assert_eq!(locked().0, vec!["fmt".to_string(), "lint".to_string()]);
+
+        let other_dir = create_temp_dir().unwrap();
+        assert!(matches!(
+            update_with_features(
+                vec![("_/tools", "1.0.0")],
+                vec![],
+                &["missing".to_string()],
+                other_dir.path()
+            ),
+            Err(Error::Lockfile(LockfileError::Features(..)))
+        ));
+        std::env::remove_var(LOCAL_REGISTRY_ENV_VAR);
+    }
+
+    #[test]
+    fn dependency_features_pick_the_installed_modules() {
+        let _local_registry = LOCAL_REGISTRY.lock().unwrap();
+        let registry_dir = create_temp_dir().unwrap();
+        let registry_root: &Path = registry_dir.as_ref();
+        let manifest = r#"[package]
+name = "_/tools"
+version = "1.0.0"
+description = "test package"
+
+[features]
+lint = []
+
+[[module]]
+name = "fmt"
+source = "fmt.wasm"
+
+[[module]]
+name = "lint"
+source = "lint.wasm"
+features = ["lint"]
+"#;
+        publish_manifest_to_directory(
+            registry_root,
+            "_",
+            "tools",
+            "1.0.0",
+            manifest,
+            &["fmt", "lint"],
+        );
+        std::env::set_var(LOCAL_REGISTRY_ENV_VAR, registry_root);
+
+        let write_manifest = |directory: &Path, dependency: &str| {
+            fs::write(
+                directory.join("wapm.toml"),
+                format!(
+                    "[package]\nname = \"_/app\"\nversion = \"0.1.0\"\ndescription = \"app\"\n\n\
+                     [dependencies]\n\"_/tools\" = {}\n",
+                    dependency
+                ),
+            )
+            .unwrap();
+        };
+        let locked_modules = |directory: &Path| match LockfileResult::find_in_directory(directory) {
+            LockfileResult::Lockfile(lockfile) => lockfile.modules["_/tools"]
+                [&Version::new(1, 0, 0)]
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>(),
+            _ => panic!("the lockfile is missing"),
+        };
+
+        let install_dir = create_temp_dir().unwrap();
+        let install_dir: &Path = install_dir.as_ref();
+        write_manifest(install_dir, "\"1.0.0\"");
+        assert!(update(vec![], vec![], install_dir).unwrap());
+        assert_eq!(locked_modules(install_dir), vec!["fmt".to_string()]);
+
+        // asking for the feature in the manifest installs the package again with it
+        let with_lint = r#"{ version = "1.0.0", features = ["lint"] }"#;
+        write_manifest(install_dir, with_lint);
+        assert!(update(vec![], vec![], install_dir).unwrap());
+        assert_eq!(
+            locked_modules(install_dir),
+            vec!["fmt".to_string(), "lint".to_string()]
+        );
+        assert!(!update(vec![], vec![], install_dir).unwrap());
+        assert!(fs::read_to_string(install_dir.join("wapm.toml"))
+            .unwrap()
+            .contains("features = [\"lint\"]"));
+
+        let other_dir = create_temp_dir().unwrap();
+        let other_dir: &Path = other_dir.as_ref();
+        write_manifest(other_dir, with_lint);
+        assert!(update(vec![], vec![], other_dir).unwrap());
+        assert_eq!(
+            locked_modules(other_dir),
+            vec!["fmt".to_string(), "lint".to_string()]
+        );
+        std::env::remove_var(LOCAL_REGISTRY_ENV_VAR);
+    }
+
+    #[test]
+    fn latest_dependencies_lock_to_a_concrete_version() {
+        let _local_registry = LOCAL_REGISTRY.lock().unwrap();
+        let registry_dir = create_temp_dir().unwrap();
+        let registry_root: &Path = registry_dir.as_ref();
+        publish_to_directory(registry_root, "_", "foo", "1.0.0");
+        publish_to_directory(registry_root, "_", "foo", "1.2.0");
+        publish_to_directory(registry_root, "_", "bar", "0.1.0-beta");
+        std::env::set_var(LOCAL_REGISTRY_ENV_VAR, registry_root);
+
+        let install_dir = create_temp_dir().unwrap();
+        let install_dir: &Path = install_dir.as_ref();
+        fs::write(
+            install_dir.join("wapm.toml"),
+            r#"[package]
+name = "_/app"
+version = "0.1.0"
+description = "app"
+
+[dependencies]
+"_/foo" = "*"
+"_/bar" = "latest"
+"#,
+        )
+        .unwrap();
+        let locked_versions = || match LockfileResult::find_in_directory(install_dir) {
+            LockfileResult::Lockfile(lockfile) => lockfile
+                .modules
+                .iter()
+                .flat_map(|(name, versions)| {
+                    versions
+                        .keys()
+                        .map(move |version| format!("{}@{}", name, version))
+                })
+                .collect::<Vec<_>>(),
+            _ => panic!("the lockfile is missing"),
+        };
+
+        assert!(update(vec![], vec![], install_dir).unwrap());
+        assert_eq!(
+            locked_versions(),
+            vec![
+                "_/app@0.1.0".to_string(),
+                "_/bar@0.1.0-beta".to_string(),
+                "_/foo@1.2.0".to_string()
+            ]
+        );
+
+        // once locked, a newer version is not picked up
+        publish_to_directory(registry_root, "_", "foo", "1.3.0");
+        assert!(!update(vec![], vec![], install_dir).unwrap());
+        assert_eq!(
+            locked_versions(),
+            vec![
+                "_/app@0.1.0".to_string(),
+                "_/bar@0.1.0-beta".to_string(),
+                "_/foo@1.2.0".to_string()
+            ]
+        );
+        std::env::remove_var(LOCAL_REGISTRY_ENV_VAR);
+    }
+
+    #[test]
+    fn latest_is_a_wildcard() {
+        assert_eq!(parse_version_req("latest").unwrap(), VersionReq::STAR);
+        assert_eq!(parse_version_req("*").unwrap(), VersionReq::STAR);
+        let range = WapmPackageRange {
+            name: "_/foo".into(),
+            version_req: parse_version_req("latest").unwrap(),
+        };
+        assert!(range.is_latest());
+        assert!(range.matches_version(&Version::parse("2.0.0-rc.1").unwrap()));
+    }
+}
@@ -0,0 +1,134 @@
+//! A cache of the package archives downloaded from the registry, kept in the globals
+//! directory with the layout of a [`DirectoryRegistry`].
+//!
+//! Every archive the registry installer downloads is stored there. With `wapm install
+//! --offline`, the packages found in the cache are installed from it and only the other
+//! ones are downloaded.
+use crate::config::Config;
+use crate::dataflow::directory_registry::DirectoryRegistry;
+use crate::dataflow::resolved_packages::DownloadUrls;
+use crate::dataflow::{PackageKey, WapmPackageKey};
+use crate::util::get_package_namespace_and_name;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub static PACKAGE_CACHE_DIR_NAME: &str = "package_cache";
+
+/// Whether packages are installed from the cache when they are in it
+static PREFER_CACHED_PACKAGES: AtomicBool = AtomicBool::new(false);
+
+pub fn set_prefer_cached_packages(prefer: bool) {
+    PREFER_CACHED_PACKAGES.store(prefer, Ordering::SeqCst);
+}
+
+pub fn prefer_cached_packages() -> bool {
+    PREFER_CACHED_PACKAGES.load(Ordering::SeqCst)
+}
+
+/// The cache in the globals directory
+pub fn package_cache_directory() -> Option<PathBuf> {
+    Config::get_globals_directory()
+        .ok()
+        .map(|globals| globals.join(PACKAGE_CACHE_DIR_NAME))
+}
+
+/// Where the archive of a package version is cached
+fn cached_archive_path(root: &Path, key: &WapmPackageKey) -> Option<PathBuf> {
+    let (namespace, name) = get_package_namespace_and_name(&key.name).ok()?;
+    Some(
+        root.join(namespace)
+            .join(name)
+            .join(format!("{}.tar.gz", key.version)),
+    )
+}
+
+/// Copies a downloaded archive to the cache rooted at `root`
+pub fn store(root: &Path, key: &WapmPackageKey, archive_path: &Path) -> io::Result<()> {
+    let cached_path = cached_archive_path(root, key).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("\"{}\" is not a package name", key.name),
+        )
+    })?;
+    if let Some(parent) = cached_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // copy next to the archive and rename, so that an interrupted copy is never
+    // installed from
+    let partial_path = cached_path.with_extension(format!("{}.partial", std::process::id()));
+    fs::copy(archive_path, &partial_path)?;
+    fs::rename(&partial_path, &cached_path)
+}
+
+/// Resolves the packages that are in the cache rooted at `root` to their cached
+/// archive, and returns the other ones to be resolved by the registry.
+pub fn resolve_cached<'a>(
+    root: &Path,
+    packages: Vec<PackageKey<'a>>,
+) -> (DownloadUrls<'a>, Vec<PackageKey<'a>>) {
+    let mut cached = vec![];
+    let mut missing = vec![];
+    for package in packages {
+        match DirectoryRegistry::resolve_in(root, vec![package.clone()]).pop() {
+            Some(resolved) => cached.push(resolved),
+            None => missing.push(package),
+        }
+    }
+    (cached, missing)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataflow::directory_registry::test::publish_to_directory;
+    use crate::util::create_temp_dir;
+    use semver::{Version, VersionReq};
+
+    #[test]
+    fn cached_packages_are_resolved_to_their_archive() {
+        let registry_dir = create_temp_dir().unwrap();
+        publish_to_directory(registry_dir.path(), "_", "foo", "1.0.0");
+        let cache_dir = create_temp_dir().unwrap();
+        let cache: &Path = cache_dir.as_ref();
+        let foo = WapmPackageKey {
+            name: "_/foo".into(),
+            version: Version::new(1, 0, 0),
+        };
+        store(
+            cache,
+            &foo,
+            &registry_dir
+                .path()
+                .join("_")
+                .join("foo")
+                .join("1.0.0.tar.gz"),
+        )
+        .unwrap();
+
+        // cache hit, for an exact version and for a range
+        let (cached, missing) = resolve_cached(
+            cache,
+            vec![
+                PackageKey::WapmPackage(foo.clone()),
+                PackageKey::new_registry_package_range("_/foo", VersionReq::parse("^1").unwrap()),
+            ],
+        );
+        assert!(missing.is_empty());
+        assert_eq!(cached.len(), 2);
+        for (key, (download_url, _)) in cached {
+            assert_eq!(key, foo);
+            assert!(download_url.starts_with("file://"));
+            assert!(download_url.ends_with("_/foo/1.0.0.tar.gz"));
+        }
+
+        // cache miss, left to the registry
+        let other_version = PackageKey::new_registry_package("_/foo", Version::new(2, 0, 0));
+        let other_package = PackageKey::new_registry_package("_/bar", Version::new(1, 0, 0));
+        let (cached, missing) =
+            resolve_cached(cache, vec![other_version.clone(), other_package.clone()]);
+        assert!(cached.is_empty());
+        assert_eq!(missing, vec![other_version, other_package]);
+    }
+}
@@ -1,5 +1,9 @@
 use crate::constants::*;
 use crate::dataflow::added_packages::AddedPackages;
+use crate::dataflow::directory_registry::{local_registry_directory, DirectoryRegistry};
+use crate::dataflow::package_cache::{
+    package_cache_directory, prefer_cached_packages, resolve_cached,
+};
 use crate::dataflow::{PackageKey, WapmPackageKey, WapmPackageRange};
 use crate::graphql::{execute_query, DateTime};
 use crate::keys;
@@ -8,6 +12,7 @@ use semver::Version;
 use std::borrow::Cow::Owned;
 use std::collections::hash_map::HashMap;
 use std::collections::hash_set::HashSet;
+use std::collections::BTreeMap;
 use thiserror::Error;
 
 #[derive(GraphQLQuery)]
@@ -61,7 +66,7 @@ impl<'a> ResolvedPackages<'a> {
     }
 }
 
-type DownloadUrls<'a> = Vec<(
+pub type DownloadUrls<'a> = Vec<(
     WapmPackageKey<'a>,
     (String, Option<keys::WapmPackageSignature>),
 )>;
@@ -85,22 +90,80 @@ impl<'a> RegistryResolver {
         let q = GetPackagesQuery::build_query(get_packages_query::Variables { names });
         execute_query(&q).unwrap()
     }
+
+    /// The latest version of each package in the registry. Packages that are not in the
+    /// registry are left out.
+    pub fn latest_versions(names: Vec<String>) -> Result<BTreeMap<String, Version>, Error> {
+        Ok(Self::versions(names)?
+            .into_iter()
+            .filter_map(|(name, versions)| Some((name, versions.into_iter().max()?)))
+            .collect())
+    }
+
+    /// All the published versions of each package in the registry. Packages that are
+    /// not in the registry are left out.
+    pub fn versions(names: Vec<String>) -> Result<BTreeMap<String, Vec<Version>>, Error> {
+        if let Some(root) = local_registry_directory() {
+            return Ok(DirectoryRegistry::versions_in(&root, &names));
+        }
+        let q = GetPackagesQuery::build_query(get_packages_query::Variables { names });
+        let response: get_packages_query::ResponseData =
+            execute_query(&q).map_err(|e| Error::CouldNotResolvePackages(e.to_string()))?;
+        Ok(response
+            .package
+            .into_iter()
+            .flatten()
+            .map(|p| {
+                let versions = p
+                    .versions
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|v| Version::parse(&v.version).ok())
+                    .collect::<Vec<_>>();
+                (p.name, versions)
+            })
+            .filter(|(_, versions)| !versions.is_empty())
+            .collect())
+    }
 }
 
 /// The Registry Resolver will resolve dependencies on a wapm.io server
 impl<'a> Resolve<'a> for RegistryResolver {
+    fn sync_packages(added_packages: Vec<PackageKey<'a>>) -> Result<DownloadUrls<'a>, Error> {
+        if local_registry_directory().is_some() {
+            return DirectoryRegistry::sync_packages(added_packages);
+        }
+        // the cached packages are installed from the cache, only the other ones are
+        // looked up in the registry
+        if let Some(root) = package_cache_directory().filter(|_| prefer_cached_packages()) {
+            let (mut cached, missing) = resolve_cached(&root, added_packages);
+            if !missing.is_empty() {
+                cached.extend(Self::sync_registry_packages(missing)?);
+            }
+            return Ok(cached);
+        }
+        Self::sync_registry_packages(added_packages)
+    }
+}
+
+impl<'a> RegistryResolver {
     /// This gross function queries the GraphQL server. See the schema in `/graphql/queries/get_packages.graphql`
-    fn sync_packages(
+    fn sync_registry_packages(
         added_packages: Vec<PackageKey<'a>>,
-    ) -> Result<
-        Vec<(
-            WapmPackageKey<'a>,
-            (String, Option<keys::WapmPackageSignature>),
-        )>,
-        Error,
-    > {
+    ) -> Result<DownloadUrls<'a>, Error> {
         // fetch data from graphql server
         let response = Self::get_response(added_packages.clone());
+        // the version the registry reports as the last one, by package name
+        let last_version_lookup: HashMap<String, Version> = response
+            .package
+            .iter()
+            .flatten()
+            .filter_map(|p| {
+                let last_version = p.last_version.as_ref()?;
+                let version = Version::parse(&last_version.version).ok()?;
+                Some((p.name.clone(), version))
+            })
+            .collect();
         let all_packages_and_download_urls: Vec<(
             String,
             Version,
@@ -184,6 +247,20 @@ impl<'a> Resolve<'a> for RegistryResolver {
                         .find(|(k, _)| k.version == wapm_package_key.version)
                         .map(|(k, (d, s))| (k.clone(), (d.clone(), s.clone())))
                 }
+                // if latest, then use the last version the registry reports
+                PackageKey::WapmPackageRange(range)
+                    if range.is_latest()
+                        && last_version_lookup.contains_key(range.name.as_ref()) =>
+                {
+                    let key = WapmPackageKey {
+                        version: last_version_lookup[range.name.as_ref()].clone(),
+                        name: range.name,
+                    };
+                    exact_package_lookup
+                        .get(&key)
+                        .cloned()
+                        .map(|(dl_url, signature)| (key, (dl_url, signature)))
+                }
                 // if a range, then filter by the requirements, and find the max version
                 PackageKey::WapmPackageRange(range) => {
                     let matching_version: Option<Version> = package_versions_lookup
@@ -192,7 +269,7 @@ impl<'a> Resolve<'a> for RegistryResolver {
                             let max_version: Option<Version> = versions
                                 .iter()
                                 .cloned()
-                                .filter(|v| range.version_req.matches(v))
+                                .filter(|v| range.matches_version(v))
                                 .max(); // get the max version number after filtering by version requirement
                             max_version
                         });
@@ -74,6 +74,7 @@ mod retained_lockfile_packages_tests {
             LockfilePackage {
                 modules: vec![],
                 commands: vec![],
+                features: vec![],
             },
         );
         lockfile_package_map.insert(
@@ -81,6 +82,7 @@ mod retained_lockfile_packages_tests {
             LockfilePackage {
                 modules: vec![],
                 commands: vec![],
+                features: vec![],
             },
         );
         lockfile_package_map.insert(
@@ -88,6 +90,7 @@ mod retained_lockfile_packages_tests {
             LockfilePackage {
                 modules: vec![],
                 commands: vec![],
+                features: vec![],
             },
         );
 
@@ -0,0 +1,328 @@
+//! The trace of `wapm run --trace-fs`: the files the module wrote to and how much. The
+//! runtime is the process doing the WASI calls, so it is run under `strace`, which
+//! reports each write with the host path of its file descriptor. Only the writes to
+//! the preopened directories of the module are kept, with the path the module knows
+//! them by; the writes of the runtime itself, like the ones to its cache, are left
+//! out. `strace` only runs on Linux.
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The tracer the runtime is run under
+const STRACE: &str = "strace";
+
+/// The system calls WASI's `fd_write` and `fd_pwrite` end up as
+const WRITE_SYSCALLS: &[&str] = &["write", "writev", "pwrite64", "pwritev", "pwritev2"];
+
+/// The name of the trace files in the trace directory, `strace` adds the process id
+const TRACE_FILE_PREFIX: &str = "trace";
+
+/// A write to a file descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsWrite {
+    pub fd: u32,
+    /// What the descriptor refers to, a path or something like `pipe:[1234]`
+    pub path: Option<String>,
+    pub bytes: u64,
+}
+
+impl fmt::Display for FsWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "write fd={} path={} bytes={}",
+            self.fd,
+            self.path.as_deref().unwrap_or("?"),
+            self.bytes
+        )
+    }
+}
+
+/// A directory the module sees, by its path in the module and on the host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preopen {
+    pub guest: PathBuf,
+    pub host: PathBuf,
+}
+
+/// The preopens of the `--dir=DIR` and `--mapdir=GUEST:HOST` flags of the runtime.
+/// Relative host paths are relative to `current_dir`, the directory of the runtime.
+pub fn preopens_from_flags(flags: &[OsString], current_dir: &Path) -> Vec<Preopen> {
+    flags
+        .iter()
+        .filter_map(|flag| {
+            let flag = flag.to_str()?;
+            let (guest, host) = if let Some(dir) = flag.strip_prefix("--dir=") {
+                (dir, dir)
+            } else {
+                flag.strip_prefix("--mapdir=")?.split_once(':')?
+            };
+            // strace reports the paths with the symbolic links resolved
+            let host = current_dir.join(host);
+            Some(Preopen {
+                guest: PathBuf::from(guest),
+                host: host.canonicalize().unwrap_or(host),
+            })
+        })
+        .collect()
+}
+
+/// The writes to the files of the `preopens`, with the path of the file in the module.
+/// The other writes, like the ones of the runtime to its cache and the ones to stdout,
+/// are left out.
+pub fn module_writes(writes: Vec<FsWrite>, preopens: &[Preopen]) -> Vec<FsWrite> {
+    writes
+        .into_iter()
+        .filter_map(|write| {
+            let host_path = PathBuf::from(write.path.as_ref()?);
+            // the innermost preopen wins when they are nested
+            let guest_path = preopens
+                .iter()
+                .filter_map(|preopen| {
+                    let relative = host_path.strip_prefix(&preopen.host).ok()?;
+                    Some((
+                        preopen.host.components().count(),
+                        preopen.guest.join(relative),
+                    ))
+                })
+                .max_by_key(|(depth, _)| *depth)
+                .map(|(_, guest_path)| guest_path)?;
+            Some(FsWrite {
+                path: Some(guest_path.to_string_lossy().to_string()),
+                ..write
+            })
+        })
+        .collect()
+}
+
+/// Whether `strace` can be run
+pub fn tracer_is_available() -> bool {
+    Command::new(STRACE)
+        .arg("-V")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// `command` run under `strace`, which writes the trace of each process to
+/// `trace_dir`. The stdio and environment of the command are set on the returned one.
+pub fn traced_command(command: &Command, trace_dir: &Path) -> Command {
+    let mut traced = Command::new(STRACE);
+    traced
+        .args(["-ff", "-qq", "-y", "-s", "0", "-e"])
+        .arg(format!("trace={}", WRITE_SYSCALLS.join(",")))
+        .arg("-o")
+        .arg(trace_dir.join(TRACE_FILE_PREFIX))
+        .arg(command.get_program())
+        .args(command.get_args());
+    traced
+}
+
+/// The writes of the trace files in `trace_dir`, process by process
+pub fn read_trace(trace_dir: &Path) -> io::Result<Vec<FsWrite>> {
+    let mut trace_files: Vec<_> = fs::read_dir(trace_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(TRACE_FILE_PREFIX))
+        })
+        .collect();
+    trace_files.sort();
+    let mut writes = vec![];
+    for trace_file in trace_files {
+        let trace = fs::read_to_string(trace_file)?;
+        writes.extend(trace.lines().filter_map(parse_trace_line));
+    }
+    Ok(writes)
+}
+
+/// Parses a line of `strace -y` like `write(3</tmp/out.txt>, ""..., 5) = 5`. Other
+/// system calls, signals and failed writes are skipped.
+fn parse_trace_line(line: &str) -> Option<FsWrite> {
+    let (syscall, arguments) = line.split_once('(')?;
+    if !WRITE_SYSCALLS.contains(&syscall.trim()) {
+        return None;
+    }
+    let fd_end = arguments.find(|c: char| !c.is_ascii_digit())?;
+    let fd = arguments[..fd_end].parse().ok()?;
+    let path = arguments[fd_end..]
+        .strip_prefix('<')
+        .and_then(|path| path.split_once(">,"))
+        .map(|(path, _)| path.to_string());
+    // a failed write returns -1, which is not a byte count
+    let (_, result) = line.rsplit_once(" = ")?;
+    let bytes = result.split_whitespace().next()?.parse().ok()?;
+    Some(FsWrite { fd, path, bytes })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::create_temp_dir;
+
+    #[test]
+    fn writes_are_parsed_with_their_paths() {
+        assert_eq!(
+            parse_trace_line("write(3</tmp/output/result.txt>, \"\"..., 12) = 12"),
+            Some(FsWrite {
+                fd: 3,
+                path: Some("/tmp/output/result.txt".to_string()),
+                bytes: 12,
+            })
+        );
+        assert_eq!(
+            parse_trace_line("writev(1</dev/pts/0>, [{iov_base=\"\"..., iov_len=6}], 1) = 6"),
+            Some(FsWrite {
+                fd: 1,
+                path: Some("/dev/pts/0".to_string()),
+                bytes: 6,
+            })
+        );
+        assert_eq!(
+            parse_trace_line("pwrite64(7, \"\"..., 4096, 8192) = 4096")
+                .unwrap()
+                .path,
+            None
+        );
+        assert_eq!(
+            parse_trace_line("write(9<pipe:[4242]>, \"\"..., 5) = -1 EPIPE (Broken pipe)"),
+            None
+        );
+        assert_eq!(
+            parse_trace_line("--- SIGCHLD {si_signo=SIGCHLD, si_code=CLD_EXITED} ---"),
+            None
+        );
+        assert_eq!(
+            FsWrite {
+                fd: 3,
+                path: Some("/tmp/out.txt".to_string()),
+                bytes: 5,
+            }
+            .to_string(),
+            "write fd=3 path=/tmp/out.txt bytes=5"
+        );
+    }
+
+    #[test]
+    fn traces_of_all_processes_are_read() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let trace_dir = tmp_dir.path();
+        fs::write(
+            trace_dir.join("trace.101"),
+            "write(3</data/out.txt>, \"\"..., 5) = 5\n",
+        )
+        .unwrap();
+        fs::write(
+            trace_dir.join("trace.102"),
+            "write(2</dev/null>, \"\"..., 7) = 7\n+++ exited with 0 +++\n",
+        )
+        .unwrap();
+        let paths: Vec<Option<String>> = read_trace(trace_dir)
+            .unwrap()
+            .into_iter()
+            .map(|write| write.path)
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                Some("/data/out.txt".to_string()),
+                Some("/dev/null".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn only_the_writes_to_preopens_are_kept_with_their_guest_path() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let current_dir = tmp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(current_dir.join("data/nested")).unwrap();
+        let preopens = preopens_from_flags(
+            &[
+                OsString::from("--dir=data"),
+                OsString::from(format!(
+                    "--mapdir=/nested:{}",
+                    current_dir.join("data/nested").display()
+                )),
+                OsString::from("--mapdir=.:/work"),
+                OsString::from("--env=KEY=VALUE"),
+            ],
+            &current_dir,
+        );
+        assert_eq!(
+            preopens,
+            vec![
+                Preopen {
+                    guest: PathBuf::from("data"),
+                    host: current_dir.join("data"),
+                },
+                Preopen {
+                    guest: PathBuf::from("/nested"),
+                    host: current_dir.join("data/nested"),
+                },
+                Preopen {
+                    guest: PathBuf::from("."),
+                    host: PathBuf::from("/work"),
+                },
+            ]
+        );
+
+        let write = |fd, path: &Path| FsWrite {
+            fd,
+            path: Some(path.to_string_lossy().to_string()),
+            bytes: 5,
+        };
+        let writes = vec![
+            write(3, &current_dir.join("data/out.txt")),
+            write(4, &current_dir.join("data/nested/log.txt")),
+            write(5, Path::new("/work/result.json")),
+            // the runtime's own writes and stdout
+            write(6, Path::new("/home/user/.wasmer/cache/module.bin")),
+            write(1, Path::new("/dev/pts/0")),
+            FsWrite {
+                fd: 7,
+                path: None,
+                bytes: 5,
+            },
+        ];
+        let paths: Vec<(u32, String)> = module_writes(writes, &preopens)
+            .into_iter()
+            .map(|write| (write.fd, write.path.unwrap()))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                (3, "data/out.txt".to_string()),
+                (4, "/nested/log.txt".to_string()),
+                (5, "./result.json".to_string()),
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn writes_of_a_traced_process_are_in_the_trace() {
+        if !tracer_is_available() {
+            return;
+        }
+        let tmp_dir = create_temp_dir().unwrap();
+        let output = tmp_dir.path().join("output.txt");
+        let trace_dir = tmp_dir.path().join("trace");
+        fs::create_dir(&trace_dir).unwrap();
+        let mut command = Command::new("sh");
+        command.args(["-c", &format!("printf hello > {}", output.display())]);
+        let status = traced_command(&command, &trace_dir).status().unwrap();
+        assert!(status.success());
+
+        let output = output.canonicalize().unwrap().to_string_lossy().to_string();
+        assert!(read_trace(&trace_dir).unwrap().contains(&FsWrite {
+            fd: 1,
+            path: Some(output),
+            bytes: 5,
+        }));
+    }
+}
@@ -1,4 +1,5 @@
 use graphql_client::{QueryBody, Response};
+use std::collections::BTreeSet;
 use std::env;
 use std::string::ToString;
 use thiserror::Error;
@@ -37,11 +38,74 @@ where
     execute_query_modifier_inner(registry_url, query, form_modifier)
 }
 
+/// The `User-Agent` of the requests to the registry: `registry.user_agent` if set,
+/// else the wapm version, the platform, the distro and the architecture
+pub fn user_agent(config: &Config) -> String {
+    config.user_agent.clone().unwrap_or_else(|| {
+        format!(
+            "wapm/{} {} {} {}",
+            VERSION,
+            whoami::platform(),
+            whoami_distro(),
+            env::consts::ARCH,
+        )
+    })
+}
+
+/// The token sent to `registry_url`: `WAPM_REGISTRY_TOKEN` if set, else the one stored
+/// for that registry
+pub fn registry_token(config: &Config, registry_url: &str) -> String {
+    env::var("WAPM_REGISTRY_TOKEN").unwrap_or_else(|_| {
+        config
+            .registry
+            .get_login_token_for_registry(registry_url)
+            .unwrap_or_default()
+    })
+}
+
 pub fn execute_query_modifier_inner<R, V, F>(
     registry_url: &str,
     query: &QueryBody<V>,
     form_modifier: F,
 ) -> anyhow::Result<R>
+where
+    for<'de> R: serde::Deserialize<'de>,
+    V: serde::Serialize,
+    F: FnOnce(Form) -> Form,
+{
+    let config = Config::from_file()?;
+    execute_query_with_config(&config, registry_url, query, form_modifier)
+}
+
+fn execute_query_with_config<R, V, F>(
+    config: &Config,
+    registry_url: &str,
+    query: &QueryBody<V>,
+    form_modifier: F,
+) -> anyhow::Result<R>
+where
+    for<'de> R: serde::Deserialize<'de>,
+    V: serde::Serialize,
+    F: FnOnce(Form) -> Form,
+{
+    send_query(
+        config,
+        registry_url,
+        query.query,
+        query.operation_name,
+        &query.variables,
+        form_modifier,
+    )
+}
+
+fn send_query<R, V, F>(
+    config: &Config,
+    registry_url: &str,
+    query: &str,
+    operation_name: &str,
+    variables: &V,
+    form_modifier: F,
+) -> anyhow::Result<R>
 where
     for<'de> R: serde::Deserialize<'de>,
     V: serde::Serialize,
@@ -58,35 +122,25 @@ where
         };
         builder.build()?
     };
-    let config = Config::from_file()?;
 
-    let vars = serde_json::to_string(&query.variables).unwrap();
+    let vars = serde_json::to_string(variables).unwrap();
 
     let form = Form::new()
-        .text("query", query.query.to_string())
-        .text("operationName", query.operation_name.to_string())
+        .text("query", query.to_string())
+        .text("operationName", operation_name.to_string())
         .text("variables", vars);
 
     let form = form_modifier(form);
 
-    let user_agent = format!(
-        "wapm/{} {} {}",
-        VERSION,
-        whoami::platform(),
-        whoami_distro(),
-    );
-
-    let res = client
+    let mut request = client
         .post(registry_url)
         .multipart(form)
-        .bearer_auth(env::var("WAPM_REGISTRY_TOKEN").unwrap_or_else(|_| {
-            config
-                .registry
-                .get_login_token_for_registry(&config.registry.get_current_registry())
-                .unwrap_or_default()
-        }))
-        .header(USER_AGENT, user_agent)
-        .send()?;
+        .bearer_auth(registry_token(config, registry_url))
+        .header(USER_AGENT, user_agent(config));
+    for (header, value) in &config.registry_headers {
+        request = request.header(header.as_str(), value.as_str());
+    }
+    let res = request.send()?;
 
     let response_body: Response<R> = res.json()?;
     if let Some(errors) = response_body.errors {
@@ -117,3 +171,196 @@ where
 {
     execute_query_modifier_inner(registry_url, query, |f| f)
 }
+
+/// Runs a query that is built at runtime instead of being generated from
+/// `graphql/schema.graphql`, for the fields that only some registries have
+pub fn execute_runtime_query_custom_registry<R, V>(
+    registry_url: &str,
+    query: &str,
+    operation_name: &str,
+    variables: &V,
+) -> anyhow::Result<R>
+where
+    for<'de> R: serde::Deserialize<'de>,
+    V: serde::Serialize,
+{
+    let config = Config::from_file()?;
+    send_query(
+        &config,
+        registry_url,
+        query,
+        operation_name,
+        variables,
+        |f| f,
+    )
+}
+
+const INPUT_FIELDS_QUERY: &str =
+    "query InputFieldsQuery($name: String!) { __type(name: $name) { inputFields { name } } }";
+
+#[derive(Deserialize)]
+struct InputFieldsResponse {
+    #[serde(rename = "__type")]
+    input_type: Option<InputType>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InputType {
+    input_fields: Option<Vec<InputField>>,
+}
+
+#[derive(Deserialize)]
+struct InputField {
+    name: String,
+}
+
+fn input_fields_query(type_name: &str) -> QueryBody<serde_json::Value> {
+    QueryBody {
+        variables: serde_json::json!({ "name": type_name }),
+        query: INPUT_FIELDS_QUERY,
+        operation_name: "InputFieldsQuery",
+    }
+}
+
+/// The names of the fields of the `type_name` input type in the schema of the
+/// registry, asked by introspection
+pub fn registry_input_fields(
+    registry_url: &str,
+    type_name: &str,
+) -> anyhow::Result<BTreeSet<String>> {
+    let response: InputFieldsResponse =
+        execute_query_custom_registry(registry_url, &input_fields_query(type_name))?;
+    Ok(response
+        .input_type
+        .and_then(|input_type| input_type.input_fields)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|field| field.name)
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Answers one GraphQL request with empty data and returns the request
+    fn serve_one_query(listener: TcpListener) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buffer = [0; 4096];
+            // the body is read too, closing with it unread would reset the connection
+            let request_is_complete = |request: &[u8]| {
+                let text = String::from_utf8_lossy(request).to_lowercase();
+                let headers_end = match text.find("\r\n\r\n") {
+                    Some(end) => end + 4,
+                    None => return false,
+                };
+                let content_length = text[..headers_end]
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .and_then(|length| length.trim().parse::<usize>().ok());
+                match content_length {
+                    Some(length) => request.len() >= headers_end + length,
+                    None => text.ends_with("0\r\n\r\n"),
+                }
+            };
+            while !request_is_complete(&request) {
+                let read = stream.read(&mut buffer).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+            let body = r#"{"data":{}}"#;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        })
+    }
+
+    #[test]
+    fn configured_user_agent_is_sent() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let registry_url = format!("http://{}/graphql", listener.local_addr().unwrap());
+        let server = serve_one_query(listener);
+
+        let config = Config {
+            user_agent: Some("corp-proxy-approved/1.0".to_string()),
+            ..Config::default()
+        };
+        let query = QueryBody {
+            variables: (),
+            query: "{ viewer { username } }",
+            operation_name: "WhoAmIQuery",
+        };
+        let _: serde_json::Value =
+            execute_query_with_config(&config, &registry_url, &query, |f| f).unwrap();
+
+        let request = server.join().unwrap().to_lowercase();
+        assert!(
+            request.contains("\r\nuser-agent: corp-proxy-approved/1.0\r\n"),
+            "{}",
+            request
+        );
+    }
+
+    #[test]
+    fn configured_headers_are_sent() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let registry_url = format!("http://{}/graphql", listener.local_addr().unwrap());
+        let server = serve_one_query(listener);
+
+        let mut config = Config::default();
+        config
+            .registry_headers
+            .insert("X-Org-Id".to_string(), "myorg".to_string());
+        let query = QueryBody {
+            variables: (),
+            query: "{ viewer { username } }",
+            operation_name: "WhoAmIQuery",
+        };
+        let _: serde_json::Value =
+            execute_query_with_config(&config, &registry_url, &query, |f| f).unwrap();
+
+        let request = server.join().unwrap().to_lowercase();
+        assert!(request.contains("\r\nx-org-id: myorg\r\n"), "{}", request);
+    }
+
+    #[test]
+    fn input_fields_are_asked_by_introspection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let registry_url = format!("http://{}/graphql", listener.local_addr().unwrap());
+        let server = serve_one_query(listener);
+
+        let query = input_fields_query("PublishPackageInput");
+        let response: InputFieldsResponse =
+            execute_query_with_config(&Config::default(), &registry_url, &query, |f| f).unwrap();
+        // a registry without the type has no fields for it
+        assert!(response.input_type.is_none());
+
+        let request = server.join().unwrap();
+        assert!(request.contains("__type(name: $name)"), "{}", request);
+        assert!(
+            request.contains(r#"{"name":"PublishPackageInput"}"#),
+            "{}",
+            request
+        );
+    }
+
+    #[test]
+    fn default_user_agent_names_the_version_and_architecture() {
+        let user_agent = user_agent(&Config::default());
+        assert!(user_agent.starts_with(&format!("wapm/{} ", VERSION)));
+        assert!(user_agent.ends_with(env::consts::ARCH));
+    }
+}
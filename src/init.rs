@@ -3,6 +3,7 @@
 use crate::abi::Abi;
 use crate::data::manifest::MANIFEST_FILE_NAME;
 use crate::data::manifest::{Command, CommandV2, Manifest, Module, Package};
+use crate::data::manifest_extensions::{manifest_from_value, manifest_in_directory, save_manifest};
 use crate::util;
 
 use dialoguer::{Confirmation, Input, Select};
@@ -69,11 +70,33 @@ pub fn validate_commands(command_names: &str) -> Result<Vec<String>, util::NameE
         .collect())
 }
 
+/// Fields of the package given on the command line, they are not asked for
+#[derive(Debug, Default)]
+pub struct PackageFields {
+    pub name: Option<String>,
+    pub version: Option<Version>,
+    pub description: Option<String>,
+    pub license: Option<String>,
+}
+
+/// Checks the fields a user can't fix in a prompt, and that the manifest reads back
+fn validate_manifest(manifest: &Manifest) -> anyhow::Result<()> {
+    util::validate_name(&manifest.package.name)?;
+    if let Some(license) = manifest.package.license.as_ref() {
+        util::validate_license(license)?;
+    }
+    manifest_from_value(manifest.to_string()?.parse()?)?;
+    Ok(())
+}
+
 pub fn init(
     dir: PathBuf,
     force_yes: bool,
     initial_project_name: Option<String>,
+    fields: PackageFields,
 ) -> anyhow::Result<()> {
+    // without a terminal to answer the prompts, the defaults are taken
+    let interactive = !force_yes && atty::is(atty::Stream::Stdin);
     let manifest_location = {
         let mut dir = match initial_project_name.as_ref() {
             Some(s) => dir.join(s),
@@ -83,21 +106,23 @@ pub fn init(
         dir
     };
     let mut manifest = if manifest_location.exists() {
-        Manifest::find_in_directory(dir)?
+        manifest_in_directory(dir)?
     } else {
-        let package_name = initial_project_name.clone().unwrap_or_else(|| {
-            dir.clone()
-                .as_path()
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-                .to_string()
+        let name = fields.name.clone().unwrap_or_else(|| {
+            let package_name = initial_project_name.clone().unwrap_or_else(|| {
+                dir.clone()
+                    .as_path()
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            });
+            let username = crate::util::get_username().ok().flatten();
+            match username {
+                Some(s) => format!("{s}/{package_name}"),
+                None => package_name,
+            }
         });
-        let username = crate::util::get_username().ok().flatten();
-        let name = match username {
-            Some(s) => format!("{s}/{package_name}"),
-            None => package_name,
-        };
         Manifest {
             base_directory_path: match initial_project_name.as_ref() {
                 Some(s) => dir.join(s),
@@ -107,9 +132,9 @@ pub fn init(
             package: Package {
                 name: name.clone(),
                 description: format!("Package description for {name}"),
-                version: Version::parse("1.0.0").unwrap(),
+                version: Version::new(0, 1, 0),
                 repository: None,
-                license: Some("ISC".to_owned()),
+                license: Some("MIT".to_owned()),
                 license_file: None,
                 homepage: None,
                 wasmer_extra_flags: None,
@@ -132,7 +157,20 @@ pub fn init(
         }
     };
 
-    if !force_yes {
+    if let Some(name) = fields.name.clone() {
+        manifest.package.name = name;
+    }
+    if let Some(version) = fields.version.clone() {
+        manifest.package.version = version;
+    }
+    if let Some(description) = fields.description.clone() {
+        manifest.package.description = description;
+    }
+    if let Some(license) = fields.license.clone() {
+        manifest.package.license = Some(license);
+    }
+
+    if interactive {
         println!(
             "This utility will walk you through creating a wapm.toml file.
 It only covers the most common items, and tries to guess sensible defaults.
@@ -143,7 +181,7 @@ save it as a dependency in the wapm.toml file.
 Press ^C at any time to quit."
         );
 
-        if initial_project_name.is_none() {
+        if initial_project_name.is_none() && fields.name.is_none() {
             manifest.package.name = ask_until_valid(
                 "Package name",
                 Some(manifest.package.name),
@@ -151,19 +189,25 @@ Press ^C at any time to quit."
             )?;
         }
 
-        manifest.package.version = ask_until_valid(
-            "Version",
-            Some(manifest.package.version.to_string()),
-            Version::parse,
-        )?;
-        manifest.package.description =
-            ask("Description", Some(manifest.package.description))?.unwrap_or_default();
+        if fields.version.is_none() {
+            manifest.package.version = ask_until_valid(
+                "Version",
+                Some(manifest.package.version.to_string()),
+                Version::parse,
+            )?;
+        }
+        if fields.description.is_none() {
+            manifest.package.description =
+                ask("Description", Some(manifest.package.description))?.unwrap_or_default();
+        }
         manifest.package.repository = ask("Repository", manifest.package.repository)?;
-        manifest.package.license = Some(ask_until_valid(
-            "License",
-            manifest.package.license,
-            util::validate_license,
-        )?);
+        if fields.license.is_none() {
+            manifest.package.license = Some(ask_until_valid(
+                "License",
+                manifest.package.license,
+                util::validate_license,
+            )?);
+        }
         // Let's reset the modules
         let mut all_modules: Vec<Module> = vec![];
         let mut all_commands: Vec<Command> = vec![];
@@ -307,7 +351,9 @@ Press ^C at any time to quit."
         };
     }
 
-    let print_text = if force_yes {
+    validate_manifest(&manifest)?;
+
+    let print_text = if !interactive {
         "Wrote to"
     } else {
         "About to write to"
@@ -320,14 +366,14 @@ Press ^C at any time to quit."
         manifest.to_string()?
     );
 
-    if force_yes
+    if !interactive
         || Confirmation::new()
             .with_text("Is this OK? (yes)")
             .default(true)
             .interact()?
     {
         let _ = std::fs::create_dir_all(&manifest.base_directory_path);
-        manifest.save()?;
+        save_manifest(&manifest)?;
         #[allow(unused_must_use)]
         {
             init_gitignore(manifest.base_directory_path);
@@ -367,3 +413,39 @@ pub fn init_gitignore(mut dir: PathBuf) -> anyhow::Result<()> {
     f.write_all(b"\nwapm_packages")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::create_temp_dir;
+
+    #[test]
+    fn non_interactive_init_takes_defaults_and_fields() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let fields = PackageFields {
+            name: Some("test-package".to_string()),
+            description: Some("A test package".to_string()),
+            ..Default::default()
+        };
+        init(tmp_dir.path().to_path_buf(), true, None, fields).unwrap();
+
+        let manifest = Manifest::find_in_directory(tmp_dir.path()).unwrap();
+        assert_eq!(manifest.package.name, "test-package");
+        assert_eq!(manifest.package.version, Version::new(0, 1, 0));
+        assert_eq!(manifest.package.description, "A test package");
+        assert_eq!(manifest.package.license.as_deref(), Some("MIT"));
+        assert_eq!(manifest.module.unwrap()[0].name, "entry");
+    }
+
+    #[test]
+    fn invalid_fields_are_not_written() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let fields = PackageFields {
+            name: Some("test-package".to_string()),
+            license: Some("NOT-A-LICENSE".to_string()),
+            ..Default::default()
+        };
+        assert!(init(tmp_dir.path().to_path_buf(), true, None, fields).is_err());
+        assert!(!tmp_dir.path().join(MANIFEST_FILE_NAME).exists());
+    }
+}
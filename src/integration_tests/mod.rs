@@ -3,3 +3,4 @@
 mod add_remove_init;
 pub mod data;
 pub mod prelude;
+mod upgrade;
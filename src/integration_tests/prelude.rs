@@ -37,6 +37,14 @@ pub fn remove_dependencies(deps: &[&str]) -> anyhow::Result<()> {
     remove(RemoveOpt::new(deps.iter().map(|s| s.to_string()).collect()))
 }
 
+/// Runs `wapm upgrade`
+pub fn upgrade_dependencies(deps: &[&str], latest: bool) -> anyhow::Result<()> {
+    upgrade(UpgradeOpt::new(
+        deps.iter().map(|s| s.to_string()).collect(),
+        latest,
+    ))
+}
+
 /// Gets the thread local Manifest
 pub fn get_manifest() -> Result<Manifest, ManifestError> {
     Manifest::find_in_directory("this isn't used in the test impl right now")
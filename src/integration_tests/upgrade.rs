@@ -0,0 +1,30 @@
+#![cfg(test)]
+
+use super::prelude::*;
+
+#[test]
+fn upgrade_latest_rewrites_the_version() {
+    let _t = set_test_dir_to_new_temp_dir();
+    set_registry_to_dev().unwrap();
+    init_manifest().unwrap();
+    add_dependencies(&["mark2/python@0.0.4"]).unwrap();
+
+    assert!(upgrade_dependencies(&["lolcat"], true).is_err());
+
+    upgrade_dependencies(&["mark2/python"], false).unwrap();
+    let manifest = get_manifest().unwrap();
+    // an exact version has nothing to upgrade to within its range
+    assert_eq!(
+        manifest.dependencies.unwrap()["mark2/python"],
+        "0.0.4".to_string()
+    );
+
+    upgrade_dependencies(&["mark2/python"], true).unwrap();
+    let manifest = get_manifest().unwrap();
+    let version = &manifest.dependencies.unwrap()["mark2/python"];
+    assert!(
+        semver::Version::parse(version).unwrap() > semver::Version::new(0, 0, 4),
+        "{}",
+        version
+    );
+}
@@ -26,14 +26,25 @@ pub mod data;
 #[cfg(feature = "full")]
 mod database;
 mod dataflow;
+#[cfg(not(target_os = "wasi"))]
+mod fs_trace;
 mod graphql;
 mod init;
 #[cfg(feature = "full")]
 mod interfaces;
 mod keys;
 pub mod logging;
+mod memory_limit;
+#[cfg(feature = "full")]
+mod module_cache;
 #[cfg(not(target_os = "wasi"))]
 mod proxy;
+#[cfg(feature = "full")]
+mod remote_source;
+#[cfg(not(target_os = "wasi"))]
+mod run_failure;
+#[cfg(not(target_os = "wasi"))]
+mod signals;
 mod sql;
 #[cfg(feature = "update-notifications")]
 pub mod update_notifier;
@@ -3,8 +3,10 @@
 use crate::config::Config;
 use crate::util;
 use fern::colors::{Color, ColoredLevelConfig};
+use std::fmt;
 use std::fs;
 use std::io;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use thiserror::Error;
 
@@ -37,8 +39,66 @@ pub(crate) fn clear_stdout() -> io::Result<()> {
     Ok(())
 }
 
+/// The format of the log records printed to stdout and stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// `[LEVEL] message`, colored when the terminal supports it
+    #[default]
+    Text,
+    /// One JSON object per line, with the level, target, message and timestamp
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = LoggingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(LoggingError::UnknownLogFormat(s.to_string())),
+        }
+    }
+}
+
+/// Removes the `--log-format <FORMAT>` option given before the subcommand from the
+/// program arguments. The loggers are set up before the arguments are parsed, so the
+/// option can't be handled by the subcommands.
+pub fn extract_log_format(args: Vec<String>) -> Result<(LogFormat, Vec<String>), LoggingError> {
+    let mut log_format = LogFormat::default();
+    let mut args = args.into_iter();
+    let mut remaining_args: Vec<String> = args.next().into_iter().collect();
+    while let Some(arg) = args.next() {
+        if arg == "--log-format" {
+            let value = args
+                .next()
+                .ok_or_else(|| LoggingError::UnknownLogFormat("".to_string()))?;
+            log_format = value.parse()?;
+        } else if let Some(value) = arg.strip_prefix("--log-format=") {
+            log_format = value.parse()?;
+        } else {
+            // everything from the subcommand on belongs to the subcommand
+            remaining_args.push(arg);
+            remaining_args.extend(args);
+            break;
+        }
+    }
+    Ok((log_format, remaining_args))
+}
+
+/// Formats a log record as a single line of JSON
+fn format_json_record(message: &fmt::Arguments, record: &log::Record) -> String {
+    serde_json::json!({
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "level": record.level().to_string(),
+        "target": record.target(),
+        "message": message.to_string(),
+    })
+    .to_string()
+}
+
 /// Subroutine to instantiate the loggers
-pub fn set_up_logging(count_lines: bool) -> anyhow::Result<()> {
+pub fn set_up_logging(count_lines: bool, log_format: LogFormat) -> anyhow::Result<()> {
     let colors_line = ColoredLevelConfig::new()
         .error(Color::Red)
         .warn(Color::Yellow)
@@ -49,7 +109,17 @@ pub fn set_up_logging(count_lines: bool) -> anyhow::Result<()> {
     let dispatch = fern::Dispatch::new()
         // stdout and stderr logging
         .chain({
-            let base = if should_color {
+            let base = if log_format == LogFormat::Json {
+                fern::Dispatch::new()
+                    .level(log::LevelFilter::Info)
+                    .filter(|metadata| metadata.target().starts_with("wapm_cli"))
+                    .format(move |out, message, record| {
+                        if count_lines && record.level() == log::Level::Info {
+                            add_lines_printed_to_stdout(1);
+                        }
+                        out.finish(format_args!("{}", format_json_record(message, record)));
+                    })
+            } else if should_color {
                 fern::Dispatch::new()
                     .level(log::LevelFilter::Info)
                     .filter(|metadata| metadata.target().starts_with("wapm_cli"))
@@ -160,4 +230,51 @@ pub enum LoggingError {
     FailedToOpenLoggingFile(String),
     #[error("Something went wrong setting up logging: {0}")]
     FailedToInstantiateLogger(String),
+    #[error("Unknown log format \"{0}\", expected \"text\" or \"json\"")]
+    UnknownLogFormat(String),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn log_format_is_taken_before_the_subcommand() {
+        let (log_format, remaining) =
+            extract_log_format(args(&["wapm", "--log-format", "json", "install"])).unwrap();
+        assert_eq!(log_format, LogFormat::Json);
+        assert_eq!(remaining, args(&["wapm", "install"]));
+
+        let (log_format, remaining) =
+            extract_log_format(args(&["wapm", "run", "cowsay", "--log-format=json"])).unwrap();
+        assert_eq!(log_format, LogFormat::Text);
+        assert_eq!(
+            remaining,
+            args(&["wapm", "run", "cowsay", "--log-format=json"])
+        );
+
+        assert!(extract_log_format(args(&["wapm", "--log-format=yaml", "list"])).is_err());
+    }
+
+    #[test]
+    fn json_log_line() {
+        let line = format_json_record(
+            &format_args!("Installing {}", "_/cowsay"),
+            &log::Record::builder()
+                .args(format_args!("Installing {}", "_/cowsay"))
+                .level(log::Level::Info)
+                .target("wapm_cli::dataflow")
+                .build(),
+        );
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "INFO");
+        assert_eq!(value["target"], "wapm_cli::dataflow");
+        assert_eq!(value["message"], "Installing _/cowsay");
+        assert!(value["timestamp"].is_string());
+    }
 }
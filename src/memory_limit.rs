@@ -0,0 +1,326 @@
+//! The memory limit of `wapm run --max-memory`. The runtime has no setting for it, so
+//! the limit is written into the module: the maximum of its memories is lowered to
+//! the limit, and growing past it fails in the module like any other allocation.
+use std::borrow::Cow;
+use thiserror::Error;
+
+/// The size of a page of wasm memory, the unit of the memory limits
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+const IMPORT_SECTION_ID: u8 = 2;
+const MEMORY_SECTION_ID: u8 = 5;
+
+/// What the runtime prints when a module fails to allocate, like Rust's
+/// `memory allocation of 1048576 bytes failed` or Go's `runtime: out of memory`
+static OUT_OF_MEMORY_MESSAGES: &[&str] = &["memory allocation of", "out of memory"];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MemoryLimitError {
+    #[error("it is not a valid wasm module: {0}")]
+    InvalidWasm(&'static str),
+    #[error("it needs {0} of memory to start")]
+    InitialMemoryTooLarge(String),
+}
+
+/// Parses a size like `256MB` or `1GB`. The units are powers of 1024, `KiB`, `MiB`
+/// and `GiB` are accepted too, and a size without a unit is in bytes.
+pub fn parse_memory_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let digits_end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(digits_end);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("\"{}\" is not a size like 256MB", value))?;
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        _ => {
+            return Err(format!(
+                "unknown unit \"{}\", use KB, MB or GB",
+                unit.trim()
+            ))
+        }
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("{} is too large", value))
+}
+
+/// Formats a size in the largest unit it is a whole number of
+pub fn format_memory_size(bytes: u64) -> String {
+    for (unit, shift) in &[("GB", 30), ("MB", 20), ("KB", 10)] {
+        if bytes >> shift > 0 && bytes.trailing_zeros() >= *shift {
+            return format!("{}{}", bytes >> shift, unit);
+        }
+    }
+    format!("{}B", bytes)
+}
+
+/// The module with the maximum of its memories, defined or imported, lowered to
+/// `max_memory` bytes. A module with no memory is returned as is.
+pub fn limit_memory(wasm: &[u8], max_memory: u64) -> Result<Vec<u8>, MemoryLimitError> {
+    if wasm.len() < 8 || &wasm[..4] != b"\0asm" {
+        return Err(MemoryLimitError::InvalidWasm("no wasm header"));
+    }
+    let max_pages = max_memory / WASM_PAGE_SIZE;
+    let mut limited = wasm[..8].to_vec();
+    let mut reader = Reader { wasm, position: 8 };
+    while !reader.is_at_end() {
+        let id = reader.byte()?;
+        let size = reader.leb()? as usize;
+        let contents = reader.bytes(size)?;
+        let contents = match id {
+            IMPORT_SECTION_ID => Cow::Owned(limit_import_section(contents, max_pages)?),
+            MEMORY_SECTION_ID => Cow::Owned(limit_memory_section(contents, max_pages)?),
+            _ => Cow::Borrowed(contents),
+        };
+        limited.push(id);
+        write_leb(&mut limited, contents.len() as u64);
+        limited.extend_from_slice(&contents);
+    }
+    Ok(limited)
+}
+
+/// Whether the runtime's stderr says that the module failed to allocate memory
+pub fn ran_out_of_memory(stderr: &str) -> bool {
+    OUT_OF_MEMORY_MESSAGES
+        .iter()
+        .any(|message| stderr.contains(message))
+}
+
+fn limit_memory_section(section: &[u8], max_pages: u64) -> Result<Vec<u8>, MemoryLimitError> {
+    let mut reader = Reader {
+        wasm: section,
+        position: 0,
+    };
+    let mut limited = vec![];
+    let count = reader.leb()?;
+    write_leb(&mut limited, count);
+    for _ in 0..count {
+        limit_memory_type(&mut reader, &mut limited, max_pages)?;
+    }
+    Ok(limited)
+}
+
+fn limit_import_section(section: &[u8], max_pages: u64) -> Result<Vec<u8>, MemoryLimitError> {
+    let mut reader = Reader {
+        wasm: section,
+        position: 0,
+    };
+    let mut limited = vec![];
+    let count = reader.leb()?;
+    write_leb(&mut limited, count);
+    for _ in 0..count {
+        let start = reader.position;
+        // the namespace and the name
+        for _ in 0..2 {
+            let length = reader.leb()? as usize;
+            reader.bytes(length)?;
+        }
+        let kind = reader.byte()?;
+        match kind {
+            // a function, by type index
+            0x00 => {
+                reader.leb()?;
+            }
+            // a tag, its attribute and type index
+            0x04 => {
+                reader.byte()?;
+                reader.leb()?;
+            }
+            // a table, its element type and limits
+            0x01 => {
+                reader.byte()?;
+                let flags = reader.byte()?;
+                reader.leb()?;
+                if flags & 0x01 != 0 {
+                    reader.leb()?;
+                }
+            }
+            // a global, its value type and mutability
+            0x03 => {
+                reader.bytes(2)?;
+            }
+            0x02 => {
+                limited.extend_from_slice(&section[start..reader.position]);
+                limit_memory_type(&mut reader, &mut limited, max_pages)?;
+                continue;
+            }
+            _ => return Err(MemoryLimitError::InvalidWasm("unknown import kind")),
+        }
+        limited.extend_from_slice(&section[start..reader.position]);
+    }
+    Ok(limited)
+}
+
+/// Copies the limits of a memory with the maximum lowered to `max_pages`
+fn limit_memory_type(
+    reader: &mut Reader,
+    limited: &mut Vec<u8>,
+    max_pages: u64,
+) -> Result<(), MemoryLimitError> {
+    // bit 0: has a maximum, bit 1: shared, bit 2: 64-bit
+    let flags = reader.byte()?;
+    if flags > 0x07 {
+        return Err(MemoryLimitError::InvalidWasm("unknown memory limits"));
+    }
+    let initial = reader.leb()?;
+    let maximum = if flags & 0x01 != 0 {
+        Some(reader.leb()?)
+    } else {
+        None
+    };
+    if initial > max_pages {
+        return Err(MemoryLimitError::InitialMemoryTooLarge(format_memory_size(
+            initial.saturating_mul(WASM_PAGE_SIZE),
+        )));
+    }
+    limited.push(flags | 0x01);
+    write_leb(limited, initial);
+    write_leb(
+        limited,
+        maximum.map_or(max_pages, |maximum| maximum.min(max_pages)),
+    );
+    Ok(())
+}
+
+struct Reader<'a> {
+    wasm: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_at_end(&self) -> bool {
+        self.position >= self.wasm.len()
+    }
+
+    fn byte(&mut self) -> Result<u8, MemoryLimitError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], MemoryLimitError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.wasm.len())
+            .ok_or(MemoryLimitError::InvalidWasm(
+                "unexpected end of the module",
+            ))?;
+        let bytes = &self.wasm[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// An unsigned LEB128 integer
+    fn leb(&mut self) -> Result<u64, MemoryLimitError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MemoryLimitError::InvalidWasm("integer too large"))
+    }
+}
+
+fn write_leb(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The memories of a module, as `(initial, maximum)` pages
+    fn memories(wasm: &[u8]) -> Vec<(u32, Option<u32>)> {
+        use wasmparser::{ImportSectionEntryType, ParserState, WasmDecoder};
+        let mut parser = wasmparser::Parser::new(wasm);
+        let mut memories = vec![];
+        loop {
+            match parser.read() {
+                ParserState::MemorySectionEntry(memory)
+                | ParserState::ImportSectionEntry {
+                    ty: ImportSectionEntryType::Memory(memory),
+                    ..
+                } => memories.push((memory.limits.initial, memory.limits.maximum)),
+                ParserState::EndWasm => return memories,
+                ParserState::Error(e) => panic!("invalid module: {:?}", e),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn memory_sizes() {
+        assert_eq!(parse_memory_size("256MB"), Ok(256 * 1024 * 1024));
+        assert_eq!(parse_memory_size("1GB"), Ok(1024 * 1024 * 1024));
+        assert_eq!(parse_memory_size("64 KiB"), Ok(64 * 1024));
+        assert_eq!(parse_memory_size("4096"), Ok(4096));
+        assert!(parse_memory_size("MB").is_err());
+        assert!(parse_memory_size("12TB").is_err());
+        assert!(parse_memory_size("99999999999GB").is_err());
+        assert_eq!(format_memory_size(256 * 1024 * 1024), "256MB");
+        assert_eq!(format_memory_size(1536 * 1024), "1536KB");
+        assert_eq!(format_memory_size(100), "100B");
+    }
+
+    #[test]
+    fn memory_growth_is_capped_at_the_limit() {
+        // a memory of 1 page that can grow to 65536 pages, 4GB, and a memory imported
+        // from the host with no maximum
+        let wasm = [
+            &b"\0asm\x01\0\0\0"[..],
+            &[0x02, 0x0f, 0x01, 0x03],
+            b"env",
+            &[0x06],
+            b"memory",
+            &[0x02, 0x00, 0x02],
+            &[0x05, 0x06, 0x01, 0x01, 0x01, 0x80, 0x80, 0x04],
+        ]
+        .concat();
+        assert_eq!(memories(&wasm), vec![(2, None), (1, Some(65536))]);
+
+        let limited = limit_memory(&wasm, parse_memory_size("256MB").unwrap()).unwrap();
+        assert_eq!(memories(&limited), vec![(2, Some(4096)), (1, Some(4096))]);
+        // a lower maximum is kept
+        let limited = limit_memory(&limited, parse_memory_size("1GB").unwrap()).unwrap();
+        assert_eq!(memories(&limited), vec![(2, Some(4096)), (1, Some(4096))]);
+
+        assert_eq!(
+            limit_memory(&wasm, parse_memory_size("64KB").unwrap()),
+            Err(MemoryLimitError::InitialMemoryTooLarge("128KB".to_string()))
+        );
+        assert!(limit_memory(&wasm[..wasm.len() - 1], WASM_PAGE_SIZE).is_err());
+
+        let without_memory = b"\0asm\x01\0\0\0";
+        assert_eq!(
+            limit_memory(without_memory, WASM_PAGE_SIZE).unwrap(),
+            without_memory
+        );
+    }
+
+    #[test]
+    fn failed_allocations_are_detected() {
+        assert!(ran_out_of_memory(
+            "memory allocation of 268435456 bytes failed\n\
+             error: failed to run `hog.wasm`\n\
+             │   1: RuntimeError: unreachable\n"
+        ));
+        assert!(ran_out_of_memory("fatal error: runtime: out of memory\n"));
+        assert!(!ran_out_of_memory("RuntimeError: unreachable\n"));
+    }
+}
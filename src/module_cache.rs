@@ -0,0 +1,157 @@
+//! A cache of modules compiled ahead of time by the default runtime, so that `wapm run`
+//! doesn't compile the same wasm on every run.
+//!
+//! Artifacts live in the `module-cache` folder of the config folder and are keyed by the
+//! sha256 of the wasm, the version of the runtime and the compiler backend. A change of
+//! any of those is a different artifact, so stale artifacts are never loaded.
+use crate::config::Config;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub static MODULE_CACHE_DIR_NAME: &str = "module-cache";
+static ARTIFACT_EXTENSION: &str = "wasmu";
+
+#[derive(Debug, Error)]
+pub enum ModuleCacheError {
+    #[error("Could not use the module cache at \"{0}\". {1}")]
+    Io(String, String),
+    #[error("Could not compile the module. {0}")]
+    Compile(String),
+}
+
+/// What a compiled artifact depends on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleCacheKey {
+    wasm_hash: String,
+    runtime_version: String,
+    backend: String,
+}
+
+impl ModuleCacheKey {
+    pub fn new(wasm: &[u8], runtime_version: &str, backend: &str) -> Self {
+        Self {
+            wasm_hash: format!("{:x}", Sha256::digest(wasm)),
+            runtime_version: runtime_version.to_string(),
+            backend: backend.to_string(),
+        }
+    }
+
+    fn file_name(&self) -> String {
+        // the version is free-form text, so it only goes in the name hashed
+        let runtime = Sha256::digest(format!("{}\n{}", self.runtime_version, self.backend));
+        format!("{}-{:x}.{}", self.wasm_hash, runtime, ARTIFACT_EXTENSION)
+    }
+}
+
+pub struct ModuleCache {
+    directory: PathBuf,
+}
+
+impl ModuleCache {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    /// The cache in the config folder
+    pub fn in_config_folder() -> anyhow::Result<Self> {
+        Ok(Self::new(Config::get_folder()?.join(MODULE_CACHE_DIR_NAME)))
+    }
+
+    fn io_error(&self, e: io::Error) -> ModuleCacheError {
+        ModuleCacheError::Io(self.directory.to_string_lossy().to_string(), e.to_string())
+    }
+
+    /// The path of the artifact for `key`. When it is not cached yet, `compile` is
+    /// called to write it to the path it is given.
+    pub fn get_or_compile<F>(
+        &self,
+        key: &ModuleCacheKey,
+        compile: F,
+    ) -> Result<PathBuf, ModuleCacheError>
+    where
+        F: FnOnce(&Path) -> Result<(), String>,
+    {
+        let artifact_path = self.directory.join(key.file_name());
+        if artifact_path.is_file() {
+            debug!("Using the compiled module at {:?}", artifact_path);
+            return Ok(artifact_path);
+        }
+        fs::create_dir_all(&self.directory).map_err(|e| self.io_error(e))?;
+        // compile next to the artifact and rename, so that a failed or concurrent
+        // compilation never leaves a partial artifact behind
+        let partial_path = artifact_path.with_extension(format!(
+            "{}.{}.partial",
+            ARTIFACT_EXTENSION,
+            std::process::id()
+        ));
+        if let Err(e) = compile(&partial_path) {
+            fs::remove_file(&partial_path).ok();
+            return Err(ModuleCacheError::Compile(e));
+        }
+        fs::rename(&partial_path, &artifact_path).map_err(|e| self.io_error(e))?;
+        Ok(artifact_path)
+    }
+
+    /// Deletes all the cached artifacts
+    pub fn clean(&self) -> Result<(), ModuleCacheError> {
+        if self.directory.exists() {
+            fs::remove_dir_all(&self.directory).map_err(|e| self.io_error(e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::create_temp_dir;
+    use std::cell::Cell;
+
+    #[test]
+    fn second_run_uses_the_cache() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let cache = ModuleCache::new(tmp_dir.path().join(MODULE_CACHE_DIR_NAME));
+        let compilations = Cell::new(0);
+        let compile = |path: &Path| {
+            compilations.set(compilations.get() + 1);
+            fs::write(path, b"compiled").map_err(|e| e.to_string())
+        };
+
+        let key = ModuleCacheKey::new(b"\0asm", "wasmer 2.3.0", "cranelift");
+        let first = cache.get_or_compile(&key, compile).unwrap();
+        let second = cache.get_or_compile(&key, compile).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(fs::read(&second).unwrap(), b"compiled");
+        assert_eq!(compilations.get(), 1);
+
+        // any change of the key is a new artifact
+        for key in &[
+            ModuleCacheKey::new(b"\0asm\x01", "wasmer 2.3.0", "cranelift"),
+            ModuleCacheKey::new(b"\0asm", "wasmer 3.0.0", "cranelift"),
+            ModuleCacheKey::new(b"\0asm", "wasmer 2.3.0", "llvm"),
+        ] {
+            assert_ne!(cache.get_or_compile(key, compile).unwrap(), first);
+        }
+        assert_eq!(compilations.get(), 4);
+
+        cache.clean().unwrap();
+        cache.get_or_compile(&key, compile).unwrap();
+        assert_eq!(compilations.get(), 5);
+    }
+
+    #[test]
+    fn failed_compilation_is_not_cached() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let cache = ModuleCache::new(tmp_dir.path().to_path_buf());
+        let key = ModuleCacheKey::new(b"\0asm", "wasmer 2.3.0", "cranelift");
+        let result = cache.get_or_compile(&key, |path| {
+            fs::write(path, b"partial").unwrap();
+            Err("unsupported".to_string())
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,167 @@
+//! Module sources hosted elsewhere: the `source` of a `[[module]]` can be the `http://`
+//! or `https://` URL of a pre-built wasm file instead of a path.
+//!
+//! Remote sources are downloaded to the `remote-sources` folder of the config folder and
+//! named by the sha256 of their URL, so each URL is only downloaded once.
+use crate::config::Config;
+use crate::data::manifest::Module;
+use crate::graphql::user_agent;
+use crate::proxy;
+use reqwest::blocking::ClientBuilder;
+use reqwest::header;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub static REMOTE_SOURCES_DIR_NAME: &str = "remote-sources";
+
+#[derive(Debug, Error)]
+pub enum RemoteSourceError {
+    #[error("Could not download the source of the \"{0}\" module from {1}. {2}")]
+    Download(String, String, String),
+    #[error("Could not store the downloaded sources at \"{0}\". {1}")]
+    Io(String, String),
+}
+
+/// The URL of a module source, when it isn't a path
+pub fn source_url(source: &Path) -> Option<&str> {
+    source
+        .to_str()
+        .filter(|source| source.starts_with("http://") || source.starts_with("https://"))
+}
+
+/// The local file of the source of `module`: the downloaded file for a URL, the path
+/// joined to `base_directory` otherwise
+pub fn resolved_module_source(module: &Module, base_directory: &Path) -> anyhow::Result<PathBuf> {
+    match source_url(&module.source) {
+        Some(url) => Ok(RemoteSources::in_config_folder()?.get_or_download(&module.name, url)?),
+        None => Ok(base_directory.join(&module.source)),
+    }
+}
+
+pub struct RemoteSources {
+    directory: PathBuf,
+}
+
+impl RemoteSources {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+
+    /// The downloads in the config folder
+    pub fn in_config_folder() -> anyhow::Result<Self> {
+        Ok(Self::new(
+            Config::get_folder()?.join(REMOTE_SOURCES_DIR_NAME),
+        ))
+    }
+
+    fn io_error(&self, e: io::Error) -> RemoteSourceError {
+        RemoteSourceError::Io(self.directory.to_string_lossy().to_string(), e.to_string())
+    }
+
+    /// The path of the file downloaded from `url`, downloading it if it isn't yet
+    pub fn get_or_download(&self, module: &str, url: &str) -> Result<PathBuf, RemoteSourceError> {
+        let path = self
+            .directory
+            .join(format!("{:x}", Sha256::digest(url.as_bytes())));
+        if path.is_file() {
+            debug!("Using the download of {} at {:?}", url, path);
+            return Ok(path);
+        }
+        fs::create_dir_all(&self.directory).map_err(|e| self.io_error(e))?;
+        let download_error =
+            |e: String| RemoteSourceError::Download(module.to_string(), url.to_string(), e);
+        let builder = ClientBuilder::new();
+        let builder =
+            match proxy::maybe_set_up_proxy().map_err(|e| download_error(e.to_string()))? {
+                Some(proxy) => builder.proxy(proxy),
+                None => builder,
+            };
+        let client = builder.build().map_err(|e| download_error(e.to_string()))?;
+        let config = Config::from_file().unwrap_or_default();
+        let mut response = client
+            .get(url)
+            .header(header::USER_AGENT, user_agent(&config))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| download_error(e.to_string()))?;
+        // download next to the file and rename, so that a failed or concurrent download
+        // never leaves a partial file behind
+        let partial_path = path.with_extension(format!("{}.partial", std::process::id()));
+        let downloaded =
+            fs::File::create(&partial_path).and_then(|mut file| io::copy(&mut response, &mut file));
+        if let Err(e) = downloaded {
+            fs::remove_file(&partial_path).ok();
+            return Err(download_error(e.to_string()));
+        }
+        fs::rename(&partial_path, &path).map_err(|e| self.io_error(e))?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::create_temp_dir;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    /// Serves `body` to every request and counts the requests
+    fn serve(body: &'static [u8]) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/module.wasm", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+                while !String::from_utf8_lossy(&request).contains("\r\n\r\n") {
+                    let read = stream.read(&mut buffer).unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    #[test]
+    fn remote_sources_are_downloaded_once() {
+        let (url, requests) = serve(b"\0asm\x01\0\0\0");
+        let tmp_dir = create_temp_dir().unwrap();
+        let sources = RemoteSources::new(tmp_dir.path().join(REMOTE_SOURCES_DIR_NAME));
+
+        let path = sources.get_or_download("module", &url).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"\0asm\x01\0\0\0");
+        assert_eq!(sources.get_or_download("module", &url).unwrap(), path);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn only_http_sources_are_urls() {
+        assert_eq!(
+            source_url(Path::new("https://example.com/module.wasm")),
+            Some("https://example.com/module.wasm")
+        );
+        assert!(source_url(Path::new("http://localhost/module.wasm")).is_some());
+        assert!(source_url(Path::new("target/module.wasm")).is_none());
+        assert!(source_url(Path::new("file:///module.wasm")).is_none());
+    }
+}
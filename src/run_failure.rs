@@ -0,0 +1,145 @@
+//! Explanations of why a module run by `wapm run --explain-error` failed. They are
+//! derived from the exit code of the runtime and from the end of what it printed to
+//! stderr, where the runtime reports traps.
+use std::io::{self, Read, Write};
+use std::process::ChildStderr;
+use std::thread::{self, JoinHandle};
+
+/// How much of the end of the runtime's stderr is kept to look for a trap
+const STDERR_TAIL_SIZE: usize = 64 * 1024;
+
+/// The messages of the runtime for each trap and what they mean
+static TRAPS: &[(&str, &str)] = &[
+    (
+        "unreachable",
+        "it executed an `unreachable` instruction. Compilers emit it for panics and aborts, \
+         so look for a panic message above",
+    ),
+    (
+        "out of bounds memory access",
+        "it read or wrote outside of its memory, usually through a dangling pointer or \
+         past the end of a buffer",
+    ),
+    (
+        "call stack exhausted",
+        "it ran out of stack, usually because of unbounded recursion",
+    ),
+    ("integer divide by zero", "it divided an integer by zero"),
+    (
+        "integer overflow",
+        "an integer division overflowed, or a float was converted to an integer too \
+         small to hold it",
+    ),
+    (
+        "invalid conversion to integer",
+        "it converted NaN to an integer",
+    ),
+    (
+        "indirect call type mismatch",
+        "it called a function through a table with the wrong signature, usually a \
+         function pointer cast to the wrong type",
+    ),
+    (
+        "undefined element",
+        "it called a function through a table index that is out of bounds",
+    ),
+    (
+        "uninitialized element",
+        "it called a function through a table entry that was never set, usually a null \
+         function pointer",
+    ),
+];
+
+/// Copies the runtime's stderr to wapm's as it comes and returns its end once the
+/// runtime closes it
+pub fn tee_stderr(mut child_stderr: ChildStderr) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut tail = vec![];
+        let mut buffer = [0; 8 * 1024];
+        loop {
+            let read = match child_stderr.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            io::stderr().write_all(&buffer[..read]).ok();
+            tail.extend_from_slice(&buffer[..read]);
+            if tail.len() > STDERR_TAIL_SIZE {
+                tail.drain(..tail.len() - STDERR_TAIL_SIZE);
+            }
+        }
+        String::from_utf8_lossy(&tail).to_string()
+    })
+}
+
+/// Why the module failed, `None` when it didn't. `stderr` is the end of what the
+/// runtime printed.
+pub fn explain_failure(module_name: &str, exit_code: Option<i32>, stderr: &str) -> Option<String> {
+    // killed by a signal, which is reported on its own
+    let exit_code = exit_code?;
+    if exit_code == 0 {
+        return None;
+    }
+    if let Some((_, meaning)) = TRAPS.iter().find(|(message, _)| stderr.contains(message)) {
+        return Some(format!(
+            "The module \"{}\" trapped: {}.",
+            module_name, meaning
+        ));
+    }
+    Some(format!(
+        "The module \"{}\" exited with code {}. It didn't trap, so it picked the code itself \
+         when it called `proc_exit` or returned from `main`: see its documentation for \
+         what the code means.",
+        module_name, exit_code
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trap_is_explained() {
+        let stderr = "error: failed to run `trap.wasm`\n\
+                      │   1: RuntimeError: unreachable\n\
+                      │            at __rust_start_panic (trap.wasm[42]:0x1f3a)\n\
+                      │            at main (trap.wasm[7]:0x2b1)\n";
+        let explanation = explain_failure("trap", Some(1), stderr).unwrap();
+        assert!(
+            explanation.starts_with("The module \"trap\" trapped: it executed an `unreachable`")
+        );
+
+        let stderr = "RuntimeError: out of bounds memory access";
+        assert!(explain_failure("trap", Some(1), stderr)
+            .unwrap()
+            .contains("outside of its memory"));
+    }
+
+    #[test]
+    fn proc_exit_is_explained() {
+        let explanation = explain_failure("exit", Some(1), "").unwrap();
+        assert!(explanation.starts_with("The module \"exit\" exited with code 1."));
+        assert_eq!(explain_failure("exit", Some(0), ""), None);
+        assert_eq!(explain_failure("exit", None, ""), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stderr_is_copied_and_kept() {
+        use std::process::{Command, Stdio};
+        let mut child = Command::new("sh")
+            .args([
+                "-c",
+                "echo 'RuntimeError: call stack exhausted' >&2; exit 1",
+            ])
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stderr_tail = tee_stderr(child.stderr.take().unwrap());
+        let status = child.wait().unwrap();
+        let stderr = stderr_tail.join().unwrap();
+        assert_eq!(stderr, "RuntimeError: call stack exhausted\n");
+        assert!(explain_failure("recursion", status.code(), &stderr)
+            .unwrap()
+            .contains("unbounded recursion"));
+    }
+}
@@ -0,0 +1,203 @@
+//! Code for passing interrupts on to the runtime that `wapm run` spawns
+//!
+//! While the runtime runs, wapm handles SIGINT and SIGTERM (Ctrl-C and Ctrl-Break on
+//! Windows) instead of dying from them, so that it can wait for the module to exit
+//! and then exit with a code derived from the signal, like shells do.
+use std::io;
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// The last signal wapm received while forwarding, 0 for none
+static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+/// The process signals are forwarded to, 0 before it is spawned
+#[cfg(unix)]
+static CHILD_PID: AtomicI32 = AtomicI32::new(0);
+
+#[cfg(unix)]
+const FORWARDED_SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+#[cfg(unix)]
+extern "C" fn forward_signal(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    _context: *mut libc::c_void,
+) {
+    RECEIVED_SIGNAL.store(signal, Ordering::SeqCst);
+    // a signal from the terminal, like Ctrl-C, already reaches the whole foreground
+    // process group, the runtime included. Only the ones another process sent to wapm
+    // have to be forwarded, or the runtime would get them twice
+    let sender = unsafe { (*info).si_pid() };
+    let child_pid = CHILD_PID.load(Ordering::SeqCst);
+    if sender != 0 && child_pid != 0 {
+        unsafe {
+            libc::kill(child_pid, signal);
+        }
+    }
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn handle_console_event(event: winapi::shared::minwindef::DWORD) -> i32 {
+    use winapi::um::wincon::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+    match event {
+        // every process attached to the console gets the event, the runtime included,
+        // so there is nothing to forward
+        CTRL_C_EVENT | CTRL_BREAK_EVENT => {
+            // reported as SIGINT, like on the other platforms
+            RECEIVED_SIGNAL.store(2, Ordering::SeqCst);
+            1
+        }
+        _ => 0,
+    }
+}
+
+/// Forwards interrupts to a child process until dropped
+pub struct ForwardSignals {
+    #[cfg(unix)]
+    previous_actions: Vec<(libc::c_int, libc::sigaction)>,
+}
+
+impl ForwardSignals {
+    /// Starts handling interrupts. Install it before spawning the child, the child
+    /// gets the default handlers back when it starts.
+    pub fn install() -> io::Result<Self> {
+        RECEIVED_SIGNAL.store(0, Ordering::SeqCst);
+        #[cfg(unix)]
+        {
+            let mut previous_actions = vec![];
+            for &signal in FORWARDED_SIGNALS.iter() {
+                unsafe {
+                    let mut action: libc::sigaction = std::mem::zeroed();
+                    action.sa_sigaction = forward_signal as *const () as libc::sighandler_t;
+                    action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+                    libc::sigemptyset(&mut action.sa_mask);
+                    let mut previous_action: libc::sigaction = std::mem::zeroed();
+                    if libc::sigaction(signal, &action, &mut previous_action) != 0 {
+                        let error = io::Error::last_os_error();
+                        drop(Self { previous_actions });
+                        return Err(error);
+                    }
+                    previous_actions.push((signal, previous_action));
+                }
+            }
+            Ok(Self { previous_actions })
+        }
+        #[cfg(windows)]
+        {
+            if unsafe {
+                winapi::um::consoleapi::SetConsoleCtrlHandler(Some(handle_console_event), 1)
+            } == 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self {})
+        }
+        #[cfg(not(any(unix, windows)))]
+        Ok(Self {})
+    }
+
+    /// Forwards the interrupts wapm gets from now on to `child`
+    pub fn forward_to(&self, child: &Child) {
+        #[cfg(unix)]
+        CHILD_PID.store(child.id() as i32, Ordering::SeqCst);
+        #[cfg(not(unix))]
+        let _ = child;
+    }
+
+    /// The signal wapm received since the handlers were installed, if any
+    pub fn received(&self) -> Option<i32> {
+        match RECEIVED_SIGNAL.load(Ordering::SeqCst) {
+            0 => None,
+            signal => Some(signal),
+        }
+    }
+}
+
+impl Drop for ForwardSignals {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            for (signal, previous_action) in self.previous_actions.drain(..) {
+                unsafe {
+                    libc::sigaction(signal, &previous_action, std::ptr::null_mut());
+                }
+            }
+            CHILD_PID.store(0, Ordering::SeqCst);
+        }
+        #[cfg(windows)]
+        unsafe {
+            winapi::um::consoleapi::SetConsoleCtrlHandler(Some(handle_console_event), 0);
+        }
+    }
+}
+
+/// The code wapm exits with when the runtime was interrupted, following the shell
+/// convention of 128 + the signal number (130 for Ctrl-C). A module that handled the
+/// interrupt and exited with a code of its own keeps it. `None` when there was no
+/// interrupt.
+pub fn interrupted_exit_code(status: ExitStatus, received: Option<i32>) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return Some(128 + signal);
+        }
+    }
+    #[cfg(windows)]
+    {
+        // STATUS_CONTROL_C_EXIT, what a process killed by Ctrl-C exits with
+        if status.code() == Some(0xC000_013Au32 as i32) {
+            return Some(128 + 2);
+        }
+    }
+    let signal = received?;
+    match status.code() {
+        Some(code) if code != 0 => Some(code),
+        _ => Some(128 + signal),
+    }
+}
+
+#[cfg(all(test, unix))]
+pub(crate) mod test {
+    use super::*;
+    use lazy_static::lazy_static;
+    use std::process::Command;
+    use std::sync::Mutex;
+
+    lazy_static! {
+        /// Held by the tests that install the signal handlers, like the ones running
+        /// `do_run`: the handlers and the signal they received are process-wide
+        pub(crate) static ref SIGNAL_HANDLERS: Mutex<()> = Mutex::new(());
+    }
+
+    #[test]
+    fn killed_by_a_signal_exits_with_the_shell_code() {
+        let status = Command::new("sh")
+            .args(["-c", "kill -INT $$"])
+            .status()
+            .unwrap();
+        assert_eq!(interrupted_exit_code(status, None), Some(130));
+
+        let status = Command::new("sh").args(["-c", "exit 3"]).status().unwrap();
+        assert_eq!(interrupted_exit_code(status, None), None);
+        // the module handled the interrupt and picked its own code
+        assert_eq!(interrupted_exit_code(status, Some(libc::SIGINT)), Some(3));
+    }
+
+    #[test]
+    fn signals_sent_to_wapm_reach_the_child() {
+        let _signal_handlers = SIGNAL_HANDLERS.lock().unwrap();
+        let forward_signals = ForwardSignals::install().unwrap();
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        forward_signals.forward_to(&child);
+
+        unsafe {
+            libc::kill(libc::getpid(), libc::SIGTERM);
+        }
+        let status = child.wait().unwrap();
+        assert_eq!(forward_signals.received(), Some(libc::SIGTERM));
+        assert_eq!(
+            interrupted_exit_code(status, forward_signals.received()),
+            Some(128 + libc::SIGTERM)
+        );
+    }
+}
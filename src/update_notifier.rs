@@ -152,6 +152,8 @@ pub fn run_async_check_base() {
         std::process::Command::new(current_wapm)
             .arg("run-background-update-check")
             .current_dir(current_dir)
+            // it outlives the command, it must not compete for the terminal's input
+            .stdin(std::process::Stdio::null())
             .stdout(std::process::Stdio::null())
             .stderr(std::process::Stdio::null())
             .spawn()
@@ -1,6 +1,6 @@
 use crate::constants::{DEFAULT_RUNTIME, WAPM_RUNTIME_ENV_KEY};
 use crate::data::manifest::PACKAGES_DIR_NAME;
-use crate::graphql::execute_query;
+use crate::graphql::{execute_query, execute_query_custom_registry};
 use graphql_client::*;
 use license_exprs;
 use semver::Version;
@@ -81,6 +81,13 @@ pub fn get_username() -> anyhow::Result<Option<String>> {
     Ok(response.viewer.map(|viewer| viewer.username))
 }
 
+/// The user the token stored for `registry` logs in as
+pub fn get_username_for_registry(registry: &str) -> anyhow::Result<Option<String>> {
+    let q = WhoAmIQuery::build_query(who_am_i_query::Variables {});
+    let response: who_am_i_query::ResponseData = execute_query_custom_registry(registry, &q)?;
+    Ok(response.viewer.map(|viewer| viewer.username))
+}
+
 #[cfg(feature = "telemetry")]
 pub fn telemetry_is_enabled() -> bool {
     let mut config = if let Ok(c) = crate::config::Config::from_file() {
@@ -2,6 +2,14 @@
     not(feature = "full"),
     allow(dead_code, unused_imports, unused_variables)
 )]
+use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::data::manifest_extensions::{
+    ManifestExtensions, ModuleWasmFeature, COMMAND_EXTENSION_KEYS, MANIFEST_EXTENSION_KEYS,
+    MODULE_EXTENSION_KEYS, PACKAGE_EXTENSION_KEYS,
+};
+use crate::data::manifest_format::{
+    COMMAND_KEY_ORDER, MODULE_KEY_ORDER, PACKAGE_KEY_ORDER, SECTION_ORDER,
+};
 #[cfg(feature = "full")]
 use crate::database;
 use crate::dataflow::{interfaces::InterfaceFromServer, manifest_packages::ManifestResult};
@@ -13,7 +21,7 @@ use std::{
     path::{Path, PathBuf},
 };
 use thiserror::Error;
-use wasmer_wasm_interface::{validate, Interface};
+use wasmer_wasm_interface::{interface_matcher::InterfaceMatcher, parser, validate, Interface};
 
 #[cfg(feature = "full")]
 pub fn validate_directory(pkg_path: PathBuf) -> anyhow::Result<()> {
@@ -23,6 +31,9 @@ pub fn validate_directory(pkg_path: PathBuf) -> anyhow::Result<()> {
         ManifestResult::ManifestError(e) => return Err(e.into()),
         ManifestResult::Manifest(manifest) => manifest,
     };
+    let extensions = ManifestExtensions::find_in_directory(&manifest.base_directory_path)?;
+    extensions.default_command()?;
+    extensions.check_documentation_url()?;
     if let Some(modules) = manifest.module {
         for module in modules.into_iter() {
             let source_path = if module.source.is_relative() {
@@ -47,13 +58,27 @@ pub fn validate_directory(pkg_path: PathBuf) -> anyhow::Result<()> {
                 validate_bindings(bindings, &manifest.base_directory_path)?;
             }
 
+            if let Some(kind) = module.kind.as_deref() {
+                if !is_known_module_kind(kind) {
+                    warn!("{}", unknown_module_kind_message(&module.name, kind));
+                }
+            }
+
+            if let Some(module_extensions) = extensions.get_module(&module.name) {
+                check_declared_wasm_features(
+                    &wasm_buffer,
+                    &module.name,
+                    &module_extensions.wasm_features,
+                );
+            }
+
             // hack, short circuit if no interface for now
             if module.interfaces.is_none() {
                 return validate_wasm_and_report_errors_old(&wasm_buffer[..], source_path_string);
             }
 
             let mut conn = database::open_db()?;
-            let mut interface: Interface = Default::default();
+            let mut candidates = vec![];
             for (interface_name, interface_version) in
                 module.interfaces.unwrap_or_default().into_iter()
             {
@@ -70,21 +95,21 @@ pub fn validate_directory(pkg_path: PathBuf) -> anyhow::Result<()> {
                         &interface_data_from_server.content,
                     )?;
                 }
-                let sub_interface = interfaces::load_interface_from_db(
+                let interface = interfaces::load_interface_from_db(
                     &mut conn,
                     &interface_name,
                     &interface_version,
                 )?;
-                interface = interface
-                    .merge(sub_interface)
-                    .map_err(|e| anyhow!("Failed to merge interface {}: {}", &interface_name, e))?;
+                candidates.push((
+                    format!("{}@{}", interface_name, interface_version),
+                    interface,
+                ));
+            }
+            if candidates.is_empty() {
+                validate_wasm_and_report_errors_old(&wasm_buffer[..], source_path_string)?;
+            } else {
+                check_best_interface_match(&wasm_buffer, source_path_string, &candidates)?;
             }
-            validate::validate_wasm_and_report_errors(&wasm_buffer, &interface).map_err(|e| {
-                ValidationError::InvalidWasm {
-                    file: source_path_string,
-                    error: format!("{:?}", e),
-                }
-            })?;
         }
     }
     debug!("package at path {:#?} validated", &pkg_path);
@@ -102,6 +127,289 @@ fn validate_bindings(
     Ok(())
 }
 
+/// Warns when the `wasm-features` declared for a module don't match the features
+/// the wasm actually uses
+fn check_declared_wasm_features(
+    wasm: &[u8],
+    module_name: &str,
+    declared_features: &[ModuleWasmFeature],
+) {
+    if declared_features.is_empty() {
+        return;
+    }
+    // invalid wasm is reported by the validation proper
+    let used_features: Vec<ModuleWasmFeature> = match validate::detect_wasm_features(wasm) {
+        Ok(features) => features.into_iter().map(ModuleWasmFeature::from).collect(),
+        Err(_) => return,
+    };
+    for feature in used_features.iter() {
+        if !declared_features.contains(feature) {
+            warn!(
+                "Module \"{}\" uses {} but does not list it in `wasm-features`",
+                module_name, feature
+            );
+        }
+    }
+    for feature in declared_features.iter() {
+        if !used_features.contains(feature) {
+            warn!(
+                "Module \"{}\" lists {} in `wasm-features` but does not use it",
+                module_name, feature
+            );
+        }
+    }
+}
+
+/// Checks that the module matches the interface it is closest to among the ones it
+/// declares, and explains how it differs from the others
+fn check_best_interface_match(
+    wasm: &[u8],
+    file: String,
+    candidates: &[(String, Interface)],
+) -> Result<(), ValidationError> {
+    let interfaces: Vec<Interface> = candidates
+        .iter()
+        .map(|(_, interface)| interface.clone())
+        .collect();
+    let interface_match = InterfaceMatcher::best_match(wasm, &interfaces).map_err(|e| {
+        ValidationError::InvalidWasm {
+            file: file.clone(),
+            error: format!("{:?}", e),
+        }
+    })?;
+    let best_name = &candidates[interface_match.best].0;
+    if !interface_match.is_match() {
+        return Err(ValidationError::NoInterfaceMatched {
+            file,
+            closest: best_name.clone(),
+            errors: interface_match.mismatches[interface_match.best].clone(),
+        });
+    }
+    if candidates.len() > 1 {
+        info!("\"{}\" matches the interface {}", file, best_name);
+    }
+    for ((name, _), errors) in candidates.iter().zip(&interface_match.mismatches) {
+        if !errors.is_empty() {
+            info!(
+                "\"{}\" does not match the interface {}:\n  - {}",
+                file,
+                name,
+                errors.join("\n  - ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Validates a wasm file against the interfaces in `interface_paths`, merged together,
+/// without looking for a manifest
+pub fn validate_wasm_against_interface_files(
+    wasm_path: &Path,
+    interface_paths: &[PathBuf],
+) -> Result<(), ValidationError> {
+    let read_file = |path: &Path| {
+        fs::read(path).map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => ValidationError::MissingFile {
+                file: path.to_string_lossy().to_string(),
+            },
+            _ => ValidationError::MiscCannotRead {
+                file: path.to_string_lossy().to_string(),
+                error: format!("{}", err),
+            },
+        })
+    };
+
+    let mut interface: Interface = Default::default();
+    for interface_path in interface_paths {
+        let interface_file = interface_path.to_string_lossy().to_string();
+        let interface_source = String::from_utf8(read_file(interface_path)?).map_err(|e| {
+            ValidationError::InvalidInterface {
+                file: interface_file.clone(),
+                error: e.to_string(),
+            }
+        })?;
+        let sub_interface = parser::parse_interface(&interface_source).map_err(|error| {
+            ValidationError::InvalidInterface {
+                file: interface_file.clone(),
+                error,
+            }
+        })?;
+        interface =
+            interface
+                .merge(sub_interface)
+                .map_err(|error| ValidationError::InvalidInterface {
+                    file: interface_file,
+                    error,
+                })?;
+    }
+
+    let wasm_file = wasm_path.to_string_lossy().to_string();
+    let wasm = read_file(wasm_path)?;
+    validate::validate_wasm_and_report_errors(&wasm, &interface).map_err(|e| match e {
+        validate::WasmValidationError::InterfaceViolated { errors } => {
+            ValidationError::InterfaceViolated {
+                file: wasm_file,
+                errors,
+            }
+        }
+        e => ValidationError::InvalidWasm {
+            file: wasm_file,
+            error: format!("{:?}", e),
+        },
+    })
+}
+
+/// The kinds of `[[module]]` that wapm knows, a module without a kind is a wasm module
+static KNOWN_MODULE_KINDS: &[&str] = &["wasm", "tensorflow"];
+/// The prefix of the kinds that are still experimental, which are not reported
+const EXPERIMENTAL_MODULE_KIND_PREFIX: &str = "x-";
+
+/// Whether `kind` is a kind of module that wapm knows or an experimental one, like
+/// `x-onnx`. Another kind is likely misspelled, and the runners would not know it.
+pub(crate) fn is_known_module_kind(kind: &str) -> bool {
+    KNOWN_MODULE_KINDS.contains(&kind) || kind.starts_with(EXPERIMENTAL_MODULE_KIND_PREFIX)
+}
+
+pub(crate) fn unknown_module_kind_message(module_name: &str, kind: &str) -> String {
+    format!(
+        "module \"{}\" has the unknown kind \"{}\", known kinds are {}. Start it with \
+         \"{}\" if it is an experimental one",
+        module_name,
+        kind,
+        KNOWN_MODULE_KINDS.join(", "),
+        EXPERIMENTAL_MODULE_KIND_PREFIX
+    )
+}
+
+/// The keys `wapm_toml` knows that `wapm fmt` doesn't order
+static OTHER_MODULE_KEYS: &[&str] = &["fs"];
+static OTHER_COMMAND_KEYS: &[&str] = &["runner", "annotations"];
+
+/// Checks that the manifest in `pkg_path` has no keys that neither `wapm_toml` nor the
+/// manifest extensions know. The manifest is parsed leniently so that manifests written
+/// for newer versions of wapm keep working, which makes misspelled keys go unnoticed.
+pub fn check_unknown_manifest_keys(pkg_path: &Path) -> anyhow::Result<()> {
+    let manifest_path = pkg_path.join(MANIFEST_FILE_NAME);
+    if !manifest_path.exists() {
+        return Ok(());
+    }
+    let file = manifest_path.to_string_lossy().to_string();
+    let source =
+        fs::read_to_string(&manifest_path).map_err(|err| ValidationError::MiscCannotRead {
+            file: file.clone(),
+            error: format!("{}", err),
+        })?;
+    let manifest: toml::Value = source.parse()?;
+    let keys = find_unknown_manifest_keys(&manifest);
+    if !keys.is_empty() {
+        return Err(ValidationError::UnknownManifestKeys { file, keys }.into());
+    }
+    Ok(())
+}
+
+/// Warns about the modules of the manifest in `pkg_path` that nothing uses, or fails
+/// with `strict`. See [`find_orphan_modules`].
+pub fn check_orphan_modules(pkg_path: &Path, strict: bool) -> anyhow::Result<()> {
+    let manifest = match ManifestResult::find_in_directory(pkg_path) {
+        ManifestResult::Manifest(manifest) => manifest,
+        // a missing or broken manifest is reported by the validation proper
+        _ => return Ok(()),
+    };
+    let modules = find_orphan_modules(&manifest);
+    if modules.is_empty() {
+        return Ok(());
+    }
+    if strict {
+        return Err(ValidationError::OrphanModules {
+            file: pkg_path
+                .join(MANIFEST_FILE_NAME)
+                .to_string_lossy()
+                .to_string(),
+            modules,
+        }
+        .into());
+    }
+    for module in modules {
+        warn!(
+            "Module \"{}\" is not used by any command and has no bindings. Is a command \
+             referring to it by another name?",
+            module
+        );
+    }
+    Ok(())
+}
+
+/// The modules that no `[[command]]` of the package runs and that have no bindings.
+/// They are usually a module that was renamed without its commands, which only shows
+/// when running them fails with "command not found".
+pub(crate) fn find_orphan_modules(manifest: &Manifest) -> Vec<String> {
+    // a command with a `package` runs a module of that dependency
+    let used_modules: Vec<String> = manifest
+        .command
+        .iter()
+        .flatten()
+        .filter(|command| command.get_package().is_none())
+        .map(|command| command.get_module())
+        .collect();
+    manifest
+        .module
+        .iter()
+        .flatten()
+        .filter(|module| module.bindings.is_none() && !used_modules.contains(&module.name))
+        .map(|module| module.name.clone())
+        .collect()
+}
+
+/// The unknown keys of a manifest, spelled like `package.repostiory` or
+/// `module[1].sorce`
+pub(crate) fn find_unknown_manifest_keys(manifest: &toml::Value) -> Vec<String> {
+    fn unknown_keys(table: &toml::Value, known: &[&[&str]], prefix: &str) -> Vec<String> {
+        table
+            .as_table()
+            .map(|table| {
+                table
+                    .keys()
+                    .filter(|key| !known.iter().any(|keys| keys.contains(&key.as_str())))
+                    .map(|key| format!("{}{}", prefix, key))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    let mut keys = unknown_keys(manifest, &[SECTION_ORDER, MANIFEST_EXTENSION_KEYS], "");
+    if let Some(package) = manifest.get("package") {
+        keys.extend(unknown_keys(
+            package,
+            &[PACKAGE_KEY_ORDER, PACKAGE_EXTENSION_KEYS],
+            "package.",
+        ));
+    }
+    for (section, known) in [
+        (
+            "module",
+            [MODULE_KEY_ORDER, MODULE_EXTENSION_KEYS, OTHER_MODULE_KEYS],
+        ),
+        (
+            "command",
+            [
+                COMMAND_KEY_ORDER,
+                COMMAND_EXTENSION_KEYS,
+                OTHER_COMMAND_KEYS,
+            ],
+        ),
+    ] {
+        let entries = manifest
+            .get(section)
+            .and_then(|entries| entries.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        for (i, entry) in entries.iter().enumerate() {
+            keys.extend(unknown_keys(entry, &known, &format!("{}[{}].", section, i)));
+        }
+    }
+    keys
+}
+
 #[cfg(not(feature = "full"))]
 pub fn validate_directory(pkg_path: PathBuf) -> anyhow::Result<()> {
     Ok(())
@@ -119,6 +427,20 @@ pub enum ValidationError {
     CannotUnpackArchive { file: String, error: String },
     #[error(transparent)]
     Imports(#[from] wapm_toml::ImportsError),
+    #[error("Interface file \"{file}\" is invalid: {error}")]
+    InvalidInterface { file: String, error: String },
+    #[error("WASM file \"{file}\" does not match the interface:\n  - {}", errors.join("\n  - "))]
+    InterfaceViolated { file: String, errors: Vec<String> },
+    #[error("WASM file \"{file}\" does not match any of its interfaces. The closest is {closest}:\n  - {}", errors.join("\n  - "))]
+    NoInterfaceMatched {
+        file: String,
+        closest: String,
+        errors: Vec<String>,
+    },
+    #[error("Manifest \"{file}\" has keys wapm doesn't know, which may be misspelled:\n  - {}", keys.join("\n  - "))]
+    UnknownManifestKeys { file: String, keys: Vec<String> },
+    #[error("Manifest \"{file}\" has modules that no command uses and that have no bindings:\n  - {}", modules.join("\n  - "))]
+    OrphanModules { file: String, modules: Vec<String> },
 }
 
 // legacy function, validates wasm.  TODO: clean up
@@ -151,3 +473,232 @@ pub fn validate_wasm_and_report_errors_old(wasm: &[u8], file_name: String) -> an
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::create_temp_dir;
+
+    /// A module importing `"ns" "fn"` with the signature `(param i32)`
+    const WASM_IMPORTING_NS_FN: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x05, 0x01, 0x60, 0x01, 0x7f, 0x00, // type section
+        0x02, 0x09, 0x01, 0x02, 0x6e, 0x73, 0x02, 0x66, 0x6e, 0x00, 0x00, // import section
+    ];
+
+    #[test]
+    fn module_kinds_must_be_known_or_experimental() {
+        assert!(is_known_module_kind("wasm"));
+        assert!(is_known_module_kind("tensorflow"));
+        assert!(is_known_module_kind("x-onnx"));
+        assert!(!is_known_module_kind("wsam"));
+        assert!(!is_known_module_kind("Wasm"));
+        assert_eq!(
+            unknown_module_kind_message("test", "wsam"),
+            "module \"test\" has the unknown kind \"wsam\", known kinds are wasm, tensorflow. \
+             Start it with \"x-\" if it is an experimental one"
+        );
+    }
+
+    fn write_files(dir: &Path, files: &[(&str, &[u8])]) {
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn wasm_matching_merged_interfaces_is_valid() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        write_files(
+            dir,
+            &[
+                ("module.wasm", WASM_IMPORTING_NS_FN),
+                (
+                    "imports.interface",
+                    br#"(interface "imports" (func (import "ns" "fn") (param i32)))"#,
+                ),
+                (
+                    "globals.interface",
+                    br#"(interface "globals" (global (import "env" "length") (type f64)))"#,
+                ),
+            ],
+        );
+
+        validate_wasm_against_interface_files(
+            &dir.join("module.wasm"),
+            &[dir.join("imports.interface"), dir.join("globals.interface")],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn wasm_violating_interface_is_reported() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        write_files(
+            dir,
+            &[
+                ("module.wasm", WASM_IMPORTING_NS_FN),
+                (
+                    "imports.interface",
+                    br#"(interface "imports" (func (import "ns" "fn") (param i64)))"#,
+                ),
+                ("broken.interface", b"(interface"),
+            ],
+        );
+
+        match validate_wasm_against_interface_files(
+            &dir.join("module.wasm"),
+            &[dir.join("imports.interface")],
+        ) {
+            Err(ValidationError::InterfaceViolated { errors, .. }) => assert_eq!(errors.len(), 1),
+            otherwise => panic!("expected an interface violation, got {:?}", otherwise),
+        }
+        assert!(matches!(
+            validate_wasm_against_interface_files(
+                &dir.join("module.wasm"),
+                &[dir.join("broken.interface")],
+            ),
+            Err(ValidationError::InvalidInterface { .. })
+        ));
+        assert!(matches!(
+            validate_wasm_against_interface_files(&dir.join("missing.wasm"), &[]),
+            Err(ValidationError::MissingFile { .. })
+        ));
+    }
+
+    #[test]
+    fn declared_interfaces_are_matched_to_the_closest() {
+        let candidate =
+            |name: &str, source: &str| (name.to_string(), parser::parse_interface(source).unwrap());
+        let wrong_type = candidate(
+            "wrong-type@0.1.0",
+            r#"(interface (func (import "ns" "fn") (param i64)))"#,
+        );
+        let matching = candidate(
+            "matching@0.1.0",
+            r#"(interface (func (import "ns" "fn") (param i32)))"#,
+        );
+        let other_namespace = candidate(
+            "other-namespace@0.1.0",
+            r#"(interface (func (import "other" "fn") (param i32)))"#,
+        );
+
+        check_best_interface_match(
+            WASM_IMPORTING_NS_FN,
+            "module.wasm".to_string(),
+            &[wrong_type.clone(), matching, other_namespace.clone()],
+        )
+        .unwrap();
+
+        match check_best_interface_match(
+            WASM_IMPORTING_NS_FN,
+            "module.wasm".to_string(),
+            &[other_namespace, wrong_type],
+        ) {
+            Err(ValidationError::NoInterfaceMatched {
+                closest, errors, ..
+            }) => {
+                assert_eq!(closest, "other-namespace@0.1.0");
+                assert_eq!(errors, vec![r#"Missing import "ns" "fn""#]);
+            }
+            otherwise => panic!("expected no interface to match, got {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn misspelled_manifest_keys_are_only_reported_when_strict() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        write_files(
+            dir,
+            &[(
+                "wapm.toml",
+                br#"[package]
+name = "test"
+version = "0.1.0"
+description = "test package"
+repostiory = "https://github.com/wasmerio/wapm-cli"
+documentation = "https://docs.wasmer.io"
+
+[[command]]
+name = "test"
+module = "test"
+default = true
+mian-args = "--help"
+"#,
+            )],
+        );
+
+        // the manifest is valid by default
+        validate_directory(dir.to_path_buf()).unwrap();
+
+        let error = check_unknown_manifest_keys(dir).unwrap_err();
+        match error.downcast_ref::<ValidationError>() {
+            Some(ValidationError::UnknownManifestKeys { keys, .. }) => assert_eq!(
+                keys,
+                &[
+                    "package.repostiory".to_string(),
+                    "command[0].mian-args".to_string()
+                ]
+            ),
+            otherwise => panic!("expected unknown keys, got {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn modules_without_commands_are_orphans() {
+        let tmp_dir = create_temp_dir().unwrap();
+        let dir: &Path = tmp_dir.as_ref();
+        write_files(
+            dir,
+            &[(
+                "wapm.toml",
+                br#"[package]
+name = "test"
+version = "0.1.0"
+description = "test package"
+
+[[module]]
+name = "server"
+source = "server.wasm"
+
+[[module]]
+name = "client"
+source = "client.wasm"
+
+[[command]]
+name = "serve"
+module = "server"
+
+[[command]]
+name = "fetch"
+module = "cleint"
+"#,
+            )],
+        );
+        let manifest = match ManifestResult::find_in_directory(dir) {
+            ManifestResult::Manifest(manifest) => manifest,
+            _ => panic!("the manifest is invalid"),
+        };
+        assert_eq!(find_orphan_modules(&manifest), vec!["client".to_string()]);
+
+        // only a warning unless strict
+        check_orphan_modules(dir, false).unwrap();
+        let error = check_orphan_modules(dir, true).unwrap_err();
+        match error.downcast_ref::<ValidationError>() {
+            Some(ValidationError::OrphanModules { modules, .. }) => {
+                assert_eq!(modules, &["client".to_string()])
+            }
+            otherwise => panic!("expected orphan modules, got {:?}", otherwise),
+        }
+
+        let manifest_path = dir.join("wapm.toml");
+        let fixed = fs::read_to_string(&manifest_path)
+            .unwrap()
+            .replace("cleint", "client");
+        fs::write(&manifest_path, fixed).unwrap();
+        check_orphan_modules(dir, true).unwrap();
+    }
+}